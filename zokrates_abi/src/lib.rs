@@ -329,6 +329,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn signed_fields() {
+        let s = r#"["-1", "2"]"#;
+        assert_eq!(
+            parse::<FieldPrime>(s).unwrap(),
+            Values(vec![
+                Value::Field(FieldPrime::max_value()),
+                Value::Field(2.into())
+            ])
+        );
+    }
+
     #[test]
     fn bools() {
         let s = "[true, false]";
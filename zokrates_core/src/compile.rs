@@ -4,7 +4,7 @@
 //! @author Thibaut Schaeffer <thibaut@schaeff.fr>
 //! @date 2018
 use absy::{Module, ModuleId, Program};
-use flatten::Flattener;
+use flatten::{FlattenError, Flattener};
 use imports::{self, Importer};
 use ir;
 use optimizer::Optimize;
@@ -50,6 +50,7 @@ pub enum CompileErrorInner {
     ImportError(imports::Error),
     SemanticError(semantics::ErrorInner),
     ReadError(io::Error),
+    FlattenError(FlattenError),
 }
 
 impl CompileErrorInner {
@@ -103,6 +104,12 @@ impl From<imports::Error> for CompileErrorInner {
     }
 }
 
+impl From<FlattenError> for CompileErrorInner {
+    fn from(error: FlattenError) -> Self {
+        CompileErrorInner::FlattenError(error)
+    }
+}
+
 impl From<io::Error> for CompileErrorInner {
     fn from(error: io::Error) -> Self {
         CompileErrorInner::ReadError(error)
@@ -125,6 +132,7 @@ impl fmt::Display for CompileErrorInner {
             CompileErrorInner::SemanticError(ref e) => write!(f, "{}", e),
             CompileErrorInner::ReadError(ref e) => write!(f, "{}", e),
             CompileErrorInner::ImportError(ref e) => write!(f, "{}", e),
+            CompileErrorInner::FlattenError(ref e) => write!(f, "{}", e),
         }
     }
 }
@@ -155,7 +163,8 @@ pub fn compile<T: Field, E: Into<imports::Error>>(
     let typed_ast = typed_ast.analyse();
 
     // flatten input program
-    let program_flattened = Flattener::flatten(typed_ast);
+    let program_flattened = Flattener::flatten(typed_ast)
+        .map_err(|e| CompileErrors::from(CompileErrorInner::from(e).in_file(&location)))?;
 
     // analyse (constant propagation after call resolution)
     let program_flattened = program_flattened.analyse();
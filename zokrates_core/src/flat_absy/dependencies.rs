@@ -0,0 +1,120 @@
+//! Module containing the backward dependency cone of variables of flat programs
+//!
+//! The cone of a variable contains the statements defining it, transitively, as well as the constraints on any of
+//! the variables they read. These are the statements to audit to trust the value of the variable.
+
+use crate::flat_absy::dot::operands;
+use crate::flat_absy::*;
+use std::collections::HashSet;
+use std::slice;
+use zokrates_field::field::Field;
+
+/// Returns the indices of the statements of the main function of `prog` in the backward cone of the variable named
+/// `var`, such as `_2` or `~out_0`
+///
+/// # Remarks
+/// * The cone contains the definitions, directives and return statement the variable depends on, and each
+/// condition reading one of the variables they depend on.
+pub fn dependencies<T: Field>(prog: &FlatProg<T>, var: &str) -> HashSet<usize> {
+    let statements = &prog.main.statements;
+
+    let mut cone: HashSet<String> = HashSet::new();
+    cone.insert(var.to_string());
+
+    let mut indices = HashSet::new();
+
+    // variables are defined before they are read, so a single backward pass finds the definitions
+    for (index, s) in statements.iter().enumerate().rev() {
+        let read = match s {
+            FlatStatement::Definition(v, e) if cone.contains(&v.to_string()) => {
+                operands(slice::from_ref(e))
+            }
+            FlatStatement::Directive(d)
+                if d.outputs.iter().any(|o| cone.contains(&o.to_string())) =>
+            {
+                operands(&d.inputs)
+            }
+            FlatStatement::Return(list) => {
+                let returned: Vec<_> = list
+                    .expressions
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| cone.contains(&FlatVariable::public(*i).to_string()))
+                    .map(|(_, e)| e.clone())
+                    .collect();
+                if returned.is_empty() {
+                    continue;
+                }
+                operands(&returned)
+            }
+            _ => continue,
+        };
+
+        indices.insert(index);
+        cone.extend(read.iter().map(|v| v.to_string()));
+    }
+
+    // conditions do not define variables, but restrict the values of the ones they read
+    for (index, s) in statements.iter().enumerate() {
+        if let FlatStatement::Condition(lhs, rhs) = s {
+            if operands(&[lhs.clone(), rhs.clone()])
+                .iter()
+                .any(|v| cone.contains(&v.to_string()))
+            {
+                indices.insert(index);
+            }
+        }
+    }
+
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    #[test]
+    fn disjoint_outputs() {
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+        let c = FlatVariable::new(2);
+        let d = FlatVariable::new(3);
+
+        // c = a * a
+        // d = b * b
+        // c == c * a
+        // return c, d
+        let prog: FlatProg<FieldPrime> = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a), FlatParameter::private(b)],
+                statements: vec![
+                    FlatStatement::Definition(c, FlatExpression::Mult(box a.into(), box a.into())),
+                    FlatStatement::Definition(d, FlatExpression::Mult(box b.into(), box b.into())),
+                    FlatStatement::Condition(
+                        c.into(),
+                        FlatExpression::Mult(box c.into(), box a.into()),
+                    ),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![c.into(), d.into()],
+                    }),
+                ],
+            },
+        };
+
+        let first = dependencies(&prog, "~out_0");
+        let second = dependencies(&prog, "~out_1");
+
+        assert_eq!(first, vec![0, 2, 3].into_iter().collect());
+        assert_eq!(second, vec![1, 3].into_iter().collect());
+        // the cones only share the return statement
+        assert_eq!(
+            first.intersection(&second).cloned().collect::<Vec<_>>(),
+            vec![3]
+        );
+
+        // intermediate variables have their own cone
+        assert_eq!(dependencies(&prog, "_3"), vec![1].into_iter().collect());
+        assert_eq!(dependencies(&prog, "_0"), vec![2].into_iter().collect());
+    }
+}
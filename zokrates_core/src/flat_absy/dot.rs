@@ -0,0 +1,140 @@
+//! Module containing the export of the dependency graph of flat programs to Graphviz DOT
+//!
+//! Each variable is a node, with edges from the operands of a definition or a directive to the variables it
+//! defines. Constraints are drawn as box-shaped assertion nodes with edges from their operands, and returned
+//! expressions as edges to the output variables.
+
+use crate::flat_absy::visitor::{walk, Visitor};
+use crate::flat_absy::*;
+use std::collections::BTreeSet;
+use std::slice;
+use zokrates_field::field::Field;
+
+/// Collects the variables occurring in a program
+#[derive(Default)]
+struct Variables {
+    variables: BTreeSet<FlatVariable>,
+}
+
+impl<T: Field> Visitor<T> for Variables {
+    fn visit_variable(&mut self, v: &FlatVariable) {
+        self.variables.insert(*v);
+    }
+}
+
+/// Returns the variables read by `expressions`
+pub(crate) fn operands<T: Field>(expressions: &[FlatExpression<T>]) -> BTreeSet<FlatVariable> {
+    let mut variables = Variables::default();
+    for e in expressions {
+        variables.visit_expression(e);
+    }
+    variables.variables
+}
+
+/// Returns the edges from each of `operands` to the node `to`
+fn edges<'a>(
+    operands: &'a BTreeSet<FlatVariable>,
+    to: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    operands
+        .iter()
+        .map(move |o| format!("\"{}\" -> \"{}\";", o, to))
+}
+
+/// Returns the dependency graph of `prog` in the Graphviz DOT format
+pub fn to_dot<T: Field>(prog: &FlatProg<T>) -> String {
+    let mut variables = Variables::default();
+    walk(prog, &mut variables);
+
+    let mut lines = vec![];
+    let mut assertions = 0;
+
+    for s in &prog.main.statements {
+        match s {
+            FlatStatement::Definition(v, e) => {
+                lines.extend(edges(&operands(slice::from_ref(e)), &v.to_string()));
+            }
+            FlatStatement::Directive(d) => {
+                let inputs = operands(&d.inputs);
+                for o in &d.outputs {
+                    lines.extend(edges(&inputs, &o.to_string()));
+                }
+            }
+            FlatStatement::Condition(lhs, rhs) => {
+                let node = format!("assert_{}", assertions);
+                assertions += 1;
+                lines.push(format!("\"{}\" [shape=box, label=\"{}\"];", node, s));
+                lines.extend(edges(&operands(&[lhs.clone(), rhs.clone()]), &node));
+            }
+            FlatStatement::Return(list) => {
+                for (index, e) in list.expressions.iter().enumerate() {
+                    let output = FlatVariable::public(index);
+                    variables.variables.insert(output);
+                    lines.extend(edges(&operands(slice::from_ref(e)), &output.to_string()));
+                }
+            }
+            FlatStatement::Comment(_) => {}
+        }
+    }
+
+    let nodes = variables.variables.iter().map(|v| format!("\"{}\";", v));
+
+    format!(
+        "digraph {{\n{}}}\n",
+        nodes
+            .chain(lines)
+            .map(|l| format!("    {}\n", l))
+            .collect::<String>()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    #[test]
+    fn dependency_graph() {
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+        let c = FlatVariable::new(2);
+
+        // c = a * b
+        // b == c * a
+        // return c + a
+        let prog: FlatProg<FieldPrime> = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a), FlatParameter::private(b)],
+                statements: vec![
+                    FlatStatement::Definition(c, FlatExpression::Mult(box a.into(), box b.into())),
+                    FlatStatement::Condition(
+                        b.into(),
+                        FlatExpression::Mult(box c.into(), box a.into()),
+                    ),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![FlatExpression::Add(box c.into(), box a.into())],
+                    }),
+                ],
+            },
+        };
+
+        let dot = to_dot(&prog);
+
+        assert!(dot.starts_with("digraph {\n"));
+        for line in &[
+            "\"_0\";",
+            "\"~out_0\";",
+            "\"_0\" -> \"_2\";",
+            "\"_1\" -> \"_2\";",
+            "\"assert_0\" [shape=box, label=\"_1 == (_2 * _0)\"];",
+            "\"_0\" -> \"assert_0\";",
+            "\"_1\" -> \"assert_0\";",
+            "\"_2\" -> \"assert_0\";",
+            "\"_0\" -> \"~out_0\";",
+            "\"_2\" -> \"~out_0\";",
+        ] {
+            assert!(dot.contains(&format!("    {}\n", line)), "missing {}", line);
+        }
+        assert!(!dot.contains("\"_1\" -> \"~out_0\";"));
+    }
+}
@@ -81,6 +81,16 @@ impl FlatVariable {
     pub fn is_output(&self) -> bool {
         self.id < 0
     }
+
+    /// Returns the internal id, `0` for `~one`, negative for outputs and positive otherwise
+    pub(crate) fn raw_id(&self) -> isize {
+        self.id
+    }
+
+    /// Returns the variable with internal id `id`, as returned by `raw_id`
+    pub(crate) fn from_raw_id(id: isize) -> Self {
+        FlatVariable { id }
+    }
 }
 
 #[cfg(test)]
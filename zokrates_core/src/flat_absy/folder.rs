@@ -0,0 +1,179 @@
+// Generic walk through a flat AST. Not mutating in place
+
+use crate::flat_absy::*;
+use zokrates_field::field::Field;
+
+pub trait Folder<T: Field>: Sized {
+    fn fold_program(&mut self, p: FlatProg<T>) -> FlatProg<T> {
+        fold_program(self, p)
+    }
+
+    fn fold_function(&mut self, f: FlatFunction<T>) -> FlatFunction<T> {
+        fold_function(self, f)
+    }
+
+    fn fold_statement(&mut self, s: FlatStatement<T>) -> Vec<FlatStatement<T>> {
+        fold_statement(self, s)
+    }
+
+    fn fold_directive(&mut self, d: FlatDirective<T>) -> FlatDirective<T> {
+        fold_directive(self, d)
+    }
+
+    fn fold_expression(&mut self, e: FlatExpression<T>) -> FlatExpression<T> {
+        fold_expression(self, e)
+    }
+
+    fn fold_variable(&mut self, v: FlatVariable) -> FlatVariable {
+        v
+    }
+}
+
+pub fn fold_program<T: Field, F: Folder<T>>(f: &mut F, p: FlatProg<T>) -> FlatProg<T> {
+    FlatProg {
+        main: f.fold_function(p.main),
+    }
+}
+
+pub fn fold_function<T: Field, F: Folder<T>>(f: &mut F, fun: FlatFunction<T>) -> FlatFunction<T> {
+    FlatFunction {
+        arguments: fun
+            .arguments
+            .into_iter()
+            .map(|p| FlatParameter {
+                id: f.fold_variable(p.id),
+                ..p
+            })
+            .collect(),
+        statements: fun
+            .statements
+            .into_iter()
+            .flat_map(|s| f.fold_statement(s))
+            .collect(),
+    }
+}
+
+pub fn fold_statement<T: Field, F: Folder<T>>(
+    f: &mut F,
+    s: FlatStatement<T>,
+) -> Vec<FlatStatement<T>> {
+    let res = match s {
+        FlatStatement::Return(list) => FlatStatement::Return(FlatExpressionList {
+            expressions: list
+                .expressions
+                .into_iter()
+                .map(|e| f.fold_expression(e))
+                .collect(),
+        }),
+        FlatStatement::Condition(lhs, rhs) => {
+            FlatStatement::Condition(f.fold_expression(lhs), f.fold_expression(rhs))
+        }
+        FlatStatement::Definition(v, e) => {
+            FlatStatement::Definition(f.fold_variable(v), f.fold_expression(e))
+        }
+        FlatStatement::Directive(d) => FlatStatement::Directive(f.fold_directive(d)),
+        FlatStatement::Comment(text) => FlatStatement::Comment(text),
+    };
+    vec![res]
+}
+
+pub fn fold_directive<T: Field, F: Folder<T>>(f: &mut F, d: FlatDirective<T>) -> FlatDirective<T> {
+    FlatDirective {
+        inputs: d.inputs.into_iter().map(|e| f.fold_expression(e)).collect(),
+        outputs: d.outputs.into_iter().map(|o| f.fold_variable(o)).collect(),
+        ..d
+    }
+}
+
+pub fn fold_expression<T: Field, F: Folder<T>>(
+    f: &mut F,
+    e: FlatExpression<T>,
+) -> FlatExpression<T> {
+    match e {
+        FlatExpression::Number(n) => FlatExpression::Number(n),
+        FlatExpression::Identifier(v) => FlatExpression::Identifier(f.fold_variable(v)),
+        FlatExpression::Add(box left, box right) => {
+            FlatExpression::Add(box f.fold_expression(left), box f.fold_expression(right))
+        }
+        FlatExpression::Sub(box left, box right) => {
+            FlatExpression::Sub(box f.fold_expression(left), box f.fold_expression(right))
+        }
+        FlatExpression::Mult(box left, box right) => {
+            FlatExpression::Mult(box f.fold_expression(left), box f.fold_expression(right))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    // a + 0 -> a, 0 + a -> a
+    struct ZeroAdditionRemover;
+
+    impl<T: Field> Folder<T> for ZeroAdditionRemover {
+        fn fold_expression(&mut self, e: FlatExpression<T>) -> FlatExpression<T> {
+            match fold_expression(self, e) {
+                FlatExpression::Add(box left, box right) => {
+                    if left == FlatExpression::Number(T::zero()) {
+                        right
+                    } else if right == FlatExpression::Number(T::zero()) {
+                        left
+                    } else {
+                        FlatExpression::Add(box left, box right)
+                    }
+                }
+                e => e,
+            }
+        }
+    }
+
+    #[test]
+    fn remove_zero_additions() {
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+
+        let zero = || FlatExpression::Number(FieldPrime::from(0));
+
+        // b = (0 + a) * (a + 0)
+        // return (b + 0) + 0, 0
+        let prog: FlatProg<FieldPrime> = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a)],
+                statements: vec![
+                    FlatStatement::Definition(
+                        b,
+                        FlatExpression::Mult(
+                            box FlatExpression::Add(box zero(), box a.into()),
+                            box FlatExpression::Add(box a.into(), box zero()),
+                        ),
+                    ),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![
+                            FlatExpression::Add(
+                                box FlatExpression::Add(box b.into(), box zero()),
+                                box zero(),
+                            ),
+                            zero(),
+                        ],
+                    }),
+                ],
+            },
+        };
+
+        let expected: FlatProg<FieldPrime> = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a)],
+                statements: vec![
+                    FlatStatement::Definition(b, FlatExpression::Mult(box a.into(), box a.into())),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![b.into(), zero()],
+                    }),
+                ],
+            },
+        };
+
+        assert_eq!(ZeroAdditionRemover.fold_program(prog), expected);
+    }
+}
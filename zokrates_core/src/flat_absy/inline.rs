@@ -0,0 +1,232 @@
+//! Module containing the inlining of linear definitions whose variable is used once
+//!
+//! A definition `v = e` with `e` linear is removed and `e` is substituted for the only use of `v`, reducing the number
+//! of variables. Uses inside a multiplication or a directive are never substituted, as these operands must stay
+//! variables for the program to be quadratic.
+
+use crate::flat_absy::folder::{fold_expression, fold_statement, Folder};
+use crate::flat_absy::visitor::{visit_expression, visit_function, visit_statement, Visitor};
+use crate::flat_absy::*;
+use std::collections::{HashMap, HashSet};
+use zokrates_field::field::Field;
+
+/// Counts the uses of each variable, and collects the ones which cannot be substituted
+#[derive(Default)]
+struct UseCounter {
+    uses: HashMap<FlatVariable, usize>,
+    definitions: HashMap<FlatVariable, usize>,
+    blocked: HashSet<FlatVariable>,
+}
+
+impl UseCounter {
+    fn block<T: Field>(&mut self, e: &FlatExpression<T>) {
+        let mut variables = VariableCollector::default();
+        variables.visit_expression(e);
+        self.blocked.extend(variables.0);
+    }
+}
+
+impl<T: Field> Visitor<T> for UseCounter {
+    fn visit_function(&mut self, f: &FlatFunction<T>) {
+        self.blocked.extend(f.arguments.iter().map(|p| p.id));
+        visit_function(self, f)
+    }
+
+    fn visit_statement(&mut self, s: &FlatStatement<T>) {
+        match s {
+            FlatStatement::Definition(v, e) => {
+                *self.definitions.entry(*v).or_insert(0) += 1;
+                self.visit_expression(e);
+            }
+            FlatStatement::Directive(d) => {
+                for e in &d.inputs {
+                    self.visit_expression(e);
+                    self.block(e);
+                }
+                self.blocked.extend(d.outputs.iter().cloned());
+            }
+            s => visit_statement(self, s),
+        }
+    }
+
+    fn visit_expression(&mut self, e: &FlatExpression<T>) {
+        if let FlatExpression::Mult(..) = e {
+            self.block(e);
+        }
+        visit_expression(self, e)
+    }
+
+    fn visit_variable(&mut self, v: &FlatVariable) {
+        *self.uses.entry(*v).or_insert(0) += 1;
+    }
+}
+
+/// Collects the variables of an expression
+#[derive(Default)]
+struct VariableCollector(HashSet<FlatVariable>);
+
+impl<T: Field> Visitor<T> for VariableCollector {
+    fn visit_variable(&mut self, v: &FlatVariable) {
+        self.0.insert(*v);
+    }
+}
+
+/// Removes the inlined definitions and substitutes their expression for their use
+struct Inliner<T: Field> {
+    candidates: HashSet<FlatVariable>,
+    substitution: HashMap<FlatVariable, FlatExpression<T>>,
+}
+
+impl<T: Field> Folder<T> for Inliner<T> {
+    fn fold_statement(&mut self, s: FlatStatement<T>) -> Vec<FlatStatement<T>> {
+        match s {
+            FlatStatement::Definition(v, e) if self.candidates.contains(&v) => {
+                let e = self.fold_expression(e);
+                self.substitution.insert(v, e);
+                vec![]
+            }
+            s => fold_statement(self, s),
+        }
+    }
+
+    fn fold_expression(&mut self, e: FlatExpression<T>) -> FlatExpression<T> {
+        match e {
+            FlatExpression::Identifier(v) => match self.substitution.remove(&v) {
+                Some(e) => e,
+                None => FlatExpression::Identifier(v),
+            },
+            e => fold_expression(self, e),
+        }
+    }
+}
+
+impl<T: Field> FlatProg<T> {
+    /// Inlines the linear definitions whose variable is used exactly once
+    pub fn inline_single_use_definitions(self) -> FlatProg<T> {
+        let mut counter = UseCounter::default();
+        counter.visit_function(&self.main);
+
+        let candidates = self
+            .main
+            .statements
+            .iter()
+            .filter_map(|s| match s {
+                FlatStatement::Definition(v, e)
+                    if e.is_linear()
+                        && counter.definitions.get(v) == Some(&1)
+                        && counter.uses.get(v) == Some(&1)
+                        && !counter.blocked.contains(v) =>
+                {
+                    Some(*v)
+                }
+                _ => None,
+            })
+            .collect();
+
+        Inliner {
+            candidates,
+            substitution: HashMap::new(),
+        }
+        .fold_program(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    #[test]
+    fn inline_single_use_sum() {
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+        let c = FlatVariable::new(2);
+        let d = FlatVariable::new(3);
+        let e = FlatVariable::new(4);
+
+        let one = || FlatExpression::Number(FieldPrime::from(1));
+
+        // b = a + 1
+        // c = b * a
+        // d = a + a
+        // e = d + 1
+        // return c + e, b
+        let statements = vec![
+            FlatStatement::Definition(b, FlatExpression::Add(box a.into(), box one())),
+            FlatStatement::Definition(c, FlatExpression::Mult(box b.into(), box a.into())),
+            FlatStatement::Definition(d, FlatExpression::Add(box a.into(), box a.into())),
+            FlatStatement::Definition(e, FlatExpression::Add(box d.into(), box one())),
+            FlatStatement::Return(FlatExpressionList {
+                expressions: vec![FlatExpression::Add(box c.into(), box e.into()), b.into()],
+            }),
+        ];
+
+        let prog = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a)],
+                statements,
+            },
+        };
+
+        // `b` is used twice, `c` is not linear, `d` and `e` are inlined
+        // b = a + 1
+        // c = b * a
+        // return c + ((a + a) + 1), b
+        let expected = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a)],
+                statements: vec![
+                    FlatStatement::Definition(b, FlatExpression::Add(box a.into(), box one())),
+                    FlatStatement::Definition(c, FlatExpression::Mult(box b.into(), box a.into())),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![
+                            FlatExpression::Add(
+                                box c.into(),
+                                box FlatExpression::Add(
+                                    box FlatExpression::Add(box a.into(), box a.into()),
+                                    box one(),
+                                ),
+                            ),
+                            b.into(),
+                        ],
+                    }),
+                ],
+            },
+        };
+
+        assert_eq!(prog.inline_single_use_definitions(), expected);
+    }
+
+    #[test]
+    fn keep_multiplication_operands() {
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+
+        // b = a + 1
+        // a == b * a
+        // return 1
+        let prog: FlatProg<FieldPrime> = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a)],
+                statements: vec![
+                    FlatStatement::Definition(
+                        b,
+                        FlatExpression::Add(
+                            box a.into(),
+                            box FlatExpression::Number(FieldPrime::from(1)),
+                        ),
+                    ),
+                    FlatStatement::Condition(
+                        a.into(),
+                        FlatExpression::Mult(box b.into(), box a.into()),
+                    ),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![FlatExpression::Number(FieldPrime::from(1))],
+                    }),
+                ],
+            },
+        };
+
+        assert_eq!(prog.clone().inline_single_use_definitions(), prog);
+    }
+}
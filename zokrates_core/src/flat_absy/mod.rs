@@ -5,14 +5,22 @@
 //! @author Jacob Eberhardt <jacob.eberhardt@tu-berlin.de>
 //! @date 2017
 
+pub mod dependencies;
+pub mod dot;
 pub mod flat_parameter;
 pub mod flat_variable;
+pub mod folder;
+pub mod inline;
+pub mod peephole;
+pub mod simplify;
+pub mod validate;
+pub mod visitor;
 
 pub use self::flat_parameter::FlatParameter;
 pub use self::flat_variable::FlatVariable;
 
 use solvers::{Signed, Solver};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use zokrates_field::field::Field;
 
@@ -22,6 +30,13 @@ pub struct FlatProg<T: Field> {
     pub main: FlatFunction<T>,
 }
 
+impl<T: Field> FlatProg<T> {
+    /// Returns an iterator over the statements of the program, in order
+    pub fn statement_iter(&self) -> std::slice::Iter<FlatStatement<T>> {
+        self.main.statements.iter()
+    }
+}
+
 impl<T: Field> fmt::Display for FlatProg<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.main)
@@ -86,12 +101,14 @@ impl<T: Field> fmt::Debug for FlatFunction<T> {
 ///
 /// * r1cs - R1CS in standard JSON data format
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Hash, Eq)]
 pub enum FlatStatement<T: Field> {
     Return(FlatExpressionList<T>),
     Condition(FlatExpression<T>, FlatExpression<T>),
     Definition(FlatVariable, FlatExpression<T>),
     Directive(FlatDirective<T>),
+    /// An annotation, which adds no constraint
+    Comment(String),
 }
 
 impl<T: Field> fmt::Display for FlatStatement<T> {
@@ -101,6 +118,7 @@ impl<T: Field> fmt::Display for FlatStatement<T> {
             FlatStatement::Return(ref expr) => write!(f, "return {}", expr),
             FlatStatement::Condition(ref lhs, ref rhs) => write!(f, "{} == {}", lhs, rhs),
             FlatStatement::Directive(ref d) => write!(f, "{}", d),
+            FlatStatement::Comment(ref text) => write!(f, "# {}", text),
         }
     }
 }
@@ -114,6 +132,7 @@ impl<T: Field> fmt::Debug for FlatStatement<T> {
                 write!(f, "FlatCondition({:?}, {:?})", lhs, rhs)
             }
             FlatStatement::Directive(ref d) => write!(f, "{:?}", d),
+            FlatStatement::Comment(ref text) => write!(f, "FlatComment({:?})", text),
         }
     }
 }
@@ -151,11 +170,12 @@ impl<T: Field> FlatStatement<T> {
                     ..d
                 })
             }
+            FlatStatement::Comment(text) => FlatStatement::Comment(text),
         }
     }
 }
 
-#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Hash, Eq)]
 pub struct FlatDirective<T: Field> {
     pub inputs: Vec<FlatExpression<T>>,
     pub outputs: Vec<FlatVariable>,
@@ -199,7 +219,13 @@ impl<T: Field> fmt::Display for FlatDirective<T> {
     }
 }
 
-#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[cfg(test)]
+thread_local! {
+    /// Number of calls to `FlatExpression::is_linear`, including recursive ones
+    pub static IS_LINEAR_CALLS: std::cell::Cell<usize> = std::cell::Cell::new(0);
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize, Hash, Eq)]
 pub enum FlatExpression<T: Field> {
     Number(T),
     Identifier(FlatVariable),
@@ -234,21 +260,95 @@ impl<T: Field> FlatExpression<T> {
     }
 
     pub fn is_linear(&self) -> bool {
+        #[cfg(test)]
+        IS_LINEAR_CALLS.with(|calls| calls.set(calls.get() + 1));
+
         match *self {
             FlatExpression::Number(_) | FlatExpression::Identifier(_) => true,
             FlatExpression::Add(ref x, ref y) | FlatExpression::Sub(ref x, ref y) => {
                 x.is_linear() && y.is_linear()
             }
-            FlatExpression::Mult(ref x, ref y) => match (x.clone(), y.clone()) {
-                (box FlatExpression::Number(_), box FlatExpression::Number(_))
-                | (box FlatExpression::Number(_), box FlatExpression::Identifier(_))
-                | (box FlatExpression::Identifier(_), box FlatExpression::Number(_)) => true,
+            FlatExpression::Mult(ref x, ref y) => match (&**x, &**y) {
+                (FlatExpression::Number(_), FlatExpression::Number(_))
+                | (FlatExpression::Number(_), FlatExpression::Identifier(_))
+                | (FlatExpression::Identifier(_), FlatExpression::Number(_)) => true,
+                // scaling a linear expression by a constant keeps it linear
+                (FlatExpression::Number(_), e) | (e, FlatExpression::Number(_)) => e.is_linear(),
                 _ => false,
             },
         }
     }
 }
 
+/// Rewrites a linear expression as a sum of `coeff * var` terms sorted by variable, followed by a constant
+///
+/// # Remarks
+/// * Terms with a coefficient of one are reduced to their variable, terms and constants equal to zero are dropped
+/// * Non-linear expressions are returned unchanged
+pub fn canonicalize_linear<T: Field>(expr: FlatExpression<T>) -> FlatExpression<T> {
+    if !expr.is_linear() {
+        return expr;
+    }
+
+    fn collect<T: Field>(
+        e: FlatExpression<T>,
+        factor: T,
+        terms: &mut BTreeMap<FlatVariable, T>,
+        constant: &mut T,
+    ) {
+        match e {
+            FlatExpression::Number(n) => *constant = constant.clone() + factor * n,
+            FlatExpression::Identifier(v) => {
+                let coeff = terms.entry(v).or_insert(T::from(0));
+                *coeff = coeff.clone() + factor;
+            }
+            FlatExpression::Add(box e1, box e2) => {
+                collect(e1, factor.clone(), terms, constant);
+                collect(e2, factor, terms, constant);
+            }
+            FlatExpression::Sub(box e1, box e2) => {
+                collect(e1, factor.clone(), terms, constant);
+                collect(e2, T::from(0) - factor, terms, constant);
+            }
+            FlatExpression::Mult(box FlatExpression::Number(n1), box FlatExpression::Number(n2)) => {
+                *constant = constant.clone() + factor * n1 * n2
+            }
+            FlatExpression::Mult(box FlatExpression::Number(n), box e)
+            | FlatExpression::Mult(box e, box FlatExpression::Number(n)) => {
+                collect(e, factor * n, terms, constant)
+            }
+            e => unreachable!("{} should be linear", e),
+        }
+    }
+
+    let mut terms = BTreeMap::new();
+    let mut constant = T::from(0);
+
+    collect(expr, T::from(1), &mut terms, &mut constant);
+
+    let terms = terms
+        .into_iter()
+        .filter(|(_, coeff)| *coeff != T::from(0))
+        .map(|(v, coeff)| match coeff == T::from(1) {
+            true => FlatExpression::Identifier(v),
+            false => FlatExpression::Mult(
+                box FlatExpression::Number(coeff),
+                box FlatExpression::Identifier(v),
+            ),
+        })
+        .chain(match constant == T::from(0) {
+            true => None,
+            false => Some(FlatExpression::Number(constant)),
+        });
+
+    terms
+        .fold(None, |acc, term| match acc {
+            None => Some(term),
+            Some(acc) => Some(FlatExpression::Add(box acc, box term)),
+        })
+        .unwrap_or(FlatExpression::Number(T::from(0)))
+}
+
 impl<T: Field> fmt::Display for FlatExpression<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -279,7 +379,7 @@ impl<T: Field> From<FlatVariable> for FlatExpression<T> {
     }
 }
 
-#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize, Hash, Eq)]
 pub struct FlatExpressionList<T: Field> {
     pub expressions: Vec<FlatExpression<T>>,
 }
@@ -327,3 +427,144 @@ impl fmt::Display for Error {
         write!(f, "{}", self.message)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    mod canonicalize_linear {
+        use super::*;
+
+        #[test]
+        fn equivalent_sums() {
+            let a = FlatVariable::new(0);
+            let b = FlatVariable::new(1);
+
+            // a + b
+            let e0: FlatExpression<FieldPrime> = FlatExpression::Add(box a.into(), box b.into());
+            // b + a
+            let e1: FlatExpression<FieldPrime> = FlatExpression::Add(box b.into(), box a.into());
+            // a + 0 + b
+            let e2: FlatExpression<FieldPrime> = FlatExpression::Add(
+                box FlatExpression::Add(
+                    box a.into(),
+                    box FlatExpression::Number(FieldPrime::from(0)),
+                ),
+                box b.into(),
+            );
+
+            let expected = FlatExpression::Add(box a.into(), box b.into());
+
+            assert_eq!(canonicalize_linear(e0), expected);
+            assert_eq!(canonicalize_linear(e1), expected);
+            assert_eq!(canonicalize_linear(e2), expected);
+        }
+
+        #[test]
+        fn coefficients_and_constant() {
+            let a = FlatVariable::new(0);
+            let b = FlatVariable::new(1);
+
+            // 3 + (b * 2) - a + a * 3
+            let e: FlatExpression<FieldPrime> = FlatExpression::Add(
+                box FlatExpression::Sub(
+                    box FlatExpression::Add(
+                        box FlatExpression::Number(FieldPrime::from(3)),
+                        box FlatExpression::Mult(
+                            box b.into(),
+                            box FlatExpression::Number(FieldPrime::from(2)),
+                        ),
+                    ),
+                    box a.into(),
+                ),
+                box FlatExpression::Mult(
+                    box a.into(),
+                    box FlatExpression::Number(FieldPrime::from(3)),
+                ),
+            );
+
+            // 2 * a + 2 * b + 3
+            let expected = FlatExpression::Add(
+                box FlatExpression::Add(
+                    box FlatExpression::Mult(
+                        box FlatExpression::Number(FieldPrime::from(2)),
+                        box a.into(),
+                    ),
+                    box FlatExpression::Mult(
+                        box FlatExpression::Number(FieldPrime::from(2)),
+                        box b.into(),
+                    ),
+                ),
+                box FlatExpression::Number(FieldPrime::from(3)),
+            );
+
+            assert_eq!(canonicalize_linear(e), expected);
+        }
+
+        #[test]
+        fn cancelling_terms() {
+            let a = FlatVariable::new(0);
+
+            // a - a
+            let e: FlatExpression<FieldPrime> = FlatExpression::Sub(box a.into(), box a.into());
+
+            assert_eq!(
+                canonicalize_linear(e),
+                FlatExpression::Number(FieldPrime::from(0))
+            );
+        }
+
+        #[test]
+        fn non_linear_unchanged() {
+            let a = FlatVariable::new(0);
+
+            let e: FlatExpression<FieldPrime> = FlatExpression::Mult(box a.into(), box a.into());
+
+            assert_eq!(canonicalize_linear(e.clone()), e);
+        }
+    }
+
+    mod hash {
+        use super::*;
+        use std::collections::HashSet;
+
+        #[test]
+        fn structurally_equal_expressions() {
+            let a = FlatVariable::new(0);
+            let b = FlatVariable::new(1);
+
+            // a * (b + 1), built twice
+            let e = || -> FlatExpression<FieldPrime> {
+                FlatExpression::Mult(
+                    box a.into(),
+                    box FlatExpression::Add(
+                        box b.into(),
+                        box FlatExpression::Number(FieldPrime::from(1)),
+                    ),
+                )
+            };
+
+            let expressions: HashSet<_> = vec![e(), e()].into_iter().collect();
+            assert_eq!(expressions.len(), 1);
+
+            // the hash is structural, so equivalent sums in a different order are different keys
+            let sums: HashSet<FlatExpression<FieldPrime>> = vec![
+                FlatExpression::Add(box a.into(), box b.into()),
+                FlatExpression::Add(box b.into(), box a.into()),
+            ]
+            .into_iter()
+            .collect();
+            assert_eq!(sums.len(), 2);
+
+            let statements: HashSet<_> = vec![
+                FlatStatement::Condition(b.into(), e()),
+                FlatStatement::Condition(b.into(), e()),
+                FlatStatement::Definition(b, e()),
+            ]
+            .into_iter()
+            .collect();
+            assert_eq!(statements.len(), 2);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Module containing a peephole optimization of flat programs
+//!
+//! Copies `x = y` are removed and `y` is used in place of `x`, additions and subtractions of zero and differences of
+//! equal expressions are simplified, and conditions between equal expressions are dropped. Simplifications can reveal
+//! new copies, so the rewrite is repeated until the program does not change anymore.
+
+use crate::flat_absy::folder::{fold_expression, fold_statement, Folder};
+use crate::flat_absy::*;
+use std::collections::HashMap;
+use zokrates_field::field::Field;
+
+/// Applies one pass of the rewrites
+struct Peephole {
+    /// Number of definitions of each variable, arguments and directive outputs included
+    definitions: HashMap<FlatVariable, usize>,
+    /// Removed copies, and the variable they copy
+    substitution: HashMap<FlatVariable, FlatVariable>,
+}
+
+impl Peephole {
+    fn new<T: Field>(f: &FlatFunction<T>) -> Peephole {
+        let mut definitions = HashMap::new();
+
+        let defined = f
+            .arguments
+            .iter()
+            .map(|p| p.id)
+            .chain(f.statements.iter().flat_map(|s| match s {
+                FlatStatement::Definition(v, _) => vec![*v],
+                FlatStatement::Directive(d) => d.outputs.clone(),
+                _ => vec![],
+            }));
+
+        for v in defined {
+            *definitions.entry(v).or_insert(0) += 1;
+        }
+
+        Peephole {
+            definitions,
+            substitution: HashMap::new(),
+        }
+    }
+
+    /// A copy can be removed if neither side is ever redefined
+    fn is_removable(&self, x: &FlatVariable, y: &FlatVariable) -> bool {
+        self.definitions.get(x) == Some(&1) && self.definitions.get(y).cloned().unwrap_or(0) <= 1
+    }
+}
+
+impl<T: Field> Folder<T> for Peephole {
+    fn fold_statement(&mut self, s: FlatStatement<T>) -> Vec<FlatStatement<T>> {
+        match s {
+            FlatStatement::Definition(x, e) => match self.fold_expression(e) {
+                FlatExpression::Identifier(y) if self.is_removable(&x, &y) => {
+                    self.substitution.insert(x, y);
+                    vec![]
+                }
+                e => vec![FlatStatement::Definition(x, e)],
+            },
+            s => fold_statement(self, s)
+                .into_iter()
+                .filter(|s| match s {
+                    FlatStatement::Condition(lhs, rhs) => lhs != rhs,
+                    _ => true,
+                })
+                .collect(),
+        }
+    }
+
+    fn fold_expression(&mut self, e: FlatExpression<T>) -> FlatExpression<T> {
+        let zero = FlatExpression::Number(T::zero());
+
+        match fold_expression(self, e) {
+            FlatExpression::Identifier(v) => {
+                FlatExpression::Identifier(*self.substitution.get(&v).unwrap_or(&v))
+            }
+            FlatExpression::Add(box left, box right) => {
+                if left == zero {
+                    right
+                } else if right == zero {
+                    left
+                } else {
+                    FlatExpression::Add(box left, box right)
+                }
+            }
+            FlatExpression::Sub(box left, box right) => {
+                if right == zero {
+                    left
+                } else if left == right {
+                    zero
+                } else {
+                    FlatExpression::Sub(box left, box right)
+                }
+            }
+            e => e,
+        }
+    }
+}
+
+impl<T: Field> FlatProg<T> {
+    /// Applies the peephole rewrites until the program does not change anymore
+    pub fn peephole(self) -> FlatProg<T> {
+        let mut prog = self;
+
+        loop {
+            let next = Peephole::new(&prog.main).fold_program(prog.clone());
+            if next == prog {
+                return next;
+            }
+            prog = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    #[test]
+    fn collapse_copies() {
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+        let c = FlatVariable::new(2);
+        let d = FlatVariable::new(3);
+        let e = FlatVariable::new(4);
+
+        let zero = || FlatExpression::Number(FieldPrime::from(0));
+
+        // b = a
+        // c = b - 0
+        // d = c + 0
+        // e = d * c
+        // d == c
+        // return e, d
+        let prog = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a)],
+                statements: vec![
+                    FlatStatement::Definition(b, a.into()),
+                    FlatStatement::Definition(c, FlatExpression::Sub(box b.into(), box zero())),
+                    FlatStatement::Definition(d, FlatExpression::Add(box c.into(), box zero())),
+                    FlatStatement::Definition(e, FlatExpression::Mult(box d.into(), box c.into())),
+                    FlatStatement::Condition(d.into(), c.into()),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![e.into(), d.into()],
+                    }),
+                ],
+            },
+        };
+
+        // e = a * a
+        // return e, a
+        let expected = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a)],
+                statements: vec![
+                    FlatStatement::Definition(e, FlatExpression::Mult(box a.into(), box a.into())),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![e.into(), a.into()],
+                    }),
+                ],
+            },
+        };
+
+        assert_eq!(prog.peephole(), expected);
+    }
+
+    #[test]
+    fn keep_redefined_copies() {
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+
+        // b = a
+        // b = b + 1
+        // return b - b, b
+        let prog: FlatProg<FieldPrime> = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a)],
+                statements: vec![
+                    FlatStatement::Definition(b, a.into()),
+                    FlatStatement::Definition(
+                        b,
+                        FlatExpression::Add(
+                            box b.into(),
+                            box FlatExpression::Number(FieldPrime::from(1)),
+                        ),
+                    ),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![
+                            FlatExpression::Sub(box b.into(), box b.into()),
+                            b.into(),
+                        ],
+                    }),
+                ],
+            },
+        };
+
+        let mut expected = prog.clone();
+        expected.main.statements[2] = FlatStatement::Return(FlatExpressionList {
+            expressions: vec![FlatExpression::Number(FieldPrime::from(0)), b.into()],
+        });
+
+        assert_eq!(prog.peephole(), expected);
+    }
+}
@@ -0,0 +1,93 @@
+//! Module containing the `UnitMultiplicationSimplifier` to rewrite code of the form
+// ```
+// a * 1
+// 1 * a
+// ```
+// to `a`
+
+use crate::flat_absy::folder::{fold_expression, Folder};
+use crate::flat_absy::*;
+use zokrates_field::field::Field;
+
+pub struct UnitMultiplicationSimplifier {}
+
+impl UnitMultiplicationSimplifier {
+    fn new() -> UnitMultiplicationSimplifier {
+        UnitMultiplicationSimplifier {}
+    }
+
+    pub fn simplify<T: Field>(p: FlatProg<T>) -> FlatProg<T> {
+        UnitMultiplicationSimplifier::new().fold_program(p)
+    }
+}
+
+impl<T: Field> Folder<T> for UnitMultiplicationSimplifier {
+    fn fold_expression(&mut self, e: FlatExpression<T>) -> FlatExpression<T> {
+        match fold_expression(self, e) {
+            FlatExpression::Mult(box left, box right) => {
+                if left == FlatExpression::Number(T::one()) {
+                    right
+                } else if right == FlatExpression::Number(T::one()) {
+                    left
+                } else {
+                    FlatExpression::Mult(box left, box right)
+                }
+            }
+            e => e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    #[test]
+    fn remove_unit_multiplications() {
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+
+        let one = || FlatExpression::Number(FieldPrime::from(1));
+
+        // b == (a * 1) * (1 * (b * 1))
+        // return 1 * 1
+        let prog: FlatProg<FieldPrime> = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a), FlatParameter::private(b)],
+                statements: vec![
+                    FlatStatement::Condition(
+                        b.into(),
+                        FlatExpression::Mult(
+                            box FlatExpression::Mult(box a.into(), box one()),
+                            box FlatExpression::Mult(
+                                box one(),
+                                box FlatExpression::Mult(box b.into(), box one()),
+                            ),
+                        ),
+                    ),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![FlatExpression::Mult(box one(), box one())],
+                    }),
+                ],
+            },
+        };
+
+        let expected: FlatProg<FieldPrime> = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a), FlatParameter::private(b)],
+                statements: vec![
+                    FlatStatement::Condition(
+                        b.into(),
+                        FlatExpression::Mult(box a.into(), box b.into()),
+                    ),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![one()],
+                    }),
+                ],
+            },
+        };
+
+        assert_eq!(UnitMultiplicationSimplifier::simplify(prog), expected);
+    }
+}
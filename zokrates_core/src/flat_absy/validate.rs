@@ -0,0 +1,115 @@
+//! Module containing the validation of flat functions
+//!
+//! Gadgets may define a variable in terms of itself once it has a value, for example to check it. A definition
+//! referring to its own variable before any value was assigned to it cannot be solved, and is rejected.
+
+use crate::flat_absy::visitor::Visitor;
+use crate::flat_absy::*;
+use std::collections::HashSet;
+use std::fmt;
+use zokrates_field::field::Field;
+
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    /// A variable is defined in terms of itself without being defined before
+    UndefinedSelfReference(FlatVariable),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::UndefinedSelfReference(v) => {
+                write!(f, "Variable {} is defined in terms of itself", v)
+            }
+        }
+    }
+}
+
+/// Finds whether a variable occurs in an expression
+struct Occurrence {
+    variable: FlatVariable,
+    found: bool,
+}
+
+impl<T: Field> Visitor<T> for Occurrence {
+    fn visit_variable(&mut self, v: &FlatVariable) {
+        self.found |= *v == self.variable;
+    }
+}
+
+impl<T: Field> FlatFunction<T> {
+    /// Checks that no variable is defined in terms of itself before being defined
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut defined: HashSet<FlatVariable> = self.arguments.iter().map(|p| p.id).collect();
+
+        for s in &self.statements {
+            match s {
+                FlatStatement::Definition(v, e) => {
+                    if !defined.contains(v) {
+                        let mut occurrence = Occurrence {
+                            variable: *v,
+                            found: false,
+                        };
+                        occurrence.visit_expression(e);
+                        if occurrence.found {
+                            return Err(ValidationError::UndefinedSelfReference(*v));
+                        }
+                    }
+                    defined.insert(*v);
+                }
+                FlatStatement::Directive(d) => defined.extend(d.outputs.iter().cloned()),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    fn increment(x: FlatVariable) -> FlatStatement<FieldPrime> {
+        FlatStatement::Definition(
+            x,
+            FlatExpression::Add(box x.into(), box FlatExpression::Number(FieldPrime::from(1))),
+        )
+    }
+
+    fn function(statements: Vec<FlatStatement<FieldPrime>>) -> FlatFunction<FieldPrime> {
+        FlatFunction {
+            arguments: vec![FlatParameter::private(FlatVariable::new(0))],
+            statements,
+        }
+    }
+
+    #[test]
+    fn undefined_self_reference() {
+        // x = x + 1
+        let x = FlatVariable::new(1);
+        assert_eq!(
+            function(vec![increment(x)]).validate(),
+            Err(ValidationError::UndefinedSelfReference(x))
+        );
+    }
+
+    #[test]
+    fn defined_self_reference() {
+        // x = _0
+        // x = x + 1
+        let x = FlatVariable::new(1);
+        let f = function(vec![
+            FlatStatement::Definition(x, FlatVariable::new(0).into()),
+            increment(x),
+        ]);
+        assert_eq!(f.validate(), Ok(()));
+
+        // _0 = _0 + 1
+        assert_eq!(
+            function(vec![increment(FlatVariable::new(0))]).validate(),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,155 @@
+// Generic walk through a flat AST. Not mutating
+
+use crate::flat_absy::*;
+use zokrates_field::field::Field;
+
+pub trait Visitor<T: Field>: Sized {
+    fn visit_function(&mut self, f: &FlatFunction<T>) {
+        visit_function(self, f)
+    }
+
+    fn visit_statement(&mut self, s: &FlatStatement<T>) {
+        visit_statement(self, s)
+    }
+
+    fn visit_condition(&mut self, lhs: &FlatExpression<T>, rhs: &FlatExpression<T>) {
+        visit_condition(self, lhs, rhs)
+    }
+
+    fn visit_directive(&mut self, d: &FlatDirective<T>) {
+        visit_directive(self, d)
+    }
+
+    fn visit_expression(&mut self, e: &FlatExpression<T>) {
+        visit_expression(self, e)
+    }
+
+    fn visit_variable(&mut self, _v: &FlatVariable) {}
+}
+
+/// Walks through all statements and expressions of `prog`, in order
+pub fn walk<T: Field, V: Visitor<T>>(prog: &FlatProg<T>, v: &mut V) {
+    v.visit_function(&prog.main)
+}
+
+pub fn visit_function<T: Field, V: Visitor<T>>(v: &mut V, f: &FlatFunction<T>) {
+    for p in &f.arguments {
+        v.visit_variable(&p.id);
+    }
+    for s in &f.statements {
+        v.visit_statement(s);
+    }
+}
+
+pub fn visit_statement<T: Field, V: Visitor<T>>(v: &mut V, s: &FlatStatement<T>) {
+    match s {
+        FlatStatement::Return(list) => {
+            for e in &list.expressions {
+                v.visit_expression(e);
+            }
+        }
+        FlatStatement::Condition(lhs, rhs) => v.visit_condition(lhs, rhs),
+        FlatStatement::Definition(var, e) => {
+            v.visit_variable(var);
+            v.visit_expression(e);
+        }
+        FlatStatement::Directive(d) => v.visit_directive(d),
+        FlatStatement::Comment(_) => {}
+    }
+}
+
+pub fn visit_condition<T: Field, V: Visitor<T>>(
+    v: &mut V,
+    lhs: &FlatExpression<T>,
+    rhs: &FlatExpression<T>,
+) {
+    v.visit_expression(lhs);
+    v.visit_expression(rhs);
+}
+
+pub fn visit_directive<T: Field, V: Visitor<T>>(v: &mut V, d: &FlatDirective<T>) {
+    for e in &d.inputs {
+        v.visit_expression(e);
+    }
+    for o in &d.outputs {
+        v.visit_variable(o);
+    }
+}
+
+pub fn visit_expression<T: Field, V: Visitor<T>>(v: &mut V, e: &FlatExpression<T>) {
+    match e {
+        FlatExpression::Number(_) => {}
+        FlatExpression::Identifier(var) => v.visit_variable(var),
+        FlatExpression::Add(left, right)
+        | FlatExpression::Sub(left, right)
+        | FlatExpression::Mult(left, right) => {
+            v.visit_expression(left);
+            v.visit_expression(right);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    struct MultCounter {
+        count: usize,
+    }
+
+    impl<T: Field> Visitor<T> for MultCounter {
+        fn visit_expression(&mut self, e: &FlatExpression<T>) {
+            if let FlatExpression::Mult(..) = e {
+                self.count += 1;
+            }
+            visit_expression(self, e)
+        }
+    }
+
+    #[test]
+    fn count_mult() {
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+
+        // b = a * (a * 2)
+        // a * a == b * 1
+        // return a + b * b
+        let prog: FlatProg<FieldPrime> = FlatProg {
+            main: FlatFunction {
+                arguments: vec![FlatParameter::private(a)],
+                statements: vec![
+                    FlatStatement::Definition(
+                        b,
+                        FlatExpression::Mult(
+                            box a.into(),
+                            box FlatExpression::Mult(
+                                box a.into(),
+                                box FlatExpression::Number(FieldPrime::from(2)),
+                            ),
+                        ),
+                    ),
+                    FlatStatement::Condition(
+                        FlatExpression::Mult(box a.into(), box a.into()),
+                        FlatExpression::Mult(
+                            box b.into(),
+                            box FlatExpression::Number(FieldPrime::from(1)),
+                        ),
+                    ),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![FlatExpression::Add(
+                            box a.into(),
+                            box FlatExpression::Mult(box b.into(), box b.into()),
+                        )],
+                    }),
+                ],
+            },
+        };
+
+        let mut counter = MultCounter { count: 0 };
+        walk(&prog, &mut counter);
+
+        assert_eq!(counter.count, 5);
+        assert_eq!(prog.statement_iter().count(), 3);
+    }
+}
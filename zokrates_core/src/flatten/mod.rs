@@ -7,14 +7,150 @@
 
 use crate::flat_absy::*;
 use crate::solvers::Solver;
+use crate::trace::Phase;
+use crate::typed_absy::folder::{fold_boolean_expression, fold_field_expression, Folder};
 use crate::typed_absy::types::{FunctionIdentifier, FunctionKey, MemberId, Signature, Type};
 use crate::typed_absy::*;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::fmt;
 use zokrates_field::field::Field;
 
+/// Errors which can occur when flattening a program
+#[derive(Debug, PartialEq)]
+pub enum FlattenError {
+    /// The bit width used by the gadgets does not fit in the field
+    BitWidthTooLarge { requested: usize, field_max: usize },
+    /// The bit width used by the gadgets is zero, which leaves no room for a sign bit
+    BitWidthZero,
+    /// The flattened program has more constraints than allowed
+    BudgetExceeded { budget: usize },
+    /// A condition between two constants does not hold
+    UnsatisfiableConstraint { constraint: String },
+    /// Arrays compared element-wise have different lengths
+    LengthMismatch { left: usize, right: usize },
+    /// The main function has no return statement, so the program has no outputs
+    EmptyProgram,
+    /// The main function has two arguments of the same name
+    DuplicateArgument { name: String },
+    /// An identifier declared as a constant is assigned to, which would be ignored when it is read
+    ConstantAssigned { name: String },
+    /// The results of the integer operations before reduction do not fit in the field
+    IntSemanticsTooWide {
+        semantics: IntSemantics,
+        field_max: usize,
+    },
+}
+
+impl fmt::Display for FlattenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlattenError::BitWidthTooLarge {
+                requested,
+                field_max,
+            } => write!(
+                f,
+                "Bit width {} is too large, the field allows at most {} bits",
+                requested, field_max
+            ),
+            FlattenError::BitWidthZero => write!(f, "Bit width must be at least 1"),
+            FlattenError::BudgetExceeded { budget } => write!(
+                f,
+                "The program exceeds the budget of {} constraints",
+                budget
+            ),
+            FlattenError::UnsatisfiableConstraint { constraint } => {
+                write!(f, "Constraint `{}` can never be satisfied", constraint)
+            }
+            FlattenError::LengthMismatch { left, right } => write!(
+                f,
+                "Cannot compare arrays of different lengths {} and {}",
+                left, right
+            ),
+            FlattenError::EmptyProgram => write!(f, "The main function does not return"),
+            FlattenError::DuplicateArgument { name } => {
+                write!(f, "Argument `{}` of main is declared twice", name)
+            }
+            FlattenError::ConstantAssigned { name } => {
+                write!(f, "Constant `{}` cannot be assigned to", name)
+            }
+            FlattenError::IntSemanticsTooWide {
+                semantics,
+                field_max,
+            } => write!(
+                f,
+                "Integer semantics {:?} need results of more than the {} bits the field allows",
+                semantics, field_max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FlattenError {}
+
+/// Callback observing the statements of the flattened program
+pub type Observer<T> = Box<dyn FnMut(&FlatStatement<T>)>;
+
+/// Semantics of the field arithmetic operations `+`, `-` and `*`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntSemantics {
+    /// Operations are the ones of the field
+    Field,
+    /// Operands are integers of the given number of bits, and results wrap around modulo `2^bits`
+    WrappingBits(usize),
+    /// Operands are integers of the given number of bits, and results are checked to fit in them
+    CheckedBits(usize),
+}
+
+impl Default for IntSemantics {
+    fn default() -> Self {
+        IntSemantics::Field
+    }
+}
+
+/// Options of a `Flattener`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FlattenerConfig {
+    /// Bit width of the operands of comparisons and bitwise operations, `None` for the default of the field
+    pub bits: Option<usize>,
+    /// Maximum number of constraints of the program, if any
+    pub constraint_budget: Option<usize>,
+    /// Whether the operands of comparisons are checked to fit in `bits` bits
+    pub checked_comparisons: bool,
+    /// Whether conditions are combined into a validity flag returned after the values of `main`
+    pub validity_output: bool,
+    /// Source name of the outputs of `main`, `None` to keep `~out`
+    pub output_name: Option<String>,
+    /// Semantics of `+`, `-` and `*`
+    pub int_semantics: IntSemantics,
+}
+
+/// The flattened first statements of `main`, with the state of the flattener needed to resume after them
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatPrefix<'ast, T: Field> {
+    /// Parameters of `main` and statements flattened so far, without a return statement
+    main: FlatFunction<T>,
+    /// Parameters of `main`
+    arguments: Vec<Parameter<'ast>>,
+    /// Statements of `main` flattened in `main`
+    statements: Vec<TypedStatement<'ast, T>>,
+    /// Variables of the identifiers after the last statement
+    layout: HashMap<Identifier<'ast>, Vec<FlatVariable>>,
+    /// Index of the next variable after the last statement
+    next_var_idx: usize,
+    /// Source names of the identifiers and variables
+    display_names: HashMap<String, String>,
+}
+
+impl<'ast, T: Field> FlatPrefix<'ast, T> {
+    /// Returns the parameters of `main` and the statements flattened so far
+    pub fn main(&self) -> &FlatFunction<T> {
+        &self.main
+    }
+}
+
 /// Flattener, computes flattened program.
-#[derive(Debug)]
 pub struct Flattener<'ast, T: Field> {
     /// Index of the next introduced variable while processing the program.
     next_var_idx: usize,
@@ -22,12 +158,117 @@ pub struct Flattener<'ast, T: Field> {
     layout: HashMap<Identifier<'ast>, Vec<FlatVariable>>,
     /// Cached `FlatFunction`s to avoid re-flattening them
     flat_cache: HashMap<FunctionKey<'ast>, FlatFunction<T>>,
+    /// Number of bits of the operands of bitwise operations
+    bits: usize,
+    /// Called on each statement of the flattened program, in order
+    on_emit: Option<Observer<T>>,
+    /// Whether `next_var_idx` is kept across flattened functions instead of restarting at 0
+    preserve_index: bool,
+    /// Maximum number of constraints of the flattened program, if any
+    constraint_budget: Option<usize>,
+    /// Whether the operands of comparisons are checked to fit in `bits` bits
+    checked_comparisons: bool,
+    /// Whether conditions are combined into a validity flag returned after the values of `main`
+    validity_output: bool,
+    /// Source name of the outputs of `main`, if any
+    output_name: Option<String>,
+    /// Source name of each identifier and `FlatVariable`, indexed by their displayed name
+    display_names: HashMap<String, String>,
+    /// Values of the identifiers known at compile time, which are flattened to numbers
+    constants: HashMap<Identifier<'ast>, T>,
+    /// Flattened `IfElse` expressions, keyed by the expressions themselves so that a hit is confirmed by `Eq`
+    ///
+    /// An entry reads the identifiers of its expression through `layout`, so it is only valid as long
+    /// as none of them is assigned again. The cache is therefore cleared by `use_variable` and when a
+    /// function starts.
+    if_else_cache: HashMap<TypedExpression<'ast, T>, Vec<FlatExpression<T>>>,
+    /// Semantics of `+`, `-` and `*`
+    int_semantics: IntSemantics,
+}
+
+impl<'ast, T: Field> fmt::Debug for Flattener<'ast, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Flattener")
+            .field("next_var_idx", &self.next_var_idx)
+            .field("layout", &self.layout)
+            .field("flat_cache", &self.flat_cache)
+            .field("bits", &self.bits)
+            .field("on_emit", &self.on_emit.is_some())
+            .field("preserve_index", &self.preserve_index)
+            .field("constraint_budget", &self.constraint_budget)
+            .field("checked_comparisons", &self.checked_comparisons)
+            .field("validity_output", &self.validity_output)
+            .field("output_name", &self.output_name)
+            .field("display_names", &self.display_names)
+            .field("constants", &self.constants)
+            .field("if_else_cache", &self.if_else_cache)
+            .field("int_semantics", &self.int_semantics)
+            .finish()
+    }
+}
+
+impl<'ast, T: Field> Default for Flattener<'ast, T> {
+    fn default() -> Self {
+        Flattener::new()
+    }
+}
+
+/// Finds the first bit width given in an expression which the gadgets cannot handle in the field
+#[derive(Default)]
+struct Widths {
+    /// The first requested width which is too large, and the largest one supported
+    too_large: Option<(usize, usize)>,
+}
+
+impl Widths {
+    fn check(&mut self, requested: usize, field_max: usize) {
+        if requested > field_max && self.too_large.is_none() {
+            self.too_large = Some((requested, field_max));
+        }
+    }
+}
+
+impl<'ast, T: Field> Folder<'ast, T> for Widths {
+    fn fold_boolean_expression(
+        &mut self,
+        e: BooleanExpression<'ast, T>,
+    ) -> BooleanExpression<'ast, T> {
+        // `lhs - rhs + 2^width` is decomposed on `width + 1` bits
+        if let BooleanExpression::LtWidth(_, _, width) | BooleanExpression::LeWidth(_, _, width) = e
+        {
+            self.check(width, T::get_required_bits() - 2);
+        }
+        fold_boolean_expression(self, e)
+    }
+
+    fn fold_field_expression(
+        &mut self,
+        e: FieldElementExpression<'ast, T>,
+    ) -> FieldElementExpression<'ast, T> {
+        match e {
+            // the value is decomposed on all bits of the field, the lowest `bits` of which are kept
+            FieldElementExpression::ReduceBits(_, bits) => {
+                self.check(bits, T::get_required_bits() - 1)
+            }
+            // the value is checked to fit in `bits` bits by its decomposition
+            FieldElementExpression::PopCount(_, bits) => {
+                self.check(bits, T::get_required_bits() - 1)
+            }
+            _ => {}
+        }
+        fold_field_expression(self, e)
+    }
 }
 
 // We introduce a trait in order to make it possible to make flattening `e` generic over the type of `e`
 
 trait Flatten<'ast, T: Field>:
-    TryFrom<TypedExpression<'ast, T>, Error = ()> + IfElse<'ast, T> + Select<'ast, T> + Member<'ast, T>
+    TryFrom<TypedExpression<'ast, T>, Error = ()>
+    + Into<TypedExpression<'ast, T>>
+    + Clone
+    + IfElse<'ast, T>
+    + Select<'ast, T>
+    + Member<'ast, T>
 {
     fn flatten(
         self,
@@ -104,10 +345,22 @@ impl<'ast, T: Field> Flatten<'ast, T> for ArrayExpression<'ast, T> {
 }
 
 impl<'ast, T: Field> Flattener<'ast, T> {
-    pub fn flatten(p: TypedProgram<'ast, T>) -> FlatProg<T> {
+    pub fn flatten(p: TypedProgram<'ast, T>) -> Result<FlatProg<T>, FlattenError> {
         Flattener::new().flatten_program(p)
     }
 
+    /// Flattens a program, calling `on_emit` on each statement of the result
+    pub fn flatten_with_observer<F: FnMut(&FlatStatement<T>) + 'static>(
+        p: TypedProgram<'ast, T>,
+        on_emit: F,
+    ) -> Result<FlatProg<T>, FlattenError> {
+        Flattener {
+            on_emit: Some(Box::new(on_emit)),
+            ..Flattener::new()
+        }
+        .flatten_program(p)
+    }
+
     /// Returns a `Flattener` with fresh `layout`.
 
     fn new() -> Flattener<'ast, T> {
@@ -115,2442 +368,8152 @@ impl<'ast, T: Field> Flattener<'ast, T> {
             next_var_idx: 0,
             layout: HashMap::new(),
             flat_cache: HashMap::new(),
+            bits: T::get_required_bits() - 2,
+            on_emit: None,
+            preserve_index: false,
+            constraint_budget: None,
+            checked_comparisons: false,
+            validity_output: false,
+            output_name: None,
+            display_names: HashMap::new(),
+            constants: HashMap::new(),
+            if_else_cache: HashMap::new(),
+            int_semantics: IntSemantics::Field,
         }
     }
 
-    // Let's assume b = [1, 1, 1, 0]
-    //
-    // 1. Init `sizeUnknown = true`
-    //    As long as `sizeUnknown` is `true` we don't yet know if a is <= than b.
-    // 2. Loop over `b`:
-    //     * b[0] = 1
-    //       when `b` is 1 we check wether `a` is 0 in that particular run and update
-    //       `sizeUnknown` accordingly:
-    //       `sizeUnknown = sizeUnknown && a[0]`
-    //     * b[1] = 1
-    //       `sizrUnknown = sizeUnknown && a[1]`
-    //     * b[2] = 1
-    //       `sizeUnknown = sizeUnknown && a[2]`
-    //     * b[3] = 0
-    //       we need to enforce that `a` is 0 in case `sizeUnknown`is still `true`,
-    //       otherwise `a` can be {0,1}:
-    //      `true == (!sizeUnknown || !a[3])`
-    //      ```
-    //                     **true => a -> 0
-    //         sizeUnkown *
-    //                     **false => a -> {0,1}
-    //      ```
-    fn strict_le_check(
-        &mut self,
-        statements_flattened: &mut Vec<FlatStatement<T>>,
-        b: &[bool],
-        a: Vec<FlatVariable>,
-    ) {
-        let len = b.len();
-        assert_eq!(a.len(), T::get_required_bits());
-        assert_eq!(a.len(), b.len());
+    /// Returns a `Flattener` with the options of `config`
+    pub fn from_config(config: FlattenerConfig) -> Flattener<'ast, T> {
+        Flattener {
+            bits: config.bits.unwrap_or(T::get_required_bits() - 2),
+            constraint_budget: config.constraint_budget,
+            checked_comparisons: config.checked_comparisons,
+            validity_output: config.validity_output,
+            output_name: config.output_name,
+            int_semantics: config.int_semantics,
+            ..Flattener::new()
+        }
+    }
 
-        let mut is_not_smaller_run = vec![];
-        let mut size_unknown = vec![];
+    /// Returns a `Flattener` operating bitwise on `bits` bits, numbering variables from `start`.
+    ///
+    /// # Remarks
+    /// * Numbering is preserved across calls to `flatten_program`, so that programs flattened separately
+    /// by the same `Flattener` do not share variables.
+    pub fn with_next_index(bits: usize, start: usize) -> Flattener<'ast, T> {
+        Flattener {
+            next_var_idx: start,
+            bits,
+            preserve_index: true,
+            ..Flattener::new()
+        }
+    }
 
-        for _ in 0..len {
-            is_not_smaller_run.push(self.use_sym());
-            size_unknown.push(self.use_sym());
+    /// Returns the index of the next introduced variable
+    pub fn next_index(&self) -> usize {
+        self.next_var_idx
+    }
+
+    /// Returns the source name of a variable given its displayed name, such as `x` for `_x_1` or `_3`
+    pub fn original_name(&self, mangled: &str) -> Option<&str> {
+        self.display_names.get(mangled).map(|name| name.as_str())
+    }
+
+    /// Makes flattening fail as soon as the flattened program has more than `budget` constraints
+    pub fn constraint_budget(self, budget: usize) -> Self {
+        Flattener {
+            constraint_budget: Some(budget),
+            ..self
         }
+    }
 
-        // init size_unknown = true
-        statements_flattened.push(FlatStatement::Definition(
-            size_unknown[0],
-            FlatExpression::Number(T::from(1)),
-        ));
+    /// Makes comparisons check that their operands fit in `bits` bits
+    ///
+    /// # Remarks
+    /// * Comparisons are only correct for operands which fit in the bit width they decompose them on. Values
+    /// overflowing the `bits` bits they are meant to hold then make the program unsatisfiable instead of
+    /// comparing wrongly.
+    pub fn checked_comparisons(self, checked: bool) -> Self {
+        Flattener {
+            checked_comparisons: checked,
+            ..self
+        }
+    }
 
-        for (i, b) in b.iter().enumerate() {
-            if *b {
-                statements_flattened.push(FlatStatement::Definition(
-                    is_not_smaller_run[i],
-                    a[i].clone().into(),
-                ));
+    /// Makes conditions set a flag returned after the values of `main` instead of failing
+    ///
+    /// # Remarks
+    /// * The last value returned by the flattened program is `1` if all conditions hold and `0` otherwise, so a
+    /// proof can be generated either way.
+    pub fn validity_output(self, validity_output: bool) -> Self {
+        Flattener {
+            validity_output,
+            ..self
+        }
+    }
 
-                // don't need to update size_unknown in the last round
-                if i < len - 1 {
-                    statements_flattened.push(FlatStatement::Definition(
-                        size_unknown[i + 1],
-                        FlatExpression::Mult(
-                            box size_unknown[i].into(),
-                            box is_not_smaller_run[i].into(),
-                        ),
-                    ));
-                }
-            } else {
-                // don't need to update size_unknown in the last round
-                if i < len - 1 {
-                    statements_flattened.push(
-                        // sizeUnknown is not changing in this case
-                        // We sill have to assign the old value to the variable of the current run
-                        // This trivial definition will later be removed by the optimiser
-                        FlatStatement::Definition(
-                            size_unknown[i + 1].into(),
-                            size_unknown[i].into(),
-                        ),
-                    );
-                }
+    /// Makes `+`, `-` and `*` operate on integers of a fixed number of bits instead of field elements
+    ///
+    /// # Remarks
+    /// * Operands are assumed to fit in the number of bits, as do results of previous operations. Products
+    /// of integers of `n` bits must fit in the field, that is `2 * n` must be less than its number of bits.
+    pub fn int_semantics(self, int_semantics: IntSemantics) -> Self {
+        Flattener {
+            int_semantics,
+            ..self
+        }
+    }
 
-                let or_left = FlatExpression::Sub(
-                    box FlatExpression::Number(T::from(1)),
-                    box size_unknown[i].clone().into(),
-                );
-                let or_right: FlatExpression<_> = FlatExpression::Sub(
-                    box FlatExpression::Number(T::from(1)),
-                    box a[i].clone().into(),
-                );
+    /// Names the outputs of `main` `name` instead of `~out`, as returned by `original_name`
+    ///
+    /// # Remarks
+    /// * A single output is named `name`, several outputs are named `name_0`, `name_1`, ...
+    pub fn output_name(self, name: String) -> Self {
+        Flattener {
+            output_name: Some(name),
+            ..self
+        }
+    }
 
-                let and_name = self.use_sym();
-                let and = FlatExpression::Mult(box or_left.clone(), box or_right.clone());
-                statements_flattened.push(FlatStatement::Definition(and_name, and));
-                let or = FlatExpression::Sub(
-                    box FlatExpression::Add(box or_left, box or_right),
-                    box and_name.into(),
-                );
+    /// Declares the field element `id` as a constant of value `value`, used in place of the identifier
+    ///
+    /// # Remarks
+    /// * `id` is then not given a variable, so flattening a program assigning to it fails with `ConstantAssigned`.
+    pub fn constant(mut self, id: Identifier<'ast>, value: T) -> Self {
+        self.constants.insert(id, value);
+        self
+    }
 
-                statements_flattened.push(FlatStatement::Condition(
-                    FlatExpression::Number(T::from(1)),
-                    or,
-                ));
+    /// Remembers the source name of the outputs returned in `statements`, if they are named
+    fn name_outputs(&mut self, statements: &[FlatStatement<T>]) {
+        let name = match self.output_name {
+            Some(ref name) => name.clone(),
+            None => return,
+        };
+
+        for s in statements {
+            if let FlatStatement::Return(list) = s {
+                let count = list.expressions.len();
+                for i in 0..count {
+                    let source = match count {
+                        1 => name.clone(),
+                        _ => format!("{}_{}", name, i),
+                    };
+                    self.display_names
+                        .insert(FlatVariable::public(i).to_string(), source);
+                }
             }
         }
     }
 
-    /// Flatten an if/else expression
+    /// Returns whether `statement` is a return statement
+    fn is_return(statement: &FlatStatement<T>) -> bool {
+        match statement {
+            FlatStatement::Return(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns whether `constraint_count` constraints are more than the budget allows
+    fn exceeds_budget(&self, constraint_count: usize) -> bool {
+        self.constraint_budget
+            .map(|budget| constraint_count > budget)
+            .unwrap_or(false)
+    }
+
+    /// Returns the number of constraints in `statements`
+    fn count_constraints(statements: &[FlatStatement<T>]) -> usize {
+        statements
+            .iter()
+            .filter(|s| match s {
+                FlatStatement::Condition(..) => true,
+                _ => false,
+            })
+            .count()
+    }
+
+    /// Returns a `Flattener` with fresh `layout` operating bitwise on `bits` bits.
+    #[cfg(test)]
+    fn with_bits(bits: usize) -> Flattener<'ast, T> {
+        Flattener {
+            bits,
+            ..Flattener::new()
+        }
+    }
+
+    /// Decomposes an expression into its `bits` lowest bits, big-endian
     ///
     /// # Arguments
     ///
-    /// * `symbols` - Available functions in this context
     /// * `statements_flattened` - Vector where new flattened statements can be added.
-    /// * `condition` - the condition as a `BooleanExpression`.
-    /// * `consequence` - the consequence of type U.
-    /// * `alternative` - the alternative of type U.
+    /// * `e` - `FlatExpression` to decompose.
+    /// * `bits` - Number of bits of the decomposition.
+    ///
     /// # Remarks
-    /// * U is the type of the expression
-    fn flatten_if_else_expression<U: Flatten<'ast, T>>(
+    /// * The bits are checked to be boolean and to sum up to `e`, so `e` is asserted to fit in `bits` bits
+    fn decompose_bits(
         &mut self,
-        symbols: &TypedFunctionSymbols<'ast, T>,
         statements_flattened: &mut Vec<FlatStatement<T>>,
-        condition: BooleanExpression<'ast, T>,
-        consequence: U,
-        alternative: U,
-    ) -> Vec<FlatExpression<T>> {
-        let condition = self.flatten_boolean_expression(symbols, statements_flattened, condition);
-
-        let consequence = consequence.flatten(self, symbols, statements_flattened);
-
-        let alternative = alternative.flatten(self, symbols, statements_flattened);
-
-        let size = consequence.len();
+        e: FlatExpression<T>,
+        bits: usize,
+    ) -> Vec<FlatVariable> {
+        let bitwidth = T::get_required_bits();
+        assert!(bits < bitwidth);
+
+        // the directive takes a variable as input
+        let e_id = self.use_sym();
+        statements_flattened.push(FlatStatement::Definition(e_id, e));
+
+        // define variables for the bits
+        let bits_be: Vec<FlatVariable> = (0..bitwidth).map(|_| self.use_sym()).collect();
+
+        // add a directive to get the bits
+        statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
+            bits_be.clone(),
+            Solver::bits(),
+            vec![e_id],
+        )));
+
+        // only keep the lowest `bits` bits, the higher ones are implicitly zero
+        let bits_be = bits_be[bitwidth - bits..].to_vec();
+
+        // bitness checks
+        for bit in &bits_be {
+            statements_flattened.push(FlatStatement::Condition(
+                FlatExpression::Identifier(*bit),
+                FlatExpression::Mult(
+                    box FlatExpression::Identifier(*bit),
+                    box FlatExpression::Identifier(*bit),
+                ),
+            ));
+        }
 
-        let condition_id = self.use_sym();
-        statements_flattened.push(FlatStatement::Definition(condition_id, condition));
+        // bit decomposition check
+        statements_flattened.push(FlatStatement::Condition(
+            FlatExpression::Identifier(e_id),
+            Self::recompose_bits(&bits_be),
+        ));
 
-        let consequence_ids: Vec<_> = (0..size).map(|_| self.use_sym()).collect();
-        statements_flattened.extend(
-            consequence
-                .into_iter()
-                .zip(consequence_ids.iter())
-                .map(|(c, c_id)| FlatStatement::Definition(*c_id, c)),
-        );
+        bits_be
+    }
 
-        let alternative_ids: Vec<_> = (0..size).map(|_| self.use_sym()).collect();
-        statements_flattened.extend(
-            alternative
-                .into_iter()
-                .zip(alternative_ids.iter())
-                .map(|(a, a_id)| FlatStatement::Definition(*a_id, a)),
-        );
+    /// Returns the linear expression summing big-endian bits weighted by their powers of two
+    fn recompose_bits(bits_be: &[FlatVariable]) -> FlatExpression<T> {
+        let len = bits_be.len();
 
-        let term0_ids: Vec<_> = (0..size).map(|_| self.use_sym()).collect();
-        statements_flattened.extend(consequence_ids.iter().zip(term0_ids.iter()).map(
-            |(c_id, t0_id)| {
-                FlatStatement::Definition(
-                    *t0_id,
-                    FlatExpression::Mult(
-                        box condition_id.clone().into(),
-                        box FlatExpression::from(*c_id),
+        bits_be.iter().enumerate().fold(
+            FlatExpression::Number(T::from(0)),
+            |acc, (i, bit)| {
+                FlatExpression::Add(
+                    box acc,
+                    box FlatExpression::Mult(
+                        box FlatExpression::Identifier(*bit),
+                        box FlatExpression::Number(T::from(2).pow(len - i - 1)),
                     ),
                 )
             },
-        ));
+        )
+    }
 
-        let term1_ids: Vec<_> = (0..size).map(|_| self.use_sym()).collect();
-        statements_flattened.extend(alternative_ids.iter().zip(term1_ids.iter()).map(
-            |(a_id, t1_id)| {
-                FlatStatement::Definition(
-                    *t1_id,
-                    FlatExpression::Mult(
-                        box FlatExpression::Sub(
-                            box FlatExpression::Number(T::one()),
-                            box condition_id.into(),
-                        ),
-                        box FlatExpression::from(*a_id),
-                    ),
-                )
-            },
-        ));
+    /// Returns the number of terms of a sum built by adding them one by one to `0`, as in `recompose_bits`
+    fn summands(e: &FlatExpression<T>) -> usize {
+        match e {
+            FlatExpression::Add(ref acc, _) => 1 + Self::summands(acc),
+            _ => 0,
+        }
+    }
 
-        let res: Vec<_> = (0..size).map(|_| self.use_sym()).collect();
-        statements_flattened.extend(term0_ids.iter().zip(term1_ids).zip(res.iter()).map(
-            |((t0_id, t1_id), r_id)| {
-                FlatStatement::Definition(
-                    *r_id,
-                    FlatExpression::Add(
-                        box FlatExpression::from(*t0_id),
-                        box FlatExpression::from(t1_id),
-                    ),
-                )
-            },
-        ));
-        res.into_iter().map(|r| r.into()).collect()
+    /// Packs big-endian bits into one linear expression per chunk of `chunk` bits
+    ///
+    /// # Remarks
+    /// * The last chunk holds the remaining bits if `chunk` does not divide the number of bits
+    pub fn pack_bits(bits_be: &[FlatVariable], chunk: usize) -> Vec<FlatExpression<T>> {
+        bits_be.chunks(chunk).map(Self::recompose_bits).collect()
     }
 
-    fn flatten_member_expression(
+    /// Removes the booleanity checks `b == b * b` on variables which were already checked earlier
+    ///
+    /// # Remarks
+    /// * Checking the same constraint twice is redundant, so the first occurrence is enough
+    fn dedup_booleanity(statements: Vec<FlatStatement<T>>) -> Vec<FlatStatement<T>> {
+        Self::dedup_booleanity_after(&mut HashSet::new(), statements)
+    }
+
+    /// Removes the booleanity checks on variables in `checked`, and adds the variables checked in `statements` to it
+    fn dedup_booleanity_after(
+        checked: &mut HashSet<FlatVariable>,
+        statements: Vec<FlatStatement<T>>,
+    ) -> Vec<FlatStatement<T>> {
+        statements
+            .into_iter()
+            .filter(|s| match s {
+                FlatStatement::Condition(
+                    FlatExpression::Identifier(b),
+                    FlatExpression::Mult(
+                        box FlatExpression::Identifier(l),
+                        box FlatExpression::Identifier(r),
+                    ),
+                ) if b == l && b == r => checked.insert(*b),
+                _ => true,
+            })
+            .collect()
+    }
+
+    /// Asserts that two flattened expressions are equal
+    fn flatten_equality(
         &mut self,
-        symbols: &TypedFunctionSymbols<'ast, T>,
         statements_flattened: &mut Vec<FlatStatement<T>>,
-        s: StructExpression<'ast, T>,
-        member_id: MemberId,
-    ) -> Vec<FlatExpression<T>> {
-        let members = s.ty().clone();
-        let expected_output_size = members
-            .iter()
-            .find(|member| *member.id == member_id)
-            .unwrap()
-            .ty
-            .get_primitive_count();
+        l: FlatExpression<T>,
+        r: FlatExpression<T>,
+    ) {
+        if l.is_linear() {
+            statements_flattened.push(FlatStatement::Condition(l, r));
+        } else if r.is_linear() {
+            // swap so that left side is linear
+            statements_flattened.push(FlatStatement::Condition(r, l));
+        } else {
+            // define the left side in a new variable so that it becomes linear
+            let id = self.use_sym();
+            statements_flattened.push(FlatStatement::Definition(id, l));
+            statements_flattened.push(FlatStatement::Condition(id.into(), r));
+        }
+    }
 
-        let res =
-            match s.into_inner() {
-                StructExpressionInner::Value(values) => {
-                    // If the struct has an explicit value, we get the value at the given member
-                    assert_eq!(values.len(), members.len());
-                    values
-                        .into_iter()
-                        .zip(members.into_iter())
-                        .filter(|(_, member)| *member.id == member_id)
-                        .flat_map(|(v, member)| match *member.ty {
-                            Type::FieldElement => FieldElementExpression::try_from(v)
-                                .unwrap()
-                                .flatten(self, symbols, statements_flattened),
-                            Type::Boolean => BooleanExpression::try_from(v).unwrap().flatten(
-                                self,
-                                symbols,
-                                statements_flattened,
-                            ),
-                            Type::Array(..) => ArrayExpression::try_from(v).unwrap().flatten(
-                                self,
-                                symbols,
-                                statements_flattened,
-                            ),
-                            Type::Struct(..) => StructExpression::try_from(v).unwrap().flatten(
-                                self,
-                                symbols,
-                                statements_flattened,
-                            ),
-                        })
-                        .collect()
-                }
-                StructExpressionInner::Identifier(id) => {
-                    // If the struct is an identifier, we allocated variables in the layout for that identifier. We need to access a subset of these values.
-                    // the struct is encoded as a sequence, so we need to identify the offset at which this member starts
-                    let offset = members
-                        .iter()
-                        .take_while(|member| *member.id != member_id)
-                        .map(|member| member.ty.get_primitive_count())
-                        .sum();
+    /// Asserts that two arrays of flattened expressions are equal element-wise
+    ///
+    /// # Arguments
+    ///
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `a` - Left array.
+    /// * `b` - Right array, of the same length as `a`.
+    pub fn assert_array_eq(
+        &mut self,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        a: &[FlatExpression<T>],
+        b: &[FlatExpression<T>],
+    ) -> Result<(), FlattenError> {
+        if a.len() != b.len() {
+            return Err(FlattenError::LengthMismatch {
+                left: a.len(),
+                right: b.len(),
+            });
+        }
 
-                    // we also need the size of this member
-                    let size = members
-                        .iter()
-                        .find(|member| *member.id == member_id)
-                        .unwrap()
-                        .ty
-                        .get_primitive_count();
-                    self.layout.get(&id).unwrap()[offset..(offset + size)]
-                        .into_iter()
-                        .map(|i| i.clone().into())
-                        .collect()
-                }
-                StructExpressionInner::Select(box array, box index) => {
-                    let offset = members
-                        .iter()
-                        .take_while(|member| *member.id != member_id)
-                        .map(|member| member.ty.get_primitive_count())
-                        .sum();
+        for (l, r) in a.iter().zip(b.iter()) {
+            self.flatten_equality(statements_flattened, l.clone(), r.clone());
+        }
 
-                    // we also need the size of this member
-                    let size = members
-                        .iter()
-                        .find(|member| *member.id == member_id)
-                        .unwrap()
-                        .ty
-                        .get_primitive_count();
+        Ok(())
+    }
 
-                    self.flatten_select_expression::<StructExpression<'ast, T>>(
-                        symbols,
-                        statements_flattened,
-                        array,
-                        index,
-                    )[offset..offset + size]
-                        .to_vec()
-                }
-                StructExpressionInner::FunctionCall(..) => unreachable!(),
-                StructExpressionInner::IfElse(box condition, box consequence, box alternative) => {
-                    // if the struct is `(if c then a else b)`, we want to access `(if c then a else b).member`
-                    // we reduce to `if c then a.member else b.member`
-                    let ty = *members
-                        .clone()
-                        .into_iter()
-                        .find(|member| *member.id == member_id)
-                        .unwrap()
-                        .ty;
+    /// Asserts that `b` is a permutation of `a`
+    ///
+    /// # Arguments
+    ///
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `a` - Left array.
+    /// * `b` - Right array, of the same length as `a`.
+    /// * `challenge` - Value at which the products are evaluated.
+    ///
+    /// # Remarks
+    /// * The products of `challenge - a_i` and of `challenge - b_i` are checked to be equal. Arrays which are not
+    /// permutations of each other pass only if `challenge` is a root of the difference of the products, so it should
+    /// not be known in advance by whoever chooses the values, for example derived from them by hashing.
+    pub fn assert_permutation(
+        &mut self,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        a: &[FlatExpression<T>],
+        b: &[FlatExpression<T>],
+        challenge: T,
+    ) -> Result<(), FlattenError> {
+        if a.len() != b.len() {
+            return Err(FlattenError::LengthMismatch {
+                left: a.len(),
+                right: b.len(),
+            });
+        }
 
-                    match ty {
-                        Type::FieldElement => self.flatten_if_else_expression(
-                            symbols,
-                            statements_flattened,
-                            condition.clone(),
-                            FieldElementExpression::member(consequence.clone(), member_id.clone()),
-                            FieldElementExpression::member(alternative.clone(), member_id),
-                        ),
-                        Type::Boolean => self.flatten_if_else_expression(
-                            symbols,
-                            statements_flattened,
-                            condition.clone(),
-                            BooleanExpression::member(consequence.clone(), member_id.clone()),
-                            BooleanExpression::member(alternative.clone(), member_id),
-                        ),
-                        Type::Struct(..) => self.flatten_if_else_expression(
-                            symbols,
-                            statements_flattened,
-                            condition.clone(),
-                            StructExpression::member(consequence.clone(), member_id.clone()),
-                            StructExpression::member(alternative.clone(), member_id),
-                        ),
-                        Type::Array(..) => self.flatten_if_else_expression(
-                            symbols,
-                            statements_flattened,
-                            condition.clone(),
-                            ArrayExpression::member(consequence.clone(), member_id.clone()),
-                            ArrayExpression::member(alternative.clone(), member_id),
-                        ),
-                    }
-                }
-                StructExpressionInner::Member(box s0, m_id) => {
-                    let e = self.flatten_member_expression(symbols, statements_flattened, s0, m_id);
+        let left = self.challenge_product(statements_flattened, a, &challenge);
+        let right = self.challenge_product(statements_flattened, b, &challenge);
 
-                    let offset = members
-                        .iter()
-                        .take_while(|member| *member.id != member_id)
-                        .map(|member| member.ty.get_primitive_count())
-                        .sum();
+        self.flatten_equality(statements_flattened, left, right);
 
-                    // we also need the size of this member
-                    let size = members
-                        .iter()
-                        .find(|member| *member.id == member_id)
-                        .unwrap()
-                        .ty
-                        .get_primitive_count();
+        Ok(())
+    }
 
-                    e[offset..(offset + size)].into()
-                }
-            };
+    /// Returns the product of `challenge - e` for `e` in `values`, defining one partial product per constraint
+    fn challenge_product(
+        &mut self,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        values: &[FlatExpression<T>],
+        challenge: &T,
+    ) -> FlatExpression<T> {
+        let mut product = FlatExpression::Number(T::one());
 
-        assert_eq!(res.len(), expected_output_size);
-        res
+        for e in values {
+            let linear = e.is_linear();
+            let e = self.define_if_non_linear(statements_flattened, e.clone(), linear);
+
+            let factor = FlatExpression::Sub(box FlatExpression::Number(challenge.clone()), box e);
+
+            let id = self.use_sym();
+            statements_flattened.push(FlatStatement::Definition(
+                id,
+                FlatExpression::Mult(box product, box factor),
+            ));
+            product = id.into();
+        }
+
+        product
     }
 
-    /// Flatten an array selection expression
+    /// Asserts that `lo <= value <= hi`
     ///
     /// # Arguments
     ///
-    /// * `symbols` - Available functions in this context
     /// * `statements_flattened` - Vector where new flattened statements can be added.
-    /// * `expression` - `TypedExpression` that will be flattened.
+    /// * `value` - Value to check.
+    /// * `lo` - Lower bound, included.
+    /// * `hi` - Upper bound, included, such that `hi - lo` fits in `T::get_required_bits() - 2` bits.
+    ///
     /// # Remarks
-    /// * U is the type of the expression
-    fn flatten_select_expression<U: Flatten<'ast, T>>(
+    /// * `value - lo` and `hi - value` are checked to fit in the bits of `hi - lo`. A value out of the interval makes
+    /// one of them negative, which is represented by a value too large to fit.
+    pub fn assert_in_range(
         &mut self,
-        symbols: &TypedFunctionSymbols<'ast, T>,
         statements_flattened: &mut Vec<FlatStatement<T>>,
-        array: ArrayExpression<'ast, T>,
-        index: FieldElementExpression<'ast, T>,
-    ) -> Vec<FlatExpression<T>> {
-        let size = array.size();
-        let ty = array.inner_type().clone();
+        value: FlatExpression<T>,
+        lo: T,
+        hi: T,
+    ) {
+        assert!(lo <= hi);
+        let width = (hi.clone() - lo.clone()).to_biguint().bits();
+        assert!(width <= T::get_required_bits() - 2);
 
-        let element_size = ty.get_primitive_count();
+        let linear = value.is_linear();
+        let value = self.define_if_non_linear(statements_flattened, value, linear);
 
-        match index {
-            FieldElementExpression::Number(n) => match array.into_inner() {
-                ArrayExpressionInner::Identifier(id) => {
-                    assert!(n < T::from(size));
-                    let n = n.to_dec_string().parse::<usize>().unwrap();
-                    self.layout.get(&id).unwrap()[n * element_size..(n + 1) * element_size]
-                        .into_iter()
-                        .map(|i| i.clone().into())
-                        .collect()
-                }
-                ArrayExpressionInner::Value(expressions) => {
-                    assert!(n < T::from(size));
-                    let n = n.to_dec_string().parse::<usize>().unwrap();
-                    U::try_from(expressions[n].clone()).unwrap().flatten(
-                        self,
-                        symbols,
-                        statements_flattened,
-                    )
-                }
-                ArrayExpressionInner::FunctionCall(..) => unreachable!(),
-                ArrayExpressionInner::IfElse(condition, consequence, alternative) => {
-                    // [if cond then [a, b] else [c, d]][1] == if cond then [a, b][1] else [c, d][1]
-                    U::if_else(
-                        *condition,
-                        U::select(*consequence, FieldElementExpression::Number(n.clone())),
-                        U::select(*alternative, FieldElementExpression::Number(n)),
-                    )
-                    .flatten(self, symbols, statements_flattened)
-                }
-                ArrayExpressionInner::Member(box s, id) => {
-                    assert!(n < T::from(size));
-                    let n = n.to_dec_string().parse::<usize>().unwrap();
-                    self.flatten_member_expression(symbols, statements_flattened, s, id)
-                        [n * ty.get_primitive_count()..(n + 1) * ty.get_primitive_count()]
-                        .to_vec()
+        // value - lo >= 0
+        self.decompose_bits(
+            statements_flattened,
+            FlatExpression::Sub(box value.clone(), box FlatExpression::Number(lo)),
+            width,
+        );
+        // hi - value >= 0
+        self.decompose_bits(
+            statements_flattened,
+            FlatExpression::Sub(box FlatExpression::Number(hi), box value),
+            width,
+        );
+    }
+
+    /// Removes the conditions between two equal constants, failing on the first one between different constants
+    ///
+    /// # Remarks
+    /// * A condition between different constants can never be satisfied, so no proof can be generated
+    fn check_constant_conditions(
+        statements: Vec<FlatStatement<T>>,
+    ) -> Result<Vec<FlatStatement<T>>, FlattenError> {
+        let mut checked = Vec::with_capacity(statements.len());
+
+        for s in statements {
+            match s {
+                FlatStatement::Condition(
+                    FlatExpression::Number(ref l),
+                    FlatExpression::Number(ref r),
+                ) => {
+                    if l != r {
+                        return Err(FlattenError::UnsatisfiableConstraint {
+                            constraint: s.to_string(),
+                        });
+                    }
                 }
-                ArrayExpressionInner::Select(box array, box index) => {
-                    assert!(n < T::from(size));
-                    let n = n.to_dec_string().parse::<usize>().unwrap();
+                s => checked.push(s),
+            }
+        }
 
-                    let e = match array.inner_type() {
-                        Type::FieldElement => self
-                            .flatten_select_expression::<FieldElementExpression<'ast, T>>(
-                                symbols,
-                                statements_flattened,
-                                array,
-                                index,
-                            ),
-                        Type::Boolean => self
-                            .flatten_select_expression::<BooleanExpression<'ast, T>>(
-                                symbols,
-                                statements_flattened,
-                                array,
-                                index,
-                            ),
-                        Type::Array(..) => self
-                            .flatten_select_expression::<ArrayExpression<'ast, T>>(
-                                symbols,
-                                statements_flattened,
-                                array,
-                                index,
-                            ),
-                        Type::Struct(..) => self
-                            .flatten_select_expression::<StructExpression<'ast, T>>(
-                                symbols,
-                                statements_flattened,
-                                array,
-                                index,
-                            ),
-                    };
+        Ok(checked)
+    }
 
-                    e[n * element_size..(n + 1) * element_size]
-                        .into_iter()
-                        .map(|i| i.clone().into())
-                        .collect()
-                }
-            },
-            e => {
-                // we have array[e] with e an arbitrary expression
-                // first we check that e is in 0..array.len(), so we check that sum(if e == i then 1 else 0) == 1
-                // here, depending on the size, we could use a proper range check based on bits
-                let range_check = (0..size)
-                    .map(|i| {
-                        FieldElementExpression::IfElse(
-                            box BooleanExpression::FieldEq(
-                                box e.clone(),
-                                box FieldElementExpression::Number(T::from(i)),
-                            ),
-                            box FieldElementExpression::Number(T::from(1)),
-                            box FieldElementExpression::Number(T::from(0)),
-                        )
-                    })
-                    .fold(FieldElementExpression::Number(T::from(0)), |acc, e| {
-                        FieldElementExpression::Add(box acc, box e)
-                    });
+    /// Flattens a check that an expression is zero, returning `1` if it is and `0` otherwise
+    ///
+    /// # Arguments
+    ///
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `x` - `FlatExpression` to check.
+    fn flatten_is_zero(
+        &mut self,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        x: FlatExpression<T>,
+    ) -> FlatExpression<T> {
+        // Wanted: (Y = (X != 0) ? 1 : 0)
+        // # Y = if X == 0 then 0 else 1 fi
+        // # M = if X == 0 then 1 else 1/X fi
+        // Y == X * M
+        // 0 == (1-Y) * X
+
+        let name_y = self.use_sym();
+        let name_m = self.use_sym();
+
+        statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
+            vec![name_y, name_m],
+            Solver::ConditionEq,
+            vec![x.clone()],
+        )));
+        statements_flattened.push(FlatStatement::Condition(
+            FlatExpression::Identifier(name_y),
+            FlatExpression::Mult(box x.clone(), box FlatExpression::Identifier(name_m)),
+        ));
 
-                let range_check_statement = TypedStatement::Condition(
-                    FieldElementExpression::Number(T::from(1)).into(),
-                    range_check.into(),
-                );
+        let res = FlatExpression::Sub(
+            box FlatExpression::Number(T::one()),
+            box FlatExpression::Identifier(name_y),
+        );
 
-                self.flatten_statement(symbols, statements_flattened, range_check_statement);
+        statements_flattened.push(FlatStatement::Condition(
+            FlatExpression::Number(T::zero()),
+            FlatExpression::Mult(box res.clone(), box x),
+        ));
 
-                // now we flatten to sum(if e == i then array[i] else false)
-                (0..size)
-                    .map(|i| {
-                        let term = match array.clone().into_inner() {
-                            // a[i] = a[i]
-                            ArrayExpressionInner::Identifier(id) => U::select(
-                                ArrayExpressionInner::Identifier(id).annotate(ty.clone(), size),
-                                FieldElementExpression::Number(T::from(i)),
-                            ),
-                            // [a_0, ..., a_n][i] = a_i
-                            ArrayExpressionInner::Value(expressions) => {
-                                assert_eq!(size, expressions.len());
-                                U::try_from(expressions[i].clone()).unwrap()
-                            }
-                            ArrayExpressionInner::FunctionCall(..) => unreachable!(),
-                            // (if c then a else b fi)[i] = if c then a[i] else b[i]
-                            ArrayExpressionInner::IfElse(condition, consequence, alternative) => {
-                                U::if_else(
-                                    *condition,
-                                    U::select(
-                                        *consequence,
-                                        FieldElementExpression::Number(T::from(i)),
-                                    ),
-                                    U::select(
-                                        *alternative,
-                                        FieldElementExpression::Number(T::from(i)),
-                                    ),
-                                )
-                            }
-                            ArrayExpressionInner::Member(box s, id) => U::member(s, id),
-                            ArrayExpressionInner::Select(box array, box index) => U::select(
-                                ArrayExpressionInner::Select(box array, box index)
-                                    .annotate(ty.clone(), size),
-                                FieldElementExpression::Number(T::from(i)),
-                            ),
-                        };
+        res
+    }
 
-                        (term, FieldElementExpression::Number(T::from(i)))
-                    })
-                    .fold(None, |acc, (term, index)| match acc {
-                        None => Some(term),
-                        Some(acc) => Some(U::if_else(
-                            BooleanExpression::FieldEq(box e.clone(), box index),
-                            term,
-                            acc,
-                        )),
-                    })
-                    .unwrap()
-                    .flatten(self, symbols, statements_flattened)
+    /// Replaces the conditions of `statements` by checks whose results are combined into `valid`, which is
+    /// returned after the other values
+    ///
+    /// # Arguments
+    ///
+    /// * `valid` - `1` if the previous conditions hold and `0` otherwise, updated with the conditions of `statements`.
+    /// * `statements` - Flattened statements, whose conditions do not fail anymore.
+    ///
+    /// # Remarks
+    /// * The conditions added to check equalities cannot fail for the values computed by the directives.
+    fn combine_conditions(
+        &mut self,
+        valid: &mut FlatExpression<T>,
+        statements: Vec<FlatStatement<T>>,
+    ) -> Vec<FlatStatement<T>> {
+        let mut combined = Vec::with_capacity(statements.len());
+
+        for s in statements {
+            match s {
+                FlatStatement::Condition(lhs, rhs) => {
+                    let linear = rhs.is_linear();
+                    let rhs = self.define_if_non_linear(&mut combined, rhs, linear);
+
+                    let is_equal =
+                        self.flatten_is_zero(&mut combined, FlatExpression::Sub(box lhs, box rhs));
+
+                    let id = self.use_sym();
+                    combined.push(FlatStatement::Definition(
+                        id,
+                        FlatExpression::Mult(box valid.clone(), box is_equal),
+                    ));
+                    *valid = id.into();
+                }
+                FlatStatement::Return(mut list) => {
+                    list.expressions.push(valid.clone());
+                    combined.push(FlatStatement::Return(list));
+                }
+                s => combined.push(s),
             }
         }
+
+        combined
     }
 
-    /// Flattens a boolean expression
+    /// Checks that linear selectors are boolean and, if `one_hot` is set, that exactly one of them is `1`
+    fn check_selectors(
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        selectors: &[FlatExpression<T>],
+        one_hot: bool,
+    ) {
+        // bitness checks
+        for selector in selectors {
+            assert!(selector.is_linear());
+            statements_flattened.push(FlatStatement::Condition(
+                selector.clone(),
+                FlatExpression::Mult(box selector.clone(), box selector.clone()),
+            ));
+        }
+
+        if one_hot {
+            // 1 == sum(selector_i)
+            let selector_sum = selectors
+                .iter()
+                .cloned()
+                .fold(FlatExpression::Number(T::zero()), |acc, s| {
+                    FlatExpression::Add(box acc, box s)
+                });
+            statements_flattened.push(FlatStatement::Condition(
+                FlatExpression::Number(T::one()),
+                selector_sum,
+            ));
+        }
+    }
+
+    /// Flattens the one-hot encoding of `index`, where the selector at position `index` is `1` and the others are `0`
     ///
     /// # Arguments
     ///
-    /// * `symbols` - Available functions in this context
     /// * `statements_flattened` - Vector where new flattened statements can be added.
-    /// * `expression` - `BooleanExpression` that will be flattened.
+    /// * `index` - `FlatExpression` of the position to select, checked to be smaller than `len`.
+    /// * `len` - Number of selectors.
+    pub fn one_hot(
+        &mut self,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        index: FlatExpression<T>,
+        len: usize,
+    ) -> Vec<FlatExpression<T>> {
+        let linear = index.is_linear();
+        let index = self.define_if_non_linear(statements_flattened, index, linear);
+
+        // selector_k = (index - k == 0)
+        let selectors: Vec<_> = (0..len)
+            .map(|k| {
+                let difference =
+                    FlatExpression::Sub(box index.clone(), box FlatExpression::Number(T::from(k)));
+                self.flatten_is_zero(statements_flattened, difference)
+            })
+            .collect();
+
+        Self::check_selectors(statements_flattened, &selectors, true);
+
+        selectors
+    }
+
+    /// Flattens `if c then (b, a) else (a, b)`, where `c` is checked to be boolean
     ///
-    /// # Postconditions
+    /// # Arguments
     ///
-    /// * `flatten_boolean_expressions` always returns a linear expression,
-    /// * in order to preserve composability.
-    fn flatten_boolean_expression(
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `c` - `FlatExpression` of the condition.
+    /// * `a` - First value.
+    /// * `b` - Second value.
+    ///
+    /// # Remarks
+    /// * `a' = a + c * (b - a)` and `b' = b + c * (a - b)` share the product `c * (b - a)`, so only
+    /// one multiplication is needed besides the booleanity check.
+    pub fn conditional_swap(
         &mut self,
-        symbols: &TypedFunctionSymbols<'ast, T>,
         statements_flattened: &mut Vec<FlatStatement<T>>,
-        expression: BooleanExpression<'ast, T>,
-    ) -> FlatExpression<T> {
-        // those will be booleans in the future
-        match expression {
-            BooleanExpression::Identifier(x) => {
-                FlatExpression::Identifier(self.layout.get(&x).unwrap().clone()[0])
-            }
-            BooleanExpression::Lt(box lhs, box rhs) => {
-                // Get the bitwidth to know the size of the binary decompsitions for this Field
-                let bitwidth = T::get_required_bits();
+        c: FlatExpression<T>,
+        a: FlatExpression<T>,
+        b: FlatExpression<T>,
+    ) -> (FlatExpression<T>, FlatExpression<T>) {
+        let linear = c.is_linear();
+        let c = self.define_if_non_linear(statements_flattened, c, linear);
+        let linear = a.is_linear();
+        let a = self.define_if_non_linear(statements_flattened, a, linear);
+        let linear = b.is_linear();
+        let b = self.define_if_non_linear(statements_flattened, b, linear);
+
+        // c == c * c
+        statements_flattened.push(FlatStatement::Condition(
+            c.clone(),
+            FlatExpression::Mult(box c.clone(), box c.clone()),
+        ));
 
-                // We know from semantic checking that lhs and rhs have the same type
-                // What the expression will flatten to depends on that type
+        // t = c * (b - a)
+        let t = self.use_sym();
+        statements_flattened.push(FlatStatement::Definition(
+            t,
+            FlatExpression::Mult(box c, box FlatExpression::Sub(box b.clone(), box a.clone())),
+        ));
 
-                let lhs_flattened =
-                    self.flatten_field_expression(symbols, statements_flattened, lhs);
-                let rhs_flattened =
-                    self.flatten_field_expression(symbols, statements_flattened, rhs);
+        (
+            FlatExpression::Add(box a, box t.into()),
+            FlatExpression::Sub(box b, box t.into()),
+        )
+    }
 
-                // lhs
-                let lhs_id = self.use_sym();
-                statements_flattened.push(FlatStatement::Definition(lhs_id, lhs_flattened));
+    /// Flattens `lhs < rhs` for operands of `width` bits
+    ///
+    /// # Remarks
+    /// * Both operands are checked to fit in `width` bits.
+    fn flatten_lt_width(
+        &mut self,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        lhs: FlatExpression<T>,
+        rhs: FlatExpression<T>,
+        width: usize,
+    ) -> FlatExpression<T> {
+        // range check both operands on `width` bits
+        self.decompose_bits(statements_flattened, lhs.clone(), width);
+        self.decompose_bits(statements_flattened, rhs.clone(), width);
+
+        // lhs - rhs + 2^width fits in `width + 1` bits, and its highest bit is set exactly if lhs >= rhs
+        let shifted = FlatExpression::Add(
+            box FlatExpression::Sub(box lhs, box rhs),
+            box FlatExpression::Number(T::from(2).pow(width)),
+        );
+        let shifted_bits = self.decompose_bits(statements_flattened, shifted, width + 1);
 
-                // check that lhs and rhs are within the right range, i.e., their higher two bits are zero. We use big-endian so they are at positions 0 and 1
+        FlatExpression::Sub(
+            box FlatExpression::Number(T::one()),
+            box shifted_bits[0].into(),
+        )
+    }
 
-                // lhs
-                {
-                    // define variables for the bits
-                    let lhs_bits_be: Vec<FlatVariable> =
-                        (0..bitwidth).map(|_| self.use_sym()).collect();
+    /// Flattens the Euclidean division of `a` by `b`, returning the quotient `q` and the remainder `r`
+    ///
+    /// # Arguments
+    ///
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `a` - Dividend.
+    /// * `b` - Divisor, checked to fit in `width` bits.
+    /// * `width` - Number of bits of the operands, less than half the bits of the field.
+    ///
+    /// # Remarks
+    /// * `a == q * b + r` and `r < b` are checked, with `q` fitting in `width` bits. `q * b + r` then cannot wrap
+    /// around the modulus, so the result is unique, and a zero divisor makes the program unsatisfiable.
+    /// * If `width` is not less than half the bits of the field, `BitWidthTooLarge` is returned and nothing is
+    /// flattened.
+    pub fn int_div_mod(
+        &mut self,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        a: FlatExpression<T>,
+        b: FlatExpression<T>,
+        width: usize,
+    ) -> Result<(FlatExpression<T>, FlatExpression<T>), FlattenError> {
+        // `q * b + r` has up to `2 * width + 1` bits
+        let field_max = (T::get_required_bits() - 2) / 2;
+        if width > field_max {
+            return Err(FlattenError::BitWidthTooLarge {
+                requested: width,
+                field_max,
+            });
+        }
 
-                    // add a directive to get the bits
-                    statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
-                        lhs_bits_be.clone(),
-                        Solver::bits(),
-                        vec![lhs_id],
-                    )));
+        let linear = a.is_linear();
+        let a = self.define_if_non_linear(statements_flattened, a, linear);
+        let linear = b.is_linear();
+        let b = self.define_if_non_linear(statements_flattened, b, linear);
+
+        let q = self.use_sym();
+        let r = self.use_sym();
+
+        // # q, r = a / b, a % b
+        statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
+            vec![q, r],
+            Solver::EuclideanDiv,
+            vec![a.clone(), b.clone()],
+        )));
+
+        // r < b, which also checks that r and b fit in `width` bits
+        let lt = self.flatten_lt_width(statements_flattened, r.into(), b.clone(), width);
+        statements_flattened.push(FlatStatement::Condition(
+            FlatExpression::Number(T::one()),
+            lt,
+        ));
 
-                    // bitness checks
-                    for i in 0..bitwidth - 2 {
-                        statements_flattened.push(FlatStatement::Condition(
-                            FlatExpression::Identifier(lhs_bits_be[i + 2]),
-                            FlatExpression::Mult(
-                                box FlatExpression::Identifier(lhs_bits_be[i + 2]),
-                                box FlatExpression::Identifier(lhs_bits_be[i + 2]),
-                            ),
-                        ));
-                    }
+        self.decompose_bits(statements_flattened, q.into(), width);
 
-                    // bit decomposition check
-                    let mut lhs_sum = FlatExpression::Number(T::from(0));
+        // a - r == q * b
+        statements_flattened.push(FlatStatement::Condition(
+            FlatExpression::Sub(box a, box r.into()),
+            FlatExpression::Mult(box q.into(), box b),
+        ));
 
-                    for i in 0..bitwidth - 2 {
-                        lhs_sum = FlatExpression::Add(
-                            box lhs_sum,
-                            box FlatExpression::Mult(
-                                box FlatExpression::Identifier(lhs_bits_be[i + 2]),
-                                box FlatExpression::Number(T::from(2).pow(bitwidth - 2 - i - 1)),
-                            ),
-                        );
-                    }
+        Ok((q.into(), r.into()))
+    }
 
-                    statements_flattened.push(FlatStatement::Condition(
-                        FlatExpression::Identifier(lhs_id),
-                        lhs_sum,
-                    ));
-                }
+    /// Flattens the sum of the `values` whose selector is `1`, the other selectors being `0`
+    ///
+    /// # Arguments
+    ///
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `selectors` - Linear `FlatExpression`s, checked to be boolean.
+    /// * `values` - `FlatExpression`s to select from, one for each selector.
+    /// * `one_hot` - Whether exactly one selector is checked to be `1`.
+    pub fn conditional_sum(
+        &mut self,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        selectors: &[FlatExpression<T>],
+        values: &[FlatExpression<T>],
+        one_hot: bool,
+    ) -> FlatExpression<T> {
+        assert_eq!(selectors.len(), values.len());
 
-                // rhs
-                let rhs_id = self.use_sym();
-                statements_flattened.push(FlatStatement::Definition(rhs_id, rhs_flattened));
+        Self::check_selectors(statements_flattened, selectors, one_hot);
 
-                // rhs
-                {
-                    // define variables for the bits
-                    let rhs_bits_be: Vec<FlatVariable> =
-                        (0..bitwidth).map(|_| self.use_sym()).collect();
+        // sum(selector_i * value_i)
+        selectors.iter().zip(values.iter()).fold(
+            FlatExpression::Number(T::zero()),
+            |acc, (selector, value)| {
+                let value = if value.is_linear() {
+                    value.clone()
+                } else {
+                    let id = self.use_sym();
+                    statements_flattened.push(FlatStatement::Definition(id, value.clone()));
+                    FlatExpression::Identifier(id)
+                };
+                let term = self.use_sym();
+                statements_flattened.push(FlatStatement::Definition(
+                    term,
+                    FlatExpression::Mult(box selector.clone(), box value),
+                ));
+                FlatExpression::Add(box acc, box FlatExpression::Identifier(term))
+            },
+        )
+    }
 
-                    // add a directive to get the bits
-                    statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
-                        rhs_bits_be.clone(),
-                        Solver::bits(),
-                        vec![rhs_id],
-                    )));
+    /// Flattens a bitwise operation
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - Available functions in this context
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `left` - Left operand.
+    /// * `right` - Right operand.
+    /// * `op` - Function returning a result bit as a linear combination of the operand bits `a`, `b` and their product `a * b`
+    fn flatten_bitwise(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        left: FieldElementExpression<'ast, T>,
+        right: FieldElementExpression<'ast, T>,
+        op: fn(FlatExpression<T>, FlatExpression<T>, FlatExpression<T>) -> FlatExpression<T>,
+    ) -> FlatExpression<T> {
+        let bits = self.bits;
 
-                    // bitness checks
-                    for i in 0..bitwidth - 2 {
-                        statements_flattened.push(FlatStatement::Condition(
-                            FlatExpression::Identifier(rhs_bits_be[i + 2]),
-                            FlatExpression::Mult(
-                                box FlatExpression::Identifier(rhs_bits_be[i + 2]),
-                                box FlatExpression::Identifier(rhs_bits_be[i + 2]),
-                            ),
-                        ));
-                    }
+        let left_flattened = self.flatten_field_expression(symbols, statements_flattened, left);
+        let right_flattened = self.flatten_field_expression(symbols, statements_flattened, right);
 
-                    // bit decomposition check
-                    let mut rhs_sum = FlatExpression::Number(T::from(0));
+        let left_bits = self.decompose_bits(statements_flattened, left_flattened, bits);
+        let right_bits = self.decompose_bits(statements_flattened, right_flattened, bits);
 
-                    for i in 0..bitwidth - 2 {
-                        rhs_sum = FlatExpression::Add(
-                            box rhs_sum,
-                            box FlatExpression::Mult(
-                                box FlatExpression::Identifier(rhs_bits_be[i + 2]),
-                                box FlatExpression::Number(T::from(2).pow(bitwidth - 2 - i - 1)),
-                            ),
-                        );
-                    }
+        let res_bits: Vec<FlatVariable> = left_bits
+            .into_iter()
+            .zip(right_bits.into_iter())
+            .map(|(l, r)| {
+                // product := l * r
+                let product = self.use_sym();
+                statements_flattened.push(FlatStatement::Definition(
+                    product,
+                    FlatExpression::Mult(box l.into(), box r.into()),
+                ));
 
-                    statements_flattened.push(FlatStatement::Condition(
-                        FlatExpression::Identifier(rhs_id),
-                        rhs_sum,
-                    ));
-                }
+                // res := op(l, r, product)
+                let res = self.use_sym();
+                statements_flattened.push(FlatStatement::Definition(
+                    res,
+                    op(l.into(), r.into(), product.into()),
+                ));
+                res
+            })
+            .collect();
 
-                // sym := (lhs * 2) - (rhs * 2)
-                let subtraction_result = FlatExpression::Sub(
-                    box FlatExpression::Mult(
-                        box FlatExpression::Number(T::from(2)),
-                        box FlatExpression::Identifier(lhs_id),
-                    ),
-                    box FlatExpression::Mult(
-                        box FlatExpression::Number(T::from(2)),
-                        box FlatExpression::Identifier(rhs_id),
-                    ),
-                );
+        Self::recompose_bits(&res_bits)
+    }
 
-                // define variables for the bits
-                let sub_bits_be: Vec<FlatVariable> =
-                    (0..bitwidth).map(|_| self.use_sym()).collect();
+    // Let's assume b = [1, 1, 1, 0]
+    //
+    // 1. Init `sizeUnknown = true`
+    //    As long as `sizeUnknown` is `true` we don't yet know if a is <= than b.
+    // 2. Loop over `b`:
+    //     * b[0] = 1
+    //       when `b` is 1 we check wether `a` is 0 in that particular run and update
+    //       `sizeUnknown` accordingly:
+    //       `sizeUnknown = sizeUnknown && a[0]`
+    //     * b[1] = 1
+    //       `sizrUnknown = sizeUnknown && a[1]`
+    //     * b[2] = 1
+    //       `sizeUnknown = sizeUnknown && a[2]`
+    //     * b[3] = 0
+    //       we need to enforce that `a` is 0 in case `sizeUnknown`is still `true`,
+    //       otherwise `a` can be {0,1}:
+    //      `true == (!sizeUnknown || !a[3])`
+    //      ```
+    //                     **true => a -> 0
+    //         sizeUnkown *
+    //                     **false => a -> {0,1}
+    //      ```
+    fn strict_le_check(
+        &mut self,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        b: &[bool],
+        a: Vec<FlatVariable>,
+    ) {
+        let len = b.len();
+        assert_eq!(a.len(), T::get_required_bits());
+        assert_eq!(a.len(), b.len());
 
-                // add a directive to get the bits
-                statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
-                    sub_bits_be.clone(),
-                    Solver::bits(),
-                    vec![subtraction_result.clone()],
-                )));
+        let mut is_not_smaller_run = vec![];
+        let mut size_unknown = vec![];
 
-                // bitness checks
-                for i in 0..bitwidth {
-                    statements_flattened.push(FlatStatement::Condition(
-                        FlatExpression::Identifier(sub_bits_be[i]),
+        for _ in 0..len {
+            is_not_smaller_run.push(self.use_sym());
+            size_unknown.push(self.use_sym());
+        }
+
+        // init size_unknown = true
+        statements_flattened.push(FlatStatement::Definition(
+            size_unknown[0],
+            FlatExpression::Number(T::from(1)),
+        ));
+
+        for (i, b) in b.iter().enumerate() {
+            if *b {
+                statements_flattened.push(FlatStatement::Definition(
+                    is_not_smaller_run[i],
+                    a[i].clone().into(),
+                ));
+
+                // don't need to update size_unknown in the last round
+                if i < len - 1 {
+                    statements_flattened.push(FlatStatement::Definition(
+                        size_unknown[i + 1],
                         FlatExpression::Mult(
-                            box FlatExpression::Identifier(sub_bits_be[i]),
-                            box FlatExpression::Identifier(sub_bits_be[i]),
+                            box size_unknown[i].into(),
+                            box is_not_smaller_run[i].into(),
                         ),
                     ));
                 }
+            } else {
+                // don't need to update size_unknown in the last round
+                if i < len - 1 {
+                    statements_flattened.push(
+                        // sizeUnknown is not changing in this case
+                        // We sill have to assign the old value to the variable of the current run
+                        // This trivial definition will later be removed by the optimiser
+                        FlatStatement::Definition(
+                            size_unknown[i + 1].into(),
+                            size_unknown[i].into(),
+                        ),
+                    );
+                }
 
-                // check that the decomposition is in the field with a strict `< p` checks
-                self.strict_le_check(
-                    statements_flattened,
-                    &T::max_value_bit_vector_be(),
-                    sub_bits_be.clone(),
+                let or_left = FlatExpression::Sub(
+                    box FlatExpression::Number(T::from(1)),
+                    box size_unknown[i].clone().into(),
+                );
+                let or_right: FlatExpression<_> = FlatExpression::Sub(
+                    box FlatExpression::Number(T::from(1)),
+                    box a[i].clone().into(),
                 );
 
-                // sum(sym_b{i} * 2**i)
-                let mut expr = FlatExpression::Number(T::from(0));
+                let and_name = self.use_sym();
+                let and = FlatExpression::Mult(box or_left.clone(), box or_right.clone());
+                statements_flattened.push(FlatStatement::Definition(and_name, and));
+                let or = FlatExpression::Sub(
+                    box FlatExpression::Add(box or_left, box or_right),
+                    box and_name.into(),
+                );
 
-                for i in 0..bitwidth {
-                    expr = FlatExpression::Add(
-                        box expr,
-                        box FlatExpression::Mult(
-                            box FlatExpression::Identifier(sub_bits_be[i]),
-                            box FlatExpression::Number(T::from(2).pow(bitwidth - i - 1)),
-                        ),
-                    );
+                statements_flattened.push(FlatStatement::Condition(
+                    FlatExpression::Number(T::from(1)),
+                    or,
+                ));
+            }
+        }
+    }
+
+    /// Tries to evaluate a condition at compile time
+    ///
+    /// # Remarks
+    /// * Only equalities between constants or between structurally identical operands are evaluated.
+    /// * Ordered comparisons are only evaluated between constants which pass the range checks of the comparison
+    /// gadget, as the flattened program is unsatisfiable otherwise.
+    fn evaluate_condition(&self, condition: &BooleanExpression<'ast, T>) -> Option<bool> {
+        // compares two constant field elements if they fit in `width` bits
+        fn compare<'ast, T: Field>(
+            lhs: &FieldElementExpression<'ast, T>,
+            rhs: &FieldElementExpression<'ast, T>,
+            width: usize,
+        ) -> Option<Ordering> {
+            let max = T::from(2).pow(width);
+            match (lhs, rhs) {
+                (FieldElementExpression::Number(lhs), FieldElementExpression::Number(rhs))
+                    if *lhs < max && *rhs < max =>
+                {
+                    lhs.partial_cmp(rhs)
                 }
+                _ => None,
+            }
+        }
 
-                statements_flattened.push(FlatStatement::Condition(subtraction_result, expr));
+        // the operands of `Lt` are checked to fit in `bitwidth - 2` bits, and in `bits` bits if checked
+        let bitwidth = T::get_required_bits();
+        let width = match self.checked_comparisons && self.bits < bitwidth - 2 {
+            true => self.bits,
+            false => bitwidth - 2,
+        };
 
-                FlatExpression::Identifier(sub_bits_be[bitwidth - 1])
+        match *condition {
+            BooleanExpression::Value(b) => Some(b),
+            BooleanExpression::Not(ref e) => self.evaluate_condition(e).map(|b| !b),
+            BooleanExpression::Lt(ref lhs, ref rhs) => {
+                compare(lhs, rhs, width).map(|o| o == Ordering::Less)
             }
-            BooleanExpression::BoolEq(box lhs, box rhs) => {
-                // lhs and rhs are booleans, they flatten to 0 or 1
-                let x = self.flatten_boolean_expression(symbols, statements_flattened, lhs);
-                let y = self.flatten_boolean_expression(symbols, statements_flattened, rhs);
-                // Wanted: Not(X - Y)**2 which is an XNOR
-                // We know that X and Y are [0, 1]
-                // (X - Y) can become a negative values, which is why squaring the result is needed
-                // Negating this returns correct result
+            BooleanExpression::Le(ref lhs, ref rhs) => {
+                compare(lhs, rhs, width).map(|o| o != Ordering::Greater)
+            }
+            BooleanExpression::Gt(ref lhs, ref rhs) => {
+                compare(lhs, rhs, width).map(|o| o == Ordering::Greater)
+            }
+            BooleanExpression::Ge(ref lhs, ref rhs) => {
+                compare(lhs, rhs, width).map(|o| o != Ordering::Less)
+            }
+            BooleanExpression::FieldEq(ref lhs, ref rhs) => match (&**lhs, &**rhs) {
+                (lhs, rhs) if lhs == rhs => Some(true),
+                (FieldElementExpression::Number(lhs), FieldElementExpression::Number(rhs)) => {
+                    Some(lhs == rhs)
+                }
+                _ => None,
+            },
+            BooleanExpression::IsZero(ref e) => match **e {
+                FieldElementExpression::Number(ref n) => Some(*n == T::zero()),
+                _ => None,
+            },
+            BooleanExpression::BoolEq(ref lhs, ref rhs) if lhs == rhs => Some(true),
+            BooleanExpression::BoolEq(ref lhs, ref rhs) => {
+                match (self.evaluate_condition(lhs), self.evaluate_condition(rhs)) {
+                    (Some(lhs), Some(rhs)) => Some(lhs == rhs),
+                    _ => None,
+                }
+            }
+            BooleanExpression::And(ref lhs, ref rhs) => {
+                match (self.evaluate_condition(lhs), self.evaluate_condition(rhs)) {
+                    (Some(lhs), Some(rhs)) => Some(lhs && rhs),
+                    _ => None,
+                }
+            }
+            BooleanExpression::Or(ref lhs, ref rhs) => {
+                match (self.evaluate_condition(lhs), self.evaluate_condition(rhs)) {
+                    (Some(lhs), Some(rhs)) => Some(lhs || rhs),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
 
-                // Non-binary Truth table for logic of operation
-                // +---+---+-------+---------------+
-                // | X | Y | X - Y | Not(X - Y)**2 |
-                // +---+---+-------+---------------+
-                // | 1 | 1 |     0 |             1 |
-                // | 1 | 0 |     1 |             0 |
-                // | 0 | 1 |    -1 |             0 |
-                // | 0 | 0 |     0 |             1 |
-                // +---+---+-------+---------------+
+    /// Flatten an if/else expression
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - Available functions in this context
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `condition` - the condition as a `BooleanExpression`.
+    /// * `consequence` - the consequence of type U.
+    /// * `alternative` - the alternative of type U.
+    /// # Remarks
+    /// * U is the type of the expression
+    /// * Nested `IfElse` expressions often share branches, which are only flattened once.
+    fn flatten_if_else_expression<U: Flatten<'ast, T>>(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        condition: BooleanExpression<'ast, T>,
+        consequence: U,
+        alternative: U,
+    ) -> Vec<FlatExpression<T>> {
+        // if the condition is known at compile time, only the branch taken is flattened
+        match self.evaluate_condition(&condition) {
+            Some(true) => return consequence.flatten(self, symbols, statements_flattened),
+            Some(false) => return alternative.flatten(self, symbols, statements_flattened),
+            None => {}
+        }
 
-                let x_sub_y = FlatExpression::Sub(box x, box y);
-                let name_x_mult_x = self.use_sym();
+        let key: TypedExpression<'ast, T> =
+            U::if_else(condition.clone(), consequence.clone(), alternative.clone()).into();
+        if let Some(flattened) = self.if_else_cache.get(&key) {
+            return flattened.clone();
+        }
 
-                statements_flattened.push(FlatStatement::Definition(
-                    name_x_mult_x,
-                    FlatExpression::Mult(box x_sub_y.clone(), box x_sub_y),
-                ));
+        let condition = self.flatten_boolean_expression(symbols, statements_flattened, condition);
 
-                FlatExpression::Sub(
-                    box FlatExpression::Number(T::one()),
-                    box FlatExpression::Identifier(name_x_mult_x),
-                )
-            }
-            BooleanExpression::FieldEq(box lhs, box rhs) => {
-                // We know from semantic checking that lhs and rhs have the same type
-                // What the expression will flatten to depends on that type
+        let consequence = consequence.flatten(self, symbols, statements_flattened);
 
-                // Wanted: (Y = (X != 0) ? 1 : 0)
-                // X = a - b
-                // # Y = if X == 0 then 0 else 1 fi
-                // # M = if X == 0 then 1 else 1/X fi
-                // Y == X * M
-                // 0 == (1-Y) * X
+        let alternative = alternative.flatten(self, symbols, statements_flattened);
 
-                let name_y = self.use_sym();
-                let name_m = self.use_sym();
+        let condition_id = self.use_sym();
+        statements_flattened.push(FlatStatement::Definition(condition_id, condition));
 
-                let x = self.flatten_field_expression(
-                    symbols,
-                    statements_flattened,
-                    FieldElementExpression::Sub(box lhs, box rhs),
-                );
-
-                statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
-                    vec![name_y, name_m],
-                    Solver::ConditionEq,
-                    vec![x.clone()],
-                )));
-                statements_flattened.push(FlatStatement::Condition(
-                    FlatExpression::Identifier(name_y),
-                    FlatExpression::Mult(box x.clone(), box FlatExpression::Identifier(name_m)),
-                ));
-
-                let res = FlatExpression::Sub(
-                    box FlatExpression::Number(T::one()),
-                    box FlatExpression::Identifier(name_y),
-                );
-
-                statements_flattened.push(FlatStatement::Condition(
-                    FlatExpression::Number(T::zero()),
-                    FlatExpression::Mult(box res.clone(), box x),
-                ));
-
-                res
-            }
-            BooleanExpression::Le(box lhs, box rhs) => {
-                let lt = self.flatten_boolean_expression(
-                    symbols,
-                    statements_flattened,
-                    BooleanExpression::Lt(box lhs.clone(), box rhs.clone()),
-                );
-                let eq = self.flatten_boolean_expression(
-                    symbols,
-                    statements_flattened,
-                    BooleanExpression::FieldEq(box lhs.clone(), box rhs.clone()),
-                );
-                FlatExpression::Add(box eq, box lt)
-            }
-            BooleanExpression::Gt(lhs, rhs) => self.flatten_boolean_expression(
-                symbols,
-                statements_flattened,
-                BooleanExpression::Lt(rhs, lhs),
-            ),
-            BooleanExpression::Ge(lhs, rhs) => self.flatten_boolean_expression(
-                symbols,
-                statements_flattened,
-                BooleanExpression::Le(rhs, lhs),
-            ),
-            BooleanExpression::Or(box lhs, box rhs) => {
-                let x = box self.flatten_boolean_expression(symbols, statements_flattened, lhs);
-                let y = box self.flatten_boolean_expression(symbols, statements_flattened, rhs);
-                assert!(x.is_linear() && y.is_linear());
-                let name_x_and_y = self.use_sym();
-                statements_flattened.push(FlatStatement::Definition(
-                    name_x_and_y,
-                    FlatExpression::Mult(x.clone(), y.clone()),
-                ));
-                FlatExpression::Sub(
-                    box FlatExpression::Add(x, y),
-                    box FlatExpression::Identifier(name_x_and_y),
-                )
-            }
-            BooleanExpression::And(box lhs, box rhs) => {
-                let x = self.flatten_boolean_expression(symbols, statements_flattened, lhs);
-                let y = self.flatten_boolean_expression(symbols, statements_flattened, rhs);
-
-                let name_x_and_y = self.use_sym();
-                assert!(x.is_linear() && y.is_linear());
-                statements_flattened.push(FlatStatement::Definition(
-                    name_x_and_y,
-                    FlatExpression::Mult(box x, box y),
-                ));
-
-                FlatExpression::Identifier(name_x_and_y)
-            }
-            BooleanExpression::Not(box exp) => {
-                let x = self.flatten_boolean_expression(symbols, statements_flattened, exp);
-                FlatExpression::Sub(box FlatExpression::Number(T::one()), box x)
-            }
-            BooleanExpression::Value(b) => FlatExpression::Number(match b {
-                true => T::from(1),
-                false => T::from(0),
-            }),
-            BooleanExpression::FunctionCall(..) => unreachable!(),
-            BooleanExpression::IfElse(box condition, box consequence, box alternative) => self
-                .flatten_if_else_expression(
-                    symbols,
-                    statements_flattened,
-                    condition,
-                    consequence,
-                    alternative,
-                )[0]
-            .clone(),
-            BooleanExpression::Member(box s, id) => {
-                self.flatten_member_expression(symbols, statements_flattened, s, id)[0].clone()
-            }
-            BooleanExpression::Select(box array, box index) => self
-                .flatten_select_expression::<BooleanExpression<'ast, T>>(
-                    symbols,
-                    statements_flattened,
-                    array,
-                    index,
-                )[0]
-            .clone(),
-        }
-    }
-
-    /// Flattens a function call
-    ///
-    /// # Arguments
-    ///
-    /// * `symbols` - Available functions in this context
-    /// * `statements_flattened` - Vector where new flattened statements can be added.
-    /// * `id` - `Identifier of the function.
-    /// * `return_types` - Types of the return values of the function
-    /// * `param_expressions` - Arguments of this call
-    fn flatten_function_call(
-        &mut self,
-        symbols: &TypedFunctionSymbols<'ast, T>,
-        statements_flattened: &mut Vec<FlatStatement<T>>,
-        id: FunctionIdentifier<'ast>,
-        return_types: Vec<Type>,
-        param_expressions: Vec<TypedExpression<'ast, T>>,
-    ) -> FlatExpressionList<T> {
-        let passed_signature = Signature::new()
-            .inputs(param_expressions.iter().map(|e| e.get_type()).collect())
-            .outputs(return_types);
-
-        let key = FunctionKey::with_id(id).signature(passed_signature);
-
-        let funct = self.get_function(&key, &symbols);
-
-        let mut replacement_map = HashMap::new();
-
-        // Handle complex parameters and assign values:
-        // Rename Parameters, assign them to values in call. Resolve complex expressions with definitions
-        let params_flattened = param_expressions
-            .into_iter()
-            .map(|param_expr| self.flatten_expression(symbols, statements_flattened, param_expr))
+        // constant branches are kept as numbers, so that their terms are linear in the condition
+        let consequence: Vec<_> = consequence
             .into_iter()
-            .flat_map(|x| x)
-            .collect::<Vec<_>>();
+            .map(|c| self.define_if_not_number(statements_flattened, c))
+            .collect();
 
-        for (concrete_argument, formal_argument) in
-            params_flattened.into_iter().zip(funct.arguments)
-        {
-            let new_var = self.use_sym();
-            statements_flattened.push(FlatStatement::Definition(new_var, concrete_argument));
-            replacement_map.insert(formal_argument.id, new_var);
-        }
+        let alternative: Vec<_> = alternative
+            .into_iter()
+            .map(|a| self.define_if_not_number(statements_flattened, a))
+            .collect();
 
-        // Ensure renaming and correct returns:
-        // add all flattened statements, adapt return statements
+        let term0: Vec<_> = consequence
+            .into_iter()
+            .map(|c| self.if_else_term(statements_flattened, condition_id, false, c))
+            .collect();
 
-        let (mut return_statements, statements): (Vec<_>, Vec<_>) =
-            funct.statements.into_iter().partition(|s| match s {
-                FlatStatement::Return(..) => true,
-                _ => false,
-            });
+        let term1: Vec<_> = alternative
+            .into_iter()
+            .map(|a| self.if_else_term(statements_flattened, condition_id, true, a))
+            .collect();
 
-        let statements: Vec<_> = statements
+        let flattened: Vec<_> = term0
             .into_iter()
-            .map(|stat| match stat {
-                // set return statements as expression result
-                FlatStatement::Return(..) => unreachable!(),
-                FlatStatement::Definition(var, rhs) => {
-                    let new_var = self.use_sym();
-                    replacement_map.insert(var, new_var);
-                    let new_rhs = rhs.apply_substitution(&replacement_map);
-                    FlatStatement::Definition(new_var, new_rhs)
-                }
-                FlatStatement::Condition(lhs, rhs) => {
-                    let new_lhs = lhs.apply_substitution(&replacement_map);
-                    let new_rhs = rhs.apply_substitution(&replacement_map);
-                    FlatStatement::Condition(new_lhs, new_rhs)
-                }
-                FlatStatement::Directive(d) => {
-                    let new_outputs = d
-                        .outputs
-                        .into_iter()
-                        .map(|o| {
-                            let new_o = self.use_sym();
-                            replacement_map.insert(o, new_o);
-                            new_o
-                        })
-                        .collect();
-                    let new_inputs = d
-                        .inputs
-                        .into_iter()
-                        .map(|i| i.apply_substitution(&replacement_map))
-                        .collect();
-                    FlatStatement::Directive(FlatDirective {
-                        outputs: new_outputs,
-                        solver: d.solver,
-                        inputs: new_inputs,
-                    })
+            .zip(term1)
+            .map(|terms| match terms {
+                (Some(FlatExpression::Identifier(t0)), Some(FlatExpression::Identifier(t1))) => {
+                    let r = self.use_sym();
+                    statements_flattened.push(FlatStatement::Definition(
+                        r,
+                        FlatExpression::Add(box t0.into(), box t1.into()),
+                    ));
+                    r.into()
                 }
+                (Some(t0), Some(t1)) => FlatExpression::Add(box t0, box t1),
+                (Some(t), None) | (None, Some(t)) => t,
+                (None, None) => FlatExpression::Number(T::zero()),
             })
             .collect();
 
-        statements_flattened.extend(statements);
+        self.if_else_cache.insert(key, flattened.clone());
 
-        match return_statements.pop().unwrap() {
-            FlatStatement::Return(list) => FlatExpressionList {
-                expressions: list
-                    .expressions
-                    .into_iter()
-                    .map(|x| x.apply_substitution(&replacement_map))
-                    .collect(),
-            },
-            _ => unreachable!(),
+        flattened
+    }
+
+    /// Returns `e` if it is a number, otherwise a new variable defined as `e`
+    fn define_if_not_number(
+        &mut self,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        e: FlatExpression<T>,
+    ) -> FlatExpression<T> {
+        match e {
+            FlatExpression::Number(n) => FlatExpression::Number(n),
+            e => {
+                let id = self.use_sym();
+                statements_flattened.push(FlatStatement::Definition(id, e));
+                FlatExpression::Identifier(id)
+            }
         }
     }
 
-    /// Flattens an expression
+    /// Flattens the term of an `IfElse` branch, that is `condition * value` or `(1 - condition) * value`
     ///
     /// # Arguments
     ///
-    /// * `symbols` - Available functions in this context
     /// * `statements_flattened` - Vector where new flattened statements can be added.
-    /// * `expr` - `TypedExpression` that will be flattened.
-    fn flatten_expression(
+    /// * `condition` - Variable holding the boolean condition.
+    /// * `negated` - Whether the branch is taken when the condition is `0`.
+    /// * `value` - Value of the branch, a number or a variable.
+    ///
+    /// # Remarks
+    /// * Constant values yield linear terms, and `None` for zero, so that no constraint is added.
+    fn if_else_term(
         &mut self,
-        symbols: &TypedFunctionSymbols<'ast, T>,
         statements_flattened: &mut Vec<FlatStatement<T>>,
-        expr: TypedExpression<'ast, T>,
-    ) -> Vec<FlatExpression<T>> {
-        match expr {
-            TypedExpression::FieldElement(e) => {
-                vec![self.flatten_field_expression(symbols, statements_flattened, e)]
-            }
-            TypedExpression::Boolean(e) => {
-                vec![self.flatten_boolean_expression(symbols, statements_flattened, e)]
+        condition: FlatVariable,
+        negated: bool,
+        value: FlatExpression<T>,
+    ) -> Option<FlatExpression<T>> {
+        match value {
+            FlatExpression::Number(ref n) if *n == T::zero() => None,
+            FlatExpression::Number(n) => {
+                let scaled = if n == T::one() {
+                    FlatExpression::Identifier(condition)
+                } else {
+                    FlatExpression::Mult(
+                        box FlatExpression::Number(n.clone()),
+                        box condition.into(),
+                    )
+                };
+                Some(match negated {
+                    true => FlatExpression::Sub(box FlatExpression::Number(n), box scaled),
+                    false => scaled,
+                })
             }
-            TypedExpression::Array(e) => match e.inner_type().clone() {
-                Type::FieldElement => self
-                    .flatten_array_expression::<FieldElementExpression<'ast, T>>(
-                        symbols,
-                        statements_flattened,
-                        e,
+            value => {
+                let selector = match negated {
+                    true => FlatExpression::Sub(
+                        box FlatExpression::Number(T::one()),
+                        box condition.into(),
                     ),
-                Type::Boolean => self.flatten_array_expression::<BooleanExpression<'ast, T>>(
-                    symbols,
-                    statements_flattened,
-                    e,
-                ),
-                Type::Array(..) => self.flatten_array_expression::<ArrayExpression<'ast, T>>(
-                    symbols,
-                    statements_flattened,
-                    e,
-                ),
-                Type::Struct(..) => self.flatten_array_expression::<StructExpression<'ast, T>>(
-                    symbols,
-                    statements_flattened,
-                    e,
-                ),
-            },
-            TypedExpression::Struct(e) => {
-                self.flatten_struct_expression(symbols, statements_flattened, e)
+                    false => condition.into(),
+                };
+                let term = self.use_sym();
+                statements_flattened.push(FlatStatement::Definition(
+                    term,
+                    FlatExpression::Mult(box selector, box value),
+                ));
+                Some(FlatExpression::Identifier(term))
             }
         }
     }
 
-    /// Flattens a field expression
+    fn flatten_member_expression(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        s: StructExpression<'ast, T>,
+        member_id: MemberId,
+    ) -> Vec<FlatExpression<T>> {
+        let members = s.ty().clone();
+        let expected_output_size = members
+            .iter()
+            .find(|member| *member.id == member_id)
+            .unwrap()
+            .ty
+            .get_primitive_count();
+
+        let res =
+            match s.into_inner() {
+                StructExpressionInner::Value(values) => {
+                    // If the struct has an explicit value, we get the value at the given member
+                    assert_eq!(values.len(), members.len());
+                    values
+                        .into_iter()
+                        .zip(members.into_iter())
+                        .filter(|(_, member)| *member.id == member_id)
+                        .flat_map(|(v, member)| match *member.ty {
+                            Type::FieldElement => FieldElementExpression::try_from(v)
+                                .unwrap()
+                                .flatten(self, symbols, statements_flattened),
+                            Type::Boolean => BooleanExpression::try_from(v).unwrap().flatten(
+                                self,
+                                symbols,
+                                statements_flattened,
+                            ),
+                            Type::Array(..) => ArrayExpression::try_from(v).unwrap().flatten(
+                                self,
+                                symbols,
+                                statements_flattened,
+                            ),
+                            Type::Struct(..) => StructExpression::try_from(v).unwrap().flatten(
+                                self,
+                                symbols,
+                                statements_flattened,
+                            ),
+                        })
+                        .collect()
+                }
+                StructExpressionInner::Identifier(id) => {
+                    // If the struct is an identifier, we allocated variables in the layout for that identifier. We need to access a subset of these values.
+                    // the struct is encoded as a sequence, so we need to identify the offset at which this member starts
+                    let offset = members
+                        .iter()
+                        .take_while(|member| *member.id != member_id)
+                        .map(|member| member.ty.get_primitive_count())
+                        .sum();
+
+                    // we also need the size of this member
+                    let size = members
+                        .iter()
+                        .find(|member| *member.id == member_id)
+                        .unwrap()
+                        .ty
+                        .get_primitive_count();
+                    self.layout.get(&id).unwrap()[offset..(offset + size)]
+                        .into_iter()
+                        .map(|i| i.clone().into())
+                        .collect()
+                }
+                StructExpressionInner::Select(box array, box index) => {
+                    let offset = members
+                        .iter()
+                        .take_while(|member| *member.id != member_id)
+                        .map(|member| member.ty.get_primitive_count())
+                        .sum();
+
+                    // we also need the size of this member
+                    let size = members
+                        .iter()
+                        .find(|member| *member.id == member_id)
+                        .unwrap()
+                        .ty
+                        .get_primitive_count();
+
+                    self.flatten_select_expression::<StructExpression<'ast, T>>(
+                        symbols,
+                        statements_flattened,
+                        array,
+                        index,
+                    )[offset..offset + size]
+                        .to_vec()
+                }
+                StructExpressionInner::FunctionCall(..) => unreachable!(),
+                StructExpressionInner::IfElse(box condition, box consequence, box alternative) => {
+                    // if the struct is `(if c then a else b)`, we want to access `(if c then a else b).member`
+                    // we reduce to `if c then a.member else b.member`
+                    let ty = *members
+                        .clone()
+                        .into_iter()
+                        .find(|member| *member.id == member_id)
+                        .unwrap()
+                        .ty;
+
+                    match ty {
+                        Type::FieldElement => self.flatten_if_else_expression(
+                            symbols,
+                            statements_flattened,
+                            condition.clone(),
+                            FieldElementExpression::member(consequence.clone(), member_id.clone()),
+                            FieldElementExpression::member(alternative.clone(), member_id),
+                        ),
+                        Type::Boolean => self.flatten_if_else_expression(
+                            symbols,
+                            statements_flattened,
+                            condition.clone(),
+                            BooleanExpression::member(consequence.clone(), member_id.clone()),
+                            BooleanExpression::member(alternative.clone(), member_id),
+                        ),
+                        Type::Struct(..) => self.flatten_if_else_expression(
+                            symbols,
+                            statements_flattened,
+                            condition.clone(),
+                            StructExpression::member(consequence.clone(), member_id.clone()),
+                            StructExpression::member(alternative.clone(), member_id),
+                        ),
+                        Type::Array(..) => self.flatten_if_else_expression(
+                            symbols,
+                            statements_flattened,
+                            condition.clone(),
+                            ArrayExpression::member(consequence.clone(), member_id.clone()),
+                            ArrayExpression::member(alternative.clone(), member_id),
+                        ),
+                    }
+                }
+                StructExpressionInner::Member(box s0, m_id) => {
+                    let e = self.flatten_member_expression(symbols, statements_flattened, s0, m_id);
+
+                    let offset = members
+                        .iter()
+                        .take_while(|member| *member.id != member_id)
+                        .map(|member| member.ty.get_primitive_count())
+                        .sum();
+
+                    // we also need the size of this member
+                    let size = members
+                        .iter()
+                        .find(|member| *member.id == member_id)
+                        .unwrap()
+                        .ty
+                        .get_primitive_count();
+
+                    e[offset..(offset + size)].into()
+                }
+            };
+
+        assert_eq!(res.len(), expected_output_size);
+        res
+    }
+
+    /// Flatten an array selection expression
     ///
     /// # Arguments
     ///
     /// * `symbols` - Available functions in this context
     /// * `statements_flattened` - Vector where new flattened statements can be added.
-    /// * `expr` - `FieldElementExpression` that will be flattened.
-    fn flatten_field_expression(
+    /// * `expression` - `TypedExpression` that will be flattened.
+    /// # Remarks
+    /// * U is the type of the expression
+    fn flatten_select_expression<U: Flatten<'ast, T>>(
         &mut self,
         symbols: &TypedFunctionSymbols<'ast, T>,
         statements_flattened: &mut Vec<FlatStatement<T>>,
-        expr: FieldElementExpression<'ast, T>,
-    ) -> FlatExpression<T> {
-        match expr {
-            FieldElementExpression::Number(x) => FlatExpression::Number(x), // force to be a field element
-            FieldElementExpression::Identifier(x) => {
-                FlatExpression::Identifier(self.layout.get(&x).unwrap().clone()[0])
-            }
-            FieldElementExpression::Add(box left, box right) => {
-                let left_flattened =
-                    self.flatten_field_expression(symbols, statements_flattened, left);
-                let right_flattened =
-                    self.flatten_field_expression(symbols, statements_flattened, right);
-                let new_left = if left_flattened.is_linear() {
-                    left_flattened
-                } else {
-                    let id = self.use_sym();
-                    statements_flattened.push(FlatStatement::Definition(id, left_flattened));
-                    FlatExpression::Identifier(id)
-                };
-                let new_right = if right_flattened.is_linear() {
-                    right_flattened
-                } else {
-                    let id = self.use_sym();
-                    statements_flattened.push(FlatStatement::Definition(id, right_flattened));
-                    FlatExpression::Identifier(id)
-                };
-                FlatExpression::Add(box new_left, box new_right)
-            }
-            FieldElementExpression::Sub(box left, box right) => {
-                let left_flattened =
-                    self.flatten_field_expression(symbols, statements_flattened, left);
-                let right_flattened =
-                    self.flatten_field_expression(symbols, statements_flattened, right);
+        array: ArrayExpression<'ast, T>,
+        index: FieldElementExpression<'ast, T>,
+    ) -> Vec<FlatExpression<T>> {
+        let size = array.size();
+        let ty = array.inner_type().clone();
+
+        let element_size = ty.get_primitive_count();
+
+        match index {
+            FieldElementExpression::Number(n) => match array.into_inner() {
+                ArrayExpressionInner::Identifier(id) => {
+                    assert!(n < T::from(size));
+                    let n = n.to_dec_string().parse::<usize>().unwrap();
+                    self.layout.get(&id).unwrap()[n * element_size..(n + 1) * element_size]
+                        .into_iter()
+                        .map(|i| i.clone().into())
+                        .collect()
+                }
+                ArrayExpressionInner::Value(expressions) => {
+                    assert!(n < T::from(size));
+                    let n = n.to_dec_string().parse::<usize>().unwrap();
+                    U::try_from(expressions[n].clone()).unwrap().flatten(
+                        self,
+                        symbols,
+                        statements_flattened,
+                    )
+                }
+                ArrayExpressionInner::FunctionCall(..) => unreachable!(),
+                ArrayExpressionInner::IfElse(condition, consequence, alternative) => {
+                    // [if cond then [a, b] else [c, d]][1] == if cond then [a, b][1] else [c, d][1]
+                    U::if_else(
+                        *condition,
+                        U::select(*consequence, FieldElementExpression::Number(n.clone())),
+                        U::select(*alternative, FieldElementExpression::Number(n)),
+                    )
+                    .flatten(self, symbols, statements_flattened)
+                }
+                ArrayExpressionInner::Member(box s, id) => {
+                    assert!(n < T::from(size));
+                    let n = n.to_dec_string().parse::<usize>().unwrap();
+                    self.flatten_member_expression(symbols, statements_flattened, s, id)
+                        [n * ty.get_primitive_count()..(n + 1) * ty.get_primitive_count()]
+                        .to_vec()
+                }
+                ArrayExpressionInner::Select(box array, box index) => {
+                    assert!(n < T::from(size));
+                    let n = n.to_dec_string().parse::<usize>().unwrap();
+
+                    let e = match array.inner_type() {
+                        Type::FieldElement => self
+                            .flatten_select_expression::<FieldElementExpression<'ast, T>>(
+                                symbols,
+                                statements_flattened,
+                                array,
+                                index,
+                            ),
+                        Type::Boolean => self
+                            .flatten_select_expression::<BooleanExpression<'ast, T>>(
+                                symbols,
+                                statements_flattened,
+                                array,
+                                index,
+                            ),
+                        Type::Array(..) => self
+                            .flatten_select_expression::<ArrayExpression<'ast, T>>(
+                                symbols,
+                                statements_flattened,
+                                array,
+                                index,
+                            ),
+                        Type::Struct(..) => self
+                            .flatten_select_expression::<StructExpression<'ast, T>>(
+                                symbols,
+                                statements_flattened,
+                                array,
+                                index,
+                            ),
+                    };
+
+                    e[n * element_size..(n + 1) * element_size]
+                        .into_iter()
+                        .map(|i| i.clone().into())
+                        .collect()
+                }
+            },
+            e => {
+                // we have array[e] with e an arbitrary expression
+                // first we check that e is in 0..array.len(), so we check that sum(if e == i then 1 else 0) == 1
+                // here, depending on the size, we could use a proper range check based on bits
+                let range_check = (0..size)
+                    .map(|i| {
+                        FieldElementExpression::IfElse(
+                            box BooleanExpression::FieldEq(
+                                box e.clone(),
+                                box FieldElementExpression::Number(T::from(i)),
+                            ),
+                            box FieldElementExpression::Number(T::from(1)),
+                            box FieldElementExpression::Number(T::from(0)),
+                        )
+                    })
+                    .fold(FieldElementExpression::Number(T::from(0)), |acc, e| {
+                        FieldElementExpression::Add(box acc, box e)
+                    });
+
+                let range_check_statement = TypedStatement::Condition(
+                    FieldElementExpression::Number(T::from(1)).into(),
+                    range_check.into(),
+                );
+
+                self.flatten_statement(symbols, statements_flattened, range_check_statement);
+
+                // now we flatten to sum(if e == i then array[i] else false)
+                (0..size)
+                    .map(|i| {
+                        let term = match array.clone().into_inner() {
+                            // a[i] = a[i]
+                            ArrayExpressionInner::Identifier(id) => U::select(
+                                ArrayExpressionInner::Identifier(id).annotate(ty.clone(), size),
+                                FieldElementExpression::Number(T::from(i)),
+                            ),
+                            // [a_0, ..., a_n][i] = a_i
+                            ArrayExpressionInner::Value(expressions) => {
+                                assert_eq!(size, expressions.len());
+                                U::try_from(expressions[i].clone()).unwrap()
+                            }
+                            ArrayExpressionInner::FunctionCall(..) => unreachable!(),
+                            // (if c then a else b fi)[i] = if c then a[i] else b[i]
+                            ArrayExpressionInner::IfElse(condition, consequence, alternative) => {
+                                U::if_else(
+                                    *condition,
+                                    U::select(
+                                        *consequence,
+                                        FieldElementExpression::Number(T::from(i)),
+                                    ),
+                                    U::select(
+                                        *alternative,
+                                        FieldElementExpression::Number(T::from(i)),
+                                    ),
+                                )
+                            }
+                            ArrayExpressionInner::Member(box s, id) => U::member(s, id),
+                            ArrayExpressionInner::Select(box array, box index) => U::select(
+                                ArrayExpressionInner::Select(box array, box index)
+                                    .annotate(ty.clone(), size),
+                                FieldElementExpression::Number(T::from(i)),
+                            ),
+                        };
+
+                        (term, FieldElementExpression::Number(T::from(i)))
+                    })
+                    .fold(None, |acc, (term, index)| match acc {
+                        None => Some(term),
+                        Some(acc) => Some(U::if_else(
+                            BooleanExpression::FieldEq(box e.clone(), box index),
+                            term,
+                            acc,
+                        )),
+                    })
+                    .unwrap()
+                    .flatten(self, symbols, statements_flattened)
+            }
+        }
+    }
+
+    /// Flattens a boolean expression
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - Available functions in this context
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `expression` - `BooleanExpression` that will be flattened.
+    ///
+    /// # Postconditions
+    ///
+    /// * `flatten_boolean_expressions` always returns a linear expression,
+    /// * in order to preserve composability.
+    fn flatten_boolean_expression(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        expression: BooleanExpression<'ast, T>,
+    ) -> FlatExpression<T> {
+        // those will be booleans in the future
+        match expression {
+            BooleanExpression::Identifier(x) => {
+                FlatExpression::Identifier(self.layout.get(&x).unwrap().clone()[0])
+            }
+            BooleanExpression::Lt(box lhs, box rhs) => {
+                // Get the bitwidth to know the size of the binary decompsitions for this Field
+                let bitwidth = T::get_required_bits();
+
+                // We know from semantic checking that lhs and rhs have the same type
+                // What the expression will flatten to depends on that type
+
+                let lhs_flattened =
+                    self.flatten_field_expression(symbols, statements_flattened, lhs);
+                let rhs_flattened =
+                    self.flatten_field_expression(symbols, statements_flattened, rhs);
+
+                // operands are range checked on the full width below, a narrower `bits` needs its own check
+                if self.checked_comparisons && self.bits < bitwidth - 2 {
+                    let bits = self.bits;
+                    self.decompose_bits(statements_flattened, lhs_flattened.clone(), bits);
+                    self.decompose_bits(statements_flattened, rhs_flattened.clone(), bits);
+                }
+
+                // lhs, only defined if it is not linear already
+                let lhs = if lhs_flattened.is_linear() {
+                    lhs_flattened
+                } else {
+                    let lhs_id = self.use_sym();
+                    statements_flattened.push(FlatStatement::Definition(lhs_id, lhs_flattened));
+                    FlatExpression::Identifier(lhs_id)
+                };
+
+                // check that lhs and rhs are within the right range, i.e., their higher two bits are zero. We use big-endian so they are at positions 0 and 1
+
+                // lhs
+                {
+                    // define variables for the bits
+                    let lhs_bits_be: Vec<FlatVariable> =
+                        (0..bitwidth).map(|_| self.use_sym()).collect();
+
+                    // add a directive to get the bits
+                    statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
+                        lhs_bits_be.clone(),
+                        Solver::bits(),
+                        vec![lhs.clone()],
+                    )));
+
+                    // bitness checks
+                    let checks = statements_flattened.len();
+                    for i in 0..bitwidth - 2 {
+                        statements_flattened.push(FlatStatement::Condition(
+                            FlatExpression::Identifier(lhs_bits_be[i + 2]),
+                            FlatExpression::Mult(
+                                box FlatExpression::Identifier(lhs_bits_be[i + 2]),
+                                box FlatExpression::Identifier(lhs_bits_be[i + 2]),
+                            ),
+                        ));
+                    }
+
+                    // bit decomposition check
+                    let mut lhs_sum = FlatExpression::Number(T::from(0));
+
+                    for i in 0..bitwidth - 2 {
+                        lhs_sum = FlatExpression::Add(
+                            box lhs_sum,
+                            box FlatExpression::Mult(
+                                box FlatExpression::Identifier(lhs_bits_be[i + 2]),
+                                box FlatExpression::Number(T::from(2).pow(bitwidth - 2 - i - 1)),
+                            ),
+                        );
+                    }
+
+                    // every bit checked above is recomposed, and no other
+                    debug_assert_eq!(
+                        statements_flattened.len() - checks,
+                        Self::summands(&lhs_sum)
+                    );
+
+                    statements_flattened.push(FlatStatement::Condition(lhs.clone(), lhs_sum));
+                }
+
+                // rhs, only defined if it is not linear already
+                let rhs = if rhs_flattened.is_linear() {
+                    rhs_flattened
+                } else {
+                    let rhs_id = self.use_sym();
+                    statements_flattened.push(FlatStatement::Definition(rhs_id, rhs_flattened));
+                    FlatExpression::Identifier(rhs_id)
+                };
+
+                // rhs
+                {
+                    // define variables for the bits
+                    let rhs_bits_be: Vec<FlatVariable> =
+                        (0..bitwidth).map(|_| self.use_sym()).collect();
+
+                    // add a directive to get the bits
+                    statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
+                        rhs_bits_be.clone(),
+                        Solver::bits(),
+                        vec![rhs.clone()],
+                    )));
+
+                    // bitness checks
+                    let checks = statements_flattened.len();
+                    for i in 0..bitwidth - 2 {
+                        statements_flattened.push(FlatStatement::Condition(
+                            FlatExpression::Identifier(rhs_bits_be[i + 2]),
+                            FlatExpression::Mult(
+                                box FlatExpression::Identifier(rhs_bits_be[i + 2]),
+                                box FlatExpression::Identifier(rhs_bits_be[i + 2]),
+                            ),
+                        ));
+                    }
+
+                    // bit decomposition check
+                    let mut rhs_sum = FlatExpression::Number(T::from(0));
+
+                    for i in 0..bitwidth - 2 {
+                        rhs_sum = FlatExpression::Add(
+                            box rhs_sum,
+                            box FlatExpression::Mult(
+                                box FlatExpression::Identifier(rhs_bits_be[i + 2]),
+                                box FlatExpression::Number(T::from(2).pow(bitwidth - 2 - i - 1)),
+                            ),
+                        );
+                    }
+
+                    // every bit checked above is recomposed, and no other
+                    debug_assert_eq!(
+                        statements_flattened.len() - checks,
+                        Self::summands(&rhs_sum)
+                    );
+
+                    statements_flattened.push(FlatStatement::Condition(rhs.clone(), rhs_sum));
+                }
+
+                // sym := (lhs + lhs) - (rhs + rhs), which is linear for any linear lhs and rhs
+                let subtraction_result = FlatExpression::Sub(
+                    box FlatExpression::Add(box lhs.clone(), box lhs),
+                    box FlatExpression::Add(box rhs.clone(), box rhs),
+                );
+
+                // define variables for the bits
+                let sub_bits_be: Vec<FlatVariable> =
+                    (0..bitwidth).map(|_| self.use_sym()).collect();
+
+                // add a directive to get the bits
+                statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
+                    sub_bits_be.clone(),
+                    Solver::bits(),
+                    vec![subtraction_result.clone()],
+                )));
+
+                // bitness checks
+                let checks = statements_flattened.len();
+                for i in 0..bitwidth {
+                    statements_flattened.push(FlatStatement::Condition(
+                        FlatExpression::Identifier(sub_bits_be[i]),
+                        FlatExpression::Mult(
+                            box FlatExpression::Identifier(sub_bits_be[i]),
+                            box FlatExpression::Identifier(sub_bits_be[i]),
+                        ),
+                    ));
+                }
+
+                let checked = statements_flattened.len() - checks;
+
+                // check that the decomposition is in the field with a strict `< p` checks
+                self.strict_le_check(
+                    statements_flattened,
+                    &T::max_value_bit_vector_be(),
+                    sub_bits_be.clone(),
+                );
+
+                // sum(sym_b{i} * 2**i)
+                let mut expr = FlatExpression::Number(T::from(0));
+
+                for i in 0..bitwidth {
+                    expr = FlatExpression::Add(
+                        box expr,
+                        box FlatExpression::Mult(
+                            box FlatExpression::Identifier(sub_bits_be[i]),
+                            box FlatExpression::Number(T::from(2).pow(bitwidth - i - 1)),
+                        ),
+                    );
+                }
+
+                // every bit checked above is recomposed, and no other
+                debug_assert_eq!(checked, Self::summands(&expr));
+
+                statements_flattened.push(FlatStatement::Condition(subtraction_result, expr));
+
+                FlatExpression::Identifier(sub_bits_be[bitwidth - 1])
+            }
+            BooleanExpression::BoolEq(box lhs, box rhs) => {
+                // lhs and rhs are booleans, they flatten to 0 or 1
+                let x = self.flatten_boolean_expression(symbols, statements_flattened, lhs);
+                let y = self.flatten_boolean_expression(symbols, statements_flattened, rhs);
+                // Wanted: Not(X - Y)**2 which is an XNOR
+                // We know that X and Y are [0, 1]
+                // (X - Y) can become a negative values, which is why squaring the result is needed
+                // Negating this returns correct result
+
+                // Non-binary Truth table for logic of operation
+                // +---+---+-------+---------------+
+                // | X | Y | X - Y | Not(X - Y)**2 |
+                // +---+---+-------+---------------+
+                // | 1 | 1 |     0 |             1 |
+                // | 1 | 0 |     1 |             0 |
+                // | 0 | 1 |    -1 |             0 |
+                // | 0 | 0 |     0 |             1 |
+                // +---+---+-------+---------------+
+
+                let x_sub_y = FlatExpression::Sub(box x, box y);
+                let name_x_mult_x = self.use_sym();
+
+                statements_flattened.push(FlatStatement::Definition(
+                    name_x_mult_x,
+                    FlatExpression::Mult(box x_sub_y.clone(), box x_sub_y),
+                ));
+
+                FlatExpression::Sub(
+                    box FlatExpression::Number(T::one()),
+                    box FlatExpression::Identifier(name_x_mult_x),
+                )
+            }
+            BooleanExpression::FieldEq(box lhs, box rhs) => {
+                // We know from semantic checking that lhs and rhs have the same type
+                // What the expression will flatten to depends on that type
+
+                let zero = FieldElementExpression::Number(T::zero());
+
+                // comparing to zero does not require a subtraction
+                if rhs == zero {
+                    return self.flatten_boolean_expression(
+                        symbols,
+                        statements_flattened,
+                        BooleanExpression::IsZero(box lhs),
+                    );
+                }
+                if lhs == zero {
+                    return self.flatten_boolean_expression(
+                        symbols,
+                        statements_flattened,
+                        BooleanExpression::IsZero(box rhs),
+                    );
+                }
+
+                // X = a - b
+                let x = self.flatten_field_difference(symbols, statements_flattened, lhs, rhs);
+
+                self.flatten_is_zero(statements_flattened, x)
+            }
+            BooleanExpression::Chain(box first, rest) => self.flatten_boolean_expression(
+                symbols,
+                statements_flattened,
+                BooleanExpression::chain_to_and(first, rest),
+            ),
+            BooleanExpression::IsZero(box e) => {
+                let (x, linear) =
+                    self.flatten_field_expression_linearity(symbols, statements_flattened, e);
+                let x = self.define_if_non_linear(statements_flattened, x, linear);
+
+                self.flatten_is_zero(statements_flattened, x)
+            }
+            BooleanExpression::LtWidth(box lhs, box rhs, width) => {
+                let lhs = self.flatten_field_expression(symbols, statements_flattened, lhs);
+                let rhs = self.flatten_field_expression(symbols, statements_flattened, rhs);
+
+                self.flatten_lt_width(statements_flattened, lhs, rhs, width)
+            }
+            BooleanExpression::LeWidth(box lhs, box rhs, width) => {
+                // lhs <= rhs if and only if not rhs < lhs
+                let gt = self.flatten_boolean_expression(
+                    symbols,
+                    statements_flattened,
+                    BooleanExpression::LtWidth(box rhs, box lhs, width),
+                );
+                FlatExpression::Sub(box FlatExpression::Number(T::one()), box gt)
+            }
+            BooleanExpression::Le(box lhs, box rhs) => {
+                let lt = self.flatten_boolean_expression(
+                    symbols,
+                    statements_flattened,
+                    BooleanExpression::Lt(box lhs.clone(), box rhs.clone()),
+                );
+                let eq = self.flatten_boolean_expression(
+                    symbols,
+                    statements_flattened,
+                    BooleanExpression::FieldEq(box lhs.clone(), box rhs.clone()),
+                );
+                FlatExpression::Add(box eq, box lt)
+            }
+            BooleanExpression::Gt(lhs, rhs) => self.flatten_boolean_expression(
+                symbols,
+                statements_flattened,
+                BooleanExpression::Lt(rhs, lhs),
+            ),
+            BooleanExpression::Ge(lhs, rhs) => self.flatten_boolean_expression(
+                symbols,
+                statements_flattened,
+                BooleanExpression::Le(rhs, lhs),
+            ),
+            BooleanExpression::Or(box lhs, box rhs) => {
+                let x = box self.flatten_boolean_expression(symbols, statements_flattened, lhs);
+                let y = box self.flatten_boolean_expression(symbols, statements_flattened, rhs);
+                assert!(x.is_linear() && y.is_linear());
+                let name_x_and_y = self.use_sym();
+                statements_flattened.push(FlatStatement::Definition(
+                    name_x_and_y,
+                    FlatExpression::Mult(x.clone(), y.clone()),
+                ));
+                FlatExpression::Sub(
+                    box FlatExpression::Add(x, y),
+                    box FlatExpression::Identifier(name_x_and_y),
+                )
+            }
+            BooleanExpression::And(box lhs, box rhs) => {
+                let x = self.flatten_boolean_expression(symbols, statements_flattened, lhs);
+                let y = self.flatten_boolean_expression(symbols, statements_flattened, rhs);
+
+                let name_x_and_y = self.use_sym();
+                assert!(x.is_linear() && y.is_linear());
+                statements_flattened.push(FlatStatement::Definition(
+                    name_x_and_y,
+                    FlatExpression::Mult(box x, box y),
+                ));
+
+                FlatExpression::Identifier(name_x_and_y)
+            }
+            BooleanExpression::Not(box exp) => {
+                let x = self.flatten_boolean_expression(symbols, statements_flattened, exp);
+                FlatExpression::Sub(box FlatExpression::Number(T::one()), box x)
+            }
+            BooleanExpression::Value(b) => FlatExpression::Number(match b {
+                true => T::from(1),
+                false => T::from(0),
+            }),
+            BooleanExpression::FunctionCall(..) => unreachable!(),
+            BooleanExpression::IfElse(box condition, box consequence, box alternative) => self
+                .flatten_if_else_expression(
+                    symbols,
+                    statements_flattened,
+                    condition,
+                    consequence,
+                    alternative,
+                )[0]
+            .clone(),
+            BooleanExpression::Member(box s, id) => {
+                self.flatten_member_expression(symbols, statements_flattened, s, id)[0].clone()
+            }
+            BooleanExpression::Select(box array, box index) => self
+                .flatten_select_expression::<BooleanExpression<'ast, T>>(
+                    symbols,
+                    statements_flattened,
+                    array,
+                    index,
+                )[0]
+            .clone(),
+        }
+    }
+
+    /// Flattens a function call
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - Available functions in this context
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `id` - `Identifier of the function.
+    /// * `return_types` - Types of the return values of the function
+    /// * `param_expressions` - Arguments of this call
+    fn flatten_function_call(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        id: FunctionIdentifier<'ast>,
+        return_types: Vec<Type>,
+        param_expressions: Vec<TypedExpression<'ast, T>>,
+    ) -> FlatExpressionList<T> {
+        let passed_signature = Signature::new()
+            .inputs(param_expressions.iter().map(|e| e.get_type()).collect())
+            .outputs(return_types);
+
+        let key = FunctionKey::with_id(id).signature(passed_signature);
+
+        let funct = self.get_function(&key, &symbols);
+
+        let mut replacement_map = HashMap::new();
+
+        // Handle complex parameters and assign values:
+        // Rename Parameters, assign them to values in call. Resolve complex expressions with definitions
+        let params_flattened = param_expressions
+            .into_iter()
+            .map(|param_expr| self.flatten_expression(symbols, statements_flattened, param_expr))
+            .into_iter()
+            .flat_map(|x| x)
+            .collect::<Vec<_>>();
+
+        for (concrete_argument, formal_argument) in
+            params_flattened.into_iter().zip(funct.arguments)
+        {
+            let new_var = self.use_sym();
+            statements_flattened.push(FlatStatement::Definition(new_var, concrete_argument));
+            replacement_map.insert(formal_argument.id, new_var);
+        }
+
+        // Ensure renaming and correct returns:
+        // add all flattened statements, adapt return statements
+
+        let (mut return_statements, statements): (Vec<_>, Vec<_>) =
+            funct.statements.into_iter().partition(|s| match s {
+                FlatStatement::Return(..) => true,
+                _ => false,
+            });
+
+        let statements: Vec<_> = statements
+            .into_iter()
+            .map(|stat| match stat {
+                // set return statements as expression result
+                FlatStatement::Return(..) => unreachable!(),
+                FlatStatement::Definition(var, rhs) => {
+                    let new_var = self.use_sym();
+                    replacement_map.insert(var, new_var);
+                    let new_rhs = rhs.apply_substitution(&replacement_map);
+                    FlatStatement::Definition(new_var, new_rhs)
+                }
+                FlatStatement::Condition(lhs, rhs) => {
+                    let new_lhs = lhs.apply_substitution(&replacement_map);
+                    let new_rhs = rhs.apply_substitution(&replacement_map);
+                    FlatStatement::Condition(new_lhs, new_rhs)
+                }
+                FlatStatement::Directive(d) => {
+                    let new_outputs = d
+                        .outputs
+                        .into_iter()
+                        .map(|o| {
+                            let new_o = self.use_sym();
+                            replacement_map.insert(o, new_o);
+                            new_o
+                        })
+                        .collect();
+                    let new_inputs = d
+                        .inputs
+                        .into_iter()
+                        .map(|i| i.apply_substitution(&replacement_map))
+                        .collect();
+                    FlatStatement::Directive(FlatDirective {
+                        outputs: new_outputs,
+                        solver: d.solver,
+                        inputs: new_inputs,
+                    })
+                }
+                FlatStatement::Comment(text) => FlatStatement::Comment(text),
+            })
+            .collect();
+
+        statements_flattened.extend(statements);
+
+        match return_statements.pop().unwrap() {
+            FlatStatement::Return(list) => FlatExpressionList {
+                expressions: list
+                    .expressions
+                    .into_iter()
+                    .map(|x| x.apply_substitution(&replacement_map))
+                    .collect(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /// Flattens an expression
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - Available functions in this context
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `expr` - `TypedExpression` that will be flattened.
+    fn flatten_expression(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        expr: TypedExpression<'ast, T>,
+    ) -> Vec<FlatExpression<T>> {
+        match expr {
+            TypedExpression::FieldElement(e) => {
+                vec![self.flatten_field_expression(symbols, statements_flattened, e)]
+            }
+            TypedExpression::Boolean(e) => {
+                vec![self.flatten_boolean_expression(symbols, statements_flattened, e)]
+            }
+            TypedExpression::Array(e) => match e.inner_type().clone() {
+                Type::FieldElement => self
+                    .flatten_array_expression::<FieldElementExpression<'ast, T>>(
+                        symbols,
+                        statements_flattened,
+                        e,
+                    ),
+                Type::Boolean => self.flatten_array_expression::<BooleanExpression<'ast, T>>(
+                    symbols,
+                    statements_flattened,
+                    e,
+                ),
+                Type::Array(..) => self.flatten_array_expression::<ArrayExpression<'ast, T>>(
+                    symbols,
+                    statements_flattened,
+                    e,
+                ),
+                Type::Struct(..) => self.flatten_array_expression::<StructExpression<'ast, T>>(
+                    symbols,
+                    statements_flattened,
+                    e,
+                ),
+            },
+            TypedExpression::Struct(e) => {
+                self.flatten_struct_expression(symbols, statements_flattened, e)
+            }
+        }
+    }
+
+    /// Flattens a field expression, returning whether the result is linear
+    ///
+    /// # Remarks
+    /// * Additions and subtractions are linear by construction, so their linearity is known without
+    /// walking through the result again. This keeps flattening long sums linear in their length.
+    fn flatten_field_expression_linearity(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        expr: FieldElementExpression<'ast, T>,
+    ) -> (FlatExpression<T>, bool) {
+        match expr {
+            e @ FieldElementExpression::Add(..)
+            | e @ FieldElementExpression::Sub(..)
+            | e @ FieldElementExpression::Sum(..) => (
+                self.flatten_field_expression(symbols, statements_flattened, e),
+                true,
+            ),
+            e => {
+                let flattened = self.flatten_field_expression(symbols, statements_flattened, e);
+                let linear = flattened.is_linear();
+                (flattened, linear)
+            }
+        }
+    }
+
+    /// Flattens `left - right` in the field, whatever the integer semantics
+    fn flatten_field_difference(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        left: FieldElementExpression<'ast, T>,
+        right: FieldElementExpression<'ast, T>,
+    ) -> FlatExpression<T> {
+        let (left_flattened, left_linear) =
+            self.flatten_field_expression_linearity(symbols, statements_flattened, left);
+        let (right_flattened, right_linear) =
+            self.flatten_field_expression_linearity(symbols, statements_flattened, right);
+
+        let new_left = self.define_if_non_linear(statements_flattened, left_flattened, left_linear);
+        let new_right =
+            self.define_if_non_linear(statements_flattened, right_flattened, right_linear);
+
+        FlatExpression::Sub(box new_left, box new_right)
+    }
+
+    /// Applies the integer semantics to the result of `+`, `-` or `*`
+    ///
+    /// # Arguments
+    ///
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `e` - Result of the operation in the field.
+    /// * `width` - Function returning the number of bits of `e` for operands of `n` bits
+    fn int_result(
+        &mut self,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        e: FlatExpression<T>,
+        width: fn(usize) -> usize,
+    ) -> FlatExpression<T> {
+        match self.int_semantics {
+            IntSemantics::Field => e,
+            IntSemantics::WrappingBits(n) => {
+                // keep the lowest `n` bits
+                let e_bits = self.decompose_bits(statements_flattened, e, width(n));
+                Self::recompose_bits(&e_bits[e_bits.len() - n..])
+            }
+            IntSemantics::CheckedBits(n) => {
+                // the decomposition checks that the result fits in `n` bits
+                let e_bits = self.decompose_bits(statements_flattened, e, n);
+                Self::recompose_bits(&e_bits)
+            }
+        }
+    }
+
+    /// Returns `e` if it is linear, otherwise a new variable defined as `e`
+    fn define_if_non_linear(
+        &mut self,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        e: FlatExpression<T>,
+        linear: bool,
+    ) -> FlatExpression<T> {
+        if linear {
+            e
+        } else {
+            let id = self.use_sym();
+            statements_flattened.push(FlatStatement::Definition(id, e));
+            FlatExpression::Identifier(id)
+        }
+    }
+
+    /// Flattens a field expression
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - Available functions in this context
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `expr` - `FieldElementExpression` that will be flattened.
+    fn flatten_field_expression(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        expr: FieldElementExpression<'ast, T>,
+    ) -> FlatExpression<T> {
+        match expr {
+            FieldElementExpression::Number(x) => FlatExpression::Number(x), // force to be a field element
+            FieldElementExpression::Identifier(x) => match self.constants.get(&x) {
+                Some(value) => FlatExpression::Number(value.clone()),
+                None => FlatExpression::Identifier(self.layout.get(&x).unwrap().clone()[0]),
+            },
+            FieldElementExpression::Add(box left, box right) => {
+                let (left_flattened, left_linear) =
+                    self.flatten_field_expression_linearity(symbols, statements_flattened, left);
+                let (right_flattened, right_linear) =
+                    self.flatten_field_expression_linearity(symbols, statements_flattened, right);
+                let new_left =
+                    self.define_if_non_linear(statements_flattened, left_flattened, left_linear);
+                let new_right =
+                    self.define_if_non_linear(statements_flattened, right_flattened, right_linear);
+                self.int_result(
+                    statements_flattened,
+                    FlatExpression::Add(box new_left, box new_right),
+                    |n| n + 1,
+                )
+            }
+            FieldElementExpression::Sum(terms) => {
+                // sum the terms iteratively, only defining the non-linear ones
+                let mut sum = None;
+
+                for term in terms {
+                    let (term_flattened, term_linear) = self.flatten_field_expression_linearity(
+                        symbols,
+                        statements_flattened,
+                        term,
+                    );
+                    let new_term = self.define_if_non_linear(
+                        statements_flattened,
+                        term_flattened,
+                        term_linear,
+                    );
+                    sum = Some(match sum {
+                        Some(sum) => self.int_result(
+                            statements_flattened,
+                            FlatExpression::Add(box sum, box new_term),
+                            |n| n + 1,
+                        ),
+                        None => new_term,
+                    });
+                }
+
+                sum.unwrap_or(FlatExpression::Number(T::zero()))
+            }
+            FieldElementExpression::Product(factors) => {
+                // multiply the factors iteratively, defining the partial product at each step
+                let mut product = None;
+
+                for factor in factors {
+                    let (factor_flattened, factor_linear) = self
+                        .flatten_field_expression_linearity(symbols, statements_flattened, factor);
+                    let new_factor = self.define_if_non_linear(
+                        statements_flattened,
+                        factor_flattened,
+                        factor_linear,
+                    );
+                    product = Some(match product {
+                        Some(product) => {
+                            let product_linear = product.is_linear();
+                            let new_product = self.define_if_non_linear(
+                                statements_flattened,
+                                product,
+                                product_linear,
+                            );
+                            self.int_result(
+                                statements_flattened,
+                                FlatExpression::Mult(box new_product, box new_factor),
+                                |n| 2 * n,
+                            )
+                        }
+                        None => new_factor,
+                    });
+                }
+
+                product.unwrap_or(FlatExpression::Number(T::one()))
+            }
+            FieldElementExpression::Sub(box left, box right) => {
+                let difference =
+                    self.flatten_field_difference(symbols, statements_flattened, left, right);
+
+                // offset by 2^n so that the result of the wrapping subtraction is its lowest n bits
+                let difference = match self.int_semantics {
+                    IntSemantics::WrappingBits(n) => FlatExpression::Add(
+                        box difference,
+                        box FlatExpression::Number(T::from(2).pow(n)),
+                    ),
+                    _ => difference,
+                };
+
+                self.int_result(statements_flattened, difference, |n| n + 1)
+            }
+            FieldElementExpression::Mult(box left, box right) => {
+                let (left_flattened, left_linear) =
+                    self.flatten_field_expression_linearity(symbols, statements_flattened, left);
+                let (right_flattened, right_linear) =
+                    self.flatten_field_expression_linearity(symbols, statements_flattened, right);
+                let new_left =
+                    self.define_if_non_linear(statements_flattened, left_flattened, left_linear);
+                let new_right =
+                    self.define_if_non_linear(statements_flattened, right_flattened, right_linear);
+                self.int_result(
+                    statements_flattened,
+                    FlatExpression::Mult(box new_left, box new_right),
+                    |n| 2 * n,
+                )
+            }
+            FieldElementExpression::Div(box left, box right) => {
+                let left_flattened =
+                    self.flatten_field_expression(symbols, statements_flattened, left);
+                let right_flattened =
+                    self.flatten_field_expression(symbols, statements_flattened, right);
+                let new_left: FlatExpression<T> = {
+                    let id = self.use_sym();
+                    statements_flattened.push(FlatStatement::Definition(id, left_flattened));
+                    id.into()
+                };
+                let new_right: FlatExpression<T> = {
+                    let id = self.use_sym();
+                    statements_flattened.push(FlatStatement::Definition(id, right_flattened));
+                    id.into()
+                };
+
+                let invb = self.use_sym();
+                let inverse = self.use_sym();
+
+                // # invb = 1/b
+                statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
+                    vec![invb],
+                    Solver::Div,
+                    vec![FlatExpression::Number(T::one()), new_right.clone()],
+                )));
+
+                // assert(invb * b == 1)
+                statements_flattened.push(FlatStatement::Condition(
+                    FlatExpression::Number(T::one()),
+                    FlatExpression::Mult(box invb.into(), box new_right.clone().into()),
+                ));
+
+                // # c = a/b
+                statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
+                    vec![inverse],
+                    Solver::Div,
+                    vec![new_left.clone(), new_right.clone()],
+                )));
+
+                // assert(c * b == a)
+                statements_flattened.push(FlatStatement::Condition(
+                    new_left.into(),
+                    FlatExpression::Mult(box new_right, box inverse.into()),
+                ));
+
+                inverse.into()
+            }
+            FieldElementExpression::Pow(box base, box exponent) => {
+                match exponent {
+                    FieldElementExpression::Number(ref e) => {
+                        // flatten the base expression
+                        let base_flattened = self.flatten_field_expression(
+                            symbols,
+                            statements_flattened,
+                            base.clone(),
+                        );
+
+                        // the base is used in several products, define it once if it is not linear
+                        let linear = base_flattened.is_linear();
+                        let base_flattened =
+                            self.define_if_non_linear(statements_flattened, base_flattened, linear);
+
+                        let e = e.to_dec_string().parse::<usize>().unwrap();
+
+                        // convert the exponent to bytes, big endian
+                        let ebytes_be = e.to_be_bytes();
+                        // convert the bytes to bits, remove leading zeroes (we only need powers up to the highest non-zero bit)
+                        let ebits_be: Vec<_> = ebytes_be
+                            .iter()
+                            .flat_map(|byte| (0..8).rev().map(move |i| byte & (1 << i) != 0)) // byte to bit, big endian
+                            .skip_while(|b| !b) // skip trailing false bits
+                            .collect();
+
+                        // reverse to start with the lowest bit first
+                        let ebits_le: Vec<_> = ebits_be.into_iter().rev().collect();
+
+                        // calculate all powers e**(2**i) by squaring
+                        let powers: Vec<FlatExpression<T>> = ebits_le
+                            .iter()
+                            .scan(None, |state, _| {
+                                match state {
+                                    // the first element is the base
+                                    None => {
+                                        *state = Some(base_flattened.clone());
+                                        Some(base_flattened.clone())
+                                    }
+                                    // any subsequent element is the square of the previous one
+                                    Some(previous) => {
+                                        // compute the square of the previous one, stored in state
+                                        let square = self.int_result(
+                                            statements_flattened,
+                                            FlatExpression::Mult(
+                                                box previous.clone(),
+                                                box previous.clone(),
+                                            ),
+                                            |n| 2 * n,
+                                        );
+                                        // introduce a new variable set to it
+                                        let id = self.use_sym();
+                                        statements_flattened
+                                            .push(FlatStatement::Definition(id.clone(), square));
+                                        // store it in the state for later squaring
+                                        *state = Some(FlatExpression::Identifier(id.clone()));
+                                        // return it for later use constructing the result
+                                        Some(FlatExpression::Identifier(id.clone()))
+                                    }
+                                }
+                            })
+                            .collect();
+
+                        // construct the result iterating through the bits, multiplying by the associated power iff the bit is true
+                        ebits_le.into_iter().zip(powers).fold(
+                            FlatExpression::Number(T::from(1)), // initialise the result at 1. If we have no bits to itegrate through, we're computing x**0 == 1
+                            |acc, (bit, power)| match bit {
+                                true => {
+                                    // the new result is the current result times the current power
+                                    let product = self.int_result(
+                                        statements_flattened,
+                                        FlatExpression::Mult(box acc.clone(), box power),
+                                        |n| 2 * n,
+                                    );
+                                    // update the result by introducing a new variable
+                                    let id = self.use_sym();
+                                    statements_flattened
+                                        .push(FlatStatement::Definition(id, product));
+                                    FlatExpression::Identifier(id)
+                                }
+                                false => acc, // this bit is false, keep the previous result
+                            },
+                        )
+                    }
+                    _ => panic!("Expected number as pow exponent"),
+                }
+            }
+            FieldElementExpression::BitAnd(box left, box right) => self.flatten_bitwise(
+                symbols,
+                statements_flattened,
+                left,
+                right,
+                |_, _, product| product,
+            ),
+            FieldElementExpression::BitOr(box left, box right) => self.flatten_bitwise(
+                symbols,
+                statements_flattened,
+                left,
+                right,
+                // a | b == a + b - a * b
+                |a, b, product| FlatExpression::Sub(box FlatExpression::Add(box a, box b), box product),
+            ),
+            FieldElementExpression::BitXor(box left, box right) => self.flatten_bitwise(
+                symbols,
+                statements_flattened,
+                left,
+                right,
+                // a ^ b == a + b - 2 * a * b
+                |a, b, product| {
+                    FlatExpression::Sub(
+                        box FlatExpression::Add(box a, box b),
+                        box FlatExpression::Mult(box FlatExpression::Number(T::from(2)), box product),
+                    )
+                },
+            ),
+            FieldElementExpression::Shl(box e, by) => {
+                let e_flattened = self.flatten_field_expression(symbols, statements_flattened, e);
+
+                let e_id = self.use_sym();
+                statements_flattened.push(FlatStatement::Definition(e_id, e_flattened));
+
+                // check that the result does not overflow, i.e. that `e` fits in `bits - by` bits
+                let bits = self.bits.saturating_sub(by);
+                self.decompose_bits(statements_flattened, e_id.into(), bits);
+
+                FlatExpression::Mult(
+                    box FlatExpression::Number(T::from(2).pow(by)),
+                    box FlatExpression::Identifier(e_id),
+                )
+            }
+            FieldElementExpression::Shr(box e, by) => {
+                let bits = self.bits;
+
+                let e_flattened = self.flatten_field_expression(symbols, statements_flattened, e);
+
+                let e_bits = self.decompose_bits(statements_flattened, e_flattened, bits);
+
+                // keep the highest `bits - by` bits
+                Self::recompose_bits(&e_bits[..bits.saturating_sub(by)])
+            }
+            FieldElementExpression::ReduceBits(box e, bits) => {
+                let bitwidth = T::get_required_bits();
+                assert!(bits < bitwidth);
+
+                let e_flattened = self.flatten_field_expression(symbols, statements_flattened, e);
+
+                // the directive takes a variable as input
+                let e_id = self.use_sym();
+                statements_flattened.push(FlatStatement::Definition(e_id, e_flattened));
+
+                // decompose on all bits, as the value may not fit in `bits` bits
+                let e_bits_be: Vec<FlatVariable> = (0..bitwidth).map(|_| self.use_sym()).collect();
+
+                statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
+                    e_bits_be.clone(),
+                    Solver::bits(),
+                    vec![e_id],
+                )));
+
+                // bitness checks
+                for bit in &e_bits_be {
+                    statements_flattened.push(FlatStatement::Condition(
+                        FlatExpression::Identifier(*bit),
+                        FlatExpression::Mult(
+                            box FlatExpression::Identifier(*bit),
+                            box FlatExpression::Identifier(*bit),
+                        ),
+                    ));
+                }
+
+                // the decomposition is unique only if it is the canonical one, below the modulus
+                self.strict_le_check(
+                    statements_flattened,
+                    &T::max_value_bit_vector_be(),
+                    e_bits_be.clone(),
+                );
+
+                // bit decomposition check
+                statements_flattened.push(FlatStatement::Condition(
+                    FlatExpression::Identifier(e_id),
+                    Self::recompose_bits(&e_bits_be),
+                ));
+
+                Self::recompose_bits(&e_bits_be[bitwidth - bits..])
+            }
+            FieldElementExpression::PopCount(box e, bits) => {
+                let e_flattened = self.flatten_field_expression(symbols, statements_flattened, e);
+
+                // the decomposition checks that the value fits in `bits` bits
+                let e_bits = self.decompose_bits(statements_flattened, e_flattened, bits);
+
+                // sum(e_bits)
+                e_bits
+                    .into_iter()
+                    .fold(FlatExpression::Number(T::zero()), |acc, bit| {
+                        FlatExpression::Add(box acc, box bit.into())
+                    })
+            }
+            FieldElementExpression::Abs(box e) => {
+                let bits = self.bits;
+
+                let e_flattened = self.flatten_field_expression(symbols, statements_flattened, e);
+
+                let e_id = self.use_sym();
+                statements_flattened.push(FlatStatement::Definition(e_id, e_flattened));
+
+                // `x < 0` cannot be used: `Lt` compares its operands as unsigned field elements, where
+                // a negative value is a large one failing the range check. The sign is read from a bit instead.
+
+                // offsetting maps the signed range `[-2^(bits-1), 2^(bits-1))` to `[0, 2^bits)`, where the
+                // highest bit is set exactly for non-negative values
+                let offset = FlatExpression::Number(T::from(2).pow(bits - 1));
+                let shifted_bits = self.decompose_bits(
+                    statements_flattened,
+                    FlatExpression::Add(box e_id.into(), box offset),
+                    bits,
+                );
+
+                // negative * e, where negative is 1 if the highest bit is not set
+                let product = self.use_sym();
+                statements_flattened.push(FlatStatement::Definition(
+                    product,
+                    FlatExpression::Mult(
+                        box FlatExpression::Sub(
+                            box FlatExpression::Number(T::one()),
+                            box shifted_bits[0].into(),
+                        ),
+                        box e_id.into(),
+                    ),
+                ));
+
+                // |e| = e - 2 * negative * e
+                FlatExpression::Sub(
+                    box e_id.into(),
+                    box FlatExpression::Add(box product.into(), box product.into()),
+                )
+            }
+            FieldElementExpression::Switch(box selector, cases, box default) => self
+                .flatten_field_expression(
+                    symbols,
+                    statements_flattened,
+                    FieldElementExpression::switch_to_if_else(selector, cases, default),
+                ),
+            FieldElementExpression::Min(box a, box b) => self.flatten_field_expression(
+                symbols,
+                statements_flattened,
+                FieldElementExpression::min_to_if_else(a, b),
+            ),
+            FieldElementExpression::Max(box a, box b) => self.flatten_field_expression(
+                symbols,
+                statements_flattened,
+                FieldElementExpression::max_to_if_else(a, b),
+            ),
+            FieldElementExpression::IfElse(box condition, box consequence, box alternative) => self
+                .flatten_if_else_expression(
+                    symbols,
+                    statements_flattened,
+                    condition,
+                    consequence,
+                    alternative,
+                )[0]
+            .clone(),
+            FieldElementExpression::FunctionCall(key, param_expressions) => {
+                let exprs_flattened = self.flatten_function_call(
+                    symbols,
+                    statements_flattened,
+                    key.id,
+                    vec![Type::FieldElement],
+                    param_expressions,
+                );
+                assert!(exprs_flattened.expressions.len() == 1); // outside of MultipleDefinition, FunctionCalls must return a single value
+                exprs_flattened.expressions[0].clone()
+            }
+            FieldElementExpression::Member(box s, id) => {
+                self.flatten_member_expression(symbols, statements_flattened, s, id)[0].clone()
+            }
+            FieldElementExpression::Select(box array, box index) => self
+                .flatten_select_expression::<FieldElementExpression<'ast, T>>(
+                    symbols,
+                    statements_flattened,
+                    array,
+                    index,
+                )[0]
+            .clone(),
+            FieldElementExpression::DynSelect(id, box index, size) => {
+                // only indices within both `size` and the elements of `id` can be selected
+                let size = std::cmp::min(size, self.layout.get(&id).unwrap().len());
+
+                let in_bounds = match index {
+                    FieldElementExpression::Number(ref n) => *n < T::from(size),
+                    _ => size > 0,
+                };
+
+                match in_bounds {
+                    // `Select` checks that its index is in bounds, which is not satisfiable here
+                    false => {
+                        statements_flattened.push(FlatStatement::Condition(
+                            FlatExpression::Number(T::one()),
+                            FlatExpression::Number(T::zero()),
+                        ));
+                        FlatExpression::Number(T::zero())
+                    }
+                    true => self.flatten_select_expression::<FieldElementExpression<'ast, T>>(
+                        symbols,
+                        statements_flattened,
+                        ArrayExpressionInner::Identifier(id).annotate(Type::FieldElement, size),
+                        index,
+                    )[0]
+                    .clone(),
+                }
+            }
+        }
+    }
+
+    /// Flattens an array expression
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - Available functions in this context
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `expr` - `StructExpression` that will be flattened.
+    fn flatten_struct_expression(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        expr: StructExpression<'ast, T>,
+    ) -> Vec<FlatExpression<T>> {
+        let ty = expr.get_type();
+        let expected_output_size = expr.get_type().get_primitive_count();
+        let members = expr.ty().clone();
+
+        let res = match expr.into_inner() {
+            StructExpressionInner::Identifier(x) => self
+                .layout
+                .get(&x)
+                .unwrap()
+                .iter()
+                .map(|v| FlatExpression::Identifier(v.clone()))
+                .collect(),
+            StructExpressionInner::Value(values) => values
+                .into_iter()
+                .flat_map(|v| self.flatten_expression(symbols, statements_flattened, v))
+                .collect(),
+            StructExpressionInner::FunctionCall(key, param_expressions) => {
+                let exprs_flattened = self.flatten_function_call(
+                    symbols,
+                    statements_flattened,
+                    key.id,
+                    vec![ty],
+                    param_expressions,
+                );
+                exprs_flattened.expressions
+            }
+            StructExpressionInner::IfElse(box condition, box consequence, box alternative) => {
+                members
+                    .into_iter()
+                    .flat_map(|member| match ty {
+                        Type::FieldElement => FieldElementExpression::if_else(
+                            condition.clone(),
+                            FieldElementExpression::member(consequence.clone(), member.id.clone()),
+                            FieldElementExpression::member(alternative.clone(), member.id.clone()),
+                        )
+                        .flatten(self, symbols, statements_flattened),
+                        Type::Boolean => BooleanExpression::if_else(
+                            condition.clone(),
+                            BooleanExpression::member(consequence.clone(), member.id.clone()),
+                            BooleanExpression::member(alternative.clone(), member.id.clone()),
+                        )
+                        .flatten(self, symbols, statements_flattened),
+                        Type::Struct(..) => StructExpression::if_else(
+                            condition.clone(),
+                            StructExpression::member(consequence.clone(), member.id.clone()),
+                            StructExpression::member(alternative.clone(), member.id.clone()),
+                        )
+                        .flatten(self, symbols, statements_flattened),
+                        Type::Array(..) => ArrayExpression::if_else(
+                            condition.clone(),
+                            ArrayExpression::member(consequence.clone(), member.id.clone()),
+                            ArrayExpression::member(alternative.clone(), member.id.clone()),
+                        )
+                        .flatten(self, symbols, statements_flattened),
+                    })
+                    .collect()
+            }
+            StructExpressionInner::Member(box s, id) => {
+                self.flatten_member_expression(symbols, statements_flattened, s, id)
+            }
+            StructExpressionInner::Select(box array, box index) => self
+                .flatten_select_expression::<StructExpression<'ast, T>>(
+                    symbols,
+                    statements_flattened,
+                    array,
+                    index,
+                ),
+        };
+
+        assert_eq!(res.len(), expected_output_size);
+        res
+    }
+
+    /// Flattens an array expression
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - Available functions in this context
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `expr` - `ArrayExpression` that will be flattened.
+    /// # Remarks
+    /// * U is the inner type of the array
+    fn flatten_array_expression<U: Flatten<'ast, T>>(
+        &mut self,
+        symbols: &HashMap<FunctionKey<'ast>, TypedFunctionSymbol<'ast, T>>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        expr: ArrayExpression<'ast, T>,
+    ) -> Vec<FlatExpression<T>> {
+        let ty = expr.get_type();
+
+        let size = expr.size();
+
+        match expr.into_inner() {
+            ArrayExpressionInner::Identifier(x) => self
+                .layout
+                .get(&x)
+                .unwrap()
+                .iter()
+                .map(|v| FlatExpression::Identifier(v.clone()))
+                .collect(),
+            ArrayExpressionInner::Value(values) => {
+                assert_eq!(size, values.len());
+                values
+                    .into_iter()
+                    .flat_map(|v| {
+                        U::try_from(v)
+                            .unwrap()
+                            .flatten(self, symbols, statements_flattened)
+                    })
+                    .collect()
+            }
+            ArrayExpressionInner::FunctionCall(key, param_expressions) => {
+                let exprs_flattened = self.flatten_function_call(
+                    symbols,
+                    statements_flattened,
+                    key.id,
+                    vec![ty],
+                    param_expressions,
+                );
+                assert!(exprs_flattened.expressions.len() == size); // outside of MultipleDefinition, FunctionCalls must return a single value
+                exprs_flattened.expressions
+            }
+            ArrayExpressionInner::IfElse(ref condition, ref consequence, ref alternative) => (0
+                ..size)
+                .flat_map(|i| {
+                    U::if_else(
+                        *condition.clone(),
+                        U::select(
+                            *consequence.clone(),
+                            FieldElementExpression::Number(T::from(i)),
+                        ),
+                        U::select(
+                            *alternative.clone(),
+                            FieldElementExpression::Number(T::from(i)),
+                        ),
+                    )
+                    .flatten(self, symbols, statements_flattened)
+                })
+                .collect(),
+            ArrayExpressionInner::Member(box s, id) => {
+                self.flatten_member_expression(symbols, statements_flattened, s, id)
+            }
+            ArrayExpressionInner::Select(box array, box index) => self
+                .flatten_select_expression::<ArrayExpression<'ast, T>>(
+                    symbols,
+                    statements_flattened,
+                    array,
+                    index,
+                ),
+        }
+    }
+
+    /// Flattens a statement
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - Available functions in this context
+    /// * `statements_flattened` - Vector where new flattened statements can be added.
+    /// * `stat` - `TypedStatement` that will be flattened.
+    fn flatten_statement(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+        stat: TypedStatement<'ast, T>,
+    ) {
+        // conditional assignments are flattened as definitions
+        match stat.desugar() {
+            TypedStatement::Return(exprs) => {
+                let flat_expressions = exprs
+                    .into_iter()
+                    .map(|expr| self.flatten_expression(symbols, statements_flattened, expr))
+                    .flat_map(|x| x)
+                    .collect::<Vec<_>>();
+
+                statements_flattened.push(FlatStatement::Return(FlatExpressionList {
+                    expressions: flat_expressions,
+                }));
+            }
+            TypedStatement::Declaration(_) => {
+                // declarations have already been checked
+                ()
+            }
+            TypedStatement::Definition(assignee, expr) => {
+                // define n variables with n the number of primitive types for v_type
+                // assign them to the n primitive types for expr
+
+                let rhs = self.flatten_expression(symbols, statements_flattened, expr);
+
+                match assignee {
+                    TypedAssignee::Identifier(ref v) => {
+                        let vars = self.use_variable(&v);
+                        // handle return of function call
+                        statements_flattened.extend(
+                            vars.into_iter()
+                                .zip(rhs)
+                                .map(|(v, e)| FlatStatement::Definition(v, canonicalize_linear(e))),
+                        );
+                    }
+                    TypedAssignee::Select(..) => unreachable!(
+                        "array element redefs should have been replaced by array redefs in unroll"
+                    ),
+                    TypedAssignee::Member(..) => unreachable!(
+                        "struct member redefs should have been replaced by struct redef in unroll"
+                    ),
+                }
+            }
+            TypedStatement::Condition(lhs, rhs) => {
+                // flatten expr1 and expr2 to n flattened expressions with n the number of primitive types for expr1
+                // add n conditions to check equality of the n expressions
+
+                let lhs = self.flatten_expression(symbols, statements_flattened, lhs);
+                let rhs = self.flatten_expression(symbols, statements_flattened, rhs);
+
+                assert_eq!(lhs.len(), rhs.len());
+
+                for (l, r) in lhs.into_iter().zip(rhs.into_iter()) {
+                    self.flatten_equality(statements_flattened, l, r);
+                }
+            }
+            TypedStatement::For(..) => unreachable!("static analyser should have unrolled"),
+            TypedStatement::MultipleDefinition(vars, rhs) => {
+                // flatten the right side to p = sum(var_i.type.primitive_count) expressions
+                // define p new variables to the right side expressions
+
+                let var_types = vars.iter().map(|v| v.get_type()).collect();
+
+                match rhs {
+                    TypedExpressionList::FunctionCall(key, exprs, _) => {
+                        let rhs_flattened = self.flatten_function_call(
+                            symbols,
+                            statements_flattened,
+                            &key.id,
+                            var_types,
+                            exprs,
+                        );
+
+                        let rhs = rhs_flattened.expressions.into_iter();
+
+                        let vars = vars.into_iter().flat_map(|v| self.use_variable(&v));
+
+                        statements_flattened
+                            .extend(vars.zip(rhs).map(|(v, r)| FlatStatement::Definition(v, r)));
+                    }
+                }
+            }
+            TypedStatement::CheckedSub(v, left, right) => {
+                // define the difference and check that it fits in `bits` bits, so that it did not wrap around
+                let diff =
+                    self.flatten_field_difference(symbols, statements_flattened, left, right);
+
+                let var = self.use_variable(&v)[0];
+                statements_flattened.push(FlatStatement::Definition(var, diff));
+
+                let bits = self.bits;
+                self.decompose_bits(statements_flattened, var.into(), bits);
+            }
+            TypedStatement::AssertBool(e) => {
+                // x == x * x, with x a variable so that duplicate checks can be removed
+                let x = match self.flatten_field_expression(symbols, statements_flattened, e) {
+                    FlatExpression::Identifier(id) => id,
+                    e => {
+                        let id = self.use_sym();
+                        statements_flattened.push(FlatStatement::Definition(id, e));
+                        id
+                    }
+                };
+
+                statements_flattened.push(FlatStatement::Condition(
+                    FlatExpression::Identifier(x),
+                    FlatExpression::Mult(
+                        box FlatExpression::Identifier(x),
+                        box FlatExpression::Identifier(x),
+                    ),
+                ));
+            }
+            TypedStatement::AssertNonZero(e) => {
+                // # _, w = if x == 0 then 0, 1 else 1, 1/x fi
+                // 1 == x * w
+                let x = self.flatten_field_expression(symbols, statements_flattened, e);
+                let linear = x.is_linear();
+                let x = self.define_if_non_linear(statements_flattened, x, linear);
+
+                let name_y = self.use_sym();
+                let name_w = self.use_sym();
+
+                statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
+                    vec![name_y, name_w],
+                    Solver::ConditionEq,
+                    vec![x.clone()],
+                )));
+                statements_flattened.push(FlatStatement::Condition(
+                    FlatExpression::Number(T::one()),
+                    FlatExpression::Mult(box x, box FlatExpression::Identifier(name_w)),
+                ));
+            }
+            TypedStatement::CondAssign(..) => unreachable!("should have been desugared"),
+            TypedStatement::Comment(text) => {
+                statements_flattened.push(FlatStatement::Comment(text));
+            }
+        }
+    }
+
+    /// Flattens a function symbol
+    ///
+    /// # Arguments
+    ///
+    /// * `funct` - `TypedFunctionSymbol` that will be flattened.
+    ///
+    /// # Remarks
+    /// * Only "flat" symbols can be flattened here. Other function calls must have been inlined previously.
+    fn flatten_function_symbol(&mut self, funct: TypedFunctionSymbol<'ast, T>) -> FlatFunction<T> {
+        match funct {
+            TypedFunctionSymbol::Flat(flat_function) => flat_function.synthetize(),
+            _ => unreachable!("only local flat symbols can be flattened"),
+        }
+    }
+
+    /// Resets the layout before flattening a function, and returns its flattened parameters
+    fn start_function(&mut self, arguments: Vec<Parameter<'ast>>) -> Vec<FlatParameter> {
+        self.layout = HashMap::new();
+        self.if_else_cache = HashMap::new();
+
+        if !self.preserve_index {
+            self.next_var_idx = 0;
+            self.display_names = HashMap::new();
+        }
+
+        arguments
+            .into_iter()
+            .flat_map(|p| self.use_parameter(&p))
+            .collect()
+    }
+
+    /// Flattens a function
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - Available functions in this context
+    /// * `funct` - `TypedFunction` that will be flattened
+    ///
+    /// # Remarks
+    /// * Flattening stops as soon as the constraint budget is exceeded, not counting the booleanity checks
+    /// removed by `dedup_booleanity`, and `BudgetExceeded` is returned.
+    fn flatten_function(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        funct: TypedFunction<'ast, T>,
+    ) -> Result<FlatFunction<T>, FlattenError> {
+        let arguments_flattened = self.start_function(funct.arguments);
+
+        let mut statements_flattened: Vec<FlatStatement<T>> = Vec::new();
+
+        self.flatten_statements(symbols, funct.statements, &mut statements_flattened)?;
+
+        Ok(FlatFunction {
+            arguments: arguments_flattened,
+            statements: statements_flattened,
+        })
+    }
+
+    /// Flattens `statements`, appending them to `statements_flattened`
+    ///
+    /// # Remarks
+    /// * Flattening stops as soon as the constraint budget is exceeded by `statements_flattened`, not counting
+    /// the booleanity checks removed by `dedup_booleanity`, and `BudgetExceeded` is returned.
+    fn flatten_statements(
+        &mut self,
+        symbols: &TypedFunctionSymbols<'ast, T>,
+        statements: Vec<TypedStatement<'ast, T>>,
+        statements_flattened: &mut Vec<FlatStatement<T>>,
+    ) -> Result<(), FlattenError> {
+        let mut checked = HashSet::new();
+        let mut constraint_count = match self.constraint_budget {
+            Some(_) => Self::count_constraints(&Self::dedup_booleanity_after(
+                &mut checked,
+                statements_flattened.clone(),
+            )),
+            None => 0,
+        };
+
+        // flatten statements in functions and apply substitution
+        for stat in statements {
+            let start = statements_flattened.len();
+            self.flatten_statement(symbols, statements_flattened, stat);
+
+            if self.constraint_budget.is_some() {
+                let added = statements_flattened[start..].to_vec();
+                constraint_count +=
+                    Self::count_constraints(&Self::dedup_booleanity_after(&mut checked, added));
+
+                if self.exceeds_budget(constraint_count) {
+                    return Err(FlattenError::BudgetExceeded {
+                        budget: self.constraint_budget.unwrap(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Flattens a program
+    ///
+    /// # Arguments
+    ///
+    /// * `prog` - `TypedProgram` that will be flattened.
+    pub fn flatten_program(
+        &mut self,
+        prog: TypedProgram<'ast, T>,
+    ) -> Result<FlatProg<T>, FlattenError> {
+        let mut statements = vec![];
+
+        let arguments = self.flatten_program_into(prog, &mut statements)?;
+
+        Ok(FlatProg {
+            main: FlatFunction {
+                arguments,
+                statements,
+            },
+        })
+    }
+
+    /// Flattens a program, appending the statements of its `main` to `out`
+    ///
+    /// # Arguments
+    ///
+    /// * `prog` - `TypedProgram` that will be flattened.
+    /// * `out` - Vector the flattened statements are appended to.
+    ///
+    /// # Remarks
+    /// * The parameters of `main` are returned. On error, `out` is left unchanged.
+    /// * Variables of successive programs only differ if the numbering is preserved, see `with_next_index`.
+    pub fn flatten_program_into(
+        &mut self,
+        prog: TypedProgram<'ast, T>,
+        out: &mut Vec<FlatStatement<T>>,
+    ) -> Result<Vec<FlatParameter>, FlattenError> {
+        let (symbols, main) = Self::main_function(&prog);
+
+        self.check_main(&main)?;
+
+        let phase = Phase::start("flatten");
+
+        let main_flattened = self.flatten_function(symbols, main)?;
+
+        phase.end(main_flattened.statements.len());
+
+        let statements = self.finish_main(main_flattened.statements)?;
+
+        out.extend(statements);
+
+        Ok(main_flattened.arguments)
+    }
+
+    /// Checks and post-processes the flattened statements of `main`, and returns the statements of the program
+    fn finish_main(
+        &mut self,
+        statements: Vec<FlatStatement<T>>,
+    ) -> Result<Vec<FlatStatement<T>>, FlattenError> {
+        let phase = Phase::start("dedup_booleanity");
+
+        let statements = Self::dedup_booleanity(statements);
+
+        phase.end(statements.len());
+
+        let statements = match self.validity_output {
+            true => self.combine_conditions(&mut FlatExpression::Number(T::one()), statements),
+            false => statements,
+        };
+
+        let statements = Self::check_constant_conditions(statements)?;
+
+        if self.exceeds_budget(Self::count_constraints(&statements)) {
+            return Err(FlattenError::BudgetExceeded {
+                budget: self.constraint_budget.unwrap(),
+            });
+        }
+
+        // the outputs of the program are defined by its return statement
+        if !statements.iter().any(Self::is_return) {
+            return Err(FlattenError::EmptyProgram);
+        }
+
+        if let Some(ref mut on_emit) = self.on_emit {
+            for statement in &statements {
+                on_emit(statement);
+            }
+        }
+
+        self.name_outputs(&statements);
+
+        Ok(statements)
+    }
+
+    /// Flattens the first `k` statements of the `main` function of a program, so that flattening a later version
+    /// of the program can resume after them with `flatten_program_incremental`
+    ///
+    /// # Remarks
+    /// * Flattening stops before the return statement of `main`, even if it is among the first `k` statements.
+    pub fn flatten_program_start(
+        &mut self,
+        prog: TypedProgram<'ast, T>,
+        k: usize,
+    ) -> Result<FlatPrefix<'ast, T>, FlattenError> {
+        let (symbols, main) = Self::main_function(&prog);
+
+        self.check_main(&main)?;
+
+        let statements: Vec<_> = main
+            .statements
+            .into_iter()
+            .take(k)
+            .take_while(|s| match s {
+                TypedStatement::Return(..) => false,
+                _ => true,
+            })
+            .collect();
+
+        let flattened = self.flatten_function(
+            symbols,
+            TypedFunction {
+                arguments: main.arguments.clone(),
+                statements: statements.clone(),
+                signature: main.signature,
+            },
+        )?;
+
+        Ok(FlatPrefix {
+            main: flattened,
+            arguments: main.arguments,
+            statements,
+            layout: self.layout.clone(),
+            next_var_idx: self.next_var_idx,
+            display_names: self.display_names.clone(),
+        })
+    }
+
+    /// Flattens a program, resuming after `prefix` if `main` still starts with the statements it flattened
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - `FlatPrefix` returned by `flatten_program_start` on this or an earlier version of the program.
+    /// * `prog` - `TypedProgram` that will be flattened.
+    ///
+    /// # Remarks
+    /// * The result is the one of `flatten_program`. If the arguments of `main` changed, or its statements do not
+    /// start with the ones of `prefix`, the whole program is flattened again.
+    /// * `prefix` must have been flattened with the same configuration, as its statements are not flattened again.
+    pub fn flatten_program_incremental(
+        &mut self,
+        prefix: &FlatPrefix<'ast, T>,
+        prog: TypedProgram<'ast, T>,
+    ) -> Result<FlatProg<T>, FlattenError> {
+        let (symbols, main) = Self::main_function(&prog);
+
+        if main.arguments != prefix.arguments || !main.statements.starts_with(&prefix.statements) {
+            return self.flatten_program(prog);
+        }
+
+        self.check_main(&main)?;
+
+        // restore the state of the flattener after the prefix, so that the following statements read its
+        // identifiers and number their variables as when flattening the whole program
+        self.layout = prefix.layout.clone();
+        self.next_var_idx = prefix.next_var_idx;
+        self.display_names = prefix.display_names.clone();
+        self.if_else_cache = HashMap::new();
+
+        let mut statements = prefix.main.statements.clone();
+
+        self.flatten_statements(
+            symbols,
+            main.statements
+                .into_iter()
+                .skip(prefix.statements.len())
+                .collect(),
+            &mut statements,
+        )?;
+
+        let statements = self.finish_main(statements)?;
+
+        Ok(FlatProg {
+            main: FlatFunction {
+                arguments: prefix.main.arguments.clone(),
+                statements,
+            },
+        })
+    }
+
+    /// Flattens the first `k` statements of the `main` function of a program
+    ///
+    /// # Remarks
+    /// * This locates the statement introducing a given constraint, by bisecting on `k`.
+    /// * The return statement is only flattened if it is among the first `k` statements, and the result is not
+    /// checked.
+    /// * The program is rejected as by `flatten_program`, and flattening stops when the constraint budget is
+    /// exceeded.
+    pub fn flatten_prefix(
+        &mut self,
+        prog: &TypedProgram<'ast, T>,
+        k: usize,
+    ) -> Result<FlatProg<T>, FlattenError> {
+        let (symbols, main) = Self::main_function(prog);
+
+        self.check_main(&main)?;
+
+        let arguments = self.start_function(main.arguments);
+
+        let mut statements = vec![];
+        self.flatten_statements(
+            symbols,
+            main.statements.into_iter().take(k).collect(),
+            &mut statements,
+        )?;
+
+        Ok(FlatProg {
+            main: FlatFunction {
+                arguments,
+                statements,
+            },
+        })
+    }
+
+    /// Flattens a program, passing each statement to `sink` as soon as it is flattened instead of collecting them
+    ///
+    /// # Arguments
+    ///
+    /// * `prog` - `TypedProgram` that will be flattened.
+    /// * `sink` - Function called on each statement of the flattened `main`, in order.
+    ///
+    /// # Remarks
+    /// * `sink` receives the statements of `flatten_program`, and the parameters of `main` are returned.
+    /// * If the budget is exceeded or `main` does not return, the statements flattened until then were already
+    /// passed to `sink`.
+    pub fn flatten_program_streaming<F: FnMut(FlatStatement<T>)>(
+        &mut self,
+        prog: TypedProgram<'ast, T>,
+        mut sink: F,
+    ) -> Result<Vec<FlatParameter>, FlattenError> {
+        let (symbols, main) = Self::main_function(&prog);
+
+        self.check_main(&main)?;
+
+        let arguments = self.start_function(main.arguments);
+
+        let mut checked = HashSet::new();
+        let mut valid = FlatExpression::Number(T::one());
+        let mut constraint_count = 0;
+        let mut returned = false;
+
+        for stat in main.statements {
+            let mut statements_flattened = vec![];
+            self.flatten_statement(symbols, &mut statements_flattened, stat);
+
+            let statements_flattened =
+                Self::dedup_booleanity_after(&mut checked, statements_flattened);
+
+            let statements_flattened = match self.validity_output {
+                true => self.combine_conditions(&mut valid, statements_flattened),
+                false => statements_flattened,
+            };
+
+            let statements_flattened = Self::check_constant_conditions(statements_flattened)?;
+
+            constraint_count += Self::count_constraints(&statements_flattened);
+            if self.exceeds_budget(constraint_count) {
+                return Err(FlattenError::BudgetExceeded {
+                    budget: self.constraint_budget.unwrap(),
+                });
+            }
+
+            self.name_outputs(&statements_flattened);
+
+            returned |= statements_flattened.iter().any(Self::is_return);
+
+            for statement in statements_flattened {
+                if let Some(ref mut on_emit) = self.on_emit {
+                    on_emit(&statement);
+                }
+                sink(statement);
+            }
+        }
+
+        if !returned {
+            return Err(FlattenError::EmptyProgram);
+        }
+
+        Ok(arguments)
+    }
+
+    /// Checks the configuration of the flattener and the `main` function of a program before flattening it
+    fn check_main(&self, main: &TypedFunction<'ast, T>) -> Result<(), FlattenError> {
+        self.check_bit_width()?;
+        self.check_int_semantics()?;
+        Self::check_arguments(main)?;
+        self.check_constants(main)?;
+        Self::check_widths(main)
+    }
+
+    /// Checks that `bits` is positive and that values of `bits` bits cannot wrap around the modulus, which
+    /// would make the gadgets unsound
+    fn check_bit_width(&self) -> Result<(), FlattenError> {
+        let field_max = T::get_required_bits() - 1;
+        match self.bits {
+            0 => Err(FlattenError::BitWidthZero),
+            bits if bits > field_max => Err(FlattenError::BitWidthTooLarge {
+                requested: bits,
+                field_max,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that the results of the integer operations fit in the field before they are reduced to `n` bits
+    fn check_int_semantics(&self) -> Result<(), FlattenError> {
+        let field_max = T::get_required_bits() - 1;
+        let widest = match self.int_semantics {
+            IntSemantics::Field => 0,
+            // a product of two integers of `n` bits has `2 * n` bits
+            IntSemantics::WrappingBits(n) => 2 * n,
+            IntSemantics::CheckedBits(n) => n,
+        };
+        match widest > field_max {
+            true => Err(FlattenError::IntSemanticsTooWide {
+                semantics: self.int_semantics,
+                field_max,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    /// Checks that the bit widths given in the expressions of `main` fit in the field, like `check_bit_width`
+    fn check_widths(main: &TypedFunction<'ast, T>) -> Result<(), FlattenError> {
+        let mut widths = Widths::default();
+        widths.fold_function(main.clone());
+
+        match widths.too_large {
+            Some((requested, field_max)) => Err(FlattenError::BitWidthTooLarge {
+                requested,
+                field_max,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the arguments of `main` have distinct names, as later reads of a duplicate would be ambiguous
+    fn check_arguments(main: &TypedFunction<'ast, T>) -> Result<(), FlattenError> {
+        let mut names = HashSet::new();
+        match main.arguments.iter().find(|p| !names.insert(&p.id.id)) {
+            Some(p) => Err(FlattenError::DuplicateArgument {
+                name: p.id.id.id.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that no argument or variable of `main` is declared as a constant, as reads would ignore its value
+    fn check_constants(&self, main: &TypedFunction<'ast, T>) -> Result<(), FlattenError> {
+        let mut assigned = vec![];
+        for p in &main.arguments {
+            assigned.push(&p.id);
+        }
+        Self::assigned_variables(&main.statements, &mut assigned);
+
+        match assigned
+            .into_iter()
+            .find(|v| self.constants.contains_key(&v.id))
+        {
+            Some(v) => Err(FlattenError::ConstantAssigned {
+                name: v.id.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Appends the variables assigned to in `statements`, including in loops, to `assigned`
+    fn assigned_variables<'a>(
+        statements: &'a [TypedStatement<'ast, T>],
+        assigned: &mut Vec<&'a Variable<'ast>>,
+    ) {
+        for s in statements {
+            match s {
+                TypedStatement::Definition(assignee, _) => {
+                    let mut assignee = assignee;
+                    loop {
+                        match *assignee {
+                            TypedAssignee::Identifier(ref v) => {
+                                assigned.push(v);
+                                break;
+                            }
+                            TypedAssignee::Select(box ref a, _)
+                            | TypedAssignee::Member(box ref a, _) => assignee = a,
+                        }
+                    }
+                }
+                TypedStatement::Declaration(v)
+                | TypedStatement::CondAssign(v, ..)
+                | TypedStatement::CheckedSub(v, ..) => assigned.push(v),
+                TypedStatement::MultipleDefinition(vs, _) => assigned.extend(vs),
+                TypedStatement::For(v, _, _, body) => {
+                    assigned.push(v);
+                    Self::assigned_variables(body, assigned);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the functions of the main module of `prog` and its `main` function
+    fn main_function<'a>(
+        prog: &'a TypedProgram<'ast, T>,
+    ) -> (&'a TypedFunctionSymbols<'ast, T>, TypedFunction<'ast, T>) {
+        let main_module = prog.modules.get(&prog.main).unwrap();
+
+        let main = main_module
+            .functions
+            .iter()
+            .find(|(k, _)| k.id == "main")
+            .unwrap()
+            .1
+            .clone();
+
+        match main {
+            TypedFunctionSymbol::Here(f) => (&main_module.functions, f),
+            _ => unreachable!("main should be a typed function locally"),
+        }
+    }
+
+    /// Checks if the given name is a not used variable and returns a fresh variable.
+    /// # Arguments
+    ///
+    /// * `name` - a String that holds the name of the variable
+    fn use_variable(&mut self, variable: &Variable<'ast>) -> Vec<FlatVariable> {
+        let vars = self.issue_new_variables(variable.get_type().get_primitive_count());
+
+        // remember the source name of the identifier and of the variables it is flattened to
+        let name = variable.id.id.to_string();
+        self.display_names
+            .insert(variable.id.to_string(), name.clone());
+        for v in &vars {
+            self.display_names.insert(v.to_string(), name.clone());
+        }
+
+        // cached expressions may read the previous value of the identifier
+        self.if_else_cache.clear();
+
+        self.layout.insert(variable.id.clone(), vars.clone());
+        vars
+    }
+
+    fn use_parameter(&mut self, parameter: &Parameter<'ast>) -> Vec<FlatParameter> {
+        let variables = self.use_variable(&parameter.id);
+
+        variables
+            .into_iter()
+            .map(|v| FlatParameter {
+                id: v,
+                private: parameter.private,
+            })
+            .collect()
+    }
+
+    fn issue_new_variable(&mut self) -> FlatVariable {
+        let var = FlatVariable::new(self.next_var_idx);
+        self.next_var_idx += 1;
+        var
+    }
+
+    fn issue_new_variables(&mut self, count: usize) -> Vec<FlatVariable> {
+        (0..count).map(|_| self.issue_new_variable()).collect()
+    }
+
+    // create an internal variable. We do not register it in the layout
+    fn use_sym(&mut self) -> FlatVariable {
+        self.issue_new_variable()
+    }
+
+    fn get_function<'a>(
+        &mut self,
+        key: &'a FunctionKey<'ast>,
+        symbols: &'a TypedFunctionSymbols<'ast, T>,
+    ) -> FlatFunction<T> {
+        let cached = self.flat_cache.get(&key).cloned();
+
+        cached.unwrap_or_else(|| {
+            let f = symbols.get(&key).unwrap().clone();
+            let res = self.flatten_function_symbol(f);
+            self.flat_cache.insert(key.clone(), res.clone());
+            res
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{self, Interpreter};
+    use crate::typed_absy::types::Signature;
+    use crate::typed_absy::types::Type;
+    use zokrates_field::field::FieldPrime;
+
+    #[test]
+    fn powers_zero() {
+        // def main():
+        //     field a = 7
+        //     field b = a**0
+        //     return b
+
+        // def main():
+        //     _0 = 7
+        //     _1 = 1         // power flattening returns 1, definition introduces _7
+        //     return _1
+        let function = TypedFunction {
+            arguments: vec![],
+            statements: vec![
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element("a".into())),
+                    FieldElementExpression::Number(FieldPrime::from(7)).into(),
+                ),
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element("b".into())),
+                    FieldElementExpression::Pow(
+                        box FieldElementExpression::Identifier("a".into()),
+                        box FieldElementExpression::Number(FieldPrime::from(0)),
+                    )
+                    .into(),
+                ),
+                TypedStatement::Return(vec![FieldElementExpression::Identifier("b".into()).into()]),
+            ],
+            signature: Signature {
+                inputs: vec![],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let mut flattener = Flattener::new();
+
+        let expected = FlatFunction {
+            arguments: vec![],
+            statements: vec![
+                FlatStatement::Definition(
+                    FlatVariable::new(0),
+                    FlatExpression::Number(FieldPrime::from(7)),
+                ),
+                FlatStatement::Definition(
+                    FlatVariable::new(1),
+                    FlatExpression::Number(FieldPrime::from(1)),
+                ),
+                FlatStatement::Return(FlatExpressionList {
+                    expressions: vec![FlatExpression::Identifier(FlatVariable::new(1))],
+                }),
+            ],
+        };
+
+        let flattened = flattener
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        assert_eq!(flattened, expected);
+    }
+
+    #[test]
+    fn power_one() {
+        // def main():
+        //     field a = 7
+        //     field b = a**1
+        //     return b
+
+        // def main():
+        //     _0 = 7
+        //     _1 = 1 * _0     // x**1
+        //     _2 = _1         // power flattening returns _1, definition introduces _2
+        //     return _2
+        let function = TypedFunction {
+            arguments: vec![],
+            statements: vec![
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element("a".into())),
+                    FieldElementExpression::Number(FieldPrime::from(7)).into(),
+                ),
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element("b".into())),
+                    FieldElementExpression::Pow(
+                        box FieldElementExpression::Identifier("a".into()),
+                        box FieldElementExpression::Number(FieldPrime::from(1)),
+                    )
+                    .into(),
+                ),
+                TypedStatement::Return(vec![FieldElementExpression::Identifier("b".into()).into()]),
+            ],
+            signature: Signature {
+                inputs: vec![],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let mut flattener = Flattener::new();
+
+        let expected = FlatFunction {
+            arguments: vec![],
+            statements: vec![
+                FlatStatement::Definition(
+                    FlatVariable::new(0),
+                    FlatExpression::Number(FieldPrime::from(7)),
+                ),
+                FlatStatement::Definition(
+                    FlatVariable::new(1),
+                    FlatExpression::Mult(
+                        box FlatExpression::Number(FieldPrime::from(1)),
+                        box FlatExpression::Identifier(FlatVariable::new(0)),
+                    ),
+                ),
+                FlatStatement::Definition(
+                    FlatVariable::new(2),
+                    FlatExpression::Identifier(FlatVariable::new(1)),
+                ),
+                FlatStatement::Return(FlatExpressionList {
+                    expressions: vec![FlatExpression::Identifier(FlatVariable::new(2))],
+                }),
+            ],
+        };
+
+        let flattened = flattener
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        assert_eq!(flattened, expected);
+    }
+
+    #[test]
+    fn power_13() {
+        // def main():
+        //     field a = 7
+        //     field b = a**13
+        //     return b
+
+        // we apply double and add
+        // 13 == 0b1101
+        // a ** 13 == a**(2**0 + 2**2 + 2**3) == a**1 * a**4 * a**8
+
+        // a_0 = a * a      // a**2
+        // a_1 = a_0 * a_0  // a**4
+        // a_2 = a_1 * a_1  // a**8
+
+        // a_3 = a * a_1    // a * a**4 == a**5
+        // a_4 = a_3 * a_2  // a**5 * a**8 == a**13
+
+        // def main():
+        //     _0 = 7
+        //     _1 = (_0 * _0)  // a**2
+        //     _2 = (_1 * _1)  // a**4
+        //     _3 = (_2 * _2)  // a**8
+        //
+        //     _4 = 1 * _0     // a
+        //     _5 = _4 * _2    // a**5
+        //     _6 = _5 * _3    // a**13
+        //     _7 = _6         // power flattening returns _6, definition introduces _7
+        //     return _7
+
+        let function = TypedFunction {
+            arguments: vec![],
+            statements: vec![
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element("a".into())),
+                    FieldElementExpression::Number(FieldPrime::from(7)).into(),
+                ),
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element("b".into())),
+                    FieldElementExpression::Pow(
+                        box FieldElementExpression::Identifier("a".into()),
+                        box FieldElementExpression::Number(FieldPrime::from(13)),
+                    )
+                    .into(),
+                ),
+                TypedStatement::Return(vec![FieldElementExpression::Identifier("b".into()).into()]),
+            ],
+            signature: Signature {
+                inputs: vec![],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let mut flattener = Flattener::new();
+
+        let expected = FlatFunction {
+            arguments: vec![],
+            statements: vec![
+                FlatStatement::Definition(
+                    FlatVariable::new(0),
+                    FlatExpression::Number(FieldPrime::from(7)),
+                ),
+                FlatStatement::Definition(
+                    FlatVariable::new(1),
+                    FlatExpression::Mult(
+                        box FlatExpression::Identifier(FlatVariable::new(0)),
+                        box FlatExpression::Identifier(FlatVariable::new(0)),
+                    ),
+                ),
+                FlatStatement::Definition(
+                    FlatVariable::new(2),
+                    FlatExpression::Mult(
+                        box FlatExpression::Identifier(FlatVariable::new(1)),
+                        box FlatExpression::Identifier(FlatVariable::new(1)),
+                    ),
+                ),
+                FlatStatement::Definition(
+                    FlatVariable::new(3),
+                    FlatExpression::Mult(
+                        box FlatExpression::Identifier(FlatVariable::new(2)),
+                        box FlatExpression::Identifier(FlatVariable::new(2)),
+                    ),
+                ),
+                FlatStatement::Definition(
+                    FlatVariable::new(4),
+                    FlatExpression::Mult(
+                        box FlatExpression::Number(FieldPrime::from(1)),
+                        box FlatExpression::Identifier(FlatVariable::new(0)),
+                    ),
+                ),
+                FlatStatement::Definition(
+                    FlatVariable::new(5),
+                    FlatExpression::Mult(
+                        box FlatExpression::Identifier(FlatVariable::new(4)),
+                        box FlatExpression::Identifier(FlatVariable::new(2)),
+                    ),
+                ),
+                FlatStatement::Definition(
+                    FlatVariable::new(6),
+                    FlatExpression::Mult(
+                        box FlatExpression::Identifier(FlatVariable::new(5)),
+                        box FlatExpression::Identifier(FlatVariable::new(3)),
+                    ),
+                ),
+                FlatStatement::Definition(
+                    FlatVariable::new(7),
+                    FlatExpression::Identifier(FlatVariable::new(6)),
+                ),
+                FlatStatement::Return(FlatExpressionList {
+                    expressions: vec![FlatExpression::Identifier(FlatVariable::new(7))],
+                }),
+            ],
+        };
+
+        let flattened = flattener
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        assert_eq!(flattened, expected);
+    }
+
+    #[test]
+    fn if_else() {
+        let expression = FieldElementExpression::IfElse(
+            box BooleanExpression::FieldEq(
+                box FieldElementExpression::Number(FieldPrime::from(32)),
+                box FieldElementExpression::Number(FieldPrime::from(4)),
+            ),
+            box FieldElementExpression::Number(FieldPrime::from(12)),
+            box FieldElementExpression::Number(FieldPrime::from(51)),
+        );
+
+        let mut flattener = Flattener::new();
+
+        flattener.flatten_field_expression(&HashMap::new(), &mut vec![], expression);
+    }
+
+    #[test]
+    fn geq_leq() {
+        let mut flattener = Flattener::new();
+        let expression_le = BooleanExpression::Le(
+            box FieldElementExpression::Number(FieldPrime::from(32)),
+            box FieldElementExpression::Number(FieldPrime::from(4)),
+        );
+        flattener.flatten_boolean_expression(&HashMap::new(), &mut vec![], expression_le);
+
+        let mut flattener = Flattener::new();
+        let expression_ge = BooleanExpression::Ge(
+            box FieldElementExpression::Number(FieldPrime::from(32)),
+            box FieldElementExpression::Number(FieldPrime::from(4)),
+        );
+        flattener.flatten_boolean_expression(&HashMap::new(), &mut vec![], expression_ge);
+    }
+
+    #[test]
+    fn bool_and() {
+        let mut flattener = Flattener::new();
+
+        let expression = FieldElementExpression::IfElse(
+            box BooleanExpression::And(
+                box BooleanExpression::FieldEq(
+                    box FieldElementExpression::Number(FieldPrime::from(4)),
+                    box FieldElementExpression::Number(FieldPrime::from(4)),
+                ),
+                box BooleanExpression::Lt(
+                    box FieldElementExpression::Number(FieldPrime::from(4)),
+                    box FieldElementExpression::Number(FieldPrime::from(20)),
+                ),
+            ),
+            box FieldElementExpression::Number(FieldPrime::from(12)),
+            box FieldElementExpression::Number(FieldPrime::from(51)),
+        );
+
+        flattener.flatten_field_expression(&HashMap::new(), &mut vec![], expression);
+    }
+
+    #[test]
+    fn div() {
+        // a = 5 / b / b
+
+        let mut flattener = Flattener::new();
+        let mut statements_flattened = vec![];
+
+        let definition = TypedStatement::Definition(
+            TypedAssignee::Identifier(Variable::field_element("b".into())),
+            FieldElementExpression::Number(FieldPrime::from(42)).into(),
+        );
+
+        let statement = TypedStatement::Definition(
+            TypedAssignee::Identifier(Variable::field_element("a".into())),
+            FieldElementExpression::Div(
+                box FieldElementExpression::Div(
+                    box FieldElementExpression::Number(FieldPrime::from(5)),
+                    box FieldElementExpression::Identifier("b".into()),
+                ),
+                box FieldElementExpression::Identifier("b".into()),
+            )
+            .into(),
+        );
+
+        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, definition);
+
+        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, statement);
+
+        // define b
+        let b = FlatVariable::new(0);
+        // define new wires for members of Div
+        let five = FlatVariable::new(1);
+        let b0 = FlatVariable::new(2);
+        // Define inverse of denominator to prevent div by 0
+        let invb0 = FlatVariable::new(3);
+        // Define inverse
+        let sym_0 = FlatVariable::new(4);
+        // Define result, which is first member to next Div
+        let sym_1 = FlatVariable::new(5);
+        // Define second member
+        let b1 = FlatVariable::new(6);
+        // Define inverse of denominator to prevent div by 0
+        let invb1 = FlatVariable::new(7);
+        // Define inverse
+        let sym_2 = FlatVariable::new(8);
+        // Define left hand side
+        let a = FlatVariable::new(9);
+
+        assert_eq!(
+            statements_flattened,
+            vec![
+                FlatStatement::Definition(b, FlatExpression::Number(FieldPrime::from(42))),
+                // inputs to first div (5/b)
+                FlatStatement::Definition(five, FlatExpression::Number(FieldPrime::from(5))),
+                FlatStatement::Definition(b0, b.into()),
+                // check div by 0
+                FlatStatement::Directive(FlatDirective::new(
+                    vec![invb0],
+                    Solver::Div,
+                    vec![FlatExpression::Number(FieldPrime::from(1)), b0.into()]
+                )),
+                FlatStatement::Condition(
+                    FlatExpression::Number(FieldPrime::from(1)),
+                    FlatExpression::Mult(box invb0.into(), box b0.into()),
+                ),
+                // execute div
+                FlatStatement::Directive(FlatDirective::new(
+                    vec![sym_0],
+                    Solver::Div,
+                    vec![five, b0]
+                )),
+                FlatStatement::Condition(
+                    five.into(),
+                    FlatExpression::Mult(box b0.into(), box sym_0.into()),
+                ),
+                // inputs to second div (res/b)
+                FlatStatement::Definition(sym_1, sym_0.into()),
+                FlatStatement::Definition(b1, b.into()),
+                // check div by 0
+                FlatStatement::Directive(FlatDirective::new(
+                    vec![invb1],
+                    Solver::Div,
+                    vec![FlatExpression::Number(FieldPrime::from(1)), b1.into()]
+                )),
+                FlatStatement::Condition(
+                    FlatExpression::Number(FieldPrime::from(1)),
+                    FlatExpression::Mult(box invb1.into(), box b1.into()),
+                ),
+                // execute div
+                FlatStatement::Directive(FlatDirective::new(
+                    vec![sym_2],
+                    Solver::Div,
+                    vec![sym_1, b1]
+                )),
+                FlatStatement::Condition(
+                    sym_1.into(),
+                    FlatExpression::Mult(box b1.into(), box sym_2.into()),
+                ),
+                // result
+                FlatStatement::Definition(a, sym_2.into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn field_array() {
+        // foo = [ , , ]
+
+        let mut flattener = Flattener::new();
+        let mut statements_flattened = vec![];
+        let statement = TypedStatement::Definition(
+            TypedAssignee::Identifier(Variable::field_array("foo".into(), 3)),
+            ArrayExpressionInner::Value(vec![
+                FieldElementExpression::Number(FieldPrime::from(1)).into(),
+                FieldElementExpression::Number(FieldPrime::from(2)).into(),
+                FieldElementExpression::Number(FieldPrime::from(3)).into(),
+            ])
+            .annotate(Type::FieldElement, 3)
+            .into(),
+        );
+        let expression =
+            ArrayExpressionInner::Identifier("foo".into()).annotate(Type::FieldElement, 3);
+
+        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, statement);
+
+        let expressions = flattener.flatten_array_expression::<FieldElementExpression<_>>(
+            &HashMap::new(),
+            &mut statements_flattened,
+            expression,
+        );
+
+        assert_eq!(
+            expressions,
+            vec![
+                FlatExpression::Identifier(FlatVariable::new(0)),
+                FlatExpression::Identifier(FlatVariable::new(1)),
+                FlatExpression::Identifier(FlatVariable::new(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn array_definition() {
+        // field[3] foo = [1, 2, 3]
+        let mut flattener = Flattener::new();
+        let mut statements_flattened = vec![];
+        let statement = TypedStatement::Definition(
+            TypedAssignee::Identifier(Variable::field_array("foo".into(), 3)),
+            ArrayExpressionInner::Value(vec![
+                FieldElementExpression::Number(FieldPrime::from(1)).into(),
+                FieldElementExpression::Number(FieldPrime::from(2)).into(),
+                FieldElementExpression::Number(FieldPrime::from(3)).into(),
+            ])
+            .annotate(Type::FieldElement, 3)
+            .into(),
+        );
+
+        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, statement);
+
+        assert_eq!(
+            statements_flattened,
+            vec![
+                FlatStatement::Definition(
+                    FlatVariable::new(0),
+                    FlatExpression::Number(FieldPrime::from(1))
+                ),
+                FlatStatement::Definition(
+                    FlatVariable::new(1),
+                    FlatExpression::Number(FieldPrime::from(2))
+                ),
+                FlatStatement::Definition(
+                    FlatVariable::new(2),
+                    FlatExpression::Number(FieldPrime::from(3))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn array_selection() {
+        // field[3] foo = [1, 2, 3]
+        // foo[1]
+
+        let mut flattener = Flattener::new();
+        let mut statements_flattened = vec![];
+        let statement = TypedStatement::Definition(
+            TypedAssignee::Identifier(Variable::field_array("foo".into(), 3)),
+            ArrayExpressionInner::Value(vec![
+                FieldElementExpression::Number(FieldPrime::from(1)).into(),
+                FieldElementExpression::Number(FieldPrime::from(2)).into(),
+                FieldElementExpression::Number(FieldPrime::from(3)).into(),
+            ])
+            .annotate(Type::FieldElement, 3)
+            .into(),
+        );
+
+        let expression = FieldElementExpression::Select(
+            box ArrayExpressionInner::Identifier("foo".into()).annotate(Type::FieldElement, 3),
+            box FieldElementExpression::Number(FieldPrime::from(1)),
+        );
+
+        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, statement);
+
+        let flat_expression = flattener.flatten_field_expression(
+            &HashMap::new(),
+            &mut statements_flattened,
+            expression,
+        );
+
+        assert_eq!(
+            flat_expression,
+            FlatExpression::Identifier(FlatVariable::new(1)),
+        );
+    }
+
+    #[test]
+    fn array_sum() {
+        // field[3] foo = [1, 2, 3]
+        // bar = foo[0] + foo[1] + foo[2]
+        // we don't optimise detecting constants, this will be done in an optimiser pass
+
+        let mut flattener = Flattener::new();
+
+        let mut statements_flattened = vec![];
+        let def = TypedStatement::Definition(
+            TypedAssignee::Identifier(Variable::field_array("foo".into(), 3)),
+            ArrayExpressionInner::Value(vec![
+                FieldElementExpression::Number(FieldPrime::from(1)).into(),
+                FieldElementExpression::Number(FieldPrime::from(2)).into(),
+                FieldElementExpression::Number(FieldPrime::from(3)).into(),
+            ])
+            .annotate(Type::FieldElement, 3)
+            .into(),
+        );
+
+        let sum = TypedStatement::Definition(
+            TypedAssignee::Identifier(Variable::field_element("bar".into())),
+            FieldElementExpression::Add(
+                box FieldElementExpression::Add(
+                    box FieldElementExpression::Select(
+                        box ArrayExpressionInner::Identifier("foo".into())
+                            .annotate(Type::FieldElement, 3),
+                        box FieldElementExpression::Number(FieldPrime::from(0)),
+                    ),
+                    box FieldElementExpression::Select(
+                        box ArrayExpressionInner::Identifier("foo".into())
+                            .annotate(Type::FieldElement, 3),
+                        box FieldElementExpression::Number(FieldPrime::from(1)),
+                    ),
+                ),
+                box FieldElementExpression::Select(
+                    box ArrayExpressionInner::Identifier("foo".into())
+                        .annotate(Type::FieldElement, 3),
+                    box FieldElementExpression::Number(FieldPrime::from(2)),
+                ),
+            )
+            .into(),
+        );
+
+        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, def);
+
+        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, sum);
+
+        assert_eq!(
+            statements_flattened[3],
+            FlatStatement::Definition(
+                FlatVariable::new(3),
+                FlatExpression::Add(
+                    box FlatExpression::Add(
+                        box FlatExpression::Identifier(FlatVariable::new(0)),
+                        box FlatExpression::Identifier(FlatVariable::new(1)),
+                    ),
+                    box FlatExpression::Identifier(FlatVariable::new(2)),
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn array_of_array_sum() {
+        // field[2][2] foo = [[1, 2], [3, 4]]
+        // bar = foo[0][0] + foo[0][1] + foo[1][0] + foo[1][1]
+        // we don't optimise detecting constants, this will be done in an optimiser pass
+
+        let mut flattener = Flattener::new();
+
+        let mut statements_flattened = vec![];
+        let def = TypedStatement::Definition(
+            TypedAssignee::Identifier(Variable::field_array("foo".into(), 4)),
+            ArrayExpressionInner::Value(vec![
+                ArrayExpressionInner::Value(vec![
+                    FieldElementExpression::Number(FieldPrime::from(1)).into(),
+                    FieldElementExpression::Number(FieldPrime::from(2)).into(),
+                ])
+                .annotate(Type::FieldElement, 2)
+                .into(),
+                ArrayExpressionInner::Value(vec![
+                    FieldElementExpression::Number(FieldPrime::from(3)).into(),
+                    FieldElementExpression::Number(FieldPrime::from(4)).into(),
+                ])
+                .annotate(Type::FieldElement, 2)
+                .into(),
+            ])
+            .annotate(Type::array(Type::FieldElement, 2), 2)
+            .into(),
+        );
+
+        let sum = TypedStatement::Definition(
+            TypedAssignee::Identifier(Variable::field_element("bar".into())),
+            FieldElementExpression::Add(
+                box FieldElementExpression::Add(
+                    box FieldElementExpression::Add(
+                        box FieldElementExpression::Select(
+                            box ArrayExpressionInner::Select(
+                                box ArrayExpressionInner::Identifier("foo".into())
+                                    .annotate(Type::array(Type::FieldElement, 2), 2),
+                                box FieldElementExpression::Number(FieldPrime::from(0)),
+                            )
+                            .annotate(Type::FieldElement, 2),
+                            box FieldElementExpression::Number(FieldPrime::from(0)),
+                        ),
+                        box FieldElementExpression::Select(
+                            box ArrayExpressionInner::Select(
+                                box ArrayExpressionInner::Identifier("foo".into())
+                                    .annotate(Type::array(Type::FieldElement, 2), 2),
+                                box FieldElementExpression::Number(FieldPrime::from(0)),
+                            )
+                            .annotate(Type::FieldElement, 2),
+                            box FieldElementExpression::Number(FieldPrime::from(1)),
+                        ),
+                    ),
+                    box FieldElementExpression::Select(
+                        box ArrayExpressionInner::Select(
+                            box ArrayExpressionInner::Identifier("foo".into())
+                                .annotate(Type::array(Type::FieldElement, 2), 2),
+                            box FieldElementExpression::Number(FieldPrime::from(1)),
+                        )
+                        .annotate(Type::FieldElement, 2),
+                        box FieldElementExpression::Number(FieldPrime::from(0)),
+                    ),
+                ),
+                box FieldElementExpression::Select(
+                    box ArrayExpressionInner::Select(
+                        box ArrayExpressionInner::Identifier("foo".into())
+                            .annotate(Type::array(Type::FieldElement, 2), 2),
+                        box FieldElementExpression::Number(FieldPrime::from(1)),
+                    )
+                    .annotate(Type::FieldElement, 2),
+                    box FieldElementExpression::Number(FieldPrime::from(1)),
+                ),
+            )
+            .into(),
+        );
+
+        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, def);
+
+        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, sum);
+
+        assert_eq!(
+            statements_flattened[4],
+            FlatStatement::Definition(
+                FlatVariable::new(4),
+                FlatExpression::Add(
+                    box FlatExpression::Add(
+                        box FlatExpression::Add(
+                            box FlatExpression::Identifier(FlatVariable::new(0)),
+                            box FlatExpression::Identifier(FlatVariable::new(1)),
+                        ),
+                        box FlatExpression::Identifier(FlatVariable::new(2))
+                    ),
+                    box FlatExpression::Identifier(FlatVariable::new(3)),
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn array_if() {
+        // if 1 == 1 then [1] else [3] fi
+        let with_arrays = {
+            let mut flattener = Flattener::new();
+            let mut statements_flattened = vec![];
+
+            let e = ArrayExpressionInner::IfElse(
+                box BooleanExpression::FieldEq(
+                    box FieldElementExpression::Number(FieldPrime::from(1)),
+                    box FieldElementExpression::Number(FieldPrime::from(1)),
+                ),
+                box ArrayExpressionInner::Value(vec![FieldElementExpression::Number(
+                    FieldPrime::from(1),
+                )
+                .into()])
+                .annotate(Type::FieldElement, 1),
+                box ArrayExpressionInner::Value(vec![FieldElementExpression::Number(
+                    FieldPrime::from(3),
+                )
+                .into()])
+                .annotate(Type::FieldElement, 2),
+            )
+            .annotate(Type::FieldElement, 1);
+
+            (
+                flattener.flatten_array_expression::<FieldElementExpression<_>>(
+                    &HashMap::new(),
+                    &mut statements_flattened,
+                    e,
+                )[0]
+                .clone(),
+                statements_flattened,
+            )
+        };
+
+        let without_arrays = {
+            let mut statements_flattened = vec![];
+            let mut flattener = Flattener::new();
+            // if 1 == 1 then 1 else 3 fi
+            let e = FieldElementExpression::IfElse(
+                box BooleanExpression::FieldEq(
+                    box FieldElementExpression::Number(FieldPrime::from(1)),
+                    box FieldElementExpression::Number(FieldPrime::from(1)),
+                ),
+                box FieldElementExpression::Number(FieldPrime::from(1)),
+                box FieldElementExpression::Number(FieldPrime::from(3)),
+            );
+
+            (
+                flattener.flatten_field_expression(&HashMap::new(), &mut statements_flattened, e),
+                statements_flattened,
+            )
+        };
+
+        assert_eq!(with_arrays, without_arrays);
+    }
+
+    #[test]
+    fn next_variable() {
+        let mut flattener: Flattener<FieldPrime> = Flattener::new();
+        assert_eq!(
+            vec![FlatVariable::new(0)],
+            flattener.use_variable(&Variable::field_element("a".into()))
+        );
+        assert_eq!(
+            flattener.layout.get(&"a".into()),
+            Some(&vec![FlatVariable::new(0)])
+        );
+        assert_eq!(
+            vec![FlatVariable::new(1)],
+            flattener.use_variable(&Variable::field_element("a".into()))
+        );
+        assert_eq!(
+            flattener.layout.get(&"a".into()),
+            Some(&vec![FlatVariable::new(1)])
+        );
+        assert_eq!(
+            vec![FlatVariable::new(2)],
+            flattener.use_variable(&Variable::field_element("a".into()))
+        );
+        assert_eq!(
+            flattener.layout.get(&"a".into()),
+            Some(&vec![FlatVariable::new(2)])
+        );
+    }
+
+    #[test]
+    fn array_argument_selection() {
+        // def main(private field[3] foo) -> (field):
+        //     return foo[2]
+
+        // def main(private _0, private _1, private _2):
+        //     return _2
+        let function = TypedFunction {
+            arguments: vec![Parameter::private(Variable::field_array("foo".into(), 3))],
+            statements: vec![TypedStatement::Return(vec![FieldElementExpression::Select(
+                box ArrayExpressionInner::Identifier("foo".into()).annotate(Type::FieldElement, 3),
+                box FieldElementExpression::Number(FieldPrime::from(2)),
+            )
+            .into()])],
+            signature: Signature {
+                inputs: vec![Type::array(Type::FieldElement, 3)],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let mut flattener = Flattener::new();
+
+        let expected = FlatFunction {
+            arguments: vec![
+                FlatParameter::private(FlatVariable::new(0)),
+                FlatParameter::private(FlatVariable::new(1)),
+                FlatParameter::private(FlatVariable::new(2)),
+            ],
+            statements: vec![FlatStatement::Return(FlatExpressionList {
+                expressions: vec![FlatExpression::Identifier(FlatVariable::new(2))],
+            })],
+        };
+
+        let flattened = flattener
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        assert_eq!(flattened, expected);
+    }
+
+    fn check_bitwise(
+        op: fn(
+            Box<FieldElementExpression<'static, FieldPrime>>,
+            Box<FieldElementExpression<'static, FieldPrime>>,
+        ) -> FieldElementExpression<'static, FieldPrime>,
+        reference: fn(i32, i32) -> i32,
+    ) {
+        // def main(private field a, private field b) -> (field):
+        //     return a op b
+
+        let function = TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+            ],
+            statements: vec![TypedStatement::Return(vec![op(
+                box FieldElementExpression::Identifier("a".into()),
+                box FieldElementExpression::Identifier("b".into()),
+            )
+            .into()])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement, Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let mut flattener = Flattener::with_bits(8);
+        let flat_function = flattener
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        let prog = ir::Prog {
+            main: ir::Function::from(flat_function),
+            private: vec![true, true],
+        };
+
+        let interpreter = Interpreter::default();
+
+        for (a, b) in vec![(0b1100, 0b1010), (255, 42), (0, 7), (0, 0)] {
+            let witness = interpreter
+                .execute(&prog, &vec![FieldPrime::from(a), FieldPrime::from(b)])
+                .unwrap();
+            assert_eq!(
+                witness.return_values(),
+                vec![FieldPrime::from(reference(a, b))]
+            );
+        }
+    }
+
+    #[test]
+    fn bitwise_and() {
+        check_bitwise(FieldElementExpression::BitAnd, |a, b| a & b);
+    }
+
+    #[test]
+    fn bitwise_or() {
+        check_bitwise(FieldElementExpression::BitOr, |a, b| a | b);
+    }
+
+    #[test]
+    fn bitwise_xor() {
+        check_bitwise(FieldElementExpression::BitXor, |a, b| a ^ b);
+    }
+
+    fn shift_prog(
+        shift: fn(
+            Box<FieldElementExpression<'static, FieldPrime>>,
+            usize,
+        ) -> FieldElementExpression<'static, FieldPrime>,
+        by: usize,
+    ) -> ir::Prog<FieldPrime> {
+        // def main(private field a) -> (field):
+        //     return a shift by
+
+        let function = TypedFunction {
+            arguments: vec![Parameter::private(Variable::field_element("a".into()))],
+            statements: vec![TypedStatement::Return(vec![shift(
+                box FieldElementExpression::Identifier("a".into()),
+                by,
+            )
+            .into()])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let mut flattener = Flattener::with_bits(8);
+        let flat_function = flattener
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        ir::Prog {
+            main: ir::Function::from(flat_function),
+            private: vec![true],
+        }
+    }
+
+    #[test]
+    fn shift_left() {
+        let prog = shift_prog(FieldElementExpression::Shl, 2);
+        let interpreter = Interpreter::default();
+
+        for a in vec![0, 3, 63] {
+            let witness = interpreter
+                .execute(&prog, &vec![FieldPrime::from(a)])
+                .unwrap();
+            assert_eq!(witness.return_values(), vec![FieldPrime::from(a << 2)]);
+        }
+
+        // 64 << 2 does not fit in 8 bits
+        assert!(interpreter
+            .execute(&prog, &vec![FieldPrime::from(64)])
+            .is_err());
+    }
+
+    #[test]
+    fn shift_right() {
+        let prog = shift_prog(FieldElementExpression::Shr, 3);
+        let interpreter = Interpreter::default();
+
+        for a in vec![0, 7, 200, 255] {
+            let witness = interpreter
+                .execute(&prog, &vec![FieldPrime::from(a)])
+                .unwrap();
+            assert_eq!(witness.return_values(), vec![FieldPrime::from(a >> 3)]);
+        }
+    }
+
+    /// Returns a program whose `main` function takes `arguments` and runs `statements`
+    fn main_program<'ast>(
+        arguments: Vec<Parameter<'ast>>,
+        statements: Vec<TypedStatement<'ast, FieldPrime>>,
+        signature: Signature,
+    ) -> TypedProgram<'ast, FieldPrime> {
+        let main = TypedFunction {
+            arguments,
+            statements,
+            signature: signature.clone(),
+        };
+
+        TypedProgram {
+            main: "main".into(),
+            modules: vec![(
+                "main".into(),
+                TypedModule {
+                    functions: vec![(
+                        FunctionKey::with_id("main").signature(signature),
+                        TypedFunctionSymbol::Here(main),
+                    )]
+                    .into_iter()
+                    .collect(),
+                },
+            )]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    #[test]
+    fn bit_width_too_large() {
+        // def main() -> (field):
+        //     return 1
+
+        fn program() -> TypedProgram<'static, FieldPrime> {
+            main_program(
+                vec![],
+                vec![TypedStatement::Return(vec![
+                    FieldElementExpression::Number(FieldPrime::from(1)).into(),
+                ])],
+                Signature::new().outputs(vec![Type::FieldElement]),
+            )
+        }
+
+        let field_max = FieldPrime::get_required_bits() - 1;
+
+        assert!(Flattener::with_bits(field_max)
+            .flatten_program(program())
+            .is_ok());
+
+        assert_eq!(
+            Flattener::with_bits(field_max + 1).flatten_program(program()),
+            Err(FlattenError::BitWidthTooLarge {
+                requested: field_max + 1,
+                field_max
+            })
+        );
+
+        assert_eq!(
+            Flattener::with_bits(0).flatten_program(program()),
+            Err(FlattenError::BitWidthZero)
+        );
+    }
+
+    #[test]
+    fn comparison_width_too_large() {
+        // def main(private field a, private field b) -> (field):
+        //     return if a < b then 1 else 0 fi, comparing on `width` bits
+        let program = |width: usize| {
+            main_program(
+                vec![
+                    Parameter::private(Variable::field_element("a".into())),
+                    Parameter::private(Variable::field_element("b".into())),
+                ],
+                vec![TypedStatement::Return(vec![FieldElementExpression::IfElse(
+                    box BooleanExpression::LtWidth(
+                        box FieldElementExpression::Identifier("a".into()),
+                        box FieldElementExpression::Identifier("b".into()),
+                        width,
+                    ),
+                    box FieldElementExpression::Number(FieldPrime::from(1)),
+                    box FieldElementExpression::Number(FieldPrime::from(0)),
+                )
+                .into()])],
+                Signature::new()
+                    .inputs(vec![Type::FieldElement, Type::FieldElement])
+                    .outputs(vec![Type::FieldElement]),
+            )
+        };
+
+        let field_max = FieldPrime::get_required_bits() - 2;
+
+        assert!(Flattener::new().flatten_program(program(field_max)).is_ok());
+
+        assert_eq!(
+            Flattener::new().flatten_program(program(field_max + 1)),
+            Err(FlattenError::BitWidthTooLarge {
+                requested: field_max + 1,
+                field_max
+            })
+        );
+    }
+
+    fn flatten_if_else_on(
+        condition: BooleanExpression<'static, FieldPrime>,
+    ) -> FlatFunction<FieldPrime> {
+        // def main(private field a, private field b) -> (field):
+        //     return if condition then a else b fi
+
+        let function = TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+            ],
+            statements: vec![TypedStatement::Return(vec![FieldElementExpression::IfElse(
+                box condition,
+                box FieldElementExpression::Identifier("a".into()),
+                box FieldElementExpression::Identifier("b".into()),
+            )
+            .into()])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement, Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let mut flattener = Flattener::new();
+        flattener
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap()
+    }
+
+    #[test]
+    fn if_else_statically_true() {
+        // 1 < 2
+        let flattened = flatten_if_else_on(BooleanExpression::Lt(
+            box FieldElementExpression::Number(FieldPrime::from(1)),
+            box FieldElementExpression::Number(FieldPrime::from(2)),
+        ));
+
+        // def main(private _0, private _1):
+        //     return _0
+        assert_eq!(
+            flattened.statements,
+            vec![FlatStatement::Return(FlatExpressionList {
+                expressions: vec![FlatExpression::Identifier(FlatVariable::new(0))],
+            })]
+        );
+
+        // a == a
+        let flattened = flatten_if_else_on(BooleanExpression::FieldEq(
+            box FieldElementExpression::Identifier("a".into()),
+            box FieldElementExpression::Identifier("a".into()),
+        ));
+
+        assert_eq!(
+            flattened.statements,
+            vec![FlatStatement::Return(FlatExpressionList {
+                expressions: vec![FlatExpression::Identifier(FlatVariable::new(0))],
+            })]
+        );
+    }
+
+    #[test]
+    fn if_else_statically_false() {
+        // a != a
+        let flattened = flatten_if_else_on(BooleanExpression::Not(box BooleanExpression::FieldEq(
+            box FieldElementExpression::Identifier("a".into()),
+            box FieldElementExpression::Identifier("a".into()),
+        )));
+
+        // def main(private _0, private _1):
+        //     return _1
+        assert_eq!(
+            flattened.statements,
+            vec![FlatStatement::Return(FlatExpressionList {
+                expressions: vec![FlatExpression::Identifier(FlatVariable::new(1))],
+            })]
+        );
+
+        // 3 > 7
+        let flattened = flatten_if_else_on(BooleanExpression::Gt(
+            box FieldElementExpression::Number(FieldPrime::from(3)),
+            box FieldElementExpression::Number(FieldPrime::from(7)),
+        ));
+
+        assert_eq!(
+            flattened.statements,
+            vec![FlatStatement::Return(FlatExpressionList {
+                expressions: vec![FlatExpression::Identifier(FlatVariable::new(1))],
+            })]
+        );
+    }
+
+    #[test]
+    fn if_else_not_constant() {
+        // a < b is not known at compile time, so the gadget is generated
+        let flattened = flatten_if_else_on(BooleanExpression::Lt(
+            box FieldElementExpression::Identifier("a".into()),
+            box FieldElementExpression::Identifier("b".into()),
+        ));
+
+        assert!(flattened.statements.len() > 1);
+    }
+
+    #[test]
+    fn if_else_out_of_range() {
+        // the gadget fails on these operands, so they are not evaluated at compile time
+        let conditions = vec![
+            // -1 < 0
+            BooleanExpression::Lt(
+                box FieldElementExpression::Number(FieldPrime::from(-1)),
+                box FieldElementExpression::Number(FieldPrime::from(0)),
+            ),
+            // a < a
+            BooleanExpression::Lt(
+                box FieldElementExpression::Identifier("a".into()),
+                box FieldElementExpression::Identifier("a".into()),
+            ),
+            // a >= a
+            BooleanExpression::Ge(
+                box FieldElementExpression::Identifier("a".into()),
+                box FieldElementExpression::Identifier("a".into()),
+            ),
+        ];
+
+        for condition in conditions {
+            assert!(flatten_if_else_on(condition).statements.len() > 1);
+        }
+
+        // with checked comparisons on 8 bits, 300 < 1 fails too
+        let condition = BooleanExpression::Lt(
+            box FieldElementExpression::Number(FieldPrime::from(300)),
+            box FieldElementExpression::Number(FieldPrime::from(1)),
+        );
+        let statements = vec![TypedStatement::Return(vec![FieldElementExpression::IfElse(
+            box condition.clone(),
+            box FieldElementExpression::Identifier("a".into()),
+            box FieldElementExpression::Identifier("b".into()),
+        )
+        .into()])];
+
+        let flattened = Flattener::with_bits(8)
+            .checked_comparisons(true)
+            .flatten_function(
+                &mut HashMap::new(),
+                TypedFunction {
+                    arguments: vec![
+                        Parameter::private(Variable::field_element("a".into())),
+                        Parameter::private(Variable::field_element("b".into())),
+                    ],
+                    statements,
+                    signature: Signature::new()
+                        .inputs(vec![Type::FieldElement, Type::FieldElement])
+                        .outputs(vec![Type::FieldElement]),
+                },
+            )
+            .unwrap();
+
+        assert!(flattened.statements.len() > 1);
+
+        // without the check, both fit in the operands of the gadget
+        assert_eq!(flatten_if_else_on(condition).statements.len(), 1);
+    }
+
+    #[test]
+    fn observe_emitted_statements() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        // def main(private field a, private field b) -> (field):
+        //     field c = a * b
+        //     return c
+
+        let signature = Signature::new()
+            .inputs(vec![Type::FieldElement, Type::FieldElement])
+            .outputs(vec![Type::FieldElement]);
+
+        let program = main_program(
+            vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+            ],
+            vec![
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element("c".into())),
+                    FieldElementExpression::Mult(
+                        box FieldElementExpression::Identifier("a".into()),
+                        box FieldElementExpression::Identifier("b".into()),
+                    )
+                    .into(),
+                ),
+                TypedStatement::Return(vec![FieldElementExpression::Identifier("c".into()).into()]),
+            ],
+            signature,
+        );
+
+        let count = Rc::new(Cell::new(0));
+        let counter = count.clone();
+
+        let flattened = Flattener::flatten_with_observer(program, move |_| {
+            counter.set(counter.get() + 1)
+        })
+        .unwrap();
+
+        // _2 = _0 * _1
+        // return _2
+        assert_eq!(count.get(), 2);
+        assert_eq!(count.get(), flattened.main.statements.len());
+    }
+
+    #[test]
+    fn condition_both_sides_quadratic() {
+        // def main(private field a, private field b, private field c, private field d):
+        //     a * b == c * d
+        //     return
+
+        // def main(private _0, private _1, private _2, private _3):
+        //     _4 = _0 * _1
+        //     _4 == _2 * _3
+        //     return
+        let function = TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+                Parameter::private(Variable::field_element("c".into())),
+                Parameter::private(Variable::field_element("d".into())),
+            ],
+            statements: vec![
+                TypedStatement::Condition(
+                    FieldElementExpression::Mult(
+                        box FieldElementExpression::Identifier("a".into()),
+                        box FieldElementExpression::Identifier("b".into()),
+                    )
+                    .into(),
+                    FieldElementExpression::Mult(
+                        box FieldElementExpression::Identifier("c".into()),
+                        box FieldElementExpression::Identifier("d".into()),
+                    )
+                    .into(),
+                ),
+                TypedStatement::Return(vec![]),
+            ],
+            signature: Signature {
+                inputs: vec![Type::FieldElement; 4],
+                outputs: vec![],
+            },
+        };
+
+        let mut flattener = Flattener::new();
+
+        let expected = FlatFunction {
+            arguments: (0..4)
+                .map(|i| FlatParameter::private(FlatVariable::new(i)))
+                .collect(),
+            statements: vec![
+                FlatStatement::Definition(
+                    FlatVariable::new(4),
+                    FlatExpression::Mult(
+                        box FlatExpression::Identifier(FlatVariable::new(0)),
+                        box FlatExpression::Identifier(FlatVariable::new(1)),
+                    ),
+                ),
+                FlatStatement::Condition(
+                    FlatExpression::Identifier(FlatVariable::new(4)),
+                    FlatExpression::Mult(
+                        box FlatExpression::Identifier(FlatVariable::new(2)),
+                        box FlatExpression::Identifier(FlatVariable::new(3)),
+                    ),
+                ),
+                FlatStatement::Return(FlatExpressionList {
+                    expressions: vec![],
+                }),
+            ],
+        };
+
+        let flattened = flattener
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        assert_eq!(flattened, expected);
+    }
+
+    #[test]
+    fn pack_bits() {
+        // def main(private _0):
+        //     decompose _0 into 8 bits
+        //     return pack(bits[0..4]), pack(bits[4..8]), pack(bits[0..8])
+
+        let mut flattener: Flattener<FieldPrime> = Flattener::with_bits(8);
+        let mut statements_flattened = vec![];
+
+        let a = flattener.use_sym();
+        let bits = flattener.decompose_bits(&mut statements_flattened, a.into(), 8);
+
+        let mut packed = Flattener::pack_bits(&bits, 4);
+        packed.extend(Flattener::pack_bits(&bits, 8));
+
+        assert_eq!(packed.len(), 3);
+
+        statements_flattened.push(FlatStatement::Return(FlatExpressionList {
+            expressions: packed,
+        }));
+
+        let prog = ir::Prog {
+            main: ir::Function::from(FlatFunction {
+                arguments: vec![FlatParameter::private(a)],
+                statements: statements_flattened,
+            }),
+            private: vec![true],
+        };
+
+        let witness = Interpreter::default()
+            .execute(&prog, &vec![FieldPrime::from(0xa5)])
+            .unwrap();
+
+        assert_eq!(
+            witness.return_values(),
+            vec![
+                FieldPrime::from(0xa),
+                FieldPrime::from(0x5),
+                FieldPrime::from(0xa5)
+            ]
+        );
+    }
+
+    #[test]
+    fn conditional_assignment() {
+        // def main(private field a, private field b) -> (field):
+        //     field c = if a == b then a else b fi
+        //     return c
+
+        let condition = BooleanExpression::FieldEq(
+            box FieldElementExpression::Identifier("a".into()),
+            box FieldElementExpression::Identifier("b".into()),
+        );
+
+        let function = |definition| TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+            ],
+            statements: vec![
+                definition,
+                TypedStatement::Return(vec![FieldElementExpression::Identifier("c".into()).into()]),
+            ],
+            signature: Signature {
+                inputs: vec![Type::FieldElement, Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let cond_assign = TypedStatement::CondAssign(
+            Variable::field_element("c".into()),
+            condition.clone(),
+            FieldElementExpression::Identifier("a".into()),
+            FieldElementExpression::Identifier("b".into()),
+        );
+
+        let definition = TypedStatement::Definition(
+            TypedAssignee::Identifier(Variable::field_element("c".into())),
+            FieldElementExpression::IfElse(
+                box condition,
+                box FieldElementExpression::Identifier("a".into()),
+                box FieldElementExpression::Identifier("b".into()),
+            )
+            .into(),
+        );
+
+        assert_eq!(cond_assign.clone().desugar(), definition);
+
+        let flattened_cond_assign = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function(cond_assign))
+            .unwrap();
+        let flattened_definition = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function(definition))
+            .unwrap();
+
+        assert_eq!(flattened_cond_assign, flattened_definition);
+    }
+
+    #[test]
+    fn switch() {
+        // def main(private field s) -> (field):
+        //     return switch s { 1 => 10, 2 => 20, 3 => 30, _ => 42 }
+
+        let s = || box FieldElementExpression::Identifier("s".into());
+        let n = |v| FieldElementExpression::Number(FieldPrime::from(v));
+
+        let switch = FieldElementExpression::Switch(
+            s(),
+            vec![
+                (FieldPrime::from(1), n(10)),
+                (FieldPrime::from(2), n(20)),
+                (FieldPrime::from(3), n(30)),
+            ],
+            box n(42),
+        );
+
+        // if s == 1 then 10 else if s == 2 then 20 else if s == 3 then 30 else 42
+        let expected = FieldElementExpression::IfElse(
+            box BooleanExpression::FieldEq(s(), box n(1)),
+            box n(10),
+            box FieldElementExpression::IfElse(
+                box BooleanExpression::FieldEq(s(), box n(2)),
+                box n(20),
+                box FieldElementExpression::IfElse(
+                    box BooleanExpression::FieldEq(s(), box n(3)),
+                    box n(30),
+                    box n(42),
+                ),
+            ),
+        );
+
+        match switch.clone() {
+            FieldElementExpression::Switch(box selector, cases, box default) => assert_eq!(
+                FieldElementExpression::switch_to_if_else(selector, cases, default),
+                expected
+            ),
+            _ => unreachable!(),
+        }
+
+        let function = TypedFunction {
+            arguments: vec![Parameter::private(Variable::field_element("s".into()))],
+            statements: vec![TypedStatement::Return(vec![switch.into()])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let flat_function = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        let prog = ir::Prog {
+            main: ir::Function::from(flat_function),
+            private: vec![true],
+        };
+
+        let interpreter = Interpreter::default();
+
+        for (selector, result) in vec![(1, 10), (2, 20), (3, 30), (0, 42), (4, 42)] {
+            let witness = interpreter
+                .execute(&prog, &vec![FieldPrime::from(selector)])
+                .unwrap();
+            assert_eq!(witness.return_values(), vec![FieldPrime::from(result)]);
+        }
+    }
+
+    #[test]
+    fn is_zero() {
+        // x == 0 routed through IsZero
+        let mut flattener: Flattener<FieldPrime> = Flattener::new();
+        let x = flattener.use_variable(&Variable::field_element("x".into()))[0];
+        let mut is_zero_statements = vec![];
+        let is_zero = flattener.flatten_boolean_expression(
+            &HashMap::new(),
+            &mut is_zero_statements,
+            BooleanExpression::IsZero(box FieldElementExpression::Identifier("x".into())),
+        );
+
+        // x == 0 routed through Eq
+        let mut flattener: Flattener<FieldPrime> = Flattener::new();
+        flattener.use_variable(&Variable::field_element("x".into()));
+        let mut eq_statements = vec![];
+        let eq = flattener.flatten_boolean_expression(
+            &HashMap::new(),
+            &mut eq_statements,
+            BooleanExpression::FieldEq(
+                box FieldElementExpression::Identifier("x".into()),
+                box FieldElementExpression::Number(FieldPrime::from(0)),
+            ),
+        );
+
+        // both produce the same result with the same number of constraints
+        assert_eq!(is_zero, eq);
+        assert_eq!(is_zero_statements.len(), eq_statements.len());
+
+        // but IsZero checks `x` directly instead of `x - 0`
+        assert_eq!(
+            is_zero_statements[0],
+            FlatStatement::Directive(FlatDirective::new(
+                vec![FlatVariable::new(1), FlatVariable::new(2)],
+                Solver::ConditionEq,
+                vec![FlatExpression::Identifier(x)],
+            ))
+        );
+
+        // check the values
+        is_zero_statements.push(FlatStatement::Return(FlatExpressionList {
+            expressions: vec![is_zero],
+        }));
+
+        let prog = ir::Prog {
+            main: ir::Function::from(FlatFunction {
+                arguments: vec![FlatParameter::private(x)],
+                statements: is_zero_statements,
+            }),
+            private: vec![true],
+        };
+
+        let interpreter = Interpreter::default();
+
+        for (value, expected) in vec![(0, 1), (1, 0), (42, 0)] {
+            let witness = interpreter
+                .execute(&prog, &vec![FieldPrime::from(value)])
+                .unwrap();
+            assert_eq!(witness.return_values(), vec![FieldPrime::from(expected)]);
+        }
+    }
+
+    #[test]
+    fn resume_numbering() {
+        // def main(private field a, private field b) -> (field):
+        //     field c = a * b
+        //     return c * c
+
+        let function = || TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+            ],
+            statements: vec![
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element("c".into())),
+                    FieldElementExpression::Mult(
+                        box FieldElementExpression::Identifier("a".into()),
+                        box FieldElementExpression::Identifier("b".into()),
+                    )
+                    .into(),
+                ),
+                TypedStatement::Return(vec![FieldElementExpression::Mult(
+                    box FieldElementExpression::Identifier("c".into()),
+                    box FieldElementExpression::Identifier("c".into()),
+                )
+                .into()]),
+            ],
+            signature: Signature::new()
+                .inputs(vec![Type::FieldElement, Type::FieldElement])
+                .outputs(vec![Type::FieldElement]),
+        };
+
+        fn variables(f: &FlatFunction<FieldPrime>) -> Vec<FlatVariable> {
+            f.arguments
+                .iter()
+                .map(|p| p.id)
+                .chain(f.statements.iter().filter_map(|s| match s {
+                    FlatStatement::Definition(v, _) => Some(*v),
+                    _ => None,
+                }))
+                .filter(|v| *v > FlatVariable::one())
+                .collect()
+        }
+
+        let mut flattener = Flattener::with_next_index(FieldPrime::get_required_bits() - 2, 10);
+
+        let first = flattener
+            .flatten_function(&mut HashMap::new(), function())
+            .unwrap();
+        let index = flattener.next_index();
+        let second = flattener
+            .flatten_function(&mut HashMap::new(), function())
+            .unwrap();
+
+        let first = variables(&first);
+        let second = variables(&second);
+
+        assert_eq!(first[0], FlatVariable::new(10));
+        assert!(first.iter().all(|v| v.id() < index));
+        assert!(second.iter().all(|v| v.id() >= index));
+        assert!(flattener.next_index() > index);
+
+        // by default, numbering restarts for each function
+        let mut flattener = Flattener::new();
+
+        let first = flattener
+            .flatten_function(&mut HashMap::new(), function())
+            .unwrap();
+        let second = flattener
+            .flatten_function(&mut HashMap::new(), function())
+            .unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn dedup_booleanity() {
+        // the bits of `a` are checked twice, as if `a` was decomposed twice
+        let a = FlatVariable::new(0);
+        let b0 = FlatVariable::new(1);
+        let b1 = FlatVariable::new(2);
+
+        let booleanity = |b: FlatVariable| {
+            FlatStatement::Condition(b.into(), FlatExpression::Mult(box b.into(), box b.into()))
+        };
+
+        let decomposition = FlatStatement::Condition(
+            a.into(),
+            FlatExpression::Add(
+                box FlatExpression::Mult(
+                    box b0.into(),
+                    box FlatExpression::Number(FieldPrime::from(2)),
+                ),
+                box b1.into(),
+            ),
+        );
+
+        let statements = vec![
+            booleanity(b0),
+            booleanity(b1),
+            decomposition.clone(),
+            booleanity(b0),
+            booleanity(b1),
+            decomposition.clone(),
+            FlatStatement::Condition(
+                b0.into(),
+                FlatExpression::Mult(box b1.into(), box b1.into()),
+            ),
+        ];
+
+        assert_eq!(
+            Flattener::dedup_booleanity(statements),
+            vec![
+                booleanity(b0),
+                booleanity(b1),
+                decomposition.clone(),
+                decomposition,
+                FlatStatement::Condition(
+                    b0.into(),
+                    FlatExpression::Mult(box b1.into(), box b1.into()),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn n_ary_sum_and_product() {
+        // def main(private field a) -> (field):
+        //     return e
+
+        let function = |e: FieldElementExpression<'static, FieldPrime>| TypedFunction {
+            arguments: vec![Parameter::private(Variable::field_element("a".into()))],
+            statements: vec![TypedStatement::Return(vec![e.into()])],
+            signature: Signature::new()
+                .inputs(vec![Type::FieldElement])
+                .outputs(vec![Type::FieldElement]),
+        };
+
+        let flatten = |e| {
+            Flattener::new()
+                .flatten_function(&mut HashMap::new(), function(e))
+                .unwrap()
+        };
+
+        // 1 * a + 2 * a + ... + 100 * a
+        let terms: Vec<_> = (1..=100)
+            .map(|i| {
+                FieldElementExpression::Mult(
+                    box FieldElementExpression::Number(FieldPrime::from(i)),
+                    box FieldElementExpression::Identifier("a".into()),
+                )
+            })
+            .collect();
+
+        let add_tree = terms[1..].iter().cloned().fold(terms[0].clone(), |acc, t| {
+            FieldElementExpression::Add(box acc, box t)
+        });
+
+        let sum = flatten(FieldElementExpression::Sum(terms));
+
+        assert_eq!(sum, flatten(add_tree));
+
+        // a * a * a * a * a
+        let factors = vec![FieldElementExpression::Identifier("a".into()); 5];
+
+        let mult_tree = factors[1..]
+            .iter()
+            .cloned()
+            .fold(factors[0].clone(), |acc, f| {
+                FieldElementExpression::Mult(box acc, box f)
+            });
+
+        let product = flatten(FieldElementExpression::Product(factors));
+
+        assert_eq!(product, flatten(mult_tree));
+
+        let run = |f: FlatFunction<FieldPrime>| {
+            let prog = ir::Prog {
+                main: ir::Function::from(f),
+                private: vec![true],
+            };
+
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(2)])
+                .unwrap()
+                .return_values()
+        };
+
+        assert_eq!(run(sum), vec![FieldPrime::from(2 * 5050)]);
+        assert_eq!(run(product), vec![FieldPrime::from(32)]);
+        assert_eq!(
+            run(flatten(FieldElementExpression::Sum(vec![]))),
+            vec![FieldPrime::from(0)]
+        );
+        assert_eq!(
+            run(flatten(FieldElementExpression::Product(vec![]))),
+            vec![FieldPrime::from(1)]
+        );
+    }
+
+    #[test]
+    fn checked_sub() {
+        // def main(private field a, private field b) -> (field):
+        //     field c = checked a - b
+        //     return c
+
+        let function = TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+            ],
+            statements: vec![
+                TypedStatement::CheckedSub(
+                    Variable::field_element("c".into()),
+                    FieldElementExpression::Identifier("a".into()),
+                    FieldElementExpression::Identifier("b".into()),
+                ),
+                TypedStatement::Return(vec![
+                    FieldElementExpression::Identifier("c".into()).into()
+                ]),
+            ],
+            signature: Signature::new()
+                .inputs(vec![Type::FieldElement, Type::FieldElement])
+                .outputs(vec![Type::FieldElement]),
+        };
+
+        let prog = ir::Prog {
+            main: ir::Function::from(
+                Flattener::with_bits(8)
+                    .flatten_function(&mut HashMap::new(), function)
+                    .unwrap(),
+            ),
+            private: vec![true, true],
+        };
+
+        let run = |a: i32, b: i32| {
+            Interpreter::default().execute(&prog, &vec![FieldPrime::from(a), FieldPrime::from(b)])
+        };
+
+        assert_eq!(
+            run(5, 3).unwrap().return_values(),
+            vec![FieldPrime::from(2)]
+        );
+        assert_eq!(
+            run(3, 3).unwrap().return_values(),
+            vec![FieldPrime::from(0)]
+        );
+        // the difference wraps around the modulus, it does not fit in 8 bits
+        assert!(run(3, 5).is_err());
+    }
+
+    mod property {
+        //! Cross-checks the flattener against the reference evaluation of random programs
+
+        extern crate rand;
+
+        use self::rand::{Rng, SeedableRng, XorShiftRng};
+        use super::*;
+
+        const VARIABLES: [&str; 2] = ["a", "b"];
+
+        /// Generates a random arithmetic expression of depth at most `depth`
+        ///
+        /// # Remarks
+        /// * If `signed` is false, no subtraction is generated so that the expression stays small for small inputs
+        fn random_expression<R: Rng>(
+            rng: &mut R,
+            depth: usize,
+            signed: bool,
+        ) -> FieldElementExpression<'static, FieldPrime> {
+            let choice = match depth {
+                0 => rng.gen_range(0, 2),
+                _ => rng.gen_range(0, 6),
+            };
+
+            match choice {
+                0 => FieldElementExpression::Number(FieldPrime::from(rng.gen_range(0u32, 10))),
+                1 => FieldElementExpression::Identifier(VARIABLES[rng.gen_range(0, 2)].into()),
+                2 => FieldElementExpression::Add(
+                    box random_expression(rng, depth - 1, signed),
+                    box random_expression(rng, depth - 1, signed),
+                ),
+                3 => FieldElementExpression::Mult(
+                    box random_expression(rng, depth - 1, signed),
+                    box random_expression(rng, depth - 1, signed),
+                ),
+                4 => FieldElementExpression::Pow(
+                    box random_expression(rng, depth - 1, signed),
+                    box FieldElementExpression::Number(FieldPrime::from(rng.gen_range(0u32, 4))),
+                ),
+                _ if signed => FieldElementExpression::Sub(
+                    box random_expression(rng, depth - 1, signed),
+                    box random_expression(rng, depth - 1, signed),
+                ),
+                _ => FieldElementExpression::Add(
+                    box random_expression(rng, depth - 1, signed),
+                    box random_expression(rng, depth - 1, signed),
+                ),
+            }
+        }
+
+        /// Generates a random comparison between two small expressions
+        fn random_condition<R: Rng>(rng: &mut R) -> BooleanExpression<'static, FieldPrime> {
+            let left = box random_expression(rng, 2, false);
+            let right = box random_expression(rng, 2, false);
+
+            match rng.gen_range(0, 5) {
+                0 => BooleanExpression::Lt(left, right),
+                1 => BooleanExpression::Le(left, right),
+                2 => BooleanExpression::Gt(left, right),
+                3 => BooleanExpression::Ge(left, right),
+                _ => BooleanExpression::FieldEq(left, right),
+            }
+        }
+
+        #[test]
+        fn flattening_matches_evaluation() {
+            let mut rng = XorShiftRng::from_seed([0x2a, 0x5eed, 0xf1a7, 0xc0de]);
+
+            for _ in 0..50 {
+                // def main(private field a, private field b) -> (field):
+                //     return if c then e1 else e2 fi
+                let e = FieldElementExpression::IfElse(
+                    box random_condition(&mut rng),
+                    box random_expression(&mut rng, 3, true),
+                    box random_expression(&mut rng, 3, true),
+                );
+
+                let function = TypedFunction {
+                    arguments: VARIABLES
+                        .iter()
+                        .map(|v| Parameter::private(Variable::field_element((*v).into())))
+                        .collect(),
+                    statements: vec![TypedStatement::Return(vec![e.clone().into()])],
+                    signature: Signature::new()
+                        .inputs(vec![Type::FieldElement, Type::FieldElement])
+                        .outputs(vec![Type::FieldElement]),
+                };
+
+                let prog = ir::Prog {
+                    main: ir::Function::from(
+                        Flattener::new()
+                            .flatten_function(&mut HashMap::new(), function)
+                            .unwrap(),
+                    ),
+                    private: vec![true, true],
+                };
+
+                for _ in 0..5 {
+                    let inputs: Vec<_> = (0..VARIABLES.len())
+                        .map(|_| FieldPrime::from(rng.gen_range(0u32, 100)))
+                        .collect();
+
+                    let env = VARIABLES
+                        .iter()
+                        .map(|v| v.to_string())
+                        .zip(inputs.clone())
+                        .collect();
+
+                    let witness = Interpreter::default()
+                        .execute(&prog, &inputs)
+                        .unwrap_or_else(|err| panic!("{} with inputs {:?}: {}", e, inputs, err));
+
+                    assert_eq!(
+                        witness.return_values(),
+                        vec![e.evaluate(&env).unwrap()],
+                        "{} with inputs {:?}",
+                        e,
+                        inputs
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn constraint_budget() {
+        // def main(private field a, private field b) -> (field):
+        //     field c = if a < b then a else b fi
+        //     return if c < b then c else a fi
+
+        fn program() -> TypedProgram<'static, FieldPrime> {
+            let min = |x: &'static str, y: &'static str| {
+                FieldElementExpression::IfElse(
+                    box BooleanExpression::Lt(
+                        box FieldElementExpression::Identifier(x.into()),
+                        box FieldElementExpression::Identifier(y.into()),
+                    ),
+                    box FieldElementExpression::Identifier(x.into()),
+                    box FieldElementExpression::Identifier(y.into()),
+                )
+            };
+
+            let signature = Signature::new()
+                .inputs(vec![Type::FieldElement, Type::FieldElement])
+                .outputs(vec![Type::FieldElement]);
+
+            main_program(
+                vec![
+                    Parameter::private(Variable::field_element("a".into())),
+                    Parameter::private(Variable::field_element("b".into())),
+                ],
+                vec![
+                    TypedStatement::Definition(
+                        TypedAssignee::Identifier(Variable::field_element("c".into())),
+                        min("a", "b").into(),
+                    ),
+                    TypedStatement::Return(vec![min("c", "b").into()]),
+                ],
+                signature,
+            )
+        }
+
+        let flattened = Flattener::new().flatten_program(program()).unwrap();
+        let constraint_count = Flattener::count_constraints(&flattened.main.statements);
+
+        // each comparison decomposes its operands into bits
+        assert!(constraint_count > 2 * FieldPrime::get_required_bits());
+
+        assert_eq!(
+            Flattener::new()
+                .constraint_budget(10)
+                .flatten_program(program()),
+            Err(FlattenError::BudgetExceeded { budget: 10 })
+        );
+        assert_eq!(
+            Flattener::new()
+                .constraint_budget(constraint_count - 1)
+                .flatten_program(program()),
+            Err(FlattenError::BudgetExceeded {
+                budget: constraint_count - 1
+            })
+        );
+        assert_eq!(
+            Flattener::new()
+                .constraint_budget(constraint_count)
+                .flatten_program(program()),
+            Ok(flattened)
+        );
+    }
+
+    #[test]
+    fn constraint_budget_after_dedup() {
+        // def main(private field a, private field b) -> (field):
+        //     assert_bool(a)
+        //     assert_bool(a)
+        //     <tail>
+        fn program<'ast>(
+            tail: Vec<TypedStatement<'ast, FieldPrime>>,
+        ) -> TypedProgram<'ast, FieldPrime> {
+            let assert_bool = |v: &'ast str| {
+                TypedStatement::AssertBool(FieldElementExpression::Identifier(v.into()))
+            };
+
+            main_program(
+                vec![
+                    Parameter::private(Variable::field_element("a".into())),
+                    Parameter::private(Variable::field_element("b".into())),
+                ],
+                vec![assert_bool("a"), assert_bool("a")]
+                    .into_iter()
+                    .chain(tail)
+                    .collect(),
+                Signature::new()
+                    .inputs(vec![Type::FieldElement, Type::FieldElement])
+                    .outputs(vec![Type::FieldElement]),
+            )
+        }
+
+        let ret =
+            || TypedStatement::Return(vec![FieldElementExpression::Identifier("a".into()).into()]);
+        let check_b = || TypedStatement::AssertBool(FieldElementExpression::Identifier("b".into()));
+
+        // the second check of `a` is removed, so one constraint remains
+        let flattened = Flattener::new()
+            .constraint_budget(1)
+            .flatten_program(program(vec![ret()]))
+            .unwrap();
+        assert_eq!(Flattener::count_constraints(&flattened.main.statements), 1);
+        assert_eq!(
+            Flattener::new()
+                .constraint_budget(1)
+                .flatten_program_streaming(program(vec![ret()]), |_| {})
+                .map(|_| ()),
+            Ok(())
+        );
+
+        // checking `b` exceeds the budget, even though the function is not flattened to its return statement
+        assert_eq!(
+            Flattener::new()
+                .constraint_budget(1)
+                .flatten_program(program(vec![check_b(), ret()])),
+            Err(FlattenError::BudgetExceeded { budget: 1 })
+        );
+        assert_eq!(
+            Flattener::new()
+                .constraint_budget(1)
+                .flatten_program_streaming(program(vec![check_b(), ret()]), |_| {}),
+            Err(FlattenError::BudgetExceeded { budget: 1 })
+        );
+    }
+
+    #[test]
+    fn original_names() {
+        // def main(private field a) -> (field):
+        //     field x = a
+        //     x = x + 1
+        //     x = x * a
+        //     return x
+
+        let x = |version| Identifier::from("x").version(version);
+
+        let function = TypedFunction {
+            arguments: vec![Parameter::private(Variable::field_element("a".into()))],
+            statements: vec![
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element(x(0))),
+                    FieldElementExpression::Identifier("a".into()).into(),
+                ),
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element(x(1))),
+                    FieldElementExpression::Add(
+                        box FieldElementExpression::Identifier(x(0)),
+                        box FieldElementExpression::Number(FieldPrime::from(1)),
+                    )
+                    .into(),
+                ),
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element(x(2))),
+                    FieldElementExpression::Mult(
+                        box FieldElementExpression::Identifier(x(1)),
+                        box FieldElementExpression::Identifier("a".into()),
+                    )
+                    .into(),
+                ),
+                TypedStatement::Return(vec![FieldElementExpression::Identifier(x(2)).into()]),
+            ],
+            signature: Signature::new()
+                .inputs(vec![Type::FieldElement])
+                .outputs(vec![Type::FieldElement]),
+        };
+
+        let mut flattener = Flattener::new();
+        flattener
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        for version in 0..3 {
+            assert_eq!(flattener.original_name(&x(version).to_string()), Some("x"));
+
+            let flat_variable = flattener.layout.get(&x(version)).unwrap()[0];
+            assert_eq!(
+                flattener.original_name(&flat_variable.to_string()),
+                Some("x")
+            );
+        }
+
+        assert_eq!(flattener.original_name("_0"), Some("a"));
+        assert_eq!(flattener.original_name("y"), None);
+    }
+
+    #[test]
+    fn incremental() {
+        // def main(private field a, private field b) -> (field):
+        //     field c = a * b
+        //     field d = if a < b then c else a fi
+        // ---
+        //     field e = d * c + a
+        //     return e / b
+
+        let arguments = vec![
+            Parameter::private(Variable::field_element("a".into())),
+            Parameter::private(Variable::field_element("b".into())),
+        ];
+
+        let signature = Signature::new()
+            .inputs(vec![Type::FieldElement, Type::FieldElement])
+            .outputs(vec![Type::FieldElement]);
+
+        let prefix = vec![
+            TypedStatement::Definition(
+                TypedAssignee::Identifier(Variable::field_element("c".into())),
+                FieldElementExpression::Mult(
+                    box FieldElementExpression::Identifier("a".into()),
+                    box FieldElementExpression::Identifier("b".into()),
+                )
+                .into(),
+            ),
+            TypedStatement::Definition(
+                TypedAssignee::Identifier(Variable::field_element("d".into())),
+                FieldElementExpression::IfElse(
+                    box BooleanExpression::Lt(
+                        box FieldElementExpression::Identifier("a".into()),
+                        box FieldElementExpression::Identifier("b".into()),
+                    ),
+                    box FieldElementExpression::Identifier("c".into()),
+                    box FieldElementExpression::Identifier("a".into()),
+                )
+                .into(),
+            ),
+        ];
+
+        let suffix = vec![
+            TypedStatement::Definition(
+                TypedAssignee::Identifier(Variable::field_element("e".into())),
+                FieldElementExpression::Add(
+                    box FieldElementExpression::Mult(
+                        box FieldElementExpression::Identifier("d".into()),
+                        box FieldElementExpression::Identifier("c".into()),
+                    ),
+                    box FieldElementExpression::Identifier("a".into()),
+                )
+                .into(),
+            ),
+            TypedStatement::Return(vec![FieldElementExpression::Div(
+                box FieldElementExpression::Identifier("e".into()),
+                box FieldElementExpression::Identifier("b".into()),
+            )
+            .into()]),
+        ];
 
-                let new_left = if left_flattened.is_linear() {
-                    left_flattened
-                } else {
-                    let id = self.use_sym();
-                    statements_flattened.push(FlatStatement::Definition(id, left_flattened));
-                    FlatExpression::Identifier(id)
-                };
-                let new_right = if right_flattened.is_linear() {
-                    right_flattened
-                } else {
-                    let id = self.use_sym();
-                    statements_flattened.push(FlatStatement::Definition(id, right_flattened));
-                    FlatExpression::Identifier(id)
-                };
+        let program = |statements: Vec<TypedStatement<'static, FieldPrime>>| {
+            main_program(arguments.clone(), statements, signature.clone())
+        };
 
-                FlatExpression::Sub(box new_left, box new_right)
-            }
-            FieldElementExpression::Mult(box left, box right) => {
-                let left_flattened =
-                    self.flatten_field_expression(symbols, statements_flattened, left);
-                let right_flattened =
-                    self.flatten_field_expression(symbols, statements_flattened, right);
-                let new_left = if left_flattened.is_linear() {
-                    left_flattened
-                } else {
-                    let id = self.use_sym();
-                    statements_flattened.push(FlatStatement::Definition(id, left_flattened));
-                    FlatExpression::Identifier(id)
-                };
-                let new_right = if right_flattened.is_linear() {
-                    right_flattened
-                } else {
-                    let id = self.use_sym();
-                    statements_flattened.push(FlatStatement::Definition(id, right_flattened));
-                    FlatExpression::Identifier(id)
-                };
-                FlatExpression::Mult(box new_left, box new_right)
-            }
-            FieldElementExpression::Div(box left, box right) => {
-                let left_flattened =
-                    self.flatten_field_expression(symbols, statements_flattened, left);
-                let right_flattened =
-                    self.flatten_field_expression(symbols, statements_flattened, right);
-                let new_left: FlatExpression<T> = {
-                    let id = self.use_sym();
-                    statements_flattened.push(FlatStatement::Definition(id, left_flattened));
-                    id.into()
-                };
-                let new_right: FlatExpression<T> = {
-                    let id = self.use_sym();
-                    statements_flattened.push(FlatStatement::Definition(id, right_flattened));
-                    id.into()
-                };
+        let whole = Flattener::new()
+            .flatten_program(program(
+                prefix.iter().cloned().chain(suffix.clone()).collect(),
+            ))
+            .unwrap();
 
-                let invb = self.use_sym();
-                let inverse = self.use_sym();
+        // the previous version of the program returned `d`
+        let previous = program(
+            prefix
+                .iter()
+                .cloned()
+                .chain(vec![TypedStatement::Return(vec![
+                    FieldElementExpression::Identifier("d".into()).into(),
+                ])])
+                .collect(),
+        );
 
-                // # invb = 1/b
-                statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
-                    vec![invb],
-                    Solver::Div,
-                    vec![FlatExpression::Number(T::one()), new_right.clone()],
-                )));
+        // the return statement is not part of the prefix, even if it is among the first `k` statements
+        let start = Flattener::new()
+            .flatten_program_start(previous.clone(), 3)
+            .unwrap();
+        assert_eq!(
+            start,
+            Flattener::new().flatten_program_start(previous, 2).unwrap()
+        );
+        assert!(!start.main().statements.iter().any(|s| match s {
+            FlatStatement::Return(..) => true,
+            _ => false,
+        }));
+
+        // the state of the flattener is restored from the prefix, not kept by the flattener
+        let incremental = Flattener::new()
+            .flatten_program_incremental(
+                &start,
+                program(prefix.iter().cloned().chain(suffix.clone()).collect()),
+            )
+            .unwrap();
+
+        assert_eq!(incremental, whole);
+
+        // a prefix the program does not start with is ignored
+        let other = Flattener::new()
+            .flatten_program_start(
+                program(vec![
+                    TypedStatement::Definition(
+                        TypedAssignee::Identifier(Variable::field_element("c".into())),
+                        FieldElementExpression::Add(
+                            box FieldElementExpression::Identifier("a".into()),
+                            box FieldElementExpression::Identifier("b".into()),
+                        )
+                        .into(),
+                    ),
+                    prefix[1].clone(),
+                ]),
+                2,
+            )
+            .unwrap();
 
-                // assert(invb * b == 1)
-                statements_flattened.push(FlatStatement::Condition(
-                    FlatExpression::Number(T::one()),
-                    FlatExpression::Mult(box invb.into(), box new_right.clone().into()),
-                ));
+        let incremental = Flattener::new()
+            .flatten_program_incremental(
+                &other,
+                program(prefix.into_iter().chain(suffix).collect()),
+            )
+            .unwrap();
 
-                // # c = a/b
-                statements_flattened.push(FlatStatement::Directive(FlatDirective::new(
-                    vec![inverse],
-                    Solver::Div,
-                    vec![new_left.clone(), new_right.clone()],
-                )));
+        assert_eq!(incremental, whole);
+    }
 
-                // assert(c * b == a)
-                statements_flattened.push(FlatStatement::Condition(
-                    new_left.into(),
-                    FlatExpression::Mult(box new_right, box inverse.into()),
-                ));
+    #[test]
+    fn conditional_sum() {
+        // def main(private field v0, ..., private field v3, private field s0, ..., private field s3) -> (field):
+        //     return s0 * v0 + ... + s3 * v3
 
-                inverse.into()
-            }
-            FieldElementExpression::Pow(box base, box exponent) => {
-                match exponent {
-                    FieldElementExpression::Number(ref e) => {
-                        // flatten the base expression
-                        let base_flattened = self.flatten_field_expression(
-                            symbols,
-                            statements_flattened,
-                            base.clone(),
-                        );
+        let run = |one_hot: bool, selectors: Vec<i32>| {
+            let mut flattener = Flattener::new();
+            let values = flattener.issue_new_variables(4);
+            let selector_variables = flattener.issue_new_variables(4);
+
+            let mut statements = vec![];
+            let res = flattener.conditional_sum(
+                &mut statements,
+                &selector_variables
+                    .iter()
+                    .map(|s| FlatExpression::Identifier(*s))
+                    .collect::<Vec<_>>(),
+                &values
+                    .iter()
+                    .map(|v| FlatExpression::Identifier(*v))
+                    .collect::<Vec<_>>(),
+                one_hot,
+            );
+            statements.push(FlatStatement::Return(FlatExpressionList {
+                expressions: vec![res],
+            }));
 
-                        // we require from the base to be linear
-                        // TODO change that
-                        assert!(base_flattened.is_linear());
+            let prog = ir::Prog {
+                main: ir::Function::from(FlatFunction {
+                    arguments: values
+                        .into_iter()
+                        .chain(selector_variables)
+                        .map(|id| FlatParameter { id, private: true })
+                        .collect(),
+                    statements,
+                }),
+                private: vec![true; 8],
+            };
 
-                        let e = e.to_dec_string().parse::<usize>().unwrap();
+            let inputs: Vec<_> = vec![10, 20, 30, 40]
+                .into_iter()
+                .chain(selectors)
+                .map(FieldPrime::from)
+                .collect();
 
-                        // convert the exponent to bytes, big endian
-                        let ebytes_be = e.to_be_bytes();
-                        // convert the bytes to bits, remove leading zeroes (we only need powers up to the highest non-zero bit)
-                        let ebits_be: Vec<_> = ebytes_be
-                            .iter()
-                            .flat_map(|byte| (0..8).rev().map(move |i| byte & (1 << i) != 0)) // byte to bit, big endian
-                            .skip_while(|b| !b) // skip trailing false bits
-                            .collect();
+            Interpreter::default()
+                .execute(&prog, &inputs)
+                .map(|w| w.return_values())
+        };
 
-                        // reverse to start with the lowest bit first
-                        let ebits_le: Vec<_> = ebits_be.into_iter().rev().collect();
+        assert_eq!(run(true, vec![0, 0, 1, 0]), Ok(vec![FieldPrime::from(30)]));
+        assert_eq!(run(false, vec![0, 1, 1, 0]), Ok(vec![FieldPrime::from(50)]));
+        assert_eq!(run(false, vec![0, 0, 0, 0]), Ok(vec![FieldPrime::from(0)]));
+        // not one-hot
+        assert!(run(true, vec![0, 1, 1, 0]).is_err());
+        assert!(run(true, vec![0, 0, 0, 0]).is_err());
+        // not boolean
+        assert!(run(false, vec![0, 0, 2, 0]).is_err());
+    }
 
-                        // calculate all powers e**(2**i) by squaring
-                        let powers: Vec<FlatExpression<T>> = ebits_le
-                            .iter()
-                            .scan(None, |state, _| {
-                                match state {
-                                    // the first element is the base
-                                    None => {
-                                        *state = Some(base_flattened.clone());
-                                        Some(base_flattened.clone())
-                                    }
-                                    // any subsequent element is the square of the previous one
-                                    Some(previous) => {
-                                        // introduce a new variable
-                                        let id = self.use_sym();
-                                        // set it to the square of the previous one, stored in state
-                                        statements_flattened.push(FlatStatement::Definition(
-                                            id.clone(),
-                                            FlatExpression::Mult(
-                                                box previous.clone(),
-                                                box previous.clone(),
-                                            ),
-                                        ));
-                                        // store it in the state for later squaring
-                                        *state = Some(FlatExpression::Identifier(id.clone()));
-                                        // return it for later use constructing the result
-                                        Some(FlatExpression::Identifier(id.clone()))
-                                    }
-                                }
-                            })
-                            .collect();
+    #[test]
+    fn dyn_select() {
+        // def main(private field[4] arr, private field i) -> (field):
+        //     return arr[i]
+
+        let function = TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_array("arr".into(), 4)),
+                Parameter::private(Variable::field_element("i".into())),
+            ],
+            statements: vec![TypedStatement::Return(vec![
+                FieldElementExpression::DynSelect(
+                    "arr".into(),
+                    box FieldElementExpression::Identifier("i".into()),
+                    4,
+                )
+                .into(),
+            ])],
+            signature: Signature {
+                inputs: vec![Type::array(Type::FieldElement, 4), Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let flat_function = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        let prog = ir::Prog {
+            main: ir::Function::from(flat_function),
+            private: vec![true; 5],
+        };
+
+        let run = |i: u32| {
+            let inputs: Vec<_> = vec![10, 20, 30, 40, i]
+                .into_iter()
+                .map(FieldPrime::from)
+                .collect();
+            Interpreter::default()
+                .execute(&prog, &inputs)
+                .map(|w| w.return_values())
+        };
+
+        assert_eq!(run(2), Ok(vec![FieldPrime::from(30)]));
+        assert_eq!(run(0), Ok(vec![FieldPrime::from(10)]));
+        // out of bounds
+        assert!(run(4).is_err());
+    }
+
+    #[test]
+    fn dyn_select_size_mismatch() {
+        // def main(private field[4] arr, private field i) -> (field):
+        //     return arr[i], selecting among `size` elements
+        let run = |size: usize, i: u32| {
+            let function = TypedFunction {
+                arguments: vec![
+                    Parameter::private(Variable::field_array("arr".into(), 4)),
+                    Parameter::private(Variable::field_element("i".into())),
+                ],
+                statements: vec![TypedStatement::Return(vec![
+                    FieldElementExpression::DynSelect(
+                        "arr".into(),
+                        box FieldElementExpression::Identifier("i".into()),
+                        size,
+                    )
+                    .into(),
+                ])],
+                signature: Signature {
+                    inputs: vec![Type::array(Type::FieldElement, 4), Type::FieldElement],
+                    outputs: vec![Type::FieldElement],
+                },
+            };
+
+            let prog = ir::Prog {
+                main: ir::Function::from(
+                    Flattener::new()
+                        .flatten_function(&mut HashMap::new(), function)
+                        .unwrap(),
+                ),
+                private: vec![true; 5],
+            };
+
+            let inputs: Vec<_> = vec![10, 20, 30, 40, i]
+                .into_iter()
+                .map(FieldPrime::from)
+                .collect();
+            Interpreter::default()
+                .execute(&prog, &inputs)
+                .map(|w| w.return_values())
+        };
+
+        // fewer elements than the array
+        assert_eq!(run(2, 1), Ok(vec![FieldPrime::from(20)]));
+        assert!(run(2, 2).is_err());
+
+        // more elements than the array
+        assert_eq!(run(6, 3), Ok(vec![FieldPrime::from(40)]));
+        assert!(run(6, 4).is_err());
+    }
+
+    #[test]
+    fn linear_sum_chain() {
+        // ((x + x) + x) + ... + x
+        let size = 200;
+
+        let mut flattener = Flattener::new();
+        flattener.use_variable(&Variable::field_element("x".into()));
+
+        let e = (1..size).fold(FieldElementExpression::Identifier("x".into()), |acc, _| {
+            FieldElementExpression::Add(
+                box acc,
+                box FieldElementExpression::<FieldPrime>::Identifier("x".into()),
+            )
+        });
+
+        crate::flat_absy::IS_LINEAR_CALLS.with(|calls| calls.set(0));
+
+        let mut statements = vec![];
+        let (flattened, linear) =
+            flattener.flatten_field_expression_linearity(&HashMap::new(), &mut statements, e);
+
+        // each term is checked once, rather than the whole chain at each level
+        let calls = crate::flat_absy::IS_LINEAR_CALLS.with(|calls| calls.get());
+        assert!(calls <= 2 * size);
+
+        // the chain is known to be linear without walking through it, and no term is defined
+        assert!(linear);
+        assert_eq!(statements, vec![]);
+
+        // the result is the same chain of additions
+        let x = FlatExpression::Identifier(FlatVariable::new(0));
+        let expected = (1..size).fold(x.clone(), |acc, _| {
+            FlatExpression::Add(box acc, box x.clone())
+        });
+        assert_eq!(flattened, expected);
+    }
+
+    #[test]
+    fn assert_bool() {
+        // def main(private field a, private field b) -> (field):
+        //     assert_bool(a)
+        //     assert_bool(a * b)
+        //     assert_bool(a)
+        //     return 1
+
+        let function = TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+            ],
+            statements: vec![
+                TypedStatement::AssertBool(FieldElementExpression::Identifier("a".into())),
+                TypedStatement::AssertBool(FieldElementExpression::Mult(
+                    box FieldElementExpression::Identifier("a".into()),
+                    box FieldElementExpression::Identifier("b".into()),
+                )),
+                TypedStatement::AssertBool(FieldElementExpression::Identifier("a".into())),
+                TypedStatement::Return(vec![
+                    FieldElementExpression::Number(FieldPrime::from(1)).into()
+                ]),
+            ],
+            signature: Signature {
+                inputs: vec![Type::FieldElement, Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let flat_function = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        let a = FlatVariable::new(0);
+        let sym = FlatVariable::new(2);
+
+        let check = |x: FlatVariable| {
+            FlatStatement::Condition(x.into(), FlatExpression::Mult(box x.into(), box x.into()))
+        };
+
+        assert_eq!(
+            flat_function.statements[..4],
+            [
+                check(a),
+                FlatStatement::Definition(
+                    sym,
+                    FlatExpression::Mult(box a.into(), box FlatVariable::new(1).into())
+                ),
+                check(sym),
+                check(a),
+            ]
+        );
+
+        let prog = ir::Prog {
+            main: ir::Function::from(FlatFunction {
+                statements: Flattener::dedup_booleanity(flat_function.statements),
+                ..flat_function
+            }),
+            private: vec![true; 2],
+        };
+
+        let run = |a: u32, b: u32| {
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(a), FieldPrime::from(b)])
+                .map(|w| w.return_values())
+        };
+
+        assert_eq!(run(0, 42), Ok(vec![FieldPrime::from(1)]));
+        assert_eq!(run(1, 0), Ok(vec![FieldPrime::from(1)]));
+        assert_eq!(run(1, 1), Ok(vec![FieldPrime::from(1)]));
+        assert!(run(2, 0).is_err());
+        assert!(run(1, 2).is_err());
+    }
+
+    #[test]
+    fn assert_non_zero() {
+        // def main(private field a, private field b) -> (field):
+        //     assert_non_zero(a * b)
+        //     return 1
+
+        let function = TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+            ],
+            statements: vec![
+                TypedStatement::AssertNonZero(FieldElementExpression::Mult(
+                    box FieldElementExpression::Identifier("a".into()),
+                    box FieldElementExpression::Identifier("b".into()),
+                )),
+                TypedStatement::Return(vec![
+                    FieldElementExpression::Number(FieldPrime::from(1)).into()
+                ]),
+            ],
+            signature: Signature {
+                inputs: vec![Type::FieldElement, Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let flat_function = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        let prog = ir::Prog {
+            main: ir::Function::from(flat_function),
+            private: vec![true; 2],
+        };
+
+        let run = |a: u32, b: u32| {
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(a), FieldPrime::from(b)])
+                .map(|w| w.return_values())
+        };
+
+        assert_eq!(run(3, 42), Ok(vec![FieldPrime::from(1)]));
+        // a zero value has no inverse, so no witness satisfies `1 == x * w`
+        assert!(run(0, 42).is_err());
+        assert!(run(3, 0).is_err());
+    }
+
+    #[test]
+    fn lt_linear_operands() {
+        // x < 5
+        let mut flattener = Flattener::new();
+        let x = flattener.use_variable(&Variable::field_element("x".into()))[0];
+
+        let mut statements = vec![];
+        flattener.flatten_boolean_expression(
+            &HashMap::new(),
+            &mut statements,
+            BooleanExpression::Lt(
+                box FieldElementExpression::Identifier("x".into()),
+                box FieldElementExpression::Number(FieldPrime::from(5)),
+            ),
+        );
+
+        // neither `x` nor `5` is given its own variable
+        let definitions: Vec<_> = statements
+            .iter()
+            .filter_map(|s| match s {
+                FlatStatement::Definition(_, e) => Some(e.clone()),
+                _ => None,
+            })
+            .collect();
+        assert!(!definitions.contains(&FlatExpression::Identifier(x)));
+        assert!(!definitions.contains(&FlatExpression::Number(FieldPrime::from(5))));
+
+        // both are decomposed directly
+        let decomposed: Vec<_> = statements
+            .iter()
+            .filter_map(|s| match s {
+                FlatStatement::Directive(d) => Some(d.inputs.clone()),
+                _ => None,
+            })
+            .take(2)
+            .collect();
+        assert_eq!(
+            decomposed,
+            vec![
+                vec![FlatExpression::Identifier(x)],
+                vec![FlatExpression::Number(FieldPrime::from(5))]
+            ]
+        );
+    }
 
-                        // construct the result iterating through the bits, multiplying by the associated power iff the bit is true
-                        ebits_le.into_iter().zip(powers).fold(
-                            FlatExpression::Number(T::from(1)), // initialise the result at 1. If we have no bits to itegrate through, we're computing x**0 == 1
-                            |acc, (bit, power)| match bit {
-                                true => {
-                                    // update the result by introducing a new variable
-                                    let id = self.use_sym();
-                                    statements_flattened.push(FlatStatement::Definition(
-                                        id,
-                                        FlatExpression::Mult(box acc.clone(), box power), // set the new result to the current result times the current power
-                                    ));
-                                    FlatExpression::Identifier(id)
-                                }
-                                false => acc, // this bit is false, keep the previous result
-                            },
-                        )
-                    }
-                    _ => panic!("Expected number as pow exponent"),
-                }
-            }
-            FieldElementExpression::IfElse(box condition, box consequence, box alternative) => self
-                .flatten_if_else_expression(
-                    symbols,
-                    statements_flattened,
-                    condition,
-                    consequence,
-                    alternative,
-                )[0]
-            .clone(),
-            FieldElementExpression::FunctionCall(key, param_expressions) => {
-                let exprs_flattened = self.flatten_function_call(
-                    symbols,
-                    statements_flattened,
-                    key.id,
-                    vec![Type::FieldElement],
-                    param_expressions,
-                );
-                assert!(exprs_flattened.expressions.len() == 1); // outside of MultipleDefinition, FunctionCalls must return a single value
-                exprs_flattened.expressions[0].clone()
-            }
-            FieldElementExpression::Member(box s, id) => {
-                self.flatten_member_expression(symbols, statements_flattened, s, id)[0].clone()
-            }
-            FieldElementExpression::Select(box array, box index) => self
-                .flatten_select_expression::<FieldElementExpression<'ast, T>>(
-                    symbols,
-                    statements_flattened,
-                    array,
-                    index,
-                )[0]
-            .clone(),
+    #[cfg(feature = "trace")]
+    #[test]
+    fn trace_phases() {
+        // def main() -> (field):
+        //     return 1
+
+        let program = main_program(
+            vec![],
+            vec![TypedStatement::Return(vec![
+                FieldElementExpression::Number(FieldPrime::from(1)).into(),
+            ])],
+            Signature::new().outputs(vec![Type::FieldElement]),
+        );
+
+        crate::trace::tests::captured();
+
+        Flattener::flatten(program).unwrap();
+
+        let captured = crate::trace::tests::captured();
+        for phase in &["flatten: 1 statements", "dedup_booleanity: 1 statements"] {
+            assert!(captured.iter().any(|m| m.starts_with(phase)));
         }
     }
 
-    /// Flattens an array expression
-    ///
-    /// # Arguments
-    ///
-    /// * `symbols` - Available functions in this context
-    /// * `statements_flattened` - Vector where new flattened statements can be added.
-    /// * `expr` - `StructExpression` that will be flattened.
-    fn flatten_struct_expression(
-        &mut self,
-        symbols: &TypedFunctionSymbols<'ast, T>,
-        statements_flattened: &mut Vec<FlatStatement<T>>,
-        expr: StructExpression<'ast, T>,
-    ) -> Vec<FlatExpression<T>> {
-        let ty = expr.get_type();
-        let expected_output_size = expr.get_type().get_primitive_count();
-        let members = expr.ty().clone();
+    #[test]
+    fn nested_boolean_if_else() {
+        // def main(private bool c1, private bool c2) -> (field):
+        //     return if c1 then (if c2 then 1 else 0 fi) else 0 fi
 
-        let res = match expr.into_inner() {
-            StructExpressionInner::Identifier(x) => self
-                .layout
-                .get(&x)
-                .unwrap()
-                .iter()
-                .map(|v| FlatExpression::Identifier(v.clone()))
-                .collect(),
-            StructExpressionInner::Value(values) => values
-                .into_iter()
-                .flat_map(|v| self.flatten_expression(symbols, statements_flattened, v))
-                .collect(),
-            StructExpressionInner::FunctionCall(key, param_expressions) => {
-                let exprs_flattened = self.flatten_function_call(
-                    symbols,
-                    statements_flattened,
-                    key.id,
-                    vec![ty],
-                    param_expressions,
-                );
-                exprs_flattened.expressions
-            }
-            StructExpressionInner::IfElse(box condition, box consequence, box alternative) => {
-                members
-                    .into_iter()
-                    .flat_map(|member| match ty {
-                        Type::FieldElement => FieldElementExpression::if_else(
-                            condition.clone(),
-                            FieldElementExpression::member(consequence.clone(), member.id.clone()),
-                            FieldElementExpression::member(alternative.clone(), member.id.clone()),
-                        )
-                        .flatten(self, symbols, statements_flattened),
-                        Type::Boolean => BooleanExpression::if_else(
-                            condition.clone(),
-                            BooleanExpression::member(consequence.clone(), member.id.clone()),
-                            BooleanExpression::member(alternative.clone(), member.id.clone()),
-                        )
-                        .flatten(self, symbols, statements_flattened),
-                        Type::Struct(..) => StructExpression::if_else(
-                            condition.clone(),
-                            StructExpression::member(consequence.clone(), member.id.clone()),
-                            StructExpression::member(alternative.clone(), member.id.clone()),
-                        )
-                        .flatten(self, symbols, statements_flattened),
-                        Type::Array(..) => ArrayExpression::if_else(
-                            condition.clone(),
-                            ArrayExpression::member(consequence.clone(), member.id.clone()),
-                            ArrayExpression::member(alternative.clone(), member.id.clone()),
-                        )
-                        .flatten(self, symbols, statements_flattened),
-                    })
-                    .collect()
-            }
-            StructExpressionInner::Member(box s, id) => {
-                self.flatten_member_expression(symbols, statements_flattened, s, id)
-            }
-            StructExpressionInner::Select(box array, box index) => self
-                .flatten_select_expression::<StructExpression<'ast, T>>(
-                    symbols,
-                    statements_flattened,
-                    array,
-                    index,
+        let function = TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::boolean("c1".into())),
+                Parameter::private(Variable::boolean("c2".into())),
+            ],
+            statements: vec![TypedStatement::Return(vec![FieldElementExpression::IfElse(
+                box BooleanExpression::Identifier("c1".into()),
+                box FieldElementExpression::IfElse(
+                    box BooleanExpression::Identifier("c2".into()),
+                    box FieldElementExpression::Number(FieldPrime::from(1)),
+                    box FieldElementExpression::Number(FieldPrime::from(0)),
                 ),
+                box FieldElementExpression::Number(FieldPrime::from(0)),
+            )
+            .into()])],
+            signature: Signature {
+                inputs: vec![Type::Boolean, Type::Boolean],
+                outputs: vec![Type::FieldElement],
+            },
         };
 
-        assert_eq!(res.len(), expected_output_size);
-        res
-    }
+        let flat_function = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
 
-    /// Flattens an array expression
-    ///
-    /// # Arguments
-    ///
-    /// * `symbols` - Available functions in this context
-    /// * `statements_flattened` - Vector where new flattened statements can be added.
-    /// * `expr` - `ArrayExpression` that will be flattened.
-    /// # Remarks
-    /// * U is the inner type of the array
-    fn flatten_array_expression<U: Flatten<'ast, T>>(
-        &mut self,
-        symbols: &HashMap<FunctionKey<'ast>, TypedFunctionSymbol<'ast, T>>,
-        statements_flattened: &mut Vec<FlatStatement<T>>,
-        expr: ArrayExpression<'ast, T>,
-    ) -> Vec<FlatExpression<T>> {
-        let ty = expr.get_type();
+        // the inner branches are constant, so only the outer conditional multiplies
+        let multiplications = flat_function
+            .statements
+            .iter()
+            .filter(|s| match s {
+                FlatStatement::Definition(_, e) => !e.is_linear(),
+                _ => false,
+            })
+            .count();
+        assert_eq!(multiplications, 1);
 
-        let size = expr.size();
+        let prog = ir::Prog {
+            main: ir::Function::from(flat_function),
+            private: vec![true; 2],
+        };
 
-        match expr.into_inner() {
-            ArrayExpressionInner::Identifier(x) => self
-                .layout
-                .get(&x)
-                .unwrap()
-                .iter()
-                .map(|v| FlatExpression::Identifier(v.clone()))
-                .collect(),
-            ArrayExpressionInner::Value(values) => {
-                assert_eq!(size, values.len());
-                values
-                    .into_iter()
-                    .flat_map(|v| {
-                        U::try_from(v)
-                            .unwrap()
-                            .flatten(self, symbols, statements_flattened)
-                    })
-                    .collect()
-            }
-            ArrayExpressionInner::FunctionCall(key, param_expressions) => {
-                let exprs_flattened = self.flatten_function_call(
-                    symbols,
-                    statements_flattened,
-                    key.id,
-                    vec![ty],
-                    param_expressions,
-                );
-                assert!(exprs_flattened.expressions.len() == size); // outside of MultipleDefinition, FunctionCalls must return a single value
-                exprs_flattened.expressions
-            }
-            ArrayExpressionInner::IfElse(ref condition, ref consequence, ref alternative) => (0
-                ..size)
-                .flat_map(|i| {
-                    U::if_else(
-                        *condition.clone(),
-                        U::select(
-                            *consequence.clone(),
-                            FieldElementExpression::Number(T::from(i)),
-                        ),
-                        U::select(
-                            *alternative.clone(),
-                            FieldElementExpression::Number(T::from(i)),
-                        ),
-                    )
-                    .flatten(self, symbols, statements_flattened)
-                })
-                .collect(),
-            ArrayExpressionInner::Member(box s, id) => {
-                self.flatten_member_expression(symbols, statements_flattened, s, id)
-            }
-            ArrayExpressionInner::Select(box array, box index) => self
-                .flatten_select_expression::<ArrayExpression<'ast, T>>(
-                    symbols,
-                    statements_flattened,
-                    array,
-                    index,
-                ),
-        }
+        let run = |c1: i32, c2: i32| {
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(c1), FieldPrime::from(c2)])
+                .map(|w| w.return_values())
+        };
+
+        assert_eq!(run(1, 1), Ok(vec![FieldPrime::from(1)]));
+        assert_eq!(run(1, 0), Ok(vec![FieldPrime::from(0)]));
+        assert_eq!(run(0, 1), Ok(vec![FieldPrime::from(0)]));
+        assert_eq!(run(0, 0), Ok(vec![FieldPrime::from(0)]));
     }
 
-    /// Flattens a statement
-    ///
-    /// # Arguments
-    ///
-    /// * `symbols` - Available functions in this context
-    /// * `statements_flattened` - Vector where new flattened statements can be added.
-    /// * `stat` - `TypedStatement` that will be flattened.
-    fn flatten_statement(
-        &mut self,
-        symbols: &TypedFunctionSymbols<'ast, T>,
-        statements_flattened: &mut Vec<FlatStatement<T>>,
-        stat: TypedStatement<'ast, T>,
-    ) {
-        match stat {
-            TypedStatement::Return(exprs) => {
-                let flat_expressions = exprs
-                    .into_iter()
-                    .map(|expr| self.flatten_expression(symbols, statements_flattened, expr))
-                    .flat_map(|x| x)
-                    .collect::<Vec<_>>();
+    #[test]
+    fn min_max() {
+        // def main(private field a, private field b) -> (field, field):
+        //     return min(a, b), max(a, b)
+
+        let a = || box FieldElementExpression::Identifier("a".into());
+        let b = || box FieldElementExpression::Identifier("b".into());
+
+        let function = TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+            ],
+            statements: vec![TypedStatement::Return(vec![
+                FieldElementExpression::Min(a(), b()).into(),
+                FieldElementExpression::Max(a(), b()).into(),
+            ])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement, Type::FieldElement],
+                outputs: vec![Type::FieldElement, Type::FieldElement],
+            },
+        };
 
-                statements_flattened.push(FlatStatement::Return(FlatExpressionList {
-                    expressions: flat_expressions,
-                }));
-            }
-            TypedStatement::Declaration(_) => {
-                // declarations have already been checked
-                ()
-            }
-            TypedStatement::Definition(assignee, expr) => {
-                // define n variables with n the number of primitive types for v_type
-                // assign them to the n primitive types for expr
+        let flat_function = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
 
-                let rhs = self.flatten_expression(symbols, statements_flattened, expr);
+        let prog = ir::Prog {
+            main: ir::Function::from(flat_function),
+            private: vec![true; 2],
+        };
 
-                match assignee {
-                    TypedAssignee::Identifier(ref v) => {
-                        let vars = self.use_variable(&v);
-                        // handle return of function call
-                        statements_flattened.extend(
-                            vars.into_iter()
-                                .zip(rhs)
-                                .map(|(v, e)| FlatStatement::Definition(v, e)),
-                        );
-                    }
-                    TypedAssignee::Select(..) => unreachable!(
-                        "array element redefs should have been replaced by array redefs in unroll"
-                    ),
-                    TypedAssignee::Member(..) => unreachable!(
-                        "struct member redefs should have been replaced by struct redef in unroll"
-                    ),
-                }
-            }
-            TypedStatement::Condition(lhs, rhs) => {
-                // flatten expr1 and expr2 to n flattened expressions with n the number of primitive types for expr1
-                // add n conditions to check equality of the n expressions
+        let run = |a: i32, b: i32| {
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(a), FieldPrime::from(b)])
+                .map(|w| w.return_values())
+        };
 
-                let lhs = self.flatten_expression(symbols, statements_flattened, lhs);
-                let rhs = self.flatten_expression(symbols, statements_flattened, rhs);
+        assert_eq!(
+            run(3, 5),
+            Ok(vec![FieldPrime::from(3), FieldPrime::from(5)])
+        );
+        assert_eq!(
+            run(5, 3),
+            Ok(vec![FieldPrime::from(3), FieldPrime::from(5)])
+        );
+        assert_eq!(
+            run(4, 4),
+            Ok(vec![FieldPrime::from(4), FieldPrime::from(4)])
+        );
+    }
 
-                assert_eq!(lhs.len(), rhs.len());
+    #[test]
+    fn abs() {
+        use zokrates_field::field::Pow;
 
-                for (l, r) in lhs.into_iter().zip(rhs.into_iter()) {
-                    if l.is_linear() {
-                        statements_flattened.push(FlatStatement::Condition(l, r));
-                    } else if r.is_linear() {
-                        // swap so that left side is linear
-                        statements_flattened.push(FlatStatement::Condition(r, l));
-                    } else {
-                        unimplemented!()
-                    }
-                }
-            }
-            TypedStatement::For(..) => unreachable!("static analyser should have unrolled"),
-            TypedStatement::MultipleDefinition(vars, rhs) => {
-                // flatten the right side to p = sum(var_i.type.primitive_count) expressions
-                // define p new variables to the right side expressions
+        // def main(private field a) -> (field):
+        //     return abs(a)
 
-                let var_types = vars.iter().map(|v| v.get_type()).collect();
+        let function = TypedFunction {
+            arguments: vec![Parameter::private(Variable::field_element("a".into()))],
+            statements: vec![TypedStatement::Return(vec![FieldElementExpression::Abs(
+                box FieldElementExpression::Identifier("a".into()),
+            )
+            .into()])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
 
-                match rhs {
-                    TypedExpressionList::FunctionCall(key, exprs, _) => {
-                        let rhs_flattened = self.flatten_function_call(
-                            symbols,
-                            statements_flattened,
-                            &key.id,
-                            var_types,
-                            exprs,
-                        );
+        let flat_function = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
 
-                        let rhs = rhs_flattened.expressions.into_iter();
+        let prog = ir::Prog {
+            main: ir::Function::from(flat_function),
+            private: vec![true],
+        };
 
-                        let vars = vars.into_iter().flat_map(|v| self.use_variable(&v));
+        let run = |a: FieldPrime| {
+            Interpreter::default()
+                .execute(&prog, &vec![a])
+                .map(|w| w.return_values())
+        };
 
-                        statements_flattened
-                            .extend(vars.zip(rhs).map(|(v, r)| FlatStatement::Definition(v, r)));
-                    }
-                }
-            }
-        }
-    }
+        assert_eq!(run(FieldPrime::from(42)), Ok(vec![FieldPrime::from(42)]));
+        assert_eq!(run(FieldPrime::from(-42)), Ok(vec![FieldPrime::from(42)]));
+        assert_eq!(run(FieldPrime::from(0)), Ok(vec![FieldPrime::from(0)]));
 
-    /// Flattens a function symbol
-    ///
-    /// # Arguments
-    ///
-    /// * `funct` - `TypedFunctionSymbol` that will be flattened.
-    ///
-    /// # Remarks
-    /// * Only "flat" symbols can be flattened here. Other function calls must have been inlined previously.
-    fn flatten_function_symbol(&mut self, funct: TypedFunctionSymbol<'ast, T>) -> FlatFunction<T> {
-        match funct {
-            TypedFunctionSymbol::Flat(flat_function) => flat_function.synthetize(),
-            _ => unreachable!("only local flat symbols can be flattened"),
-        }
+        // the smallest signed value is negated, the largest unsigned one is out of range
+        let half = FieldPrime::from(2).pow(FieldPrime::get_required_bits() - 3);
+        assert_eq!(
+            run(FieldPrime::from(0) - half.clone()),
+            Ok(vec![half.clone()])
+        );
+        assert!(run(half).is_err());
     }
 
-    /// Flattens a function
-    ///
-    /// # Arguments
-    ///
-    /// * `symbols` - Available functions in this context
-    /// * `funct` - `TypedFunction` that will be flattened
-    fn flatten_function(
-        &mut self,
-        symbols: &TypedFunctionSymbols<'ast, T>,
-        funct: TypedFunction<'ast, T>,
-    ) -> FlatFunction<T> {
-        self.layout = HashMap::new();
+    #[test]
+    fn eq_zero() {
+        let flatten = |e: BooleanExpression<'static, FieldPrime>| {
+            let mut flattener: Flattener<FieldPrime> = Flattener::new();
+            flattener.use_variable(&Variable::field_element("x".into()));
+            let mut statements = vec![];
+            let res = flattener.flatten_boolean_expression(&HashMap::new(), &mut statements, e);
+            (res, statements)
+        };
 
-        self.next_var_idx = 0;
-        let mut statements_flattened: Vec<FlatStatement<T>> = Vec::new();
+        let x = || box FieldElementExpression::Identifier("x".into());
+        let zero = || box FieldElementExpression::Number(FieldPrime::from(0));
 
-        // push parameters
-        let arguments_flattened = funct
-            .arguments
-            .into_iter()
-            .flat_map(|p| self.use_parameter(&p))
-            .collect();
+        // x == 0 and 0 == x check `x` directly, without subtracting zero
+        let is_zero = flatten(BooleanExpression::IsZero(x()));
+        assert_eq!(flatten(BooleanExpression::FieldEq(x(), zero())), is_zero);
+        assert_eq!(flatten(BooleanExpression::FieldEq(zero(), x())), is_zero);
 
-        // flatten statements in functions and apply substitution
-        for stat in funct.statements {
-            self.flatten_statement(symbols, &mut statements_flattened, stat);
-        }
+        // x * x == 0 defines the product before checking it
+        let (res, mut statements) = flatten(BooleanExpression::FieldEq(
+            box FieldElementExpression::Mult(x(), x()),
+            zero(),
+        ));
+        assert_eq!(
+            statements[0],
+            FlatStatement::Definition(
+                FlatVariable::new(1),
+                FlatExpression::Mult(
+                    box FlatVariable::new(0).into(),
+                    box FlatVariable::new(0).into()
+                )
+            )
+        );
 
-        FlatFunction {
-            arguments: arguments_flattened,
-            statements: statements_flattened,
+        statements.push(FlatStatement::Return(FlatExpressionList {
+            expressions: vec![res],
+        }));
+
+        let prog = ir::Prog {
+            main: ir::Function::from(FlatFunction {
+                arguments: vec![FlatParameter::private(FlatVariable::new(0))],
+                statements,
+            }),
+            private: vec![true],
+        };
+
+        for (value, expected) in vec![(0, 1), (3, 0)] {
+            assert_eq!(
+                Interpreter::default()
+                    .execute(&prog, &vec![FieldPrime::from(value)])
+                    .map(|w| w.return_values()),
+                Ok(vec![FieldPrime::from(expected)])
+            );
         }
     }
 
-    /// Flattens a program
-    ///
-    /// # Arguments
-    ///
-    /// * `prog` - `TypedProgram` that will be flattened.
-    fn flatten_program(&mut self, prog: TypedProgram<'ast, T>) -> FlatProg<T> {
-        let main_module = prog.modules.get(&prog.main).unwrap();
+    #[test]
+    fn chained_comparison() {
+        // a < b < c
+        let e = |name: &'static str| FieldElementExpression::Identifier(name.into());
+        let chain = BooleanExpression::Chain(
+            box e("a"),
+            vec![(CompareOp::Lt, e("b")), (CompareOp::Lt, e("c"))],
+        );
 
-        let main = main_module
-            .functions
-            .iter()
-            .find(|(k, _)| k.id == "main")
-            .unwrap()
-            .1
-            .clone();
+        assert_eq!(chain.to_string(), "a < b < c");
 
-        let symbols = &main_module.functions;
+        // a < b && b < c
+        let and = BooleanExpression::And(
+            box BooleanExpression::Lt(box e("a"), box e("b")),
+            box BooleanExpression::Lt(box e("b"), box e("c")),
+        );
 
-        let main_flattened = match main {
-            TypedFunctionSymbol::Here(f) => self.flatten_function(&symbols, f),
-            _ => unreachable!("main should be a typed function locally"),
+        let flatten = |condition: BooleanExpression<'static, FieldPrime>| {
+            let mut flattener = Flattener::new();
+            for name in &["a", "b", "c"] {
+                flattener.use_variable(&Variable::field_element((*name).into()));
+            }
+            let mut statements = vec![];
+            let res =
+                flattener.flatten_boolean_expression(&HashMap::new(), &mut statements, condition);
+            (res, statements)
         };
 
-        FlatProg {
-            main: main_flattened,
-        }
-    }
+        let (res, mut statements) = flatten(chain);
+        assert_eq!((res.clone(), statements.clone()), flatten(and));
 
-    /// Checks if the given name is a not used variable and returns a fresh variable.
-    /// # Arguments
-    ///
-    /// * `name` - a String that holds the name of the variable
-    fn use_variable(&mut self, variable: &Variable<'ast>) -> Vec<FlatVariable> {
-        let vars = self.issue_new_variables(variable.get_type().get_primitive_count());
+        statements.push(FlatStatement::Return(FlatExpressionList {
+            expressions: vec![res],
+        }));
 
-        self.layout.insert(variable.id.clone(), vars.clone());
-        vars
+        let prog = ir::Prog {
+            main: ir::Function::from(FlatFunction {
+                arguments: (0..3)
+                    .map(|i| FlatParameter::private(FlatVariable::new(i)))
+                    .collect(),
+                statements,
+            }),
+            private: vec![true; 3],
+        };
+
+        for (inputs, expected) in vec![(vec![1, 2, 3], 1), (vec![1, 3, 2], 0), (vec![2, 1, 3], 0)] {
+            let inputs = inputs.into_iter().map(FieldPrime::from).collect();
+            assert_eq!(
+                Interpreter::default()
+                    .execute(&prog, &inputs)
+                    .map(|w| w.return_values()),
+                Ok(vec![FieldPrime::from(expected)])
+            );
+        }
     }
 
-    fn use_parameter(&mut self, parameter: &Parameter<'ast>) -> Vec<FlatParameter> {
-        let variables = self.use_variable(&parameter.id);
+    #[test]
+    fn comments() {
+        // def main(private field a) -> (field):
+        //     # check a
+        //     a == 1
+        //     # done
+        //     return a
+
+        let signature = Signature::new()
+            .inputs(vec![Type::FieldElement])
+            .outputs(vec![Type::FieldElement]);
+
+        let statements = vec![
+            TypedStatement::Comment("check a".to_string()),
+            TypedStatement::Condition(
+                FieldElementExpression::Identifier("a".into()).into(),
+                FieldElementExpression::Number(FieldPrime::from(1)).into(),
+            ),
+            TypedStatement::Comment("done".to_string()),
+            TypedStatement::Return(vec![FieldElementExpression::Identifier("a".into()).into()]),
+        ];
 
-        variables
-            .into_iter()
-            .map(|v| FlatParameter {
-                id: v,
-                private: parameter.private,
-            })
-            .collect()
-    }
+        assert_eq!(statements[0].to_string(), "# check a");
 
-    fn issue_new_variable(&mut self) -> FlatVariable {
-        let var = FlatVariable::new(self.next_var_idx);
-        self.next_var_idx += 1;
-        var
-    }
+        let program = main_program(
+            vec![Parameter::private(Variable::field_element("a".into()))],
+            statements,
+            signature,
+        );
 
-    fn issue_new_variables(&mut self, count: usize) -> Vec<FlatVariable> {
-        (0..count).map(|_| self.issue_new_variable()).collect()
-    }
+        let flattened = Flattener::new().flatten_program(program).unwrap();
 
-    // create an internal variable. We do not register it in the layout
-    fn use_sym(&mut self) -> FlatVariable {
-        self.issue_new_variable()
-    }
+        let comments: Vec<_> = flattened
+            .main
+            .statements
+            .iter()
+            .filter(|s| match s {
+                FlatStatement::Comment(..) => true,
+                _ => false,
+            })
+            .collect();
 
-    fn get_function<'a>(
-        &mut self,
-        key: &'a FunctionKey<'ast>,
-        symbols: &'a TypedFunctionSymbols<'ast, T>,
-    ) -> FlatFunction<T> {
-        let cached = self.flat_cache.get(&key).cloned();
+        assert_eq!(
+            comments,
+            vec![
+                &FlatStatement::Comment("check a".to_string()),
+                &FlatStatement::Comment("done".to_string())
+            ]
+        );
+        assert_eq!(comments[0].to_string(), "# check a");
 
-        cached.unwrap_or_else(|| {
-            let f = symbols.get(&key).unwrap().clone();
-            let res = self.flatten_function_symbol(f);
-            self.flat_cache.insert(key.clone(), res.clone());
-            res
-        })
+        // comments emit no constraint
+        let function = ir::Function::from(flattened.main);
+        assert_eq!(
+            function
+                .statements
+                .iter()
+                .filter(|s| s.to_string().contains('#'))
+                .count(),
+            0
+        );
+        assert_eq!(function.statements.len(), 1);
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::typed_absy::types::Signature;
-    use crate::typed_absy::types::Type;
-    use zokrates_field::field::FieldPrime;
 
     #[test]
-    fn powers_zero() {
-        // def main():
-        //     field a = 7
-        //     field b = a**0
-        //     return b
+    fn constant_conditions() {
+        // def main(private field a) -> (field):
+        //     a == 1
+        //     1 == <value>
+        //     return a
+        fn program<'ast>(value: u32) -> TypedProgram<'ast, FieldPrime> {
+            let signature = Signature::new()
+                .inputs(vec![Type::FieldElement])
+                .outputs(vec![Type::FieldElement]);
+
+            main_program(
+                vec![Parameter::private(Variable::field_element("a".into()))],
+                vec![
+                    TypedStatement::Condition(
+                        FieldElementExpression::Identifier("a".into()).into(),
+                        FieldElementExpression::Number(FieldPrime::from(1)).into(),
+                    ),
+                    TypedStatement::Condition(
+                        FieldElementExpression::Number(FieldPrime::from(1)).into(),
+                        FieldElementExpression::Number(FieldPrime::from(value)).into(),
+                    ),
+                    TypedStatement::Return(vec![
+                        FieldElementExpression::Identifier("a".into()).into()
+                    ]),
+                ],
+                signature,
+            )
+        }
 
-        // def main():
-        //     _0 = 7
-        //     _1 = 1         // power flattening returns 1, definition introduces _7
-        //     return _1
-        let function = TypedFunction {
-            arguments: vec![],
-            statements: vec![
-                TypedStatement::Definition(
-                    TypedAssignee::Identifier(Variable::field_element("a".into())),
-                    FieldElementExpression::Number(FieldPrime::from(7)).into(),
-                ),
-                TypedStatement::Definition(
-                    TypedAssignee::Identifier(Variable::field_element("b".into())),
-                    FieldElementExpression::Pow(
-                        box FieldElementExpression::Identifier("a".into()),
-                        box FieldElementExpression::Number(FieldPrime::from(0)),
-                    )
-                    .into(),
-                ),
-                TypedStatement::Return(vec![FieldElementExpression::Identifier("b".into()).into()]),
-            ],
+        // 1 == 1 always holds and is removed
+        let flattened = Flattener::new().flatten_program(program(1)).unwrap();
+        assert_eq!(
+            flattened.main.statements[0],
+            FlatStatement::Condition(
+                FlatVariable::new(0).into(),
+                FlatExpression::Number(FieldPrime::from(1))
+            )
+        );
+        assert_eq!(Flattener::count_constraints(&flattened.main.statements), 1);
+
+        // 1 == 2 never holds
+        assert_eq!(
+            Flattener::new().flatten_program(program(2)),
+            Err(FlattenError::UnsatisfiableConstraint {
+                constraint: String::from("1 == 2")
+            })
+        );
+    }
+
+    #[test]
+    fn comparison_widths() {
+        // def main(private field a, private field b, private field c, private field d) -> (field, field):
+        //     return if a <_8 b then 1 else 0 fi, if c <=_64 d then 1 else 0 fi
+        use zokrates_field::field::Pow;
+
+        let e = |name: &'static str| box FieldElementExpression::Identifier(name.into());
+        let to_field = |condition| {
+            FieldElementExpression::IfElse(
+                box condition,
+                box FieldElementExpression::Number(FieldPrime::from(1)),
+                box FieldElementExpression::Number(FieldPrime::from(0)),
+            )
+            .into()
+        };
+
+        let function = TypedFunction {
+            arguments: vec!["a", "b", "c", "d"]
+                .into_iter()
+                .map(|name| Parameter::private(Variable::field_element(name.into())))
+                .collect(),
+            statements: vec![TypedStatement::Return(vec![
+                to_field(BooleanExpression::LtWidth(e("a"), e("b"), 8)),
+                to_field(BooleanExpression::LeWidth(e("c"), e("d"), 64)),
+            ])],
             signature: Signature {
-                inputs: vec![],
-                outputs: vec![Type::FieldElement],
+                inputs: vec![Type::FieldElement; 4],
+                outputs: vec![Type::FieldElement; 2],
             },
         };
 
-        let mut flattener = Flattener::new();
+        let flat_function = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
 
-        let expected = FlatFunction {
-            arguments: vec![],
-            statements: vec![
-                FlatStatement::Definition(
-                    FlatVariable::new(0),
-                    FlatExpression::Number(FieldPrime::from(7)),
-                ),
-                FlatStatement::Definition(
-                    FlatVariable::new(1),
-                    FlatExpression::Number(FieldPrime::from(1)),
-                ),
-                FlatStatement::Return(FlatExpressionList {
-                    expressions: vec![FlatExpression::Identifier(FlatVariable::new(1))],
-                }),
-            ],
+        // each operand and each difference is decomposed on its own width only
+        assert!(
+            Flattener::count_constraints(&flat_function.statements)
+                < 2 * FieldPrime::get_required_bits()
+        );
+
+        let prog = ir::Prog {
+            main: ir::Function::from(flat_function),
+            private: vec![true; 4],
         };
 
-        let flattened = flattener.flatten_function(&mut HashMap::new(), function);
+        let run = |inputs: Vec<FieldPrime>| {
+            Interpreter::default()
+                .execute(&prog, &inputs)
+                .map(|w| w.return_values())
+        };
 
-        assert_eq!(flattened, expected);
+        let n = |x: u32| FieldPrime::from(x);
+        let large = FieldPrime::from(2).pow(63);
+
+        assert_eq!(
+            run(vec![n(3), n(255), large.clone(), large.clone()]),
+            Ok(vec![n(1), n(1)])
+        );
+        assert_eq!(
+            run(vec![n(255), n(3), large.clone(), n(42)]),
+            Ok(vec![n(0), n(0)])
+        );
+        assert_eq!(
+            run(vec![n(7), n(7), n(42), large.clone()]),
+            Ok(vec![n(0), n(1)])
+        );
+
+        // 256 does not fit in 8 bits, 2^64 does not fit in 64 bits
+        assert!(run(vec![n(256), n(3), n(0), n(0)]).is_err());
+        assert!(run(vec![n(0), n(0), FieldPrime::from(2).pow(64), n(0)]).is_err());
     }
 
     #[test]
-    fn power_one() {
-        // def main():
-        //     field a = 7
-        //     field b = a**1
-        //     return b
+    fn power_of_two_scaling() {
+        let e = |name: &'static str| box FieldElementExpression::Identifier(name.into());
+        let eight = || box FieldElementExpression::Number(FieldPrime::from(8));
 
-        // def main():
-        //     _0 = 7
-        //     _1 = 1 * _0     // x**1
-        //     _2 = _1         // power flattening returns _1, definition introduces _2
-        //     return _2
-        let function = TypedFunction {
-            arguments: vec![],
-            statements: vec![
-                TypedStatement::Definition(
-                    TypedAssignee::Identifier(Variable::field_element("a".into())),
-                    FieldElementExpression::Number(FieldPrime::from(7)).into(),
-                ),
-                TypedStatement::Definition(
-                    TypedAssignee::Identifier(Variable::field_element("b".into())),
-                    FieldElementExpression::Pow(
-                        box FieldElementExpression::Identifier("a".into()),
-                        box FieldElementExpression::Number(FieldPrime::from(1)),
-                    )
-                    .into(),
+        let mut flattener = Flattener::new();
+        for name in &["a", "b", "c"] {
+            flattener.use_variable(&Variable::field_element((*name).into()));
+        }
+
+        // a * 8
+        let mut statements = vec![];
+        let flattened = flattener.flatten_field_expression(
+            &HashMap::new(),
+            &mut statements,
+            FieldElementExpression::Mult(e("a"), eight()),
+        );
+        assert!(flattened.is_linear());
+        assert_eq!(statements, vec![]);
+
+        // (a + b) * 8 + c
+        let flattened = flattener.flatten_field_expression(
+            &HashMap::new(),
+            &mut statements,
+            FieldElementExpression::Add(
+                box FieldElementExpression::Mult(
+                    box FieldElementExpression::Add(e("a"), e("b")),
+                    eight(),
                 ),
-                TypedStatement::Return(vec![FieldElementExpression::Identifier("b".into()).into()]),
-            ],
-            signature: Signature {
-                inputs: vec![],
-                outputs: vec![Type::FieldElement],
-            },
+                e("c"),
+            ),
+        );
+        assert!(flattened.is_linear());
+        assert_eq!(statements, vec![]);
+
+        // the scaling is kept in the linear combination
+        let prog = ir::Prog {
+            main: ir::Function::from(FlatFunction {
+                arguments: (0..3)
+                    .map(|i| FlatParameter::private(FlatVariable::new(i)))
+                    .collect(),
+                statements: vec![FlatStatement::Return(FlatExpressionList {
+                    expressions: vec![flattened],
+                })],
+            }),
+            private: vec![true; 3],
         };
 
-        let mut flattener = Flattener::new();
+        assert_eq!(
+            Interpreter::default()
+                .execute(
+                    &prog,
+                    &vec![
+                        FieldPrime::from(1),
+                        FieldPrime::from(2),
+                        FieldPrime::from(3)
+                    ]
+                )
+                .map(|w| w.return_values()),
+            Ok(vec![FieldPrime::from(27)])
+        );
+    }
 
-        let expected = FlatFunction {
-            arguments: vec![],
-            statements: vec![
-                FlatStatement::Definition(
-                    FlatVariable::new(0),
-                    FlatExpression::Number(FieldPrime::from(7)),
-                ),
-                FlatStatement::Definition(
-                    FlatVariable::new(1),
-                    FlatExpression::Mult(
-                        box FlatExpression::Number(FieldPrime::from(1)),
-                        box FlatExpression::Identifier(FlatVariable::new(0)),
-                    ),
-                ),
-                FlatStatement::Definition(
-                    FlatVariable::new(2),
-                    FlatExpression::Identifier(FlatVariable::new(1)),
-                ),
-                FlatStatement::Return(FlatExpressionList {
-                    expressions: vec![FlatExpression::Identifier(FlatVariable::new(2))],
-                }),
-            ],
+    #[test]
+    fn constant_scaling() {
+        let x = || box FieldElementExpression::Identifier("x".into());
+        let y = || box FieldElementExpression::Identifier("y".into());
+        let n = |v: u32| box FieldElementExpression::Number(FieldPrime::from(v));
+
+        let mut flattener = Flattener::new();
+        let x_var = flattener.use_variable(&Variable::field_element("x".into()))[0];
+        let y_var = flattener.use_variable(&Variable::field_element("y".into()))[0];
+
+        let mut flatten = |e| {
+            let mut statements = vec![];
+            let flattened = flattener.flatten_field_expression(&HashMap::new(), &mut statements, e);
+            assert_eq!(statements, vec![]);
+            assert!(flattened.is_linear());
+            ir::LinComb::from(flattened)
         };
 
-        let flattened = flattener.flatten_function(&mut HashMap::new(), function);
+        // 3 * x
+        assert_eq!(
+            flatten(FieldElementExpression::Mult(n(3), x())),
+            ir::LinComb::summand(3, x_var)
+        );
+        // x * 3
+        assert_eq!(
+            flatten(FieldElementExpression::Mult(x(), n(3))),
+            ir::LinComb::summand(3, x_var)
+        );
+        // 3 * x + 2 * y
+        assert_eq!(
+            flatten(FieldElementExpression::Add(
+                box FieldElementExpression::Mult(n(3), x()),
+                box FieldElementExpression::Mult(n(2), y()),
+            )),
+            ir::LinComb::summand(3, x_var) + ir::LinComb::summand(2, y_var)
+        );
+        // 3 * (x + 2 * y)
+        assert_eq!(
+            flatten(FieldElementExpression::Mult(
+                n(3),
+                box FieldElementExpression::Add(x(), box FieldElementExpression::Mult(n(2), y())),
+            )),
+            ir::LinComb::summand(3, x_var) + ir::LinComb::summand(6, y_var)
+        );
+    }
 
-        assert_eq!(flattened, expected);
+    #[test]
+    fn flatten_error_messages() {
+        let errors = vec![
+            (
+                FlattenError::BitWidthTooLarge {
+                    requested: 300,
+                    field_max: 253,
+                },
+                vec!["300", "253"],
+            ),
+            (FlattenError::BitWidthZero, vec!["1"]),
+            (FlattenError::BudgetExceeded { budget: 42 }, vec!["42"]),
+            (
+                FlattenError::UnsatisfiableConstraint {
+                    constraint: String::from("1 == 2"),
+                },
+                vec!["1 == 2"],
+            ),
+            (
+                FlattenError::LengthMismatch { left: 3, right: 2 },
+                vec!["3", "2"],
+            ),
+            (FlattenError::EmptyProgram, vec!["return"]),
+            (
+                FlattenError::DuplicateArgument {
+                    name: String::from("a"),
+                },
+                vec!["`a`"],
+            ),
+            (
+                FlattenError::ConstantAssigned {
+                    name: String::from("N"),
+                },
+                vec!["`N`"],
+            ),
+            (
+                FlattenError::IntSemanticsTooWide {
+                    semantics: IntSemantics::WrappingBits(200),
+                    field_max: 253,
+                },
+                vec!["WrappingBits(200)", "253"],
+            ),
+        ];
+
+        for (error, context) in errors {
+            let message = error.to_string();
+            assert!(!message.is_empty());
+            for c in context {
+                assert!(message.contains(c), "{} should mention {}", message, c);
+            }
+
+            // usable as a boxed error
+            let boxed: Box<dyn std::error::Error> = Box::new(error);
+            assert_eq!(boxed.to_string(), message);
+        }
     }
 
     #[test]
-    fn power_13() {
-        // def main():
-        //     field a = 7
-        //     field b = a**13
-        //     return b
+    fn assert_array_eq() {
+        let mut flattener = Flattener::new();
+        let a: Vec<FlatExpression<FieldPrime>> =
+            (0..3).map(|_| flattener.use_sym().into()).collect();
+        let b: Vec<FlatExpression<FieldPrime>> =
+            (0..3).map(|_| flattener.use_sym().into()).collect();
 
-        // we apply double and add
-        // 13 == 0b1101
-        // a ** 13 == a**(2**0 + 2**2 + 2**3) == a**1 * a**4 * a**8
+        let mut statements = vec![];
+        assert_eq!(flattener.assert_array_eq(&mut statements, &a, &b), Ok(()));
+        assert_eq!(
+            statements,
+            a.iter()
+                .zip(b.iter())
+                .map(|(l, r)| FlatStatement::Condition(l.clone(), r.clone()))
+                .collect::<Vec<_>>()
+        );
 
-        // a_0 = a * a      // a**2
-        // a_1 = a_0 * a_0  // a**4
-        // a_2 = a_1 * a_1  // a**8
+        // a non-linear side is moved to the right
+        let mut statements = vec![];
+        let product = FlatExpression::Mult(box a[0].clone(), box a[1].clone());
+        assert_eq!(
+            flattener.assert_array_eq(&mut statements, &[product.clone()], &b[..1]),
+            Ok(())
+        );
+        assert_eq!(
+            statements,
+            vec![FlatStatement::Condition(b[0].clone(), product)]
+        );
 
-        // a_3 = a * a_1    // a * a**4 == a**5
-        // a_4 = a_3 * a_2  // a**5 * a**8 == a**13
+        let mut statements = vec![];
+        assert_eq!(
+            flattener.assert_array_eq(&mut statements, &a, &b[..2]),
+            Err(FlattenError::LengthMismatch { left: 3, right: 2 })
+        );
+        assert_eq!(statements, vec![]);
+    }
 
-        // def main():
-        //     _0 = 7
-        //     _1 = (_0 * _0)  // a**2
-        //     _2 = (_1 * _1)  // a**4
-        //     _3 = (_2 * _2)  // a**8
-        //
-        //     _4 = 1 * _0     // a
-        //     _5 = _4 * _2    // a**5
-        //     _6 = _5 * _3    // a**13
-        //     _7 = _6         // power flattening returns _6, definition introduces _7
-        //     return _7
+    #[test]
+    fn one_hot() {
+        let mut flattener = Flattener::new();
+        let index = flattener.use_variable(&Variable::field_element("index".into()))[0];
 
-        let function = TypedFunction {
-            arguments: vec![],
-            statements: vec![
-                TypedStatement::Definition(
-                    TypedAssignee::Identifier(Variable::field_element("a".into())),
-                    FieldElementExpression::Number(FieldPrime::from(7)).into(),
-                ),
-                TypedStatement::Definition(
-                    TypedAssignee::Identifier(Variable::field_element("b".into())),
-                    FieldElementExpression::Pow(
-                        box FieldElementExpression::Identifier("a".into()),
-                        box FieldElementExpression::Number(FieldPrime::from(13)),
-                    )
-                    .into(),
-                ),
-                TypedStatement::Return(vec![FieldElementExpression::Identifier("b".into()).into()]),
-            ],
-            signature: Signature {
-                inputs: vec![],
-                outputs: vec![Type::FieldElement],
-            },
+        let mut statements = vec![];
+        let selectors = flattener.one_hot(&mut statements, index.into(), 4);
+        assert_eq!(selectors.len(), 4);
+
+        statements.push(FlatStatement::Return(FlatExpressionList {
+            expressions: selectors,
+        }));
+
+        let prog = ir::Prog {
+            main: ir::Function::from(FlatFunction {
+                arguments: vec![FlatParameter::private(index)],
+                statements,
+            }),
+            private: vec![true],
         };
 
-        let mut flattener = Flattener::new();
+        let run = |index: u32| {
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(index)])
+                .map(|w| w.return_values())
+        };
 
-        let expected = FlatFunction {
-            arguments: vec![],
-            statements: vec![
-                FlatStatement::Definition(
-                    FlatVariable::new(0),
-                    FlatExpression::Number(FieldPrime::from(7)),
-                ),
-                FlatStatement::Definition(
-                    FlatVariable::new(1),
-                    FlatExpression::Mult(
-                        box FlatExpression::Identifier(FlatVariable::new(0)),
-                        box FlatExpression::Identifier(FlatVariable::new(0)),
-                    ),
-                ),
-                FlatStatement::Definition(
-                    FlatVariable::new(2),
-                    FlatExpression::Mult(
-                        box FlatExpression::Identifier(FlatVariable::new(1)),
-                        box FlatExpression::Identifier(FlatVariable::new(1)),
-                    ),
-                ),
-                FlatStatement::Definition(
-                    FlatVariable::new(3),
-                    FlatExpression::Mult(
-                        box FlatExpression::Identifier(FlatVariable::new(2)),
-                        box FlatExpression::Identifier(FlatVariable::new(2)),
-                    ),
-                ),
-                FlatStatement::Definition(
-                    FlatVariable::new(4),
-                    FlatExpression::Mult(
-                        box FlatExpression::Number(FieldPrime::from(1)),
-                        box FlatExpression::Identifier(FlatVariable::new(0)),
-                    ),
-                ),
-                FlatStatement::Definition(
-                    FlatVariable::new(5),
-                    FlatExpression::Mult(
-                        box FlatExpression::Identifier(FlatVariable::new(4)),
-                        box FlatExpression::Identifier(FlatVariable::new(2)),
-                    ),
-                ),
-                FlatStatement::Definition(
-                    FlatVariable::new(6),
-                    FlatExpression::Mult(
-                        box FlatExpression::Identifier(FlatVariable::new(5)),
-                        box FlatExpression::Identifier(FlatVariable::new(3)),
+        let n = |x: u32| FieldPrime::from(x);
+
+        assert_eq!(run(2), Ok(vec![n(0), n(0), n(1), n(0)]));
+        assert_eq!(run(0), Ok(vec![n(1), n(0), n(0), n(0)]));
+
+        // no selector can be set for an index out of range
+        assert!(run(4).is_err());
+    }
+
+    #[test]
+    fn conditional_swap() {
+        let mut flattener = Flattener::new();
+        let c = flattener.use_variable(&Variable::field_element("c".into()))[0];
+        let a = flattener.use_variable(&Variable::field_element("a".into()))[0];
+        let b = flattener.use_variable(&Variable::field_element("b".into()))[0];
+
+        let mut statements = vec![];
+        let (left, right) =
+            flattener.conditional_swap(&mut statements, c.into(), a.into(), b.into());
+
+        statements.push(FlatStatement::Return(FlatExpressionList {
+            expressions: vec![left, right],
+        }));
+
+        let prog = ir::Prog {
+            main: ir::Function::from(FlatFunction {
+                arguments: vec![
+                    FlatParameter::private(c),
+                    FlatParameter::private(a),
+                    FlatParameter::private(b),
+                ],
+                statements,
+            }),
+            private: vec![true; 3],
+        };
+
+        let n = |x: u32| FieldPrime::from(x);
+
+        let run = |c: u32| {
+            Interpreter::default()
+                .execute(&prog, &vec![n(c), n(3), n(42)])
+                .map(|w| w.return_values())
+        };
+
+        assert_eq!(run(0), Ok(vec![n(3), n(42)]));
+        assert_eq!(run(1), Ok(vec![n(42), n(3)]));
+
+        // the condition must be boolean
+        assert!(run(2).is_err());
+    }
+
+    #[test]
+    fn square_from_power() {
+        // def main(field x) -> (field):
+        //     return x**2
+        let function = TypedFunction {
+            arguments: vec![Parameter::private(Variable::field_element("x".into()))],
+            statements: vec![TypedStatement::Return(vec![FieldElementExpression::Pow(
+                box FieldElementExpression::Identifier("x".into()),
+                box FieldElementExpression::Number(FieldPrime::from(2)),
+            )
+            .into()])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let flat_function = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+        let function = ir::Function::from(flat_function);
+
+        let x = ir::LinComb::from(FlatVariable::new(0));
+
+        let squares: Vec<_> = function
+            .statements
+            .iter()
+            .filter_map(|s| s.try_square())
+            .collect();
+
+        assert_eq!(squares.len(), 1);
+        assert_eq!(squares[0].0, &x);
+    }
+
+    #[test]
+    fn flatten_program_streaming() {
+        // def main(private field a, private field b) -> (field):
+        //     assert_bool(a)
+        //     field c = if a < b then a * b else b fi
+        //     assert_bool(a)
+        //     return c
+        fn program<'ast>() -> TypedProgram<'ast, FieldPrime> {
+            let signature = Signature {
+                inputs: vec![Type::FieldElement, Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            };
+
+            main_program(
+                vec![
+                    Parameter::private(Variable::field_element("a".into())),
+                    Parameter::private(Variable::field_element("b".into())),
+                ],
+                vec![
+                    TypedStatement::AssertBool(FieldElementExpression::Identifier("a".into())),
+                    TypedStatement::Definition(
+                        TypedAssignee::Identifier(Variable::field_element("c".into())),
+                        FieldElementExpression::IfElse(
+                            box BooleanExpression::Lt(
+                                box FieldElementExpression::Identifier("a".into()),
+                                box FieldElementExpression::Identifier("b".into()),
+                            ),
+                            box FieldElementExpression::Mult(
+                                box FieldElementExpression::Identifier("a".into()),
+                                box FieldElementExpression::Identifier("b".into()),
+                            ),
+                            box FieldElementExpression::Identifier("b".into()),
+                        )
+                        .into(),
                     ),
+                    TypedStatement::AssertBool(FieldElementExpression::Identifier("a".into())),
+                    TypedStatement::Return(vec![
+                        FieldElementExpression::Identifier("c".into()).into()
+                    ]),
+                ],
+                signature,
+            )
+        }
+
+        let buffered = Flattener::new().flatten_program(program()).unwrap();
+
+        let mut statements = vec![];
+        let arguments = Flattener::new()
+            .flatten_program_streaming(program(), |s| statements.push(s))
+            .unwrap();
+
+        assert_eq!(
+            FlatFunction {
+                arguments,
+                statements,
+            },
+            buffered.main
+        );
+    }
+
+    #[test]
+    fn checked_comparisons() {
+        let prog = |checked: bool| {
+            let mut flattener = Flattener::with_bits(8).checked_comparisons(checked);
+            let a = flattener.use_variable(&Variable::field_element("a".into()))[0];
+            let b = flattener.use_variable(&Variable::field_element("b".into()))[0];
+
+            let mut statements = vec![];
+            let lt = flattener.flatten_boolean_expression(
+                &HashMap::new(),
+                &mut statements,
+                BooleanExpression::Lt(
+                    box FieldElementExpression::Identifier("a".into()),
+                    box FieldElementExpression::Identifier("b".into()),
                 ),
-                FlatStatement::Definition(
-                    FlatVariable::new(7),
-                    FlatExpression::Identifier(FlatVariable::new(6)),
-                ),
-                FlatStatement::Return(FlatExpressionList {
-                    expressions: vec![FlatExpression::Identifier(FlatVariable::new(7))],
+            );
+            statements.push(FlatStatement::Return(FlatExpressionList {
+                expressions: vec![lt],
+            }));
+
+            ir::Prog {
+                main: ir::Function::from(FlatFunction {
+                    arguments: vec![FlatParameter::private(a), FlatParameter::private(b)],
+                    statements,
                 }),
-            ],
+                private: vec![true; 2],
+            }
+        };
+
+        let run = |prog: &ir::Prog<FieldPrime>, a: u32, b: u32| {
+            Interpreter::default()
+                .execute(prog, &vec![FieldPrime::from(a), FieldPrime::from(b)])
+                .map(|w| w.return_values())
         };
 
-        let flattened = flattener.flatten_function(&mut HashMap::new(), function);
+        let unchecked = prog(false);
+        let checked = prog(true);
 
-        assert_eq!(flattened, expected);
+        assert_eq!(run(&checked, 3, 5), Ok(vec![FieldPrime::from(1)]));
+        assert_eq!(run(&checked, 255, 5), Ok(vec![FieldPrime::from(0)]));
+
+        // 256 does not fit in 8 bits
+        assert_eq!(run(&unchecked, 256, 5), Ok(vec![FieldPrime::from(0)]));
+        assert!(run(&checked, 256, 5).is_err());
+        assert!(run(&checked, 3, 256).is_err());
     }
 
     #[test]
-    fn if_else() {
-        let expression = FieldElementExpression::IfElse(
-            box BooleanExpression::FieldEq(
-                box FieldElementExpression::Number(FieldPrime::from(32)),
-                box FieldElementExpression::Number(FieldPrime::from(4)),
-            ),
-            box FieldElementExpression::Number(FieldPrime::from(12)),
-            box FieldElementExpression::Number(FieldPrime::from(51)),
+    fn int_div_mod() {
+        let mut flattener = Flattener::new();
+        let a = flattener.use_variable(&Variable::field_element("a".into()))[0];
+        let b = flattener.use_variable(&Variable::field_element("b".into()))[0];
+
+        let mut statements = vec![];
+        let field_max = (FieldPrime::get_required_bits() - 2) / 2;
+        assert_eq!(
+            flattener.int_div_mod(&mut statements, a.into(), b.into(), field_max + 1),
+            Err(FlattenError::BitWidthTooLarge {
+                requested: field_max + 1,
+                field_max
+            })
         );
+        assert_eq!(statements, vec![]);
 
-        let mut flattener = Flattener::new();
+        let (q, r) = flattener
+            .int_div_mod(&mut statements, a.into(), b.into(), 16)
+            .unwrap();
 
-        flattener.flatten_field_expression(&HashMap::new(), &mut vec![], expression);
+        statements.push(FlatStatement::Return(FlatExpressionList {
+            expressions: vec![q, r],
+        }));
+
+        let prog = ir::Prog {
+            main: ir::Function::from(FlatFunction {
+                arguments: vec![FlatParameter::private(a), FlatParameter::private(b)],
+                statements,
+            }),
+            private: vec![true; 2],
+        };
+
+        let n = |x: u32| FieldPrime::from(x);
+
+        let run = |a: u32, b: u32| {
+            Interpreter::default()
+                .execute(&prog, &vec![n(a), n(b)])
+                .map(|w| w.return_values())
+        };
+
+        assert_eq!(run(47, 5), Ok(vec![n(9), n(2)]));
+        assert_eq!(run(4, 7), Ok(vec![n(0), n(4)]));
+        assert_eq!(run(65535, 1), Ok(vec![n(65535), n(0)]));
+
+        // division by zero
+        assert!(run(47, 0).is_err());
     }
 
     #[test]
-    fn geq_leq() {
-        let mut flattener = Flattener::new();
-        let expression_le = BooleanExpression::Le(
-            box FieldElementExpression::Number(FieldPrime::from(32)),
-            box FieldElementExpression::Number(FieldPrime::from(4)),
-        );
-        flattener.flatten_boolean_expression(&HashMap::new(), &mut vec![], expression_le);
+    fn reduce_bits() {
+        // def main(private field a) -> (field):
+        //     return reduce_bits(a, 8)
 
-        let mut flattener = Flattener::new();
-        let expression_ge = BooleanExpression::Ge(
-            box FieldElementExpression::Number(FieldPrime::from(32)),
-            box FieldElementExpression::Number(FieldPrime::from(4)),
-        );
-        flattener.flatten_boolean_expression(&HashMap::new(), &mut vec![], expression_ge);
+        let function = TypedFunction {
+            arguments: vec![Parameter::private(Variable::field_element("a".into()))],
+            statements: vec![TypedStatement::Return(vec![
+                FieldElementExpression::ReduceBits(
+                    box FieldElementExpression::Identifier("a".into()),
+                    8,
+                )
+                .into(),
+            ])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let flat_function = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        let prog = ir::Prog {
+            main: ir::Function::from(flat_function),
+            private: vec![true],
+        };
+
+        let run = |a: i32| {
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(a)])
+                .map(|w| w.return_values())
+        };
+
+        // values on 8 bits are unchanged
+        assert_eq!(run(42), Ok(vec![FieldPrime::from(42)]));
+        assert_eq!(run(255), Ok(vec![FieldPrime::from(255)]));
+
+        // larger values are truncated to their lowest bits
+        assert_eq!(run(256 + 42), Ok(vec![FieldPrime::from(42)]));
+        // -2 is represented by p - 2, whose lowest 8 bits are all set
+        assert_eq!(run(-2), Ok(vec![FieldPrime::from(255)]));
     }
 
     #[test]
-    fn bool_and() {
-        let mut flattener = Flattener::new();
+    fn reduce_bits_too_large() {
+        // def main(private field a) -> (field):
+        //     return reduce_bits(a, bits)
+        let program = |bits: usize| {
+            main_program(
+                vec![Parameter::private(Variable::field_element("a".into()))],
+                vec![TypedStatement::Return(vec![
+                    FieldElementExpression::ReduceBits(
+                        box FieldElementExpression::Identifier("a".into()),
+                        bits,
+                    )
+                    .into(),
+                ])],
+                Signature::new()
+                    .inputs(vec![Type::FieldElement])
+                    .outputs(vec![Type::FieldElement]),
+            )
+        };
 
-        let expression = FieldElementExpression::IfElse(
-            box BooleanExpression::And(
-                box BooleanExpression::FieldEq(
-                    box FieldElementExpression::Number(FieldPrime::from(4)),
-                    box FieldElementExpression::Number(FieldPrime::from(4)),
-                ),
-                box BooleanExpression::Lt(
-                    box FieldElementExpression::Number(FieldPrime::from(4)),
-                    box FieldElementExpression::Number(FieldPrime::from(20)),
-                ),
-            ),
-            box FieldElementExpression::Number(FieldPrime::from(12)),
-            box FieldElementExpression::Number(FieldPrime::from(51)),
+        let field_max = FieldPrime::get_required_bits() - 1;
+
+        assert!(Flattener::new().flatten_program(program(field_max)).is_ok());
+
+        assert_eq!(
+            Flattener::new().flatten_program(program(field_max + 1)),
+            Err(FlattenError::BitWidthTooLarge {
+                requested: field_max + 1,
+                field_max
+            })
         );
+    }
 
-        flattener.flatten_field_expression(&HashMap::new(), &mut vec![], expression);
+    #[test]
+    fn from_config() {
+        let flattener: Flattener<FieldPrime> = Flattener::from_config(FlattenerConfig {
+            bits: Some(8),
+            constraint_budget: Some(100),
+            checked_comparisons: true,
+            validity_output: true,
+            output_name: Some("result".to_string()),
+            int_semantics: IntSemantics::WrappingBits(8),
+        });
+
+        assert_eq!(flattener.bits, 8);
+        assert_eq!(flattener.constraint_budget, Some(100));
+        assert!(flattener.checked_comparisons);
+        assert!(flattener.validity_output);
+        assert_eq!(flattener.output_name, Some("result".to_string()));
+        assert_eq!(flattener.int_semantics, IntSemantics::WrappingBits(8));
+
+        // the default configuration is the one of `Flattener::default`
+        let flattener: Flattener<FieldPrime> = Flattener::from_config(FlattenerConfig::default());
+        let default: Flattener<FieldPrime> = Flattener::default();
+
+        assert_eq!(flattener.bits, FieldPrime::get_required_bits() - 2);
+        assert_eq!(flattener.bits, default.bits);
+        assert_eq!(flattener.constraint_budget, None);
+        assert!(!flattener.checked_comparisons);
+        assert!(!flattener.validity_output);
+        assert_eq!(flattener.int_semantics, IntSemantics::Field);
     }
 
     #[test]
-    fn div() {
-        // a = 5 / b / b
+    fn flatten_program_into() {
+        // def main(private field a) -> (field):
+        //     field b = a * a
+        //     return b
+        fn program<'ast>() -> TypedProgram<'ast, FieldPrime> {
+            let signature = Signature {
+                inputs: vec![Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            };
 
-        let mut flattener = Flattener::new();
-        let mut statements_flattened = vec![];
+            main_program(
+                vec![Parameter::private(Variable::field_element("a".into()))],
+                vec![
+                    TypedStatement::Definition(
+                        TypedAssignee::Identifier(Variable::field_element("b".into())),
+                        FieldElementExpression::Mult(
+                            box FieldElementExpression::Identifier("a".into()),
+                            box FieldElementExpression::Identifier("a".into()),
+                        )
+                        .into(),
+                    ),
+                    TypedStatement::Return(vec![
+                        FieldElementExpression::Identifier("b".into()).into()
+                    ]),
+                ],
+                signature,
+            )
+        }
 
-        let definition = TypedStatement::Definition(
-            TypedAssignee::Identifier(Variable::field_element("b".into())),
-            FieldElementExpression::Number(FieldPrime::from(42)).into(),
+        let defined = |arguments: &[FlatParameter], statements: &[FlatStatement<FieldPrime>]| {
+            arguments
+                .iter()
+                .map(|p| p.id)
+                .chain(statements.iter().flat_map(|s| match s {
+                    FlatStatement::Definition(v, _) => vec![*v],
+                    FlatStatement::Directive(d) => d.outputs.clone(),
+                    _ => vec![],
+                }))
+                .collect::<HashSet<_>>()
+        };
+
+        let mut flattener = Flattener::with_next_index(FieldPrime::get_required_bits() - 2, 0);
+        let mut statements = vec![];
+
+        let first = flattener
+            .flatten_program_into(program(), &mut statements)
+            .unwrap();
+        let len = statements.len();
+        let second = flattener
+            .flatten_program_into(program(), &mut statements)
+            .unwrap();
+
+        // the first program is kept as is, and is flattened like on its own
+        assert_eq!(
+            FlatProg {
+                main: FlatFunction {
+                    arguments: first.clone(),
+                    statements: statements[..len].to_vec(),
+                }
+            },
+            Flattener::new().flatten_program(program()).unwrap()
         );
 
-        let statement = TypedStatement::Definition(
-            TypedAssignee::Identifier(Variable::field_element("a".into())),
-            FieldElementExpression::Div(
-                box FieldElementExpression::Div(
-                    box FieldElementExpression::Number(FieldPrime::from(5)),
-                    box FieldElementExpression::Identifier("b".into()),
-                ),
-                box FieldElementExpression::Identifier("b".into()),
+        // the variables of the two programs do not collide
+        let first = defined(&first, &statements[..len]);
+        let second = defined(&second, &statements[len..]);
+
+        assert!(!second.is_empty());
+        assert!(first.is_disjoint(&second));
+    }
+
+    #[test]
+    fn assert_permutation() {
+        let mut flattener = Flattener::new();
+        let a: Vec<FlatVariable> = (0..3).map(|_| flattener.use_sym()).collect();
+        let b: Vec<FlatVariable> = (0..3).map(|_| flattener.use_sym()).collect();
+
+        let mut statements = vec![];
+        let expressions = |v: &[FlatVariable]| v.iter().map(|v| (*v).into()).collect::<Vec<_>>();
+        flattener
+            .assert_permutation(
+                &mut statements,
+                &expressions(&a),
+                &expressions(&b),
+                FieldPrime::from(42),
             )
-            .into(),
-        );
+            .unwrap();
+
+        statements.push(FlatStatement::Return(FlatExpressionList {
+            expressions: vec![],
+        }));
+
+        let prog = ir::Prog {
+            main: ir::Function::from(FlatFunction {
+                arguments: a
+                    .iter()
+                    .chain(b.iter())
+                    .map(|v| FlatParameter::private(*v))
+                    .collect(),
+                statements,
+            }),
+            private: vec![true; 6],
+        };
 
-        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, definition);
+        let run = |values: Vec<u32>| {
+            Interpreter::default()
+                .execute(&prog, &values.into_iter().map(FieldPrime::from).collect())
+        };
 
-        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, statement);
+        assert!(run(vec![1, 2, 3, 3, 1, 2]).is_ok());
+        assert!(run(vec![1, 2, 2, 2, 1, 2]).is_ok());
 
-        // define b
-        let b = FlatVariable::new(0);
-        // define new wires for members of Div
-        let five = FlatVariable::new(1);
-        let b0 = FlatVariable::new(2);
-        // Define inverse of denominator to prevent div by 0
-        let invb0 = FlatVariable::new(3);
-        // Define inverse
-        let sym_0 = FlatVariable::new(4);
-        // Define result, which is first member to next Div
-        let sym_1 = FlatVariable::new(5);
-        // Define second member
-        let b1 = FlatVariable::new(6);
-        // Define inverse of denominator to prevent div by 0
-        let invb1 = FlatVariable::new(7);
-        // Define inverse
-        let sym_2 = FlatVariable::new(8);
-        // Define left hand side
-        let a = FlatVariable::new(9);
+        // same sum, different values
+        assert!(run(vec![1, 2, 3, 2, 2, 2]).is_err());
+        assert!(run(vec![1, 2, 3, 1, 1, 3]).is_err());
 
         assert_eq!(
-            statements_flattened,
+            flattener.assert_permutation(
+                &mut vec![],
+                &expressions(&a),
+                &expressions(&b[..2]),
+                FieldPrime::from(42)
+            ),
+            Err(FlattenError::LengthMismatch { left: 3, right: 2 })
+        );
+    }
+
+    #[test]
+    fn validity_output() {
+        // def main(private field a, private field b) -> (field):
+        //     a == b
+        //     a * a == 4
+        //     return a
+        let signature = Signature {
+            inputs: vec![Type::FieldElement, Type::FieldElement],
+            outputs: vec![Type::FieldElement],
+        };
+
+        let program = main_program(
             vec![
-                FlatStatement::Definition(b, FlatExpression::Number(FieldPrime::from(42))),
-                // inputs to first div (5/b)
-                FlatStatement::Definition(five, FlatExpression::Number(FieldPrime::from(5))),
-                FlatStatement::Definition(b0, b.into()),
-                // check div by 0
-                FlatStatement::Directive(FlatDirective::new(
-                    vec![invb0],
-                    Solver::Div,
-                    vec![FlatExpression::Number(FieldPrime::from(1)), b0.into()]
-                )),
-                FlatStatement::Condition(
-                    FlatExpression::Number(FieldPrime::from(1)),
-                    FlatExpression::Mult(box invb0.into(), box b0.into()),
-                ),
-                // execute div
-                FlatStatement::Directive(FlatDirective::new(
-                    vec![sym_0],
-                    Solver::Div,
-                    vec![five, b0]
-                )),
-                FlatStatement::Condition(
-                    five.into(),
-                    FlatExpression::Mult(box b0.into(), box sym_0.into()),
-                ),
-                // inputs to second div (res/b)
-                FlatStatement::Definition(sym_1, sym_0.into()),
-                FlatStatement::Definition(b1, b.into()),
-                // check div by 0
-                FlatStatement::Directive(FlatDirective::new(
-                    vec![invb1],
-                    Solver::Div,
-                    vec![FlatExpression::Number(FieldPrime::from(1)), b1.into()]
-                )),
-                FlatStatement::Condition(
-                    FlatExpression::Number(FieldPrime::from(1)),
-                    FlatExpression::Mult(box invb1.into(), box b1.into()),
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+            ],
+            vec![
+                TypedStatement::Condition(
+                    FieldElementExpression::Identifier("a".into()).into(),
+                    FieldElementExpression::Identifier("b".into()).into(),
                 ),
-                // execute div
-                FlatStatement::Directive(FlatDirective::new(
-                    vec![sym_2],
-                    Solver::Div,
-                    vec![sym_1, b1]
-                )),
-                FlatStatement::Condition(
-                    sym_1.into(),
-                    FlatExpression::Mult(box b1.into(), box sym_2.into()),
+                TypedStatement::Condition(
+                    FieldElementExpression::Mult(
+                        box FieldElementExpression::Identifier("a".into()),
+                        box FieldElementExpression::Identifier("a".into()),
+                    )
+                    .into(),
+                    FieldElementExpression::Number(FieldPrime::from(4)).into(),
                 ),
-                // result
-                FlatStatement::Definition(a, sym_2.into()),
-            ]
+                TypedStatement::Return(vec![FieldElementExpression::Identifier("a".into()).into()]),
+            ],
+            signature,
         );
+
+        let flat_prog = Flattener::new()
+            .validity_output(true)
+            .flatten_program(program)
+            .unwrap();
+
+        let prog = ir::Prog::from(flat_prog);
+
+        let run = |a: i32, b: i32| {
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(a), FieldPrime::from(b)])
+                .map(|w| w.return_values())
+        };
+
+        let valid = |a: i32| Ok(vec![FieldPrime::from(a), FieldPrime::from(1)]);
+        let invalid = |a: i32| Ok(vec![FieldPrime::from(a), FieldPrime::from(0)]);
+
+        // the flag is set exactly when both conditions hold, and the program is satisfied either way
+        assert_eq!(run(2, 2), valid(2));
+        assert_eq!(run(-2, -2), valid(-2));
+        assert_eq!(run(2, 3), invalid(2));
+        assert_eq!(run(3, 3), invalid(3));
+        assert_eq!(run(3, 2), invalid(3));
     }
 
     #[test]
-    fn field_array() {
-        // foo = [ , , ]
-
-        let mut flattener = Flattener::new();
-        let mut statements_flattened = vec![];
-        let statement = TypedStatement::Definition(
-            TypedAssignee::Identifier(Variable::field_array("foo".into(), 3)),
-            ArrayExpressionInner::Value(vec![
-                FieldElementExpression::Number(FieldPrime::from(1)).into(),
-                FieldElementExpression::Number(FieldPrime::from(2)).into(),
-                FieldElementExpression::Number(FieldPrime::from(3)).into(),
-            ])
-            .annotate(Type::FieldElement, 3)
-            .into(),
-        );
-        let expression =
-            ArrayExpressionInner::Identifier("foo".into()).annotate(Type::FieldElement, 3);
+    fn empty_program() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        // def main(private field a) -> ():
+        //     field b = a * a
+        fn program<'ast>() -> TypedProgram<'ast, FieldPrime> {
+            let signature = Signature {
+                inputs: vec![Type::FieldElement],
+                outputs: vec![],
+            };
 
-        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, statement);
+            main_program(
+                vec![Parameter::private(Variable::field_element("a".into()))],
+                vec![TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element("b".into())),
+                    FieldElementExpression::Mult(
+                        box FieldElementExpression::Identifier("a".into()),
+                        box FieldElementExpression::Identifier("a".into()),
+                    )
+                    .into(),
+                )],
+                signature,
+            )
+        }
 
-        let expressions = flattener.flatten_array_expression::<FieldElementExpression<_>>(
-            &HashMap::new(),
-            &mut statements_flattened,
-            expression,
+        assert_eq!(
+            Flattener::flatten(program()),
+            Err(FlattenError::EmptyProgram)
+        );
+        assert_eq!(
+            Flattener::new().flatten_program_streaming(program(), |_| {}),
+            Err(FlattenError::EmptyProgram)
         );
 
+        // the rejected statements are not observed
+        let count = Rc::new(Cell::new(0));
+        let counter = count.clone();
         assert_eq!(
-            expressions,
-            vec![
-                FlatExpression::Identifier(FlatVariable::new(0)),
-                FlatExpression::Identifier(FlatVariable::new(1)),
-                FlatExpression::Identifier(FlatVariable::new(2)),
-            ]
+            Flattener::flatten_with_observer(program(), move |_| counter.set(counter.get() + 1)),
+            Err(FlattenError::EmptyProgram)
         );
+        assert_eq!(count.get(), 0);
     }
 
     #[test]
-    fn array_definition() {
-        // field[3] foo = [1, 2, 3]
+    fn lt_bits_consumed() {
+        // x < y
         let mut flattener = Flattener::new();
-        let mut statements_flattened = vec![];
-        let statement = TypedStatement::Definition(
-            TypedAssignee::Identifier(Variable::field_array("foo".into(), 3)),
-            ArrayExpressionInner::Value(vec![
-                FieldElementExpression::Number(FieldPrime::from(1)).into(),
-                FieldElementExpression::Number(FieldPrime::from(2)).into(),
-                FieldElementExpression::Number(FieldPrime::from(3)).into(),
-            ])
-            .annotate(Type::FieldElement, 3)
-            .into(),
+        flattener.use_variable(&Variable::field_element("x".into()));
+        flattener.use_variable(&Variable::field_element("y".into()));
+
+        let mut statements = vec![];
+        flattener.flatten_boolean_expression(
+            &HashMap::new(),
+            &mut statements,
+            BooleanExpression::Lt(
+                box FieldElementExpression::Identifier("x".into()),
+                box FieldElementExpression::Identifier("y".into()),
+            ),
         );
 
-        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, statement);
+        // the bits of a recomposition `0 + b_0 * 2^n + ... + b_n * 2^0`, in order
+        fn recomposed(e: &FlatExpression<FieldPrime>) -> Option<Vec<FlatVariable>> {
+            match *e {
+                FlatExpression::Number(ref n) if *n == FieldPrime::from(0) => Some(vec![]),
+                FlatExpression::Add(
+                    box ref acc,
+                    box FlatExpression::Mult(
+                        box FlatExpression::Identifier(bit),
+                        box FlatExpression::Number(_),
+                    ),
+                ) => recomposed(acc).map(|mut bits| {
+                    bits.push(bit);
+                    bits
+                }),
+                _ => None,
+            }
+        }
 
-        assert_eq!(
-            statements_flattened,
-            vec![
-                FlatStatement::Definition(
-                    FlatVariable::new(0),
-                    FlatExpression::Number(FieldPrime::from(1))
-                ),
-                FlatStatement::Definition(
-                    FlatVariable::new(1),
-                    FlatExpression::Number(FieldPrime::from(2))
-                ),
-                FlatStatement::Definition(
-                    FlatVariable::new(2),
-                    FlatExpression::Number(FieldPrime::from(3))
-                ),
-            ]
-        );
+        let mut checked = HashSet::new();
+        let mut recompositions = vec![];
+        for s in &statements {
+            match *s {
+                FlatStatement::Condition(
+                    FlatExpression::Identifier(v),
+                    FlatExpression::Mult(box ref l, box ref r),
+                ) if *l == FlatExpression::Identifier(v) && l == r => {
+                    checked.insert(v);
+                }
+                FlatStatement::Condition(_, ref rhs) => {
+                    recompositions.extend(recomposed(rhs).filter(|bits| !bits.is_empty()))
+                }
+                _ => {}
+            }
+        }
+
+        let decompositions: Vec<_> = statements
+            .iter()
+            .filter_map(|s| match s {
+                FlatStatement::Directive(d) => Some(d.outputs.clone()),
+                _ => None,
+            })
+            .collect();
+
+        // lhs, rhs and their difference
+        assert_eq!(decompositions.len(), 3);
+        assert_eq!(recompositions.len(), 3);
+
+        for (bits, recomposed) in decompositions.iter().zip(recompositions.iter()) {
+            assert_eq!(bits.len(), FieldPrime::get_required_bits());
+            let checked_bits: Vec<_> = bits
+                .iter()
+                .filter(|b| checked.contains(*b))
+                .cloned()
+                .collect();
+            // each bit which is checked is recomposed, in the same order
+            assert_eq!(&checked_bits, recomposed);
+        }
     }
 
     #[test]
-    fn array_selection() {
-        // field[3] foo = [1, 2, 3]
-        // foo[1]
+    fn power_by_squaring() {
+        // def main(field x) -> (field):
+        //     return x**8
+        let x = || FieldElementExpression::Identifier("x".into());
+        let main = |e: FieldElementExpression<'static, FieldPrime>| TypedFunction {
+            arguments: vec![Parameter::private(Variable::field_element("x".into()))],
+            statements: vec![TypedStatement::Return(vec![e.into()])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
 
-        let mut flattener = Flattener::new();
-        let mut statements_flattened = vec![];
-        let statement = TypedStatement::Definition(
-            TypedAssignee::Identifier(Variable::field_array("foo".into(), 3)),
-            ArrayExpressionInner::Value(vec![
-                FieldElementExpression::Number(FieldPrime::from(1)).into(),
-                FieldElementExpression::Number(FieldPrime::from(2)).into(),
-                FieldElementExpression::Number(FieldPrime::from(3)).into(),
-            ])
-            .annotate(Type::FieldElement, 3)
-            .into(),
-        );
+        let power = Flattener::new()
+            .flatten_function(
+                &mut HashMap::new(),
+                main(FieldElementExpression::Pow(
+                    box x(),
+                    box FieldElementExpression::Number(FieldPrime::from(8)),
+                )),
+            )
+            .unwrap();
 
-        let expression = FieldElementExpression::Select(
-            box ArrayExpressionInner::Identifier("foo".into()).annotate(Type::FieldElement, 3),
-            box FieldElementExpression::Number(FieldPrime::from(1)),
-        );
+        // x**2, x**4 and x**8 are squares, and the result is 1 * x**8
+        let products: Vec<_> = power
+            .statements
+            .iter()
+            .filter_map(|s| match *s {
+                FlatStatement::Definition(_, FlatExpression::Mult(box ref l, box ref r)) => {
+                    Some(l == r)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(products, vec![true, true, true, false]);
 
-        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, statement);
+        // x * x * x * x * x * x * x * x
+        let naive = Flattener::new()
+            .flatten_function(
+                &mut HashMap::new(),
+                main((1..8).fold(x(), |acc, _| FieldElementExpression::Mult(box acc, box x()))),
+            )
+            .unwrap();
 
-        let flat_expression = flattener.flatten_field_expression(
-            &HashMap::new(),
-            &mut statements_flattened,
-            expression,
-        );
+        let run = |f: FlatFunction<FieldPrime>, x: u32| {
+            let prog = ir::Prog {
+                main: ir::Function::from(f),
+                private: vec![true],
+            };
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(x)])
+                .map(|w| w.return_values())
+                .unwrap()
+        };
+
+        assert_eq!(run(power.clone(), 3), vec![FieldPrime::from(6561)]);
+        for x in 0..5 {
+            assert_eq!(run(power.clone(), x), run(naive.clone(), x));
+        }
+    }
+
+    #[test]
+    fn power_non_linear_base() {
+        // def main(field x) -> (field):
+        //     return (x * x)**3
+        let function = TypedFunction {
+            arguments: vec![Parameter::private(Variable::field_element("x".into()))],
+            statements: vec![TypedStatement::Return(vec![FieldElementExpression::Pow(
+                box FieldElementExpression::Mult(
+                    box FieldElementExpression::Identifier("x".into()),
+                    box FieldElementExpression::Identifier("x".into()),
+                ),
+                box FieldElementExpression::Number(FieldPrime::from(3)),
+            )
+            .into()])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let prog = ir::Prog {
+            main: ir::Function::from(
+                Flattener::new()
+                    .flatten_function(&mut HashMap::new(), function)
+                    .unwrap(),
+            ),
+            private: vec![true],
+        };
 
         assert_eq!(
-            flat_expression,
-            FlatExpression::Identifier(FlatVariable::new(1)),
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(2)])
+                .map(|w| w.return_values()),
+            Ok(vec![FieldPrime::from(64)])
         );
     }
 
     #[test]
-    fn array_sum() {
-        // field[3] foo = [1, 2, 3]
-        // bar = foo[0] + foo[1] + foo[2]
-        // we don't optimise detecting constants, this will be done in an optimiser pass
+    fn constant() {
+        // const field N = 41
+        // def main(field a) -> (field):
+        //     return a + N
+        let function = TypedFunction {
+            arguments: vec![Parameter::private(Variable::field_element("a".into()))],
+            statements: vec![TypedStatement::Return(vec![FieldElementExpression::Add(
+                box FieldElementExpression::Identifier("a".into()),
+                box FieldElementExpression::Identifier("N".into()),
+            )
+            .into()])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
 
-        let mut flattener = Flattener::new();
+        let flattened = Flattener::new()
+            .constant("N".into(), FieldPrime::from(41))
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
 
-        let mut statements_flattened = vec![];
-        let def = TypedStatement::Definition(
-            TypedAssignee::Identifier(Variable::field_array("foo".into(), 3)),
-            ArrayExpressionInner::Value(vec![
-                FieldElementExpression::Number(FieldPrime::from(1)).into(),
-                FieldElementExpression::Number(FieldPrime::from(2)).into(),
-                FieldElementExpression::Number(FieldPrime::from(3)).into(),
-            ])
-            .annotate(Type::FieldElement, 3)
-            .into(),
+        // `N` is folded into a number rather than read from a variable
+        assert_eq!(
+            flattened.statements,
+            vec![FlatStatement::Return(FlatExpressionList {
+                expressions: vec![FlatExpression::Add(
+                    box FlatExpression::Identifier(FlatVariable::new(0)),
+                    box FlatExpression::Number(FieldPrime::from(41)),
+                )],
+            })]
         );
+    }
 
-        let sum = TypedStatement::Definition(
-            TypedAssignee::Identifier(Variable::field_element("bar".into())),
-            FieldElementExpression::Add(
-                box FieldElementExpression::Add(
-                    box FieldElementExpression::Select(
-                        box ArrayExpressionInner::Identifier("foo".into())
-                            .annotate(Type::FieldElement, 3),
-                        box FieldElementExpression::Number(FieldPrime::from(0)),
-                    ),
-                    box FieldElementExpression::Select(
-                        box ArrayExpressionInner::Identifier("foo".into())
-                            .annotate(Type::FieldElement, 3),
-                        box FieldElementExpression::Number(FieldPrime::from(1)),
+    #[test]
+    fn constant_assigned() {
+        // const field N = 41
+        // def main(field <argument>) -> (field):
+        //     field <variable> = 1
+        //     return 1
+        let one = || FieldElementExpression::Number(FieldPrime::from(1));
+
+        let program = |argument: &'static str, variable: &'static str| {
+            main_program(
+                vec![Parameter::private(Variable::field_element(argument.into()))],
+                vec![
+                    TypedStatement::Definition(
+                        TypedAssignee::Identifier(Variable::field_element(variable.into())),
+                        one().into(),
                     ),
-                ),
-                box FieldElementExpression::Select(
-                    box ArrayExpressionInner::Identifier("foo".into())
-                        .annotate(Type::FieldElement, 3),
-                    box FieldElementExpression::Number(FieldPrime::from(2)),
-                ),
+                    TypedStatement::Return(vec![one().into()]),
+                ],
+                Signature::new()
+                    .inputs(vec![Type::FieldElement])
+                    .outputs(vec![Type::FieldElement]),
             )
-            .into(),
+        };
+
+        let flatten = |argument, variable| {
+            Flattener::new()
+                .constant("N".into(), FieldPrime::from(41))
+                .flatten_program(program(argument, variable))
+                .map(|_| ())
+        };
+
+        let expected = || FlattenError::ConstantAssigned {
+            name: String::from("N"),
+        };
+
+        assert_eq!(flatten("a", "b"), Ok(()));
+        assert_eq!(flatten("N", "b"), Err(expected()));
+        assert_eq!(flatten("a", "N"), Err(expected()));
+    }
+
+    #[test]
+    fn pop_count() {
+        // def main(private field a) -> (field):
+        //     return pop_count(a, 8)
+
+        let function = TypedFunction {
+            arguments: vec![Parameter::private(Variable::field_element("a".into()))],
+            statements: vec![TypedStatement::Return(vec![
+                FieldElementExpression::PopCount(
+                    box FieldElementExpression::Identifier("a".into()),
+                    8,
+                )
+                .into(),
+            ])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            },
+        };
+
+        let flat_function = Flattener::new()
+            .flatten_function(&mut HashMap::new(), function)
+            .unwrap();
+
+        let prog = ir::Prog {
+            main: ir::Function::from(flat_function),
+            private: vec![true],
+        };
+
+        let run = |a: u32| {
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(a)])
+                .map(|w| w.return_values())
+        };
+
+        assert_eq!(run(0), Ok(vec![FieldPrime::from(0)]));
+        // 0b10110101
+        assert_eq!(run(181), Ok(vec![FieldPrime::from(5)]));
+        assert_eq!(run(255), Ok(vec![FieldPrime::from(8)]));
+        // values which do not fit in 8 bits are rejected
+        assert!(run(256).is_err());
+    }
+
+    #[test]
+    fn pop_count_too_large() {
+        // def main(private field a) -> (field):
+        //     return pop_count(a, bits)
+        let program = |bits: usize| {
+            main_program(
+                vec![Parameter::private(Variable::field_element("a".into()))],
+                vec![TypedStatement::Return(vec![
+                    FieldElementExpression::PopCount(
+                        box FieldElementExpression::Identifier("a".into()),
+                        bits,
+                    )
+                    .into(),
+                ])],
+                Signature::new()
+                    .inputs(vec![Type::FieldElement])
+                    .outputs(vec![Type::FieldElement]),
+            )
+        };
+
+        let field_max = FieldPrime::get_required_bits() - 1;
+
+        assert!(Flattener::new().flatten_program(program(field_max)).is_ok());
+
+        assert_eq!(
+            Flattener::new().flatten_program(program(field_max + 1)),
+            Err(FlattenError::BitWidthTooLarge {
+                requested: field_max + 1,
+                field_max
+            })
         );
+    }
 
-        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, def);
+    #[test]
+    fn flatten_prefix() {
+        // def main(private field a) -> (field):
+        //     field b = a * a
+        //     return b
+        let signature = Signature {
+            inputs: vec![Type::FieldElement],
+            outputs: vec![Type::FieldElement],
+        };
+
+        let prog = main_program(
+            vec![Parameter::private(Variable::field_element("a".into()))],
+            vec![
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(Variable::field_element("b".into())),
+                    FieldElementExpression::Mult(
+                        box FieldElementExpression::Identifier("a".into()),
+                        box FieldElementExpression::Identifier("a".into()),
+                    )
+                    .into(),
+                ),
+                TypedStatement::Return(vec![FieldElementExpression::Identifier("b".into()).into()]),
+            ],
+            signature,
+        );
 
-        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, sum);
+        let prefix = Flattener::new().flatten_prefix(&prog, 1).unwrap();
 
         assert_eq!(
-            statements_flattened[3],
-            FlatStatement::Definition(
-                FlatVariable::new(3),
-                FlatExpression::Add(
-                    box FlatExpression::Add(
+            prefix.main,
+            FlatFunction {
+                arguments: vec![FlatParameter::private(FlatVariable::new(0))],
+                statements: vec![FlatStatement::Definition(
+                    FlatVariable::new(1),
+                    FlatExpression::Mult(
+                        box FlatExpression::Identifier(FlatVariable::new(0)),
                         box FlatExpression::Identifier(FlatVariable::new(0)),
-                        box FlatExpression::Identifier(FlatVariable::new(1)),
                     ),
-                    box FlatExpression::Identifier(FlatVariable::new(2)),
-                )
-            )
+                )],
+            }
         );
-    }
-
-    #[test]
-    fn array_of_array_sum() {
-        // field[2][2] foo = [[1, 2], [3, 4]]
-        // bar = foo[0][0] + foo[0][1] + foo[1][0] + foo[1][1]
-        // we don't optimise detecting constants, this will be done in an optimiser pass
 
-        let mut flattener = Flattener::new();
+        // the program is checked even if no statement is flattened
+        assert_eq!(
+            Flattener::with_bits(0).flatten_prefix(&prog, 0),
+            Err(FlattenError::BitWidthZero)
+        );
 
-        let mut statements_flattened = vec![];
-        let def = TypedStatement::Definition(
-            TypedAssignee::Identifier(Variable::field_array("foo".into(), 4)),
-            ArrayExpressionInner::Value(vec![
-                ArrayExpressionInner::Value(vec![
-                    FieldElementExpression::Number(FieldPrime::from(1)).into(),
-                    FieldElementExpression::Number(FieldPrime::from(2)).into(),
-                ])
-                .annotate(Type::FieldElement, 2)
-                .into(),
-                ArrayExpressionInner::Value(vec![
-                    FieldElementExpression::Number(FieldPrime::from(3)).into(),
-                    FieldElementExpression::Number(FieldPrime::from(4)).into(),
-                ])
-                .annotate(Type::FieldElement, 2)
-                .into(),
-            ])
-            .annotate(Type::array(Type::FieldElement, 2), 2)
-            .into(),
+        // all statements give the whole program
+        assert_eq!(
+            Flattener::new().flatten_prefix(&prog, 2),
+            Flattener::new().flatten_program(prog)
         );
+    }
 
-        let sum = TypedStatement::Definition(
-            TypedAssignee::Identifier(Variable::field_element("bar".into())),
-            FieldElementExpression::Add(
-                box FieldElementExpression::Add(
-                    box FieldElementExpression::Add(
-                        box FieldElementExpression::Select(
-                            box ArrayExpressionInner::Select(
-                                box ArrayExpressionInner::Identifier("foo".into())
-                                    .annotate(Type::array(Type::FieldElement, 2), 2),
-                                box FieldElementExpression::Number(FieldPrime::from(0)),
-                            )
-                            .annotate(Type::FieldElement, 2),
-                            box FieldElementExpression::Number(FieldPrime::from(0)),
-                        ),
-                        box FieldElementExpression::Select(
-                            box ArrayExpressionInner::Select(
-                                box ArrayExpressionInner::Identifier("foo".into())
-                                    .annotate(Type::array(Type::FieldElement, 2), 2),
-                                box FieldElementExpression::Number(FieldPrime::from(0)),
-                            )
-                            .annotate(Type::FieldElement, 2),
-                            box FieldElementExpression::Number(FieldPrime::from(1)),
-                        ),
-                    ),
-                    box FieldElementExpression::Select(
-                        box ArrayExpressionInner::Select(
-                            box ArrayExpressionInner::Identifier("foo".into())
-                                .annotate(Type::array(Type::FieldElement, 2), 2),
-                            box FieldElementExpression::Number(FieldPrime::from(1)),
-                        )
-                        .annotate(Type::FieldElement, 2),
-                        box FieldElementExpression::Number(FieldPrime::from(0)),
-                    ),
+    #[test]
+    fn if_else_shared_branches() {
+        // e_0 = x * y
+        // e_i+1 = if c_i then e_i else e_i + e_i fi
+        fn nested(depth: usize) -> FieldElementExpression<'static, FieldPrime> {
+            let names = ["c0", "c1", "c2", "c3"];
+            (0..depth).fold(
+                FieldElementExpression::Mult(
+                    box FieldElementExpression::Identifier("x".into()),
+                    box FieldElementExpression::Identifier("y".into()),
                 ),
-                box FieldElementExpression::Select(
-                    box ArrayExpressionInner::Select(
-                        box ArrayExpressionInner::Identifier("foo".into())
-                            .annotate(Type::array(Type::FieldElement, 2), 2),
-                        box FieldElementExpression::Number(FieldPrime::from(1)),
+                |e, i| {
+                    FieldElementExpression::IfElse(
+                        box BooleanExpression::Identifier(names[i].into()),
+                        box e.clone(),
+                        box FieldElementExpression::Add(box e.clone(), box e),
                     )
-                    .annotate(Type::FieldElement, 2),
-                    box FieldElementExpression::Number(FieldPrime::from(1)),
-                ),
+                },
             )
-            .into(),
-        );
+        }
 
-        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, def);
+        let function = |depth: usize| TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("x".into())),
+                Parameter::private(Variable::field_element("y".into())),
+                Parameter::private(Variable::boolean("c0".into())),
+                Parameter::private(Variable::boolean("c1".into())),
+                Parameter::private(Variable::boolean("c2".into())),
+                Parameter::private(Variable::boolean("c3".into())),
+            ],
+            statements: vec![TypedStatement::Return(vec![nested(depth).into()])],
+            signature: Signature {
+                inputs: vec![
+                    Type::FieldElement,
+                    Type::FieldElement,
+                    Type::Boolean,
+                    Type::Boolean,
+                    Type::Boolean,
+                    Type::Boolean,
+                ],
+                outputs: vec![Type::FieldElement],
+            },
+        };
 
-        flattener.flatten_statement(&HashMap::new(), &mut statements_flattened, sum);
+        let flat_functions: Vec<_> = (0..5)
+            .map(|depth| {
+                Flattener::new()
+                    .flatten_function(&mut HashMap::new(), function(depth))
+                    .unwrap()
+            })
+            .collect();
+
+        // each level adds the same number of statements, shared branches are flattened once
+        let sizes: Vec<_> = flat_functions.iter().map(|f| f.statements.len()).collect();
+        for i in 2..4 {
+            assert_eq!(sizes[i + 1] - sizes[i], sizes[2] - sizes[1]);
+        }
+
+        let prog = ir::Prog {
+            main: ir::Function::from(flat_functions[4].clone()),
+            private: vec![true; 6],
+        };
 
+        // x * y * 2 * 2, the first and last conditions being true
+        let inputs: Vec<_> = vec![2, 3, 1, 0, 0, 1]
+            .into_iter()
+            .map(FieldPrime::from)
+            .collect();
         assert_eq!(
-            statements_flattened[4],
-            FlatStatement::Definition(
-                FlatVariable::new(4),
-                FlatExpression::Add(
-                    box FlatExpression::Add(
-                        box FlatExpression::Add(
-                            box FlatExpression::Identifier(FlatVariable::new(0)),
-                            box FlatExpression::Identifier(FlatVariable::new(1)),
-                        ),
-                        box FlatExpression::Identifier(FlatVariable::new(2))
-                    ),
-                    box FlatExpression::Identifier(FlatVariable::new(3)),
-                )
-            )
+            Interpreter::default()
+                .execute(&prog, &inputs)
+                .map(|w| w.return_values()),
+            Ok(vec![FieldPrime::from(24)])
         );
     }
 
     #[test]
-    fn array_if() {
-        // if 1 == 1 then [1] else [3] fi
-        let with_arrays = {
-            let mut flattener = Flattener::new();
-            let mut statements_flattened = vec![];
-
-            let e = ArrayExpressionInner::IfElse(
-                box BooleanExpression::FieldEq(
-                    box FieldElementExpression::Number(FieldPrime::from(1)),
-                    box FieldElementExpression::Number(FieldPrime::from(1)),
-                ),
-                box ArrayExpressionInner::Value(vec![FieldElementExpression::Number(
-                    FieldPrime::from(1),
-                )
-                .into()])
-                .annotate(Type::FieldElement, 1),
-                box ArrayExpressionInner::Value(vec![FieldElementExpression::Number(
-                    FieldPrime::from(3),
-                )
-                .into()])
-                .annotate(Type::FieldElement, 2),
-            )
-            .annotate(Type::FieldElement, 1);
+    fn int_semantics() {
+        // def main(private field a, private field b) -> (field, field, field):
+        //     return a + b, a - b, a * b
+        let a = || box FieldElementExpression::Identifier("a".into());
+        let b = || box FieldElementExpression::Identifier("b".into());
 
-            (
-                flattener.flatten_array_expression::<FieldElementExpression<_>>(
-                    &HashMap::new(),
-                    &mut statements_flattened,
-                    e,
-                )[0]
-                .clone(),
-                statements_flattened,
-            )
+        let function = TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+            ],
+            statements: vec![TypedStatement::Return(vec![
+                FieldElementExpression::Add(a(), b()).into(),
+                FieldElementExpression::Sub(a(), b()).into(),
+                FieldElementExpression::Mult(a(), b()).into(),
+            ])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement, Type::FieldElement],
+                outputs: vec![Type::FieldElement; 3],
+            },
         };
 
-        let without_arrays = {
-            let mut statements_flattened = vec![];
-            let mut flattener = Flattener::new();
-            // if 1 == 1 then 1 else 3 fi
-            let e = FieldElementExpression::IfElse(
-                box BooleanExpression::FieldEq(
-                    box FieldElementExpression::Number(FieldPrime::from(1)),
-                    box FieldElementExpression::Number(FieldPrime::from(1)),
-                ),
-                box FieldElementExpression::Number(FieldPrime::from(1)),
-                box FieldElementExpression::Number(FieldPrime::from(3)),
-            );
+        let run = |semantics: IntSemantics, a: u32, b: u32| {
+            let flat_function = Flattener::new()
+                .int_semantics(semantics)
+                .flatten_function(&mut HashMap::new(), function.clone())
+                .unwrap();
 
-            (
-                flattener.flatten_field_expression(&HashMap::new(), &mut statements_flattened, e),
-                statements_flattened,
-            )
+            let prog = ir::Prog {
+                main: ir::Function::from(flat_function),
+                private: vec![true; 2],
+            };
+
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(a), FieldPrime::from(b)])
+                .map(|w| w.return_values())
         };
 
-        assert_eq!(with_arrays, without_arrays);
+        let values =
+            |values: Vec<i32>| values.into_iter().map(FieldPrime::from).collect::<Vec<_>>();
+
+        // the default is field arithmetic
+        assert_eq!(
+            run(IntSemantics::Field, 200, 100),
+            Ok(values(vec![300, 100, 20000]))
+        );
+        assert_eq!(run(IntSemantics::Field, 3, 5), Ok(values(vec![8, -2, 15])));
+
+        // 300 == 256 + 44, 20000 == 78 * 256 + 32, -2 == 254 - 256
+        assert_eq!(
+            run(IntSemantics::WrappingBits(8), 200, 100),
+            Ok(values(vec![44, 100, 32]))
+        );
+        assert_eq!(
+            run(IntSemantics::WrappingBits(8), 3, 5),
+            Ok(values(vec![8, 254, 15]))
+        );
+
+        assert_eq!(
+            run(IntSemantics::CheckedBits(8), 10, 5),
+            Ok(values(vec![15, 5, 50]))
+        );
+        // the sum exceeds 8 bits
+        assert!(run(IntSemantics::CheckedBits(8), 200, 100).is_err());
+        // the difference is negative
+        assert!(run(IntSemantics::CheckedBits(8), 3, 5).is_err());
     }
 
     #[test]
-    fn next_variable() {
-        let mut flattener: Flattener<FieldPrime> = Flattener::new();
+    fn int_semantics_sum_product_pow() {
+        // def main(private field a, private field b) -> (field, field, field):
+        //     return a + b + b, a * b * b, a ** 2
+        let a = || FieldElementExpression::Identifier("a".into());
+        let b = || FieldElementExpression::Identifier("b".into());
+
+        let function = TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter::private(Variable::field_element("b".into())),
+            ],
+            statements: vec![TypedStatement::Return(vec![
+                FieldElementExpression::Sum(vec![a(), b(), b()]).into(),
+                FieldElementExpression::Product(vec![a(), b(), b()]).into(),
+                FieldElementExpression::Pow(
+                    box a(),
+                    box FieldElementExpression::Number(FieldPrime::from(2)),
+                )
+                .into(),
+            ])],
+            signature: Signature {
+                inputs: vec![Type::FieldElement, Type::FieldElement],
+                outputs: vec![Type::FieldElement; 3],
+            },
+        };
+
+        let run = |semantics: IntSemantics, a: u32, b: u32| {
+            let flat_function = Flattener::new()
+                .int_semantics(semantics)
+                .flatten_function(&mut HashMap::new(), function.clone())
+                .unwrap();
+
+            let prog = ir::Prog {
+                main: ir::Function::from(flat_function),
+                private: vec![true; 2],
+            };
+
+            Interpreter::default()
+                .execute(&prog, &vec![FieldPrime::from(a), FieldPrime::from(b)])
+                .map(|w| w.return_values())
+        };
+
+        let values =
+            |values: Vec<u32>| values.into_iter().map(FieldPrime::from).collect::<Vec<_>>();
+
+        // 400 == 256 + 144, 2000000 == 7812 * 256 + 128, 40000 == 156 * 256 + 64
         assert_eq!(
-            vec![FlatVariable::new(0)],
-            flattener.use_variable(&Variable::field_element("a".into()))
+            run(IntSemantics::WrappingBits(8), 200, 100),
+            Ok(values(vec![144, 128, 64]))
         );
+
         assert_eq!(
-            flattener.layout.get(&"a".into()),
-            Some(&vec![FlatVariable::new(0)])
+            run(IntSemantics::CheckedBits(8), 10, 5),
+            Ok(values(vec![20, 250, 100]))
         );
+        // the product exceeds 8 bits
+        assert!(run(IntSemantics::CheckedBits(8), 10, 20).is_err());
+        // the power exceeds 8 bits
+        assert!(run(IntSemantics::CheckedBits(8), 20, 1).is_err());
+    }
+
+    #[test]
+    fn int_semantics_too_wide() {
+        // def main(private field a, private field b) -> (field):
+        //     return a * b
+        let program = || {
+            main_program(
+                vec![
+                    Parameter::private(Variable::field_element("a".into())),
+                    Parameter::private(Variable::field_element("b".into())),
+                ],
+                vec![TypedStatement::Return(vec![FieldElementExpression::Mult(
+                    box FieldElementExpression::Identifier("a".into()),
+                    box FieldElementExpression::Identifier("b".into()),
+                )
+                .into()])],
+                Signature::new()
+                    .inputs(vec![Type::FieldElement, Type::FieldElement])
+                    .outputs(vec![Type::FieldElement]),
+            )
+        };
+
+        let field_max = FieldPrime::get_required_bits() - 1;
+
+        let flatten = |semantics| {
+            Flattener::new()
+                .int_semantics(semantics)
+                .flatten_program(program())
+                .map(|_| ())
+        };
+
+        assert_eq!(flatten(IntSemantics::WrappingBits(field_max / 2)), Ok(()));
         assert_eq!(
-            vec![FlatVariable::new(1)],
-            flattener.use_variable(&Variable::field_element("a".into()))
+            flatten(IntSemantics::WrappingBits(field_max / 2 + 1)),
+            Err(FlattenError::IntSemanticsTooWide {
+                semantics: IntSemantics::WrappingBits(field_max / 2 + 1),
+                field_max
+            })
         );
+
+        assert_eq!(flatten(IntSemantics::CheckedBits(field_max)), Ok(()));
         assert_eq!(
-            flattener.layout.get(&"a".into()),
-            Some(&vec![FlatVariable::new(1)])
+            flatten(IntSemantics::CheckedBits(field_max + 1)),
+            Err(FlattenError::IntSemanticsTooWide {
+                semantics: IntSemantics::CheckedBits(field_max + 1),
+                field_max
+            })
         );
-        assert_eq!(
-            vec![FlatVariable::new(2)],
-            flattener.use_variable(&Variable::field_element("a".into()))
+    }
+
+    #[test]
+    fn assert_in_range() {
+        let mut flattener = Flattener::new();
+        let x = flattener.use_sym();
+
+        let mut statements = vec![];
+        flattener.assert_in_range(
+            &mut statements,
+            x.into(),
+            FieldPrime::from(10),
+            FieldPrime::from(20),
         );
+
+        statements.push(FlatStatement::Return(FlatExpressionList {
+            expressions: vec![],
+        }));
+
+        let prog = ir::Prog {
+            main: ir::Function::from(FlatFunction {
+                arguments: vec![FlatParameter::private(x)],
+                statements,
+            }),
+            private: vec![true],
+        };
+
+        let run = |x: i32| Interpreter::default().execute(&prog, &vec![FieldPrime::from(x)]);
+
+        // in range, including the bounds
+        assert!(run(10).is_ok());
+        assert!(run(15).is_ok());
+        assert!(run(20).is_ok());
+
+        // below lo
+        assert!(run(9).is_err());
+        assert!(run(0).is_err());
+        assert!(run(-1).is_err());
+
+        // above hi
+        assert!(run(21).is_err());
+        assert!(run(31).is_err());
+    }
+
+    #[test]
+    fn duplicate_argument() {
+        // def main(private field a, private field a) -> (field):
+        //     return a
+        fn program<'ast>() -> TypedProgram<'ast, FieldPrime> {
+            let signature = Signature {
+                inputs: vec![Type::FieldElement, Type::FieldElement],
+                outputs: vec![Type::FieldElement],
+            };
+
+            main_program(
+                vec![
+                    Parameter::private(Variable::field_element("a".into())),
+                    Parameter::private(Variable::field_element("a".into())),
+                ],
+                vec![TypedStatement::Return(vec![
+                    FieldElementExpression::Identifier("a".into()).into(),
+                ])],
+                signature,
+            )
+        }
+
+        let expected = || FlattenError::DuplicateArgument {
+            name: String::from("a"),
+        };
+
+        assert_eq!(Flattener::flatten(program()), Err(expected()));
+
+        // nothing is streamed
+        let mut streamed = vec![];
         assert_eq!(
-            flattener.layout.get(&"a".into()),
-            Some(&vec![FlatVariable::new(2)])
+            Flattener::new().flatten_program_streaming(program(), |s| streamed.push(s)),
+            Err(expected())
         );
+        assert!(streamed.is_empty());
     }
 }
@@ -0,0 +1,417 @@
+//! Module containing a compact binary encoding of `Prog`
+//!
+//! Integers are little-endian. Lengths of lists and strings are `u32`, variables are `i64` and field elements are
+//! their `get_required_bytes()` little-endian bytes. Statements and solvers start with a tag byte.
+//!
+//! Inputs are not trusted: field elements must be reduced, directives must match the signature of their solver, and
+//! lengths are not used to allocate memory before the corresponding data is read.
+
+use crate::flat_absy::FlatVariable;
+use crate::ir::{Directive, Function, LinComb, Prog, QuadComb, Statement};
+use crate::solvers::{Signed, Solver};
+use std::io::{self, Read, Write};
+use zokrates_field::field::Field;
+
+const CONSTRAINT: u8 = 0;
+const DIRECTIVE: u8 = 1;
+
+const CONDITION_EQ: u8 = 0;
+const BITS: u8 = 1;
+const DIV: u8 = 2;
+const SHA256_ROUND: u8 = 3;
+const EUCLIDEAN_DIV: u8 = 4;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_u32<W: Write>(w: &mut W, n: usize) -> io::Result<()> {
+    assert!(n <= u32::max_value() as usize);
+    w.write_all(&(n as u32).to_le_bytes())
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<usize> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes) as usize)
+}
+
+/// Reads a `u32` length followed by as many items read by `read_item`
+fn read_list<R: Read, U, F: FnMut(&mut R) -> io::Result<U>>(
+    r: &mut R,
+    mut read_item: F,
+) -> io::Result<Vec<U>> {
+    let len = read_u32(r)?;
+    // the length is not trusted to reserve memory, the vector grows as items are actually read
+    let mut items = vec![];
+    for _ in 0..len {
+        items.push(read_item(r)?);
+    }
+    Ok(items)
+}
+
+/// Reads a `u32` length followed by as many bytes
+fn read_bytes<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u32(r)?;
+    let mut bytes = vec![];
+    r.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+    match bytes.len() == len {
+        true => Ok(bytes),
+        false => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("Expected {} bytes, found {}", len, bytes.len()),
+        )),
+    }
+}
+
+fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut byte = [0; 1];
+    r.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn write_variable<W: Write>(w: &mut W, v: &FlatVariable) -> io::Result<()> {
+    w.write_all(&(v.raw_id() as i64).to_le_bytes())
+}
+
+fn read_variable<R: Read>(r: &mut R) -> io::Result<FlatVariable> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(FlatVariable::from_raw_id(i64::from_le_bytes(bytes) as isize))
+}
+
+fn write_variables<W: Write>(w: &mut W, vs: &[FlatVariable]) -> io::Result<()> {
+    write_u32(w, vs.len())?;
+    vs.iter().map(|v| write_variable(w, v)).collect()
+}
+
+fn read_variables<R: Read>(r: &mut R) -> io::Result<Vec<FlatVariable>> {
+    read_list(r, read_variable)
+}
+
+fn write_element<T: Field, W: Write>(w: &mut W, e: &T) -> io::Result<()> {
+    w.write_all(&e.to_byte_vector())
+}
+
+fn read_element<T: Field, R: Read>(r: &mut R) -> io::Result<T> {
+    let mut bytes = vec![0; T::get_required_bytes()];
+    r.read_exact(&mut bytes)?;
+
+    // compare with the largest element from the most significant byte, both having the same length
+    let max = T::max_value().to_byte_vector();
+    if bytes.iter().rev().cmp(max.iter().rev()) == std::cmp::Ordering::Greater {
+        return Err(invalid(String::from("Field element is not reduced")));
+    }
+
+    Ok(T::from_byte_vector(&bytes))
+}
+
+fn write_lin_comb<T: Field, W: Write>(w: &mut W, l: &LinComb<T>) -> io::Result<()> {
+    write_u32(w, l.0.len())?;
+    l.0.iter()
+        .map(|(v, c)| {
+            write_variable(w, v)?;
+            write_element(w, c)
+        })
+        .collect()
+}
+
+fn read_lin_comb<T: Field, R: Read>(r: &mut R) -> io::Result<LinComb<T>> {
+    read_list(r, |r| Ok((read_variable(r)?, read_element(r)?))).map(LinComb)
+}
+
+fn write_solver<W: Write>(w: &mut W, s: &Solver) -> io::Result<()> {
+    let tag = match s {
+        Solver::ConditionEq => CONDITION_EQ,
+        Solver::Bits => BITS,
+        Solver::Div => DIV,
+        Solver::Sha256Round => SHA256_ROUND,
+        Solver::EuclideanDiv => EUCLIDEAN_DIV,
+    };
+    w.write_all(&[tag])
+}
+
+fn read_solver<R: Read>(r: &mut R) -> io::Result<Solver> {
+    match read_u8(r)? {
+        CONDITION_EQ => Ok(Solver::ConditionEq),
+        BITS => Ok(Solver::Bits),
+        DIV => Ok(Solver::Div),
+        SHA256_ROUND => Ok(Solver::Sha256Round),
+        EUCLIDEAN_DIV => Ok(Solver::EuclideanDiv),
+        tag => Err(invalid(format!("Unknown solver tag {}", tag))),
+    }
+}
+
+fn write_statement<T: Field, W: Write>(w: &mut W, s: &Statement<T>) -> io::Result<()> {
+    match s {
+        Statement::Constraint(quad, lin) => {
+            w.write_all(&[CONSTRAINT])?;
+            write_lin_comb(w, &quad.left)?;
+            write_lin_comb(w, &quad.right)?;
+            write_lin_comb(w, lin)
+        }
+        Statement::Directive(d) => {
+            w.write_all(&[DIRECTIVE])?;
+            write_u32(w, d.inputs.len())?;
+            for i in &d.inputs {
+                write_lin_comb(w, i)?;
+            }
+            write_variables(w, &d.outputs)?;
+            write_solver(w, &d.solver)
+        }
+    }
+}
+
+fn read_statement<T: Field, R: Read>(r: &mut R) -> io::Result<Statement<T>> {
+    match read_u8(r)? {
+        CONSTRAINT => {
+            let left = read_lin_comb(r)?;
+            let right = read_lin_comb(r)?;
+            let lin = read_lin_comb(r)?;
+            Ok(Statement::Constraint(
+                QuadComb::from_linear_combinations(left, right),
+                lin,
+            ))
+        }
+        DIRECTIVE => {
+            let inputs = read_list(r, read_lin_comb)?;
+            let outputs = read_variables(r)?;
+            let solver = read_solver(r)?;
+
+            // the interpreter relies on the number of inputs and outputs of the solver
+            let signature = solver.get_signature();
+            if (inputs.len(), outputs.len()) != signature {
+                return Err(invalid(format!(
+                    "Directive with {} inputs and {} outputs, {:?} expects {} and {}",
+                    inputs.len(),
+                    outputs.len(),
+                    solver,
+                    signature.0,
+                    signature.1
+                )));
+            }
+
+            Ok(Statement::Directive(Directive {
+                inputs,
+                outputs,
+                solver,
+            }))
+        }
+        tag => Err(invalid(format!("Unknown statement tag {}", tag))),
+    }
+}
+
+/// Writes `prog` to `w` in the binary encoding
+pub fn write_binary<T: Field, W: Write>(prog: &Prog<T>, w: &mut W) -> io::Result<()> {
+    write_u32(w, prog.main.id.len())?;
+    w.write_all(prog.main.id.as_bytes())?;
+    write_variables(w, &prog.main.arguments)?;
+    write_u32(w, prog.main.statements.len())?;
+    for s in &prog.main.statements {
+        write_statement(w, s)?;
+    }
+    write_variables(w, &prog.main.returns)?;
+    write_u32(w, prog.private.len())?;
+    w.write_all(
+        &prog
+            .private
+            .iter()
+            .map(|private| *private as u8)
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Reads a program written by `write_binary` from `r`
+pub fn read_binary<T: Field, R: Read>(r: &mut R) -> io::Result<Prog<T>> {
+    let id = read_bytes(r)?;
+    let id = String::from_utf8(id).map_err(|e| invalid(e.to_string()))?;
+
+    let arguments = read_variables(r)?;
+
+    let statements = read_list(r, read_statement)?;
+
+    let returns = read_variables(r)?;
+
+    let private = read_bytes(r)?.into_iter().map(|p| p != 0).collect();
+
+    Ok(Prog {
+        main: Function {
+            id,
+            arguments,
+            statements,
+            returns,
+        },
+        private,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    fn prog() -> Prog<FieldPrime> {
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+        let c = FlatVariable::new(2);
+
+        // a directive with as many inputs and outputs as `solver` expects
+        let directive = |solver: Solver| {
+            let (input_count, output_count) = solver.get_signature();
+            Statement::Directive(Directive {
+                inputs: (0..input_count)
+                    .map(|i| LinComb::summand(i + 1, a))
+                    .collect(),
+                outputs: (0..output_count)
+                    .map(|i| FlatVariable::new(i + 3))
+                    .collect(),
+                solver,
+            })
+        };
+
+        Prog {
+            main: Function {
+                id: "main".to_string(),
+                arguments: vec![a, b],
+                statements: vec![
+                    // (a + 42 * ~one) * (b) == -1 * c
+                    Statement::constraint(
+                        QuadComb::from_linear_combinations(
+                            LinComb::summand(1, a) + LinComb::summand(42, FlatVariable::one()),
+                            b.into(),
+                        ),
+                        LinComb::summand(-1, c),
+                    ),
+                    // () * () == ()
+                    Statement::constraint(
+                        QuadComb::from_linear_combinations(LinComb(vec![]), LinComb(vec![])),
+                        LinComb(vec![]),
+                    ),
+                    directive(Solver::ConditionEq),
+                    directive(Solver::Bits),
+                    directive(Solver::Div),
+                    directive(Solver::Sha256Round),
+                    directive(Solver::EuclideanDiv),
+                    Statement::definition(FlatVariable::public(0), c),
+                ],
+                returns: vec![FlatVariable::public(0)],
+            },
+            private: vec![true, false],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buffer = vec![];
+        write_binary(&prog(), &mut buffer).unwrap();
+
+        let decoded: Prog<FieldPrime> = read_binary(&mut &buffer[..]).unwrap();
+        assert_eq!(decoded, prog());
+
+        // coefficients are compared canonically, check their encoding too
+        assert_eq!(
+            format!("{:?}", decoded.main.statements),
+            format!("{:?}", prog().main.statements)
+        );
+
+        assert!(buffer.len() < serde_json::to_string(&prog()).unwrap().len());
+    }
+
+    #[test]
+    fn invalid_input() {
+        let mut buffer = vec![];
+        write_binary(&prog(), &mut buffer).unwrap();
+
+        // truncated
+        let truncated = &buffer[..buffer.len() - 1];
+        assert!(read_binary::<FieldPrime, _>(&mut &truncated[..]).is_err());
+
+        // unknown statement tag, right after the id and the arguments
+        let tag_position = 4 + 4 + 4 + 2 * 8 + 4;
+        assert_eq!(buffer[tag_position], CONSTRAINT);
+        buffer[tag_position] = 42;
+        assert!(read_binary::<FieldPrime, _>(&mut &buffer[..]).is_err());
+    }
+
+    #[test]
+    fn unreduced_element() {
+        // ~out_0 == (p - 1) * a
+        let max = FieldPrime::max_value();
+        let prog = Prog {
+            main: Function {
+                id: "main".to_string(),
+                arguments: vec![FlatVariable::new(0)],
+                statements: vec![Statement::definition(
+                    FlatVariable::public(0),
+                    LinComb::summand(max.clone(), FlatVariable::new(0)),
+                )],
+                returns: vec![FlatVariable::public(0)],
+            },
+            private: vec![true],
+        };
+
+        let mut buffer = vec![];
+        write_binary(&prog, &mut buffer).unwrap();
+        assert_eq!(
+            read_binary::<FieldPrime, _>(&mut &buffer[..]).unwrap(),
+            prog
+        );
+
+        // replace p - 1 by p
+        let max_bytes = max.to_byte_vector();
+        let position = buffer
+            .windows(max_bytes.len())
+            .position(|w| w == &max_bytes[..])
+            .unwrap();
+        assert!(buffer[position] < 255);
+        buffer[position] += 1;
+
+        assert!(read_binary::<FieldPrime, _>(&mut &buffer[..]).is_err());
+    }
+
+    #[test]
+    fn directive_signature() {
+        // # c = a / (), missing the divisor
+        let prog = Prog {
+            main: Function {
+                id: "main".to_string(),
+                arguments: vec![FlatVariable::new(0)],
+                statements: vec![Statement::Directive(Directive {
+                    inputs: vec![FlatVariable::new(0).into()],
+                    outputs: vec![FlatVariable::new(1)],
+                    solver: Solver::Div,
+                })],
+                returns: vec![],
+            },
+            private: vec![true],
+        };
+
+        let mut buffer = vec![];
+        write_binary(&prog, &mut buffer).unwrap();
+        assert!(read_binary::<FieldPrime, _>(&mut &buffer[..]).is_err());
+    }
+
+    #[test]
+    fn large_length() {
+        // an id of u32::MAX bytes, without the bytes
+        let buffer = u32::max_value().to_le_bytes();
+        assert_eq!(
+            read_binary::<FieldPrime, _>(&mut &buffer[..])
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        // u32::MAX statements, without the statements
+        let mut buffer = vec![];
+        write_u32(&mut buffer, 0).unwrap();
+        write_variables(&mut buffer, &[]).unwrap();
+        write_u32(&mut buffer, u32::max_value() as usize).unwrap();
+        assert_eq!(
+            read_binary::<FieldPrime, _>(&mut &buffer[..])
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+}
@@ -38,6 +38,14 @@ impl<T: Field> QuadComb<T> {
 
         None
     }
+
+    /// Returns `l` if this is the square `l * l`, which some backends constrain more cheaply than a product
+    pub fn try_square(&self) -> Option<&LinComb<T>> {
+        match self.left == self.right {
+            true => Some(&self.left),
+            false => None,
+        }
+    }
 }
 
 impl<T: Field> From<T> for LinComb<T> {
@@ -304,6 +312,21 @@ mod tests {
             };
             assert_eq!(&a.to_string(), "(0) * (1 * _21)");
         }
+
+        #[test]
+        fn square() {
+            let a: LinComb<FieldPrime> = LinComb::summand(3, FlatVariable::new(42))
+                + LinComb::summand(4, FlatVariable::new(33));
+            // the order of the summands does not matter
+            let b: LinComb<FieldPrime> = LinComb::summand(4, FlatVariable::new(33))
+                + LinComb::summand(3, FlatVariable::new(42));
+
+            let square = QuadComb::from_linear_combinations(a.clone(), b);
+            assert_eq!(square.try_square(), Some(&a));
+
+            let product = QuadComb::from_linear_combinations(a, FlatVariable::new(42).into());
+            assert_eq!(product.try_square(), None);
+        }
     }
 
     mod try {
@@ -0,0 +1,122 @@
+//! Module containing the fingerprint of a `Prog`
+//!
+//! Internal variables are renamed by order of first occurrence and linear combinations are made canonical before
+//! hashing, so that programs which only differ in their `id`, in the numbering of their internal variables or in the
+//! order of the summands of their linear combinations have the same fingerprint.
+
+use crate::flat_absy::FlatVariable;
+use crate::ir::folder::{self, Folder};
+use crate::ir::*;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use zokrates_field::field::Field;
+
+/// Renames internal variables to their position of first occurrence, and sorts the summands by the new names
+#[derive(Default)]
+struct Canonicalizer {
+    positions: HashMap<FlatVariable, FlatVariable>,
+}
+
+impl<T: Field> Folder<T> for Canonicalizer {
+    fn fold_variable(&mut self, v: FlatVariable) -> FlatVariable {
+        // `~one` and the outputs keep their name
+        if v <= FlatVariable::one() {
+            return v;
+        }
+
+        let next = FlatVariable::new(self.positions.len());
+        *self.positions.entry(v).or_insert(next)
+    }
+
+    fn fold_linear_combination(&mut self, e: LinComb<T>) -> LinComb<T> {
+        // merge the summands of the same variable and drop the zero ones
+        let e = folder::fold_linear_combination(self, e);
+        LinComb(e.as_canonical().0.into_iter().collect())
+    }
+}
+
+/// Returns a hash of the structure of `prog`, ignoring its `id` and the numbering of its internal variables
+pub fn fingerprint<T: Field>(prog: &Prog<T>) -> [u8; 32] {
+    let mut prog = Canonicalizer::default().fold_module(prog.clone());
+    prog.main.id = String::new();
+
+    let mut bytes = vec![];
+    write_binary(&prog, &mut bytes).unwrap();
+
+    let mut hash = [0; 32];
+    hash.copy_from_slice(&Sha256::digest(&bytes));
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    // def main(a, b) -> (1):
+    //     c = a * b
+    //     (c + <k> * a) == ~out_0
+    //     return ~out_0
+    fn prog(id: &str, a: usize, b: usize, c: usize, k: i32) -> Prog<FieldPrime> {
+        let v = FlatVariable::new;
+        let (a, b, c) = (v(a), v(b), v(c));
+
+        Prog {
+            main: Function {
+                id: id.to_string(),
+                arguments: vec![a, b],
+                statements: vec![
+                    Statement::definition(
+                        c,
+                        QuadComb::from_linear_combinations(a.into(), b.into()),
+                    ),
+                    Statement::definition(
+                        FlatVariable::public(0),
+                        LinComb::from(c) + LinComb::summand(k, a),
+                    ),
+                ],
+                returns: vec![FlatVariable::public(0)],
+            },
+            private: vec![true, false],
+        }
+    }
+
+    #[test]
+    fn ignore_id_and_numbering() {
+        assert_eq!(
+            fingerprint(&prog("main", 0, 1, 2, 2)),
+            fingerprint(&prog("other", 3, 5, 42, 2))
+        );
+    }
+
+    #[test]
+    fn ignore_summand_order() {
+        let mut permuted = prog("main", 0, 1, 2, 2);
+        // (<2> * a + c) == ~out_0
+        permuted.main.statements[1] = Statement::definition(
+            FlatVariable::public(0),
+            LinComb::summand(2, FlatVariable::new(0)) + LinComb::from(FlatVariable::new(2)),
+        );
+
+        let mut duplicated = prog("main", 0, 1, 2, 2);
+        // (a + c + a) == ~out_0
+        duplicated.main.statements[1] = Statement::definition(
+            FlatVariable::public(0),
+            LinComb::from(FlatVariable::new(0))
+                + LinComb::from(FlatVariable::new(2))
+                + LinComb::from(FlatVariable::new(0)),
+        );
+
+        let fingerprint_main = fingerprint(&prog("main", 0, 1, 2, 2));
+        assert_eq!(fingerprint(&permuted), fingerprint_main);
+        assert_eq!(fingerprint(&duplicated), fingerprint_main);
+    }
+
+    #[test]
+    fn structural_change() {
+        assert_ne!(
+            fingerprint(&prog("main", 0, 1, 2, 2)),
+            fingerprint(&prog("main", 0, 1, 2, 3))
+        );
+    }
+}
@@ -28,7 +28,8 @@ impl<T: Field> From<FlatFunction<T>> for Function<T> {
                 .statements
                 .into_iter()
                 .filter_map(|s| match s {
-                    FlatStatement::Return(..) => None,
+                    // comments add no constraint
+                    FlatStatement::Return(..) | FlatStatement::Comment(..) => None,
                     s => Some(s.into()),
                 })
                 .chain(
@@ -92,6 +93,8 @@ impl<T: Field> From<FlatExpression<T>> for LinComb<T> {
                 box FlatExpression::Identifier(v1),
                 box FlatExpression::Number(n1),
             ) => LinComb::summand(n1, v1),
+            FlatExpression::Mult(box FlatExpression::Number(n), box e)
+            | FlatExpression::Mult(box e, box FlatExpression::Number(n)) => LinComb::from(e) * &n,
             e => unimplemented!("{}", e),
         }
     }
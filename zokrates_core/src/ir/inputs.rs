@@ -0,0 +1,249 @@
+//! Module containing the validation and substitution of named inputs of a `Prog`
+
+use crate::flat_absy::FlatVariable;
+use crate::ir::folder::Folder;
+use crate::ir::{Function, LinComb, Prog};
+use crate::optimizer::Optimize;
+use std::collections::HashMap;
+use std::fmt;
+use zokrates_field::field::Field;
+
+#[derive(Debug, PartialEq)]
+pub enum InputError {
+    /// Inputs required by the program which were not provided, in argument order
+    Missing(Vec<String>),
+    /// Inputs provided which the program does not take, in alphabetical order
+    Unexpected(Vec<String>),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::Missing(names) => write!(f, "Missing inputs: {}", names.join(", ")),
+            InputError::Unexpected(names) => write!(f, "Unexpected inputs: {}", names.join(", ")),
+        }
+    }
+}
+
+impl<T: Field> Prog<T> {
+    /// Returns the names of the inputs the program takes, in argument order
+    pub fn free_inputs(&self) -> Vec<String> {
+        self.main.arguments.iter().map(|a| a.to_string()).collect()
+    }
+}
+
+/// Checks that `inputs` provides exactly the inputs `prog` takes
+///
+/// # Arguments
+/// * `prog` - The program to run
+/// * `inputs` - Input values, indexed by argument name
+/// * `strict` - Whether inputs the program does not take are an error. If not, they are ignored.
+///
+/// # Remarks
+/// * Missing inputs are reported before unexpected ones.
+pub fn check_inputs<T: Field>(
+    prog: &Prog<T>,
+    inputs: &HashMap<String, T>,
+    strict: bool,
+) -> Result<(), InputError> {
+    let required = prog.free_inputs();
+
+    let missing: Vec<_> = required
+        .iter()
+        .filter(|name| !inputs.contains_key(*name))
+        .cloned()
+        .collect();
+
+    if !missing.is_empty() {
+        return Err(InputError::Missing(missing));
+    }
+
+    let mut unexpected: Vec<_> = inputs
+        .keys()
+        .filter(|name| !required.contains(*name))
+        .cloned()
+        .collect();
+    unexpected.sort();
+
+    if strict && !unexpected.is_empty() {
+        return Err(InputError::Unexpected(unexpected));
+    }
+
+    Ok(())
+}
+
+/// Replaces the variables of inputs by constant values
+struct InputSubstitution<T: Field> {
+    values: HashMap<FlatVariable, T>,
+}
+
+impl<T: Field> Folder<T> for InputSubstitution<T> {
+    fn fold_linear_combination(&mut self, e: LinComb<T>) -> LinComb<T> {
+        LinComb(
+            e.0.into_iter()
+                .map(|(variable, coefficient)| match self.values.get(&variable) {
+                    Some(value) => (FlatVariable::one(), coefficient * value.clone()),
+                    None => (variable, coefficient),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Specializes `prog` to some of its inputs, which stop being inputs
+///
+/// # Arguments
+/// * `prog` - The program to specialize
+/// * `fixed` - Values of the fixed inputs, indexed by argument name
+///
+/// # Remarks
+/// * The program is optimized after substitution, so that constraints made linear by the fixed values are removed.
+/// * Names in `fixed` which are not inputs of `prog` are ignored.
+pub fn substitute_inputs<T: Field>(prog: Prog<T>, fixed: &HashMap<String, T>) -> Prog<T> {
+    let values: HashMap<_, _> = prog
+        .main
+        .arguments
+        .iter()
+        .filter_map(|a| fixed.get(&a.to_string()).map(|value| (*a, value.clone())))
+        .collect();
+
+    let prog = InputSubstitution { values }.fold_module(prog);
+
+    let (arguments, private) = prog
+        .main
+        .arguments
+        .iter()
+        .zip(prog.private.iter())
+        .filter(|(a, _)| !fixed.contains_key(&a.to_string()))
+        .map(|(a, private)| (*a, *private))
+        .unzip();
+
+    Prog {
+        main: Function {
+            arguments,
+            ..prog.main
+        },
+        private,
+    }
+    .optimize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flat_absy::*;
+    use crate::ir::{Interpreter, Statement};
+    use zokrates_field::field::FieldPrime;
+
+    fn prog() -> Prog<FieldPrime> {
+        Prog {
+            main: Function {
+                id: "main".to_string(),
+                arguments: vec![FlatVariable::new(0), FlatVariable::new(1)],
+                statements: vec![],
+                returns: vec![],
+            },
+            private: vec![true, false],
+        }
+    }
+
+    fn inputs(names: Vec<&str>) -> HashMap<String, FieldPrime> {
+        names
+            .into_iter()
+            .map(|n| (n.to_string(), FieldPrime::from(42)))
+            .collect()
+    }
+
+    #[test]
+    fn exact_inputs() {
+        assert_eq!(prog().free_inputs(), vec!["_0", "_1"]);
+        assert_eq!(
+            check_inputs(&prog(), &inputs(vec!["_1", "_0"]), true),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn missing_inputs() {
+        assert_eq!(
+            check_inputs(&prog(), &inputs(vec!["_1"]), false),
+            Err(InputError::Missing(vec!["_0".to_string()]))
+        );
+        assert_eq!(
+            check_inputs(&prog(), &inputs(vec!["_2"]), true),
+            Err(InputError::Missing(vec![
+                "_0".to_string(),
+                "_1".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn extra_inputs() {
+        let extra = inputs(vec!["_0", "_1", "_3", "_2"]);
+        assert_eq!(
+            check_inputs(&prog(), &extra, true),
+            Err(InputError::Unexpected(vec![
+                "_2".to_string(),
+                "_3".to_string()
+            ]))
+        );
+        assert_eq!(check_inputs(&prog(), &extra, false), Ok(()));
+    }
+
+    #[test]
+    fn substitute_one_input() {
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+        let c = FlatVariable::new(2);
+        let d = FlatVariable::new(3);
+
+        // def main(private a, private b):
+        //     c = a * b
+        //     d = c * b
+        //     return d
+        let prog: Prog<FieldPrime> = Prog {
+            main: Function::from(FlatFunction {
+                arguments: vec![FlatParameter::private(a), FlatParameter::private(b)],
+                statements: vec![
+                    FlatStatement::Definition(c, FlatExpression::Mult(box a.into(), box b.into())),
+                    FlatStatement::Definition(d, FlatExpression::Mult(box c.into(), box b.into())),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![d.into()],
+                    }),
+                ],
+            }),
+            private: vec![true, false],
+        };
+
+        let fixed = vec![("_0".to_string(), FieldPrime::from(3))]
+            .into_iter()
+            .collect();
+
+        let specialized = substitute_inputs(prog.clone().optimize(), &fixed);
+
+        assert_eq!(specialized.free_inputs(), vec!["_1"]);
+        assert_eq!(specialized.private, vec![false]);
+        assert!(specialized.constraint_count() < prog.optimize().constraint_count());
+
+        // `a` is not referenced anymore
+        assert!(specialized.main.statements.iter().all(|s| match s {
+            Statement::Constraint(quad, lin) => quad
+                .left
+                .0
+                .iter()
+                .chain(quad.right.0.iter())
+                .chain(lin.0.iter())
+                .all(|(v, _)| *v != a),
+            Statement::Directive(d) => d.inputs.iter().all(|i| i.0.iter().all(|(v, _)| *v != a)),
+        }));
+
+        // 3 * 2 * 2
+        assert_eq!(
+            Interpreter::default()
+                .execute(&specialized, &vec![FieldPrime::from(2)])
+                .map(|w| w.return_values()),
+            Ok(vec![FieldPrime::from(12)])
+        );
+    }
+}
@@ -2,7 +2,7 @@ use crate::flat_absy::flat_variable::FlatVariable;
 use crate::ir::{LinComb, Prog, QuadComb, Statement, Witness};
 use ir::Directive;
 use solvers::Solver;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use zokrates_embed::generate_sha256_round_witness;
 use zokrates_field::field::Field;
@@ -178,6 +178,16 @@ impl Interpreter {
                     .map(|x| T::from_bellman(x))
                     .collect()
             }
+            Solver::EuclideanDiv => {
+                if inputs[1].is_zero() {
+                    return Err(String::from("Division by zero"));
+                }
+                let (a, b) = (inputs[0].to_biguint(), inputs[1].to_biguint());
+                vec![
+                    T::from_byte_vector(&(&a / &b).to_bytes_le()),
+                    T::from_byte_vector(&(&a % &b).to_bytes_le()),
+                ]
+            }
         };
 
         assert_eq!(res.len(), expected_output_count);
@@ -186,6 +196,52 @@ impl Interpreter {
     }
 }
 
+/// Checks that `assignment` satisfies the constraints of `prog`, independently of how it was generated
+///
+/// # Arguments
+/// * `prog` - The program whose constraints are checked
+/// * `assignment` - Values of the variables, indexed by name such as `_0` or `~out_0`
+///
+/// # Remarks
+/// * `~one` is assigned `1` unless `assignment` provides it.
+/// * A constraint on a variable which is not assigned is violated. Names which are not variables are ignored.
+/// * The error holds the indices in `prog.main.statements` of the violated constraints, directives included.
+pub fn check_assignment<T: Field>(
+    prog: &Prog<T>,
+    assignment: &HashMap<String, T>,
+) -> Result<(), Vec<usize>> {
+    let mut witness: BTreeMap<_, _> = assignment
+        .iter()
+        .filter_map(|(name, value)| {
+            FlatVariable::try_from_human_readable(name)
+                .ok()
+                .map(|v| (v, value.clone()))
+        })
+        .collect();
+    witness.entry(FlatVariable::one()).or_insert_with(T::one);
+
+    let violated: Vec<_> = prog
+        .main
+        .statements
+        .iter()
+        .enumerate()
+        .filter_map(|(index, statement)| match statement {
+            Statement::Constraint(quad, lin) => {
+                match (quad.evaluate(&witness), lin.evaluate(&witness)) {
+                    (Ok(ref left), Ok(ref right)) if left == right => None,
+                    _ => Some(index),
+                }
+            }
+            Statement::Directive(..) => None,
+        })
+        .collect();
+
+    match violated.is_empty() {
+        true => Ok(()),
+        false => Err(violated),
+    }
+}
+
 impl<T: Field> LinComb<T> {
     fn evaluate(&self, witness: &BTreeMap<FlatVariable, T>) -> Result<T, ()> {
         self.0
@@ -311,4 +367,61 @@ mod tests {
         assert_eq!(res[248], FieldPrime::from(1));
         assert_eq!(res[247], FieldPrime::from(0));
     }
+
+    #[test]
+    fn assignment_check() {
+        use crate::ir::Function;
+
+        // def main(a, b) -> (1):
+        //     # _2 = Div(a, b)
+        //     _2 * b == a
+        //     (a * b) == ~out_0
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+        let c = FlatVariable::new(2);
+
+        let prog = Prog {
+            main: Function {
+                id: "main".to_string(),
+                arguments: vec![a, b],
+                statements: vec![
+                    Statement::Directive(Directive {
+                        inputs: vec![a.into(), b.into()],
+                        outputs: vec![c],
+                        solver: Solver::Div,
+                    }),
+                    Statement::constraint(
+                        QuadComb::from_linear_combinations(c.into(), b.into()),
+                        a,
+                    ),
+                    Statement::definition(
+                        FlatVariable::public(0),
+                        QuadComb::from_linear_combinations(a.into(), b.into()),
+                    ),
+                ],
+                returns: vec![FlatVariable::public(0)],
+            },
+            private: vec![true, true],
+        };
+
+        let witness = Interpreter::default()
+            .execute(&prog, &vec![FieldPrime::from(6), FieldPrime::from(3)])
+            .unwrap();
+
+        let mut assignment: HashMap<_, _> = witness
+            .0
+            .iter()
+            .map(|(variable, value)| (variable.to_string(), value.clone()))
+            .collect();
+
+        assert_eq!(check_assignment(&prog, &assignment), Ok(()));
+
+        // a wrong output only violates the last constraint
+        assignment.insert("~out_0".to_string(), FieldPrime::from(17));
+        assert_eq!(check_assignment(&prog, &assignment), Err(vec![2]));
+
+        // an unassigned variable violates the constraints using it
+        assignment.remove("_2");
+        assert_eq!(check_assignment(&prog, &assignment), Err(vec![1, 2]));
+    }
 }
@@ -0,0 +1,180 @@
+//! Module containing the sequential composition of two `Prog`s
+//!
+//! The outputs of the first program become internal variables of the merged program, and can be wired
+//! into arguments of the second program. Variables of the second program are shifted so that they do not collide
+//! with the ones of the first program.
+
+use crate::flat_absy::flat_variable::FlatVariable;
+use crate::ir::folder::Folder;
+use crate::ir::rename::Renamer;
+use crate::ir::*;
+use std::collections::{HashMap, HashSet};
+use zokrates_field::field::Field;
+
+impl<T: Field> Prog<T> {
+    /// Composes two programs sequentially
+    ///
+    /// # Arguments
+    ///
+    /// * `first` - `Prog` executed first. Its outputs become internal variables.
+    /// * `second` - `Prog` executed second. Its outputs are the outputs of the merged program.
+    /// * `wire` - Pairs of human-readable variables `(output of first, argument of second)`, such as `("~out_0", "_1")`.
+    ///
+    /// # Remarks
+    /// * The arguments of the merged program are the arguments of `first` followed by the arguments of `second` which are not wired.
+    ///
+    /// # Panics
+    /// * If a variable in `wire` cannot be parsed or is not an output of `first` or an argument of `second`.
+    pub fn merge(first: Prog<T>, second: Prog<T>, wire: &[(String, String)]) -> Prog<T> {
+        let first_returns = first.main.returns.clone();
+        let output_count = first_returns.len();
+
+        // the outputs of `first` take the lowest indices, its internal variables are shifted after them
+        let mut first_renamer = Renamer::new(
+            (0..output_count)
+                .map(|i| (FlatVariable::public(i), FlatVariable::new(i)))
+                .collect(),
+            output_count,
+        );
+
+        let first_main = first_renamer.fold_function(first.main);
+
+        // the wired arguments of `second` are replaced by the outputs of `first`
+        let substitution: HashMap<FlatVariable, FlatVariable> = wire
+            .iter()
+            .map(|(output, argument)| {
+                let output = FlatVariable::try_from_human_readable(output)
+                    .unwrap_or_else(|e| panic!("Cannot parse variable {}", e));
+                let argument = FlatVariable::try_from_human_readable(argument)
+                    .unwrap_or_else(|e| panic!("Cannot parse variable {}", e));
+
+                assert!(
+                    first_returns.contains(&output),
+                    "{} is not an output of the first program",
+                    output
+                );
+                assert!(
+                    second.main.arguments.contains(&argument),
+                    "{} is not an argument of the second program",
+                    argument
+                );
+
+                (argument, *first_renamer.substitution.get(&output).unwrap())
+            })
+            .collect();
+
+        let wired: HashSet<FlatVariable> = substitution.keys().cloned().collect();
+
+        // the wired arguments are not arguments of the merged program anymore
+        let (second_arguments, second_private): (Vec<_>, Vec<_>) = second
+            .main
+            .arguments
+            .into_iter()
+            .zip(second.private.into_iter())
+            .filter(|(argument, _)| !wired.contains(argument))
+            .unzip();
+
+        let mut second_renamer = Renamer::new(substitution, first_renamer.next_index);
+
+        let second_main = second_renamer.fold_function(Function {
+            arguments: second_arguments,
+            ..second.main
+        });
+
+        Prog {
+            main: Function {
+                id: first_main.id,
+                arguments: first_main
+                    .arguments
+                    .into_iter()
+                    .chain(second_main.arguments)
+                    .collect(),
+                statements: first_main
+                    .statements
+                    .into_iter()
+                    .chain(second_main.statements)
+                    .collect(),
+                returns: second_main.returns,
+            },
+            private: first.private.into_iter().chain(second_private).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    #[test]
+    fn merge_doubler_and_adder() {
+        // def main(_0) -> (1):
+        //     (2 * _0) == ~out_0
+        //     return ~out_0
+        let doubler: Prog<FieldPrime> = Prog {
+            main: Function {
+                id: "doubler".to_string(),
+                arguments: vec![FlatVariable::new(0)],
+                statements: vec![Statement::definition(
+                    FlatVariable::public(0),
+                    LinComb::summand(2, FlatVariable::new(0)),
+                )],
+                returns: vec![FlatVariable::public(0)],
+            },
+            private: vec![true],
+        };
+
+        // def main(_0, _1) -> (1):
+        //     (_0 + _1) == ~out_0
+        //     return ~out_0
+        let adder: Prog<FieldPrime> = Prog {
+            main: Function {
+                id: "adder".to_string(),
+                arguments: vec![FlatVariable::new(0), FlatVariable::new(1)],
+                statements: vec![Statement::definition(
+                    FlatVariable::public(0),
+                    LinComb::from(FlatVariable::new(0)) + LinComb::from(FlatVariable::new(1)),
+                )],
+                returns: vec![FlatVariable::public(0)],
+            },
+            private: vec![true, false],
+        };
+
+        let merged = Prog::merge(
+            doubler,
+            adder,
+            &[("~out_0".to_string(), "_0".to_string())],
+        );
+
+        // def main(_1, _3) -> (1):
+        //     (2 * _1) == _0
+        //     (_0 + _3) == ~out_0
+        //     return ~out_0
+        let expected = Prog {
+            main: Function {
+                id: "doubler".to_string(),
+                arguments: vec![FlatVariable::new(1), FlatVariable::new(3)],
+                statements: vec![
+                    Statement::definition(
+                        FlatVariable::new(0),
+                        LinComb::summand(2, FlatVariable::new(1)),
+                    ),
+                    Statement::definition(
+                        FlatVariable::public(0),
+                        LinComb::from(FlatVariable::new(0)) + LinComb::from(FlatVariable::new(3)),
+                    ),
+                ],
+                returns: vec![FlatVariable::public(0)],
+            },
+            private: vec![true, false],
+        };
+
+        assert_eq!(merged, expected);
+
+        let witness = Interpreter::default()
+            .execute(&merged, &vec![FieldPrime::from(3), FieldPrime::from(4)])
+            .unwrap();
+
+        assert_eq!(witness.return_values(), vec![FieldPrime::from(10)]);
+    }
+}
@@ -4,16 +4,26 @@ use crate::solvers::Solver;
 use std::fmt;
 use zokrates_field::field::Field;
 
+mod binary;
 mod expression;
+mod fingerprint;
 pub mod folder;
 mod from_flat;
+mod inputs;
 mod interpreter;
+mod merge;
+mod order;
+mod rename;
 mod witness;
 
+pub use self::binary::{read_binary, write_binary};
 pub use self::expression::QuadComb;
 pub use self::expression::{CanonicalLinComb, LinComb};
+pub use self::fingerprint::fingerprint;
 
-pub use self::interpreter::{Error, ExecutionResult, Interpreter};
+pub use self::inputs::{check_inputs, substitute_inputs, InputError};
+pub use self::interpreter::{check_assignment, Error, ExecutionResult, Interpreter};
+pub use self::order::order_for_witness;
 pub use self::witness::Witness;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Hash, Eq)]
@@ -30,6 +40,14 @@ impl<T: Field> Statement<T> {
     pub fn constraint<U: Into<QuadComb<T>>, V: Into<LinComb<T>>>(quad: U, lin: V) -> Self {
         Statement::Constraint(quad.into(), lin.into())
     }
+
+    /// Returns `(l, r)` if this is the squaring constraint `l * l == r`
+    pub fn try_square(&self) -> Option<(&LinComb<T>, &LinComb<T>)> {
+        match self {
+            Statement::Constraint(quad, lin) => quad.try_square().map(|l| (l, lin)),
+            Statement::Directive(..) => None,
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize, Hash, Eq)]
@@ -120,6 +138,22 @@ impl<T: Field> Prog<T> {
             .count()
     }
 
+    /// Returns the number of constraints of this program which multiply two non-constant linear combinations
+    ///
+    /// # Remarks
+    /// * Linear constraints, including multiplications by a constant, are cheap in most proving schemes, so this is
+    /// a better estimate of the proving cost than `constraint_count`.
+    pub fn multiplication_count(&self) -> usize {
+        self.main
+            .statements
+            .iter()
+            .filter(|s| match s {
+                Statement::Constraint(quad, _) => quad.try_linear().is_none(),
+                _ => false,
+            })
+            .count()
+    }
+
     pub fn arguments_count(&self) -> usize {
         self.private.len()
     }
@@ -135,6 +169,63 @@ impl<T: Field> Prog<T> {
             })
             .collect()
     }
+
+    /// Returns the names of the outputs of this program, in order
+    pub fn outputs(&self) -> Vec<String> {
+        self.main.returns.iter().map(|v| v.to_string()).collect()
+    }
+
+    /// Returns the names of the public variables of this program, in the order of the public segment of the witness
+    ///
+    /// # Remarks
+    /// * The public segment is `~one`, then the public arguments, then the outputs. All other variables, including
+    /// private arguments, are private.
+    pub fn public_variables(&self) -> Vec<String> {
+        let arguments = self
+            .main
+            .arguments
+            .iter()
+            .zip(self.private.iter())
+            .filter(|(_, private)| !**private)
+            .map(|(v, _)| v);
+
+        std::iter::once(&FlatVariable::one())
+            .chain(arguments)
+            .chain(self.main.returns.iter())
+            .map(|v| v.to_string())
+            .collect()
+    }
+
+    /// Removes the directives of this program, keeping its constraints
+    ///
+    /// # Remarks
+    /// * Directives compute the variables the constraints only check, so the `Interpreter` cannot generate a witness
+    /// for a program without them. This is only useful for backends which compute the witness by other means.
+    pub fn strip_directives(self) -> Prog<T> {
+        Prog {
+            main: Function {
+                statements: self
+                    .main
+                    .statements
+                    .into_iter()
+                    .filter(|s| match s {
+                        Statement::Directive(..) => false,
+                        _ => true,
+                    })
+                    .collect(),
+                ..self.main
+            },
+            ..self
+        }
+    }
+}
+
+/// Checks two programs for structural equality, ignoring the `id` of their main function
+pub fn prog_eq<T: Field>(a: &Prog<T>, b: &Prog<T>) -> bool {
+    a.private == b.private
+        && a.main.arguments == b.main.arguments
+        && a.main.statements == b.main.statements
+        && a.main.returns == b.main.returns
 }
 
 impl<T: Field> fmt::Display for Prog<T> {
@@ -163,4 +254,189 @@ mod tests {
             assert_eq!(format!("{}", c), "(1 * _42) * (1 * _42) == 1 * _42")
         }
     }
+
+    mod prog {
+        use super::*;
+
+        fn prog(id: &str, statements: Vec<Statement<FieldPrime>>) -> Prog<FieldPrime> {
+            Prog {
+                main: Function {
+                    id: id.to_string(),
+                    arguments: vec![FlatVariable::new(0)],
+                    statements,
+                    returns: vec![FlatVariable::public(0)],
+                },
+                private: vec![true],
+            }
+        }
+
+        #[test]
+        fn prog_eq_ignores_id() {
+            let statements = vec![Statement::definition(
+                FlatVariable::public(0),
+                LinComb::summand(2, FlatVariable::new(0)),
+            )];
+
+            let a = prog("main", statements.clone());
+            let b = prog("other", statements);
+
+            assert!(a != b);
+            assert!(prog_eq(&a, &b));
+            assert!(prog_eq(&a.clone(), &a));
+        }
+
+        #[test]
+        fn prog_eq_compares_statements() {
+            let a = prog(
+                "main",
+                vec![Statement::definition(
+                    FlatVariable::public(0),
+                    LinComb::summand(2, FlatVariable::new(0)),
+                )],
+            );
+            let b = prog(
+                "main",
+                vec![Statement::definition(
+                    FlatVariable::public(0),
+                    LinComb::summand(3, FlatVariable::new(0)),
+                )],
+            );
+
+            assert!(!prog_eq(&a, &b));
+        }
+
+        #[test]
+        fn outputs() {
+            // ~out_0 = 2 * _0
+            // ~out_1 = _0 * _0
+            let x = FlatVariable::new(0);
+            let p = Prog {
+                main: Function {
+                    returns: vec![FlatVariable::public(0), FlatVariable::public(1)],
+                    ..prog(
+                        "main",
+                        vec![
+                            Statement::definition(FlatVariable::public(0), LinComb::summand(2, x)),
+                            Statement::definition(
+                                FlatVariable::public(1),
+                                QuadComb::from_linear_combinations(x.into(), x.into()),
+                            ),
+                        ],
+                    )
+                    .main
+                },
+                private: vec![true],
+            };
+
+            assert_eq!(p.outputs(), vec!["~out_0", "~out_1"]);
+
+            let witness = Interpreter::default()
+                .execute(&p, &vec![FieldPrime::from(3)])
+                .unwrap();
+
+            assert_eq!(
+                witness.named_outputs(),
+                vec![
+                    ("~out_0".to_string(), FieldPrime::from(6)),
+                    ("~out_1".to_string(), FieldPrime::from(9))
+                ]
+            );
+            assert_eq!(
+                witness.return_values(),
+                vec![FieldPrime::from(6), FieldPrime::from(9)]
+            );
+        }
+
+        #[test]
+        fn public_variables() {
+            // def main(public _0, private _1) -> (1):
+            //     _2 = _0 * _1
+            //     ~out_0 = _2 + _0
+            let p: Prog<FieldPrime> = Prog {
+                main: Function {
+                    id: "main".to_string(),
+                    arguments: vec![FlatVariable::new(0), FlatVariable::new(1)],
+                    statements: vec![
+                        Statement::definition(
+                            FlatVariable::new(2),
+                            QuadComb::from_linear_combinations(
+                                FlatVariable::new(0).into(),
+                                FlatVariable::new(1).into(),
+                            ),
+                        ),
+                        Statement::definition(
+                            FlatVariable::public(0),
+                            LinComb::from(FlatVariable::new(2)) + FlatVariable::new(0).into(),
+                        ),
+                    ],
+                    returns: vec![FlatVariable::public(0)],
+                },
+                private: vec![false, true],
+            };
+
+            // the private argument `_1` and the intermediate variable `_2` are private
+            assert_eq!(p.public_variables(), vec!["~one", "_0", "~out_0"]);
+        }
+
+        #[test]
+        fn strip_directives() {
+            // # _1, _2 = ConditionEq(_0)
+            // (_0 * _1) == ~out_0
+            // _1 == ~out_0
+            let x = FlatVariable::new(0);
+            let y = FlatVariable::new(1);
+            let constraints = vec![
+                Statement::constraint(
+                    QuadComb::from_linear_combinations(x.into(), y.into()),
+                    FlatVariable::public(0),
+                ),
+                Statement::constraint(y, FlatVariable::public(0)),
+            ];
+
+            let mut statements = constraints.clone();
+            statements.insert(
+                0,
+                Statement::Directive(Directive {
+                    inputs: vec![x.into()],
+                    outputs: vec![y, FlatVariable::new(2)],
+                    solver: Solver::ConditionEq,
+                }),
+            );
+
+            let stripped = prog("main", statements).strip_directives();
+
+            assert_eq!(stripped, prog("main", constraints));
+            assert_eq!(stripped.constraint_count(), 2);
+        }
+
+        #[test]
+        fn multiplication_count() {
+            // _0 * _1 == _2
+            // 3 * _0 == _1
+            // (3 * _0) * 2 == _1
+            let x = FlatVariable::new(0);
+            let y = FlatVariable::new(1);
+            let p: Prog<FieldPrime> = prog(
+                "main",
+                vec![
+                    Statement::constraint(
+                        QuadComb::from_linear_combinations(x.into(), y.into()),
+                        FlatVariable::new(2),
+                    ),
+                    Statement::constraint(LinComb::summand(3, x), y),
+                    Statement::constraint(
+                        QuadComb::from_linear_combinations(
+                            LinComb::summand(3, x),
+                            LinComb::summand(2, FlatVariable::one()),
+                        ),
+                        y,
+                    ),
+                ],
+            );
+
+            assert_eq!(p.constraint_count(), 3);
+            // only `_0 * _1` is a multiplication
+            assert_eq!(p.multiplication_count(), 1);
+        }
+    }
 }
@@ -0,0 +1,208 @@
+//! Module containing the reordering of the statements of a `Prog` for witness computation
+//!
+//! Each variable is computed either by a directive or by a constraint of the form `quad == v`. The statements are
+//! reordered so that variables are computed before they are used, keeping the original order whenever possible.
+
+use crate::flat_absy::flat_variable::FlatVariable;
+use crate::ir::*;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use zokrates_field::field::Field;
+
+/// Returns the variables of a linear combination
+fn lin_variables<'a, T: Field>(l: &'a LinComb<T>) -> impl Iterator<Item = FlatVariable> + 'a {
+    l.0.iter().map(|(v, _)| *v)
+}
+
+/// Returns the variable defined by a constraint `quad == v`, if any
+fn assignee<T: Field>(lin: &LinComb<T>) -> Option<FlatVariable> {
+    match lin.0.as_slice() {
+        [(v, c)] if *c == T::one() => Some(*v),
+        _ => None,
+    }
+}
+
+/// Reorders the statements of a program so that each variable is computed before it is used
+///
+/// # Remarks
+/// * The sort is stable: statements are kept in their original order unless a dependency requires otherwise.
+/// * Statements whose dependencies cannot be computed are left at the end, in their original order.
+pub fn order_for_witness<T: Field>(prog: Prog<T>) -> Prog<T> {
+    let statements = prog.main.statements;
+
+    // the variables available before any statement is run
+    let inputs: HashSet<FlatVariable> = std::iter::once(FlatVariable::one())
+        .chain(prog.main.arguments.iter().cloned())
+        .collect();
+
+    // directives compute their outputs
+    let mut definer: HashMap<FlatVariable, usize> = HashMap::new();
+    for (i, s) in statements.iter().enumerate() {
+        if let Statement::Directive(d) = s {
+            for o in &d.outputs {
+                definer.entry(*o).or_insert(i);
+            }
+        }
+    }
+
+    // the first constraint `quad == v` computes `v` if nothing else does
+    for (i, s) in statements.iter().enumerate() {
+        if let Statement::Constraint(_, lin) = s {
+            if let Some(v) = assignee(lin) {
+                if !inputs.contains(&v) {
+                    definer.entry(v).or_insert(i);
+                }
+            }
+        }
+    }
+
+    // the statements each statement depends on
+    let dependencies: Vec<HashSet<usize>> = statements
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let used: Vec<FlatVariable> = match s {
+                Statement::Directive(d) => d.inputs.iter().flat_map(lin_variables).collect(),
+                Statement::Constraint(quad, lin) => lin_variables(&quad.left)
+                    .chain(lin_variables(&quad.right))
+                    .chain(lin_variables(lin))
+                    .collect(),
+            };
+
+            used.into_iter()
+                .filter_map(|v| definer.get(&v).cloned())
+                .filter(|d| *d != i)
+                .collect()
+        })
+        .collect();
+
+    let mut dependents: Vec<Vec<usize>> = vec![vec![]; statements.len()];
+    for (i, deps) in dependencies.iter().enumerate() {
+        for d in deps {
+            dependents[*d].push(i);
+        }
+    }
+
+    let mut remaining: Vec<usize> = dependencies.iter().map(|deps| deps.len()).collect();
+
+    // always pick the earliest statement whose dependencies are computed
+    let mut ready: BTreeSet<usize> = (0..statements.len())
+        .filter(|i| remaining[*i] == 0)
+        .collect();
+
+    let mut order = vec![];
+
+    while let Some(i) = ready.iter().next().cloned() {
+        ready.remove(&i);
+        order.push(i);
+        for d in &dependents[i] {
+            remaining[*d] -= 1;
+            if remaining[*d] == 0 {
+                ready.insert(*d);
+            }
+        }
+    }
+
+    let ordered: HashSet<usize> = order.iter().cloned().collect();
+    order.extend((0..statements.len()).filter(|i| !ordered.contains(i)));
+
+    let mut statements: Vec<Option<Statement<T>>> = statements.into_iter().map(Some).collect();
+
+    Prog {
+        main: Function {
+            statements: order
+                .into_iter()
+                .map(|i| statements[i].take().unwrap())
+                .collect(),
+            ..prog.main
+        },
+        ..prog
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solvers::Solver;
+    use zokrates_field::field::FieldPrime;
+
+    #[test]
+    fn reorder_scrambled() {
+        let a = FlatVariable::new(0);
+        let x = FlatVariable::new(1);
+        let y = FlatVariable::new(2);
+        let m = FlatVariable::new(3);
+        let b = FlatVariable::new(4);
+
+        // x = a * a
+        let square =
+            Statement::definition(x, QuadComb::from_linear_combinations(a.into(), a.into()));
+        // # y, m = ConditionEq(x)
+        let hint = Statement::Directive(Directive {
+            inputs: vec![x.into()],
+            outputs: vec![y, m],
+            solver: Solver::ConditionEq,
+        });
+        // y == x * m
+        let check =
+            Statement::constraint(QuadComb::from_linear_combinations(x.into(), m.into()), y);
+        // b = 2 * a, independent from the above
+        let double = Statement::definition(b, LinComb::summand(2, a));
+        // ~out_0 = y + b
+        let output =
+            Statement::definition(FlatVariable::public(0), LinComb::from(y) + LinComb::from(b));
+
+        let prog = |statements| Prog::<FieldPrime> {
+            main: Function {
+                id: "main".to_string(),
+                arguments: vec![a],
+                statements,
+                returns: vec![FlatVariable::public(0)],
+            },
+            private: vec![true],
+        };
+
+        let scrambled = prog(vec![
+            output.clone(),
+            check.clone(),
+            double.clone(),
+            hint.clone(),
+            square.clone(),
+        ]);
+
+        let ordered = order_for_witness(scrambled);
+
+        // `output` only depends on `hint` and `double`, so it comes before `check`
+        assert_eq!(ordered, prog(vec![double, square, hint, output, check]));
+
+        let witness = Interpreter::default()
+            .execute(&ordered, &vec![FieldPrime::from(3)])
+            .unwrap();
+
+        assert_eq!(witness.return_values(), vec![FieldPrime::from(7)]);
+    }
+
+    #[test]
+    fn keep_valid_order() {
+        let a = FlatVariable::new(0);
+        let b = FlatVariable::new(1);
+        let c = FlatVariable::new(2);
+
+        let statements = vec![
+            Statement::definition(b, LinComb::summand(3, a)),
+            Statement::definition(c, QuadComb::from_linear_combinations(a.into(), a.into())),
+            Statement::definition(FlatVariable::public(0), LinComb::from(b) + LinComb::from(c)),
+        ];
+
+        let prog = Prog::<FieldPrime> {
+            main: Function {
+                id: "main".to_string(),
+                arguments: vec![a],
+                statements,
+                returns: vec![FlatVariable::public(0)],
+            },
+            private: vec![true],
+        };
+
+        assert_eq!(order_for_witness(prog.clone()), prog);
+    }
+}
@@ -0,0 +1,152 @@
+//! Module containing the renaming of the variables of a `Prog`
+
+use crate::flat_absy::flat_variable::FlatVariable;
+use crate::ir::folder::Folder;
+use crate::ir::*;
+use std::collections::HashMap;
+use zokrates_field::field::Field;
+
+/// Renames the variables of a program, keeping track of the next free index
+pub struct Renamer {
+    /// Explicit renamings, applied before shifting
+    pub substitution: HashMap<FlatVariable, FlatVariable>,
+    /// Shift applied to internal variables which are not explicitly renamed
+    pub offset: usize,
+    /// One past the largest index of an internal variable after renaming
+    pub next_index: usize,
+}
+
+impl Renamer {
+    pub fn new(substitution: HashMap<FlatVariable, FlatVariable>, offset: usize) -> Self {
+        Renamer {
+            substitution,
+            offset,
+            next_index: 0,
+        }
+    }
+}
+
+impl<T: Field> Folder<T> for Renamer {
+    fn fold_variable(&mut self, v: FlatVariable) -> FlatVariable {
+        // internal variables are the ones greater than `~one`, outputs are smaller
+        let v = match self.substitution.get(&v) {
+            Some(v) => *v,
+            None if v > FlatVariable::one() => FlatVariable::new(v.id() + self.offset),
+            None => v,
+        };
+
+        if v > FlatVariable::one() {
+            self.next_index = std::cmp::max(self.next_index, v.id() + 1);
+        }
+
+        v
+    }
+}
+
+impl<T: Field> Prog<T> {
+    /// Renames the variables of this program according to `map`
+    ///
+    /// # Arguments
+    ///
+    /// * `map` - Mapping between human-readable variables, such as `"_0" => "_42"`. Unmapped variables are left unchanged.
+    ///
+    /// # Panics
+    /// * If a variable in `map` cannot be parsed.
+    pub fn rename_variables(self, map: &HashMap<String, String>) -> Prog<T> {
+        let parse = |v: &String| {
+            FlatVariable::try_from_human_readable(v)
+                .unwrap_or_else(|e| panic!("Cannot parse variable {}", e))
+        };
+
+        let substitution = map
+            .iter()
+            .map(|(from, to)| (parse(from), parse(to)))
+            .collect();
+
+        Renamer::new(substitution, 0).fold_module(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solvers::Solver;
+    use zokrates_field::field::FieldPrime;
+
+    #[test]
+    fn rename_everywhere() {
+        // def main(_0, _1) -> (1):
+        //     # _2 = ConditionEq(_0 - _1)
+        //     (_0 * _2) == _1
+        //     (_0 + _1) == ~out_0
+        //     return ~out_0
+        let prog = |a: FlatVariable, b: FlatVariable, c: FlatVariable| Prog::<FieldPrime> {
+            main: Function {
+                id: "main".to_string(),
+                arguments: vec![a, b],
+                statements: vec![
+                    Statement::Directive(Directive {
+                        inputs: vec![LinComb::from(a) - LinComb::from(b)],
+                        outputs: vec![c],
+                        solver: Solver::ConditionEq,
+                    }),
+                    Statement::constraint(
+                        QuadComb::from_linear_combinations(a.into(), c.into()),
+                        b,
+                    ),
+                    Statement::definition(
+                        FlatVariable::public(0),
+                        LinComb::from(a) + LinComb::from(b),
+                    ),
+                ],
+                returns: vec![FlatVariable::public(0)],
+            },
+            private: vec![true, true],
+        };
+
+        let map = vec![
+            ("_0".to_string(), "_42".to_string()),
+            ("_2".to_string(), "_7".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        let renamed = prog(
+            FlatVariable::new(0),
+            FlatVariable::new(1),
+            FlatVariable::new(2),
+        )
+        .rename_variables(&map);
+
+        assert_eq!(
+            renamed,
+            prog(
+                FlatVariable::new(42),
+                FlatVariable::new(1),
+                FlatVariable::new(7)
+            )
+        );
+    }
+
+    #[test]
+    fn rename_outputs() {
+        let prog = |out: FlatVariable| Prog::<FieldPrime> {
+            main: Function {
+                id: "main".to_string(),
+                arguments: vec![FlatVariable::new(0)],
+                statements: vec![Statement::definition(out, FlatVariable::new(0))],
+                returns: vec![out],
+            },
+            private: vec![true],
+        };
+
+        let map = vec![("~out_0".to_string(), "_1".to_string())]
+            .into_iter()
+            .collect();
+
+        assert_eq!(
+            prog(FlatVariable::public(0)).rename_variables(&map),
+            prog(FlatVariable::new(1))
+        );
+    }
+}
@@ -22,6 +22,16 @@ impl<T: Field> Witness<T> {
             .collect()
     }
 
+    /// Returns the outputs of this witness with their name, in order
+    pub fn named_outputs(&self) -> Vec<(String, T)> {
+        let count = self.0.keys().filter(|k| k.is_output()).count();
+
+        (0..count)
+            .map(FlatVariable::public)
+            .map(|v| (v.to_string(), self.0.get(&v).unwrap().clone()))
+            .collect()
+    }
+
     pub fn format_outputs(&self) -> String {
         self.0
             .iter()
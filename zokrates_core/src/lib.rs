@@ -12,20 +12,24 @@ extern crate bellman_ce as bellman;
 extern crate bincode;
 extern crate ff_ce as ff;
 extern crate lazy_static;
+#[cfg(feature = "trace")]
+extern crate log;
 extern crate pairing_ce as pairing;
 extern crate regex;
+extern crate sha2;
 extern crate zokrates_embed;
 extern crate zokrates_field;
 extern crate zokrates_pest_ast;
 
 mod embed;
-mod flatten;
+pub mod flatten;
 pub mod imports;
 mod optimizer;
 mod parser;
 mod semantics;
 mod solvers;
 mod static_analysis;
+mod trace;
 
 pub mod absy;
 pub mod compile;
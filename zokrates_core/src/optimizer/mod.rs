@@ -13,6 +13,7 @@ use self::redefinition::RedefinitionOptimizer;
 use self::tautology::TautologyOptimizer;
 
 use crate::ir::Prog;
+use crate::trace::Phase;
 use zokrates_field::field::Field;
 
 pub trait Optimize {
@@ -22,11 +23,17 @@ pub trait Optimize {
 impl<T: Field> Optimize for Prog<T> {
     fn optimize(self) -> Self {
         // remove redefinitions
+        let phase = Phase::start("optimize_redefinitions");
         let r = RedefinitionOptimizer::optimize(self);
+        phase.end(r.main.statements.len());
         // remove constraints that are always satisfied
+        let phase = Phase::start("optimize_tautologies");
         let r = TautologyOptimizer::optimize(r);
+        phase.end(r.main.statements.len());
         // remove duplicate constraints
+        let phase = Phase::start("optimize_duplicates");
         let r = DuplicateOptimizer::optimize(r);
+        phase.end(r.main.statements.len());
         r
     }
 }
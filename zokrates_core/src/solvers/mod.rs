@@ -7,6 +7,7 @@ pub enum Solver {
     Bits,
     Div,
     Sha256Round,
+    EuclideanDiv,
 }
 
 impl fmt::Display for Solver {
@@ -22,6 +23,7 @@ impl Signed for Solver {
             Solver::Bits => (1, 254),
             Solver::Div => (2, 1),
             Solver::Sha256Round => (768, 26935),
+            Solver::EuclideanDiv => (2, 2),
         }
     }
 }
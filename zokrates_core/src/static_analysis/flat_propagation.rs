@@ -81,6 +81,7 @@ impl<T: Field> FlatStatement<T> {
                     .collect(),
                 ..d
             })),
+            FlatStatement::Comment(text) => Some(FlatStatement::Comment(text)),
         }
     }
 }
@@ -161,4 +162,121 @@ mod tests {
             }
         }
     }
+
+    #[cfg(test)]
+    mod function {
+        use super::*;
+
+        #[test]
+        fn constant_definition() {
+            // def main(_0):
+            //     _1 = 5
+            //     _2 = _0 * _1
+            //     _2 == _1 * _0
+            //     return _2 + _1
+
+            // def main(_0):
+            //     _2 = _0 * 5
+            //     _2 == 5 * _0
+            //     return _2 + 5
+
+            let f: FlatFunction<FieldPrime> = FlatFunction {
+                arguments: vec![FlatParameter::private(FlatVariable::new(0))],
+                statements: vec![
+                    FlatStatement::Definition(
+                        FlatVariable::new(1),
+                        FlatExpression::Number(FieldPrime::from(5)),
+                    ),
+                    FlatStatement::Definition(
+                        FlatVariable::new(2),
+                        FlatExpression::Mult(
+                            box FlatExpression::Identifier(FlatVariable::new(0)),
+                            box FlatExpression::Identifier(FlatVariable::new(1)),
+                        ),
+                    ),
+                    FlatStatement::Condition(
+                        FlatExpression::Identifier(FlatVariable::new(2)),
+                        FlatExpression::Mult(
+                            box FlatExpression::Identifier(FlatVariable::new(1)),
+                            box FlatExpression::Identifier(FlatVariable::new(0)),
+                        ),
+                    ),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![FlatExpression::Add(
+                            box FlatExpression::Identifier(FlatVariable::new(2)),
+                            box FlatExpression::Identifier(FlatVariable::new(1)),
+                        )],
+                    }),
+                ],
+            };
+
+            let expected = FlatFunction {
+                arguments: vec![FlatParameter::private(FlatVariable::new(0))],
+                statements: vec![
+                    FlatStatement::Definition(
+                        FlatVariable::new(2),
+                        FlatExpression::Mult(
+                            box FlatExpression::Identifier(FlatVariable::new(0)),
+                            box FlatExpression::Number(FieldPrime::from(5)),
+                        ),
+                    ),
+                    FlatStatement::Condition(
+                        FlatExpression::Identifier(FlatVariable::new(2)),
+                        FlatExpression::Mult(
+                            box FlatExpression::Number(FieldPrime::from(5)),
+                            box FlatExpression::Identifier(FlatVariable::new(0)),
+                        ),
+                    ),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![FlatExpression::Add(
+                            box FlatExpression::Identifier(FlatVariable::new(2)),
+                            box FlatExpression::Number(FieldPrime::from(5)),
+                        )],
+                    }),
+                ],
+            };
+
+            assert_eq!(f.propagate(), expected);
+        }
+
+        #[test]
+        fn chained_constant_definitions() {
+            // def main():
+            //     _0 = 2
+            //     _1 = _0 * 3
+            //     return _1
+
+            // def main():
+            //     return 6
+
+            let f: FlatFunction<FieldPrime> = FlatFunction {
+                arguments: vec![],
+                statements: vec![
+                    FlatStatement::Definition(
+                        FlatVariable::new(0),
+                        FlatExpression::Number(FieldPrime::from(2)),
+                    ),
+                    FlatStatement::Definition(
+                        FlatVariable::new(1),
+                        FlatExpression::Mult(
+                            box FlatExpression::Identifier(FlatVariable::new(0)),
+                            box FlatExpression::Number(FieldPrime::from(3)),
+                        ),
+                    ),
+                    FlatStatement::Return(FlatExpressionList {
+                        expressions: vec![FlatExpression::Identifier(FlatVariable::new(1))],
+                    }),
+                ],
+            };
+
+            let expected = FlatFunction {
+                arguments: vec![],
+                statements: vec![FlatStatement::Return(FlatExpressionList {
+                    expressions: vec![FlatExpression::Number(FieldPrime::from(6))],
+                })],
+            };
+
+            assert_eq!(f.propagate(), expected);
+        }
+    }
 }
@@ -0,0 +1,326 @@
+//! Module containing the hoisting of loop-invariant definitions
+//!
+//! A definition in the body of a `for` loop which depends neither on the loop index nor on any variable assigned in
+//! the body computes the same value at each iteration. It is moved before the loop, so that unrolling emits it once
+//! instead of once per iteration.
+
+use crate::typed_absy::folder::*;
+use crate::typed_absy::*;
+use std::collections::{HashMap, HashSet};
+use zokrates_field::field::Field;
+
+pub struct Hoister;
+
+impl Hoister {
+    pub fn hoist_invariants<'ast, T: Field>(p: TypedProgram<'ast, T>) -> TypedProgram<'ast, T> {
+        Hoister.fold_program(p)
+    }
+}
+
+/// Collects the identifiers read by expressions
+#[derive(Default)]
+struct Reads<'ast> {
+    identifiers: HashSet<Identifier<'ast>>,
+}
+
+impl<'ast, T: Field> Folder<'ast, T> for Reads<'ast> {
+    // declared and assigned variables are not read
+    fn fold_variable(&mut self, v: Variable<'ast>) -> Variable<'ast> {
+        v
+    }
+
+    fn fold_name(&mut self, n: Identifier<'ast>) -> Identifier<'ast> {
+        self.identifiers.insert(n.clone());
+        n
+    }
+}
+
+/// Returns the identifiers read by `s`
+fn reads<'ast, T: Field>(s: &TypedStatement<'ast, T>) -> HashSet<Identifier<'ast>> {
+    let mut reads = Reads::default();
+    reads.fold_statement(s.clone());
+    reads.identifiers
+}
+
+/// Returns the variable whose value is changed by assigning to `a`
+fn assigned<'ast, T: Field>(a: &TypedAssignee<'ast, T>) -> &Identifier<'ast> {
+    match a {
+        TypedAssignee::Identifier(v) => &v.id,
+        TypedAssignee::Select(a, _) | TypedAssignee::Member(a, _) => assigned(a),
+    }
+}
+
+/// Counts the assignments to each variable in `statements`, including in nested loops
+fn count_assignments<'ast, T: Field>(
+    statements: &[TypedStatement<'ast, T>],
+    counts: &mut HashMap<Identifier<'ast>, usize>,
+) {
+    for s in statements {
+        let ids = match s {
+            TypedStatement::Definition(a, _) => vec![assigned(a)],
+            TypedStatement::MultipleDefinition(variables, _) => {
+                variables.iter().map(|v| &v.id).collect()
+            }
+            TypedStatement::CondAssign(v, ..) | TypedStatement::CheckedSub(v, ..) => vec![&v.id],
+            TypedStatement::For(v, _, _, statements) => {
+                count_assignments(statements, counts);
+                vec![&v.id]
+            }
+            _ => vec![],
+        };
+
+        for id in ids {
+            *counts.entry(id.clone()).or_insert(0) += 1;
+        }
+    }
+}
+
+/// Splits the body of a loop over `index` into the invariant definitions and the remaining statements
+///
+/// # Remarks
+/// * A definition of `x` is invariant if it is the only assignment to `x` in the body, if `x` is not read earlier
+/// in the body, and if its value only reads variables which are not assigned in the body.
+/// * The declaration of `x`, if it is in the body, is hoisted along with its definition.
+fn split<'ast, T: Field>(
+    index: &Variable<'ast>,
+    statements: Vec<TypedStatement<'ast, T>>,
+) -> (Vec<TypedStatement<'ast, T>>, Vec<TypedStatement<'ast, T>>) {
+    let mut counts = HashMap::new();
+    count_assignments(&statements, &mut counts);
+    counts.insert(index.id.clone(), 1);
+
+    let mut read = HashSet::new();
+    let mut hoisted = vec![];
+    let mut body = vec![];
+
+    for s in statements {
+        let invariant = match s {
+            TypedStatement::Definition(TypedAssignee::Identifier(ref x), _) => {
+                counts.get(&x.id) == Some(&1)
+                    && !read.contains(&x.id)
+                    && reads(&s).iter().all(|id| !counts.contains_key(id))
+            }
+            _ => false,
+        };
+
+        if invariant {
+            let x = match s {
+                TypedStatement::Definition(TypedAssignee::Identifier(ref x), _) => x.clone(),
+                _ => unreachable!(),
+            };
+
+            counts.remove(&x.id);
+
+            let declaration = TypedStatement::Declaration(x);
+            if let Some(position) = body.iter().position(|s| *s == declaration) {
+                hoisted.push(body.remove(position));
+            }
+
+            hoisted.push(s);
+        } else {
+            read.extend(reads(&s));
+            body.push(s);
+        }
+    }
+
+    (hoisted, body)
+}
+
+impl<'ast, T: Field> Folder<'ast, T> for Hoister {
+    fn fold_statement(&mut self, s: TypedStatement<'ast, T>) -> Vec<TypedStatement<'ast, T>> {
+        match s {
+            TypedStatement::For(v, from, to, statements) => {
+                // inner loops first, so that their invariants can be hoisted further
+                let statements = statements
+                    .into_iter()
+                    .flat_map(|s| self.fold_statement(s))
+                    .collect();
+
+                // hoisted definitions are executed even if the loop is not, so it must run at least once
+                let runs = match (&from, &to) {
+                    (FieldElementExpression::Number(from), FieldElementExpression::Number(to)) => {
+                        from < to
+                    }
+                    _ => false,
+                };
+
+                if !runs {
+                    return vec![TypedStatement::For(v, from, to, statements)];
+                }
+
+                let (mut hoisted, body) = split(&v, statements);
+                hoisted.push(TypedStatement::For(v, from, to, body));
+                hoisted
+            }
+            s => fold_statement(self, s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::static_analysis::propagate_unroll::PropagatedUnroller;
+    use typed_absy::types::{FunctionKey, Signature};
+    use zokrates_field::field::FieldPrime;
+
+    fn declare(id: &str) -> TypedStatement<FieldPrime> {
+        TypedStatement::Declaration(Variable::field_element(id.into()))
+    }
+
+    fn define<'ast>(
+        id: &'ast str,
+        e: FieldElementExpression<'ast, FieldPrime>,
+    ) -> TypedStatement<'ast, FieldPrime> {
+        TypedStatement::Definition(
+            TypedAssignee::Identifier(Variable::field_element(id.into())),
+            e.into(),
+        )
+    }
+
+    fn mult<'ast>(left: &'ast str, right: &'ast str) -> FieldElementExpression<'ast, FieldPrime> {
+        FieldElementExpression::Mult(
+            box FieldElementExpression::Identifier(left.into()),
+            box FieldElementExpression::Identifier(right.into()),
+        )
+    }
+
+    fn add<'ast>(left: &'ast str, right: &'ast str) -> FieldElementExpression<'ast, FieldPrime> {
+        FieldElementExpression::Add(
+            box FieldElementExpression::Identifier(left.into()),
+            box FieldElementExpression::Identifier(right.into()),
+        )
+    }
+
+    fn for_loop<'ast>(
+        to: usize,
+        statements: Vec<TypedStatement<'ast, FieldPrime>>,
+    ) -> TypedStatement<'ast, FieldPrime> {
+        TypedStatement::For(
+            Variable::field_element("i".into()),
+            FieldElementExpression::Number(FieldPrime::from(0)),
+            FieldElementExpression::Number(FieldPrime::from(to)),
+            statements,
+        )
+    }
+
+    #[test]
+    fn hoist_invariant_definitions() {
+        // for field i in 0..3 do
+        //     field b = a * a
+        //     field d = b * b
+        //     c = c + i
+        //     e = c * b
+        // endfor
+        let s = for_loop(
+            3,
+            vec![
+                declare("b"),
+                define("b", mult("a", "a")),
+                declare("d"),
+                define("d", mult("b", "b")),
+                define("c", add("c", "i")),
+                define("e", mult("c", "b")),
+            ],
+        );
+
+        // `d` only depends on `b`, which is hoisted first
+        let expected = vec![
+            declare("b"),
+            define("b", mult("a", "a")),
+            declare("d"),
+            define("d", mult("b", "b")),
+            for_loop(
+                3,
+                vec![define("c", add("c", "i")), define("e", mult("c", "b"))],
+            ),
+        ];
+
+        assert_eq!(Hoister.fold_statement(s), expected);
+    }
+
+    #[test]
+    fn keep_variant_definitions() {
+        // a definition read before it in the body
+        let read_before = for_loop(
+            3,
+            vec![define("c", add("c", "b")), define("b", mult("a", "a"))],
+        );
+        // a variable assigned twice
+        let assigned_twice = for_loop(
+            3,
+            vec![define("b", mult("a", "a")), define("b", add("b", "i"))],
+        );
+        // a loop which never runs
+        let empty_range = for_loop(0, vec![define("b", mult("a", "a"))]);
+
+        for s in vec![read_before, assigned_twice, empty_range] {
+            assert_eq!(Hoister.fold_statement(s.clone()), vec![s]);
+        }
+    }
+
+    #[test]
+    fn emit_invariant_once() {
+        // def main(field a) -> ():
+        //     field c = 0
+        //     for field i in 0..3 do
+        //         field b = a * a
+        //         c = c + b
+        //     endfor
+        let statements = vec![
+            declare("c"),
+            define("c", FieldElementExpression::Number(FieldPrime::from(0))),
+            for_loop(
+                3,
+                vec![
+                    declare("b"),
+                    define("b", mult("a", "a")),
+                    define("c", add("c", "b")),
+                ],
+            ),
+        ];
+
+        let p = TypedProgram {
+            modules: vec![(
+                "main".into(),
+                TypedModule {
+                    functions: vec![(
+                        FunctionKey::with_id("main"),
+                        TypedFunctionSymbol::Here(TypedFunction {
+                            arguments: vec![Parameter::private(Variable::field_element(
+                                "a".into(),
+                            ))],
+                            signature: Signature::new().inputs(vec![Type::FieldElement]),
+                            statements,
+                        }),
+                    )]
+                    .into_iter()
+                    .collect(),
+                },
+            )]
+            .into_iter()
+            .collect(),
+            main: "main".into(),
+        };
+
+        let p = PropagatedUnroller::unroll(Hoister::hoist_invariants(p)).unwrap();
+
+        let statements = match p.modules[&p.main].functions[&FunctionKey::with_id("main")] {
+            TypedFunctionSymbol::Here(ref f) => f.statements.clone(),
+            _ => unreachable!(),
+        };
+
+        let products = statements
+            .iter()
+            .filter(|s| match s {
+                TypedStatement::Definition(
+                    _,
+                    TypedExpression::FieldElement(FieldElementExpression::Mult(..)),
+                ) => true,
+                _ => false,
+            })
+            .count();
+
+        assert_eq!(products, 1);
+    }
+}
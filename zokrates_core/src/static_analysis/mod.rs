@@ -6,12 +6,14 @@
 
 mod constrain_inputs;
 mod flat_propagation;
+mod hoist;
 mod inline;
 mod propagate_unroll;
 mod propagation;
 mod unroll;
 
 use self::constrain_inputs::InputConstrainer;
+use self::hoist::Hoister;
 use self::inline::Inliner;
 use self::propagate_unroll::PropagatedUnroller;
 use self::propagation::Propagator;
@@ -25,8 +27,10 @@ pub trait Analyse {
 
 impl<'ast, T: Field> Analyse for TypedProgram<'ast, T> {
     fn analyse(self) -> Self {
+        // hoist loop invariants
+        let r = Hoister::hoist_invariants(self);
         // propagated unrolling
-        let r = PropagatedUnroller::unroll(self).unwrap_or_else(|e| panic!(e));
+        let r = PropagatedUnroller::unroll(r).unwrap_or_else(|e| panic!(e));
         // inline
         let r = Inliner::inline(r);
         // propagate
@@ -66,6 +66,11 @@ fn is_constant<'ast, T: Field>(e: &TypedExpression<'ast, T>) -> bool {
     }
 }
 
+/// Returns whether `n` represents a negative integer, i.e. whether it lies in the upper half of the field
+fn is_negative<T: Field>(n: &T) -> bool {
+    T::zero() - n.clone() < *n
+}
+
 impl<'ast, T: Field> Folder<'ast, T> for Propagator<'ast, T> {
     fn fold_function(&mut self, f: TypedFunction<'ast, T>) -> TypedFunction<'ast, T> {
         self.constants = HashMap::new();
@@ -73,7 +78,8 @@ impl<'ast, T: Field> Folder<'ast, T> for Propagator<'ast, T> {
     }
 
     fn fold_statement(&mut self, s: TypedStatement<'ast, T>) -> Vec<TypedStatement<'ast, T>> {
-        let res = match s {
+        // conditional assignments are propagated as definitions
+        let res = match s.desugar() {
             TypedStatement::Declaration(v) => Some(TypedStatement::Declaration(v)),
             TypedStatement::Return(expressions) => Some(TypedStatement::Return(
                 expressions
@@ -135,6 +141,20 @@ impl<'ast, T: Field> Folder<'ast, T> for Propagator<'ast, T> {
                     expression_list,
                 ))
             }
+            // the range check is kept even if both sides are constant
+            TypedStatement::CheckedSub(var, left, right) => Some(TypedStatement::CheckedSub(
+                var,
+                self.fold_field_expression(left),
+                self.fold_field_expression(right),
+            )),
+            TypedStatement::AssertBool(e) => {
+                Some(TypedStatement::AssertBool(self.fold_field_expression(e)))
+            }
+            TypedStatement::AssertNonZero(e) => {
+                Some(TypedStatement::AssertNonZero(self.fold_field_expression(e)))
+            }
+            TypedStatement::Comment(text) => Some(TypedStatement::Comment(text)),
+            TypedStatement::CondAssign(..) => unreachable!("should have been desugared"),
         };
 
         // In verbose mode, we always return a statement
@@ -173,6 +193,20 @@ impl<'ast, T: Field> Folder<'ast, T> for Propagator<'ast, T> {
                 (FieldElementExpression::Number(n1), FieldElementExpression::Number(n2)) => {
                     FieldElementExpression::Number(n1 + n2)
                 }
+                // x + (-n) == x - n
+                (e1, FieldElementExpression::Number(n2)) if is_negative(&n2) => {
+                    FieldElementExpression::Sub(
+                        box e1,
+                        box FieldElementExpression::Number(T::zero() - n2),
+                    )
+                }
+                // (-n) + x == x - n
+                (FieldElementExpression::Number(n1), e2) if is_negative(&n1) => {
+                    FieldElementExpression::Sub(
+                        box e2,
+                        box FieldElementExpression::Number(T::zero() - n1),
+                    )
+                }
                 (e1, e2) => FieldElementExpression::Add(box e1, box e2),
             },
             FieldElementExpression::Sub(box e1, box e2) => match (
@@ -182,6 +216,13 @@ impl<'ast, T: Field> Folder<'ast, T> for Propagator<'ast, T> {
                 (FieldElementExpression::Number(n1), FieldElementExpression::Number(n2)) => {
                     FieldElementExpression::Number(n1 - n2)
                 }
+                // x - (-n) == x + n
+                (e1, FieldElementExpression::Number(n2)) if is_negative(&n2) => {
+                    FieldElementExpression::Add(
+                        box e1,
+                        box FieldElementExpression::Number(T::zero() - n2),
+                    )
+                }
                 (e1, e2) => FieldElementExpression::Sub(box e1, box e2),
             },
             FieldElementExpression::Mult(box e1, box e2) => match (
@@ -273,6 +314,30 @@ impl<'ast, T: Field> Folder<'ast, T> for Propagator<'ast, T> {
                     }
                 }
             }
+            FieldElementExpression::DynSelect(id, box index, size) => {
+                let array = self.fold_array_expression(
+                    ArrayExpressionInner::Identifier(id).annotate(Type::FieldElement, size),
+                );
+                let index = self.fold_field_expression(index);
+
+                match (array.into_inner(), index) {
+                    (ArrayExpressionInner::Identifier(id), FieldElementExpression::Number(n)) => {
+                        self.fold_field_expression(FieldElementExpression::Select(
+                            box ArrayExpressionInner::Identifier(id)
+                                .annotate(Type::FieldElement, size),
+                            box FieldElementExpression::Number(n),
+                        ))
+                    }
+                    (ArrayExpressionInner::Identifier(id), index) => {
+                        FieldElementExpression::DynSelect(id, box index, size)
+                    }
+                    // the array is a known value, fall back to a regular selection
+                    (a, i) => FieldElementExpression::Select(
+                        box a.annotate(Type::FieldElement, size),
+                        box i,
+                    ),
+                }
+            }
             FieldElementExpression::Member(box s, m) => {
                 let s = self.fold_struct_expression(s);
 
@@ -531,6 +596,10 @@ impl<'ast, T: Field> Folder<'ast, T> for Propagator<'ast, T> {
                     (e1, e2) => BooleanExpression::FieldEq(box e1, box e2),
                 }
             }
+            BooleanExpression::IsZero(box e) => match self.fold_field_expression(e) {
+                FieldElementExpression::Number(n) => BooleanExpression::Value(n == T::zero()),
+                e => BooleanExpression::IsZero(box e),
+            },
             BooleanExpression::BoolEq(box e1, box e2) => {
                 let e1 = self.fold_boolean_expression(e1);
                 let e2 = self.fold_boolean_expression(e2);
@@ -708,6 +777,48 @@ mod tests {
                 );
             }
 
+            #[test]
+            fn negative_literals() {
+                let x = || FieldElementExpression::Identifier("x".into());
+                let minus_three = || FieldElementExpression::Number(FieldPrime::from(-3));
+                let three = || FieldElementExpression::Number(FieldPrime::from(3));
+
+                // x + (-3) == x - 3
+                let e = FieldElementExpression::Add(box x(), box minus_three());
+                assert_eq!(
+                    Propagator::new().fold_field_expression(e),
+                    FieldElementExpression::Sub(box x(), box three())
+                );
+
+                // (-3) + x == x - 3
+                let e = FieldElementExpression::Add(box minus_three(), box x());
+                assert_eq!(
+                    Propagator::new().fold_field_expression(e),
+                    FieldElementExpression::Sub(box x(), box three())
+                );
+
+                // x - (-3) == x + 3
+                let e = FieldElementExpression::Sub(box x(), box minus_three());
+                assert_eq!(
+                    Propagator::new().fold_field_expression(e),
+                    FieldElementExpression::Add(box x(), box three())
+                );
+
+                // 5 + (-3) == 2
+                let e = FieldElementExpression::Add(
+                    box FieldElementExpression::Number(FieldPrime::from(5)),
+                    box minus_three(),
+                );
+                assert_eq!(
+                    Propagator::new().fold_field_expression(e),
+                    FieldElementExpression::Number(FieldPrime::from(2))
+                );
+
+                // x + 3 is left unchanged
+                let e = FieldElementExpression::Add(box x(), box three());
+                assert_eq!(Propagator::new().fold_field_expression(e.clone()), e);
+            }
+
             #[test]
             fn mult() {
                 let e = FieldElementExpression::Mult(
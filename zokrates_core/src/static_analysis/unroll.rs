@@ -341,7 +341,8 @@ fn linear<'ast, T: Field>(a: TypedAssignee<'ast, T>) -> (Variable, Vec<Access<'a
 impl<'ast, T: Field> Folder<'ast, T> for Unroller<'ast> {
     fn fold_statement(&mut self, s: TypedStatement<'ast, T>) -> Vec<TypedStatement<'ast, T>> {
         self.statement_count += 1;
-        match s {
+        // conditional assignments are unrolled as definitions
+        match s.desugar() {
             TypedStatement::Declaration(_) => vec![],
             TypedStatement::Definition(assignee, expr) => {
                 let expr = self.fold_expression(expr);
@@ -397,6 +398,16 @@ impl<'ast, T: Field> Folder<'ast, T> for Unroller<'ast> {
 
                 vec![TypedStatement::MultipleDefinition(variables, exprs)]
             }
+            TypedStatement::CheckedSub(variable, left, right) => {
+                let left = self.fold_field_expression(left);
+                let right = self.fold_field_expression(right);
+
+                vec![TypedStatement::CheckedSub(
+                    self.issue_next_ssa_variable(variable),
+                    left,
+                    right,
+                )]
+            }
             TypedStatement::For(v, from, to, stats) => {
                 let from = self.fold_field_expression(from);
                 let to = self.fold_field_expression(to);
@@ -0,0 +1,101 @@
+//! Module containing the instrumentation of compilation phases
+//!
+//! With the `trace` feature, the number of statements after each phase and the time it took are logged
+//! with the `log` crate, under the `zokrates::phase` target. Without it, phases are not timed.
+
+#[cfg(feature = "trace")]
+use log::info;
+#[cfg(feature = "trace")]
+use std::time::Instant;
+
+/// A compilation phase being timed
+pub struct Phase {
+    #[cfg(feature = "trace")]
+    name: &'static str,
+    #[cfg(feature = "trace")]
+    start: Instant,
+}
+
+impl Phase {
+    /// Starts timing the phase called `name`
+    #[allow(unused_variables)]
+    pub fn start(name: &'static str) -> Phase {
+        Phase {
+            #[cfg(feature = "trace")]
+            name,
+            #[cfg(feature = "trace")]
+            start: Instant::now(),
+        }
+    }
+
+    /// Ends the phase, which left `statement_count` statements
+    #[allow(unused_variables)]
+    pub fn end(self, statement_count: usize) {
+        #[cfg(feature = "trace")]
+        info!(
+            target: "zokrates::phase",
+            "{}: {} statements in {:?}",
+            self.name,
+            statement_count,
+            self.start.elapsed()
+        );
+    }
+}
+
+#[cfg(all(test, feature = "trace"))]
+pub mod tests {
+    use super::*;
+    use lazy_static::lazy_static;
+    use log::{Log, Metadata, Record};
+    use std::sync::{Mutex, Once};
+
+    /// A logger keeping the messages of the `zokrates::phase` target
+    struct CapturingLogger {
+        messages: Mutex<Vec<String>>,
+    }
+
+    impl Log for CapturingLogger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.target() == "zokrates::phase"
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                self.messages
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}", record.args()));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    lazy_static! {
+        static ref LOGGER: CapturingLogger = CapturingLogger {
+            messages: Mutex::new(vec![]),
+        };
+    }
+
+    static INIT: Once = Once::new();
+
+    /// Returns the messages logged so far, by all tests
+    pub fn captured() -> Vec<String> {
+        INIT.call_once(|| {
+            log::set_logger(&*LOGGER).unwrap();
+            log::set_max_level(log::LevelFilter::Info);
+        });
+        LOGGER.messages.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn log_phase() {
+        captured();
+
+        Phase::start("test_phase").end(42);
+
+        assert!(captured()
+            .iter()
+            .any(|m| m.starts_with("test_phase: 42 statements in ")));
+    }
+}
@@ -0,0 +1,470 @@
+//! Module containing the evaluation of typed expressions against an assignment of their variables
+//!
+//! This is a reference semantics independent of flattening: bitwise operations and comparisons operate on the
+//! canonical integer representation of field elements. As in the flattened program, comparisons are only defined for
+//! operands of at most `T::get_required_bits() - 2` bits.
+
+use crate::typed_absy::*;
+use std::collections::HashMap;
+use zokrates_field::field::Field;
+
+/// Errors which can occur when evaluating an expression
+#[derive(Debug, PartialEq)]
+pub enum EvalError {
+    /// The assignment does not contain this variable
+    UndefinedVariable(String),
+    /// The divisor of a division evaluates to zero
+    DivisionByZero,
+    /// The expression cannot be evaluated without its context, such as a function call
+    Unsupported(String),
+    /// An operand of a comparison is too large to be compared
+    OutOfRange(String),
+}
+
+/// Checks that a comparison operand fits in `T::get_required_bits() - 2` bits, like the flattened comparison does
+fn comparable<T: Field>(e: T) -> Result<T, EvalError> {
+    comparable_width(e, T::get_required_bits() - 2)
+}
+
+/// Checks that a comparison operand fits in `width` bits
+fn comparable_width<T: Field>(e: T, width: usize) -> Result<T, EvalError> {
+    match e < T::from(2).pow(width) {
+        true => Ok(e),
+        false => Err(EvalError::OutOfRange(e.to_string())),
+    }
+}
+
+/// Applies a bytewise operation to the canonical representations of `left` and `right`
+fn bitwise<T: Field>(left: T, right: T, op: fn(u8, u8) -> u8) -> T {
+    left.to_byte_vector()
+        .into_iter()
+        .zip(right.to_byte_vector())
+        .map(|(l, r)| op(l, r))
+        .rev()
+        .fold(T::zero(), |acc, b| acc * T::from(256) + T::from(b as u32))
+}
+
+impl<'ast, T: Field> FieldElementExpression<'ast, T> {
+    /// Evaluates this expression given the values of its variables
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - Values of the variables, indexed by their name. Booleans are represented as `0` and `1`.
+    pub fn evaluate(&self, env: &HashMap<String, T>) -> Result<T, EvalError> {
+        match self {
+            FieldElementExpression::Number(n) => Ok(n.clone()),
+            FieldElementExpression::Identifier(id) => env
+                .get(&id.to_string())
+                .cloned()
+                .ok_or_else(|| EvalError::UndefinedVariable(id.to_string())),
+            FieldElementExpression::Add(left, right) => {
+                Ok(left.evaluate(env)? + right.evaluate(env)?)
+            }
+            FieldElementExpression::Sub(left, right) => {
+                Ok(left.evaluate(env)? - right.evaluate(env)?)
+            }
+            FieldElementExpression::Mult(left, right) => {
+                Ok(left.evaluate(env)? * right.evaluate(env)?)
+            }
+            FieldElementExpression::Div(left, right) => {
+                let left = left.evaluate(env)?;
+                let right = right.evaluate(env)?;
+                if right == T::zero() {
+                    return Err(EvalError::DivisionByZero);
+                }
+                Ok(left / right)
+            }
+            FieldElementExpression::Pow(base, exponent) => {
+                Ok(base.evaluate(env)?.pow(exponent.evaluate(env)?))
+            }
+            FieldElementExpression::BitAnd(left, right) => Ok(bitwise(
+                left.evaluate(env)?,
+                right.evaluate(env)?,
+                |a, b| a & b,
+            )),
+            FieldElementExpression::BitOr(left, right) => Ok(bitwise(
+                left.evaluate(env)?,
+                right.evaluate(env)?,
+                |a, b| a | b,
+            )),
+            FieldElementExpression::BitXor(left, right) => Ok(bitwise(
+                left.evaluate(env)?,
+                right.evaluate(env)?,
+                |a, b| a ^ b,
+            )),
+            FieldElementExpression::Shl(e, by) => Ok(e.evaluate(env)? * T::from(2).pow(*by)),
+            FieldElementExpression::Shr(e, by) => {
+                let res = e.evaluate(env)?.to_biguint() >> *by;
+                Ok(T::try_from_dec_str(&res.to_str_radix(10)).unwrap())
+            }
+            FieldElementExpression::ReduceBits(e, bits) => {
+                let res = e.evaluate(env)?.to_biguint() % T::from(2).pow(*bits).to_biguint();
+                Ok(T::try_from_dec_str(&res.to_str_radix(10)).unwrap())
+            }
+            FieldElementExpression::PopCount(e, bits) => {
+                let e = comparable_width(e.evaluate(env)?, *bits)?;
+                let count: u32 = e.to_byte_vector().iter().map(|b| b.count_ones()).sum();
+                Ok(T::from(count))
+            }
+            FieldElementExpression::Abs(e) => {
+                // signed values have as many bits as comparison operands, `n`, and are in `[-2^(n-1), 2^(n-1))`
+                let e = e.evaluate(env)?;
+                let half = T::from(2).pow(T::get_required_bits() - 3);
+                if e < half {
+                    Ok(e)
+                } else if e >= T::zero() - half {
+                    Ok(T::zero() - e)
+                } else {
+                    Err(EvalError::OutOfRange(e.to_string()))
+                }
+            }
+            FieldElementExpression::Sum(terms) => Ok(terms
+                .iter()
+                .map(|t| t.evaluate(env))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .fold(T::zero(), |acc, t| acc + t)),
+            FieldElementExpression::Product(factors) => Ok(factors
+                .iter()
+                .map(|f| f.evaluate(env))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .fold(T::one(), |acc, f| acc * f)),
+            FieldElementExpression::Min(left, right) => {
+                let (left, right) = (left.evaluate(env)?, right.evaluate(env)?);
+                match comparable(left.clone())? < comparable(right.clone())? {
+                    true => Ok(left),
+                    false => Ok(right),
+                }
+            }
+            FieldElementExpression::Max(left, right) => {
+                let (left, right) = (left.evaluate(env)?, right.evaluate(env)?);
+                match comparable(left.clone())? < comparable(right.clone())? {
+                    true => Ok(right),
+                    false => Ok(left),
+                }
+            }
+            FieldElementExpression::Switch(selector, cases, default) => {
+                let selector = selector.evaluate(env)?;
+                cases
+                    .iter()
+                    .find(|(value, _)| *value == selector)
+                    .map(|(_, result)| result)
+                    .unwrap_or(default.as_ref())
+                    .evaluate(env)
+            }
+            FieldElementExpression::IfElse(condition, consequence, alternative) => {
+                match condition.evaluate(env)? {
+                    true => consequence.evaluate(env),
+                    false => alternative.evaluate(env),
+                }
+            }
+            e => Err(EvalError::Unsupported(e.to_string())),
+        }
+    }
+}
+
+impl<'ast, T: Field> BooleanExpression<'ast, T> {
+    /// Evaluates this condition given the values of its variables
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - Values of the variables, indexed by their name. Booleans are represented as `0` and `1`.
+    pub fn evaluate(&self, env: &HashMap<String, T>) -> Result<bool, EvalError> {
+        match self {
+            BooleanExpression::Identifier(id) => env
+                .get(&id.to_string())
+                .map(|v| *v == T::one())
+                .ok_or_else(|| EvalError::UndefinedVariable(id.to_string())),
+            BooleanExpression::Value(v) => Ok(*v),
+            BooleanExpression::Lt(left, right) => {
+                Ok(comparable(left.evaluate(env)?)? < comparable(right.evaluate(env)?)?)
+            }
+            BooleanExpression::Le(left, right) => {
+                Ok(comparable(left.evaluate(env)?)? <= comparable(right.evaluate(env)?)?)
+            }
+            BooleanExpression::LtWidth(left, right, width) => {
+                Ok(comparable_width(left.evaluate(env)?, *width)?
+                    < comparable_width(right.evaluate(env)?, *width)?)
+            }
+            BooleanExpression::LeWidth(left, right, width) => {
+                Ok(comparable_width(left.evaluate(env)?, *width)?
+                    <= comparable_width(right.evaluate(env)?, *width)?)
+            }
+            BooleanExpression::Gt(left, right) => {
+                Ok(comparable(left.evaluate(env)?)? > comparable(right.evaluate(env)?)?)
+            }
+            BooleanExpression::Ge(left, right) => {
+                Ok(comparable(left.evaluate(env)?)? >= comparable(right.evaluate(env)?)?)
+            }
+            BooleanExpression::FieldEq(left, right) => {
+                Ok(left.evaluate(env)? == right.evaluate(env)?)
+            }
+            BooleanExpression::BoolEq(left, right) => {
+                Ok(left.evaluate(env)? == right.evaluate(env)?)
+            }
+            BooleanExpression::Or(left, right) => Ok(left.evaluate(env)? || right.evaluate(env)?),
+            BooleanExpression::And(left, right) => Ok(left.evaluate(env)? && right.evaluate(env)?),
+            BooleanExpression::Not(e) => Ok(!e.evaluate(env)?),
+            BooleanExpression::IsZero(e) => Ok(e.evaluate(env)? == T::zero()),
+            BooleanExpression::Chain(first, rest) => {
+                BooleanExpression::chain_to_and(*first.clone(), rest.clone()).evaluate(env)
+            }
+            BooleanExpression::IfElse(condition, consequence, alternative) => {
+                match condition.evaluate(env)? {
+                    true => consequence.evaluate(env),
+                    false => alternative.evaluate(env),
+                }
+            }
+            e => Err(EvalError::Unsupported(e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::{FieldPrime, Pow};
+
+    fn env() -> HashMap<String, FieldPrime> {
+        vec![
+            ("a".to_string(), FieldPrime::from(6)),
+            ("b".to_string(), FieldPrime::from(3)),
+            ("t".to_string(), FieldPrime::from(1)),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    fn a() -> Box<FieldElementExpression<'static, FieldPrime>> {
+        box FieldElementExpression::Identifier("a".into())
+    }
+
+    fn b() -> Box<FieldElementExpression<'static, FieldPrime>> {
+        box FieldElementExpression::Identifier("b".into())
+    }
+
+    fn n(v: i32) -> Box<FieldElementExpression<'static, FieldPrime>> {
+        box FieldElementExpression::Number(FieldPrime::from(v))
+    }
+
+    mod field {
+        use super::*;
+
+        fn eval(e: FieldElementExpression<'static, FieldPrime>) -> Result<FieldPrime, EvalError> {
+            e.evaluate(&env())
+        }
+
+        #[test]
+        fn number_and_identifier() {
+            assert_eq!(eval(*n(42)), Ok(FieldPrime::from(42)));
+            assert_eq!(eval(*a()), Ok(FieldPrime::from(6)));
+            assert_eq!(
+                eval(FieldElementExpression::Identifier("c".into())),
+                Err(EvalError::UndefinedVariable("c".to_string()))
+            );
+        }
+
+        #[test]
+        fn arithmetic() {
+            assert_eq!(
+                eval(FieldElementExpression::Add(a(), b())),
+                Ok(FieldPrime::from(9))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::Sub(b(), a())),
+                Ok(FieldPrime::from(-3))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::Mult(a(), b())),
+                Ok(FieldPrime::from(18))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::Pow(a(), n(3))),
+                Ok(FieldPrime::from(216))
+            );
+        }
+
+        #[test]
+        fn div() {
+            assert_eq!(
+                eval(FieldElementExpression::Div(a(), b())),
+                Ok(FieldPrime::from(2))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::Div(b(), a())),
+                Ok(FieldPrime::from(1) / FieldPrime::from(2))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::Div(a(), n(0))),
+                Err(EvalError::DivisionByZero)
+            );
+        }
+
+        #[test]
+        fn bitwise() {
+            // 6 = 0b110, 3 = 0b011
+            assert_eq!(
+                eval(FieldElementExpression::BitAnd(a(), b())),
+                Ok(FieldPrime::from(2))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::BitOr(a(), b())),
+                Ok(FieldPrime::from(7))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::BitXor(a(), b())),
+                Ok(FieldPrime::from(5))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::Shl(a(), 2)),
+                Ok(FieldPrime::from(24))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::Shr(a(), 1)),
+                Ok(FieldPrime::from(3))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::ReduceBits(a(), 2)),
+                Ok(FieldPrime::from(2))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::PopCount(a(), 3)),
+                Ok(FieldPrime::from(2))
+            );
+        }
+
+        #[test]
+        fn n_ary() {
+            assert_eq!(
+                eval(FieldElementExpression::Sum(vec![*a(), *b(), *n(1)])),
+                Ok(FieldPrime::from(10))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::Product(vec![*a(), *b(), *n(2)])),
+                Ok(FieldPrime::from(36))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::Sum(vec![])),
+                Ok(FieldPrime::from(0))
+            );
+        }
+
+        #[test]
+        fn switch() {
+            let switch = |selector| {
+                FieldElementExpression::Switch(
+                    selector,
+                    vec![(FieldPrime::from(3), *n(30)), (FieldPrime::from(6), *n(60))],
+                    n(0),
+                )
+            };
+
+            assert_eq!(eval(switch(a())), Ok(FieldPrime::from(60)));
+            assert_eq!(eval(switch(b())), Ok(FieldPrime::from(30)));
+            assert_eq!(eval(switch(n(1))), Ok(FieldPrime::from(0)));
+        }
+
+        #[test]
+        fn if_else() {
+            assert_eq!(
+                eval(FieldElementExpression::IfElse(
+                    box BooleanExpression::Lt(b(), a()),
+                    a(),
+                    b()
+                )),
+                Ok(FieldPrime::from(6))
+            );
+            assert_eq!(
+                eval(FieldElementExpression::IfElse(
+                    box BooleanExpression::Lt(a(), b()),
+                    a(),
+                    box FieldElementExpression::Div(a(), n(0))
+                )),
+                Err(EvalError::DivisionByZero)
+            );
+        }
+
+        #[test]
+        fn function_call() {
+            assert!(eval(FieldElementExpression::FunctionCall(
+                FunctionKey::with_id("foo"),
+                vec![]
+            ))
+            .is_err());
+        }
+    }
+
+    mod boolean {
+        use super::*;
+
+        fn eval(e: BooleanExpression<'static, FieldPrime>) -> Result<bool, EvalError> {
+            e.evaluate(&env())
+        }
+
+        #[test]
+        fn identifier_and_value() {
+            assert_eq!(eval(BooleanExpression::Identifier("t".into())), Ok(true));
+            assert_eq!(eval(BooleanExpression::Value(false)), Ok(false));
+            assert_eq!(
+                eval(BooleanExpression::Identifier("u".into())),
+                Err(EvalError::UndefinedVariable("u".to_string()))
+            );
+        }
+
+        #[test]
+        fn comparisons() {
+            assert_eq!(eval(BooleanExpression::Lt(b(), a())), Ok(true));
+            assert_eq!(eval(BooleanExpression::Le(a(), a())), Ok(true));
+            assert_eq!(eval(BooleanExpression::Gt(b(), a())), Ok(false));
+            assert_eq!(eval(BooleanExpression::Ge(b(), a())), Ok(false));
+            assert_eq!(eval(BooleanExpression::FieldEq(a(), n(6))), Ok(true));
+            assert_eq!(
+                eval(BooleanExpression::IsZero(box FieldElementExpression::Sub(
+                    a(),
+                    n(6)
+                ))),
+                Ok(true)
+            );
+        }
+
+        #[test]
+        fn comparison_boundaries() {
+            let max = FieldPrime::from(2).pow(FieldPrime::get_required_bits() - 2);
+            let largest = box FieldElementExpression::Number(max.clone() - FieldPrime::from(1));
+            let too_large = box FieldElementExpression::Number(max.clone());
+            let minus_one = box FieldElementExpression::Sub(n(0), n(1));
+
+            assert_eq!(eval(BooleanExpression::Lt(n(0), n(0))), Ok(false));
+            assert_eq!(eval(BooleanExpression::Le(n(0), n(0))), Ok(true));
+            assert_eq!(eval(BooleanExpression::Gt(n(1), n(0))), Ok(true));
+            assert_eq!(eval(BooleanExpression::Ge(n(0), n(1))), Ok(false));
+            assert_eq!(eval(BooleanExpression::Lt(n(0), largest.clone())), Ok(true));
+            assert_eq!(
+                eval(BooleanExpression::Ge(largest.clone(), largest)),
+                Ok(true)
+            );
+            assert_eq!(
+                eval(BooleanExpression::Lt(n(0), too_large)),
+                Err(EvalError::OutOfRange(max.to_string()))
+            );
+            // -1 is p - 1 in the field, which is out of range
+            assert!(eval(BooleanExpression::Lt(minus_one, n(0))).is_err());
+        }
+
+        #[test]
+        fn logic() {
+            let t = || box BooleanExpression::Value(true);
+            let f = || box BooleanExpression::Value(false);
+
+            assert_eq!(eval(BooleanExpression::And(t(), f())), Ok(false));
+            assert_eq!(eval(BooleanExpression::Or(t(), f())), Ok(true));
+            assert_eq!(eval(BooleanExpression::Not(f())), Ok(true));
+            assert_eq!(eval(BooleanExpression::BoolEq(f(), f())), Ok(true));
+            assert_eq!(
+                eval(BooleanExpression::IfElse(box BooleanExpression::Value(false), f(), t())),
+                Ok(true)
+            );
+        }
+    }
+}
@@ -169,6 +169,24 @@ pub fn fold_statement<'ast, T: Field, F: Folder<'ast, T>>(
             variables.into_iter().map(|v| f.fold_variable(v)).collect(),
             f.fold_expression_list(elist),
         ),
+        TypedStatement::CondAssign(v, condition, consequence, alternative) => {
+            TypedStatement::CondAssign(
+                f.fold_variable(v),
+                f.fold_boolean_expression(condition),
+                f.fold_field_expression(consequence),
+                f.fold_field_expression(alternative),
+            )
+        }
+        TypedStatement::CheckedSub(v, left, right) => TypedStatement::CheckedSub(
+            f.fold_variable(v),
+            f.fold_field_expression(left),
+            f.fold_field_expression(right),
+        ),
+        TypedStatement::AssertBool(e) => TypedStatement::AssertBool(f.fold_field_expression(e)),
+        TypedStatement::AssertNonZero(e) => {
+            TypedStatement::AssertNonZero(f.fold_field_expression(e))
+        }
+        TypedStatement::Comment(text) => TypedStatement::Comment(text),
     };
     vec![res]
 }
@@ -274,6 +292,72 @@ pub fn fold_field_expression<'ast, T: Field, F: Folder<'ast, T>>(
             let e2 = f.fold_field_expression(e2);
             FieldElementExpression::Pow(box e1, box e2)
         }
+        FieldElementExpression::BitAnd(box e1, box e2) => {
+            let e1 = f.fold_field_expression(e1);
+            let e2 = f.fold_field_expression(e2);
+            FieldElementExpression::BitAnd(box e1, box e2)
+        }
+        FieldElementExpression::BitOr(box e1, box e2) => {
+            let e1 = f.fold_field_expression(e1);
+            let e2 = f.fold_field_expression(e2);
+            FieldElementExpression::BitOr(box e1, box e2)
+        }
+        FieldElementExpression::BitXor(box e1, box e2) => {
+            let e1 = f.fold_field_expression(e1);
+            let e2 = f.fold_field_expression(e2);
+            FieldElementExpression::BitXor(box e1, box e2)
+        }
+        FieldElementExpression::Shl(box e, by) => {
+            let e = f.fold_field_expression(e);
+            FieldElementExpression::Shl(box e, by)
+        }
+        FieldElementExpression::Shr(box e, by) => {
+            let e = f.fold_field_expression(e);
+            FieldElementExpression::Shr(box e, by)
+        }
+        FieldElementExpression::Abs(box e) => {
+            let e = f.fold_field_expression(e);
+            FieldElementExpression::Abs(box e)
+        }
+        FieldElementExpression::ReduceBits(box e, bits) => {
+            let e = f.fold_field_expression(e);
+            FieldElementExpression::ReduceBits(box e, bits)
+        }
+        FieldElementExpression::PopCount(box e, bits) => {
+            let e = f.fold_field_expression(e);
+            FieldElementExpression::PopCount(box e, bits)
+        }
+        FieldElementExpression::Sum(terms) => FieldElementExpression::Sum(
+            terms
+                .into_iter()
+                .map(|t| f.fold_field_expression(t))
+                .collect(),
+        ),
+        FieldElementExpression::Product(factors) => FieldElementExpression::Product(
+            factors
+                .into_iter()
+                .map(|t| f.fold_field_expression(t))
+                .collect(),
+        ),
+        FieldElementExpression::Min(box e1, box e2) => {
+            let e1 = f.fold_field_expression(e1);
+            let e2 = f.fold_field_expression(e2);
+            FieldElementExpression::Min(box e1, box e2)
+        }
+        FieldElementExpression::Max(box e1, box e2) => {
+            let e1 = f.fold_field_expression(e1);
+            let e2 = f.fold_field_expression(e2);
+            FieldElementExpression::Max(box e1, box e2)
+        }
+        FieldElementExpression::Switch(box selector, cases, box default) => {
+            let selector = f.fold_field_expression(selector);
+            let cases = cases
+                .into_iter()
+                .map(|(value, result)| (value, f.fold_field_expression(result)))
+                .collect();
+            let default = f.fold_field_expression(default);
+            FieldElementExpression::Switch(box selector, cases, box default)
+        }
         FieldElementExpression::IfElse(box cond, box cons, box alt) => {
             let cond = f.fold_boolean_expression(cond);
             let cons = f.fold_field_expression(cons);
@@ -293,6 +377,11 @@ pub fn fold_field_expression<'ast, T: Field, F: Folder<'ast, T>>(
             let index = f.fold_field_expression(index);
             FieldElementExpression::Select(box array, box index)
         }
+        FieldElementExpression::DynSelect(id, box index, size) => {
+            let id = f.fold_name(id);
+            let index = f.fold_field_expression(index);
+            FieldElementExpression::DynSelect(id, box index, size)
+        }
     }
 }
 
@@ -323,6 +412,16 @@ pub fn fold_boolean_expression<'ast, T: Field, F: Folder<'ast, T>>(
             let e2 = f.fold_field_expression(e2);
             BooleanExpression::Le(box e1, box e2)
         }
+        BooleanExpression::LtWidth(box e1, box e2, width) => {
+            let e1 = f.fold_field_expression(e1);
+            let e2 = f.fold_field_expression(e2);
+            BooleanExpression::LtWidth(box e1, box e2, width)
+        }
+        BooleanExpression::LeWidth(box e1, box e2, width) => {
+            let e1 = f.fold_field_expression(e1);
+            let e2 = f.fold_field_expression(e2);
+            BooleanExpression::LeWidth(box e1, box e2, width)
+        }
         BooleanExpression::Gt(box e1, box e2) => {
             let e1 = f.fold_field_expression(e1);
             let e2 = f.fold_field_expression(e2);
@@ -347,6 +446,18 @@ pub fn fold_boolean_expression<'ast, T: Field, F: Folder<'ast, T>>(
             let e = f.fold_boolean_expression(e);
             BooleanExpression::Not(box e)
         }
+        BooleanExpression::IsZero(box e) => {
+            let e = f.fold_field_expression(e);
+            BooleanExpression::IsZero(box e)
+        }
+        BooleanExpression::Chain(box first, rest) => {
+            let first = f.fold_field_expression(first);
+            let rest = rest
+                .into_iter()
+                .map(|(op, e)| (op, f.fold_field_expression(e)))
+                .collect();
+            BooleanExpression::Chain(box first, rest)
+        }
         BooleanExpression::FunctionCall(key, exps) => {
             let exps = exps.into_iter().map(|e| f.fold_expression(e)).collect();
             BooleanExpression::FunctionCall(key, exps)
@@ -6,13 +6,18 @@
 //! @date 2017
 
 pub mod abi;
+mod evaluate;
 pub mod folder;
 mod parameter;
+mod size;
 pub mod types;
+mod usage;
 mod variable;
 
+pub use crate::typed_absy::evaluate::EvalError;
 pub use crate::typed_absy::parameter::Parameter;
 pub use crate::typed_absy::types::{Signature, Type};
+pub use crate::typed_absy::usage::unused_arguments;
 pub use crate::typed_absy::variable::Variable;
 use std::path::PathBuf;
 
@@ -346,6 +351,36 @@ pub enum TypedStatement<'ast, T: Field> {
         Vec<TypedStatement<'ast, T>>,
     ),
     MultipleDefinition(Vec<Variable<'ast>>, TypedExpressionList<'ast, T>),
+    CondAssign(
+        Variable<'ast>,
+        BooleanExpression<'ast, T>,
+        FieldElementExpression<'ast, T>,
+        FieldElementExpression<'ast, T>,
+    ),
+    CheckedSub(
+        Variable<'ast>,
+        FieldElementExpression<'ast, T>,
+        FieldElementExpression<'ast, T>,
+    ),
+    AssertBool(FieldElementExpression<'ast, T>),
+    AssertNonZero(FieldElementExpression<'ast, T>),
+    Comment(String),
+}
+
+impl<'ast, T: Field> TypedStatement<'ast, T> {
+    /// Rewrites `x = if c then y else z` as a definition of `x` to an `IfElse` expression, leaves other statements unchanged
+    pub fn desugar(self) -> TypedStatement<'ast, T> {
+        match self {
+            TypedStatement::CondAssign(variable, condition, consequence, alternative) => {
+                TypedStatement::Definition(
+                    TypedAssignee::Identifier(variable),
+                    FieldElementExpression::IfElse(box condition, box consequence, box alternative)
+                        .into(),
+                )
+            }
+            s => s,
+        }
+    }
 }
 
 impl<'ast, T: Field> fmt::Debug for TypedStatement<'ast, T> {
@@ -378,6 +413,19 @@ impl<'ast, T: Field> fmt::Debug for TypedStatement<'ast, T> {
             TypedStatement::MultipleDefinition(ref lhs, ref rhs) => {
                 write!(f, "MultipleDefinition({:?}, {:?})", lhs, rhs)
             }
+            TypedStatement::CondAssign(ref var, ref condition, ref consequence, ref alternative) => {
+                write!(
+                    f,
+                    "CondAssign({:?}, {:?}, {:?}, {:?})",
+                    var, condition, consequence, alternative
+                )
+            }
+            TypedStatement::CheckedSub(ref var, ref left, ref right) => {
+                write!(f, "CheckedSub({:?}, {:?}, {:?})", var, left, right)
+            }
+            TypedStatement::AssertBool(ref e) => write!(f, "AssertBool({:?})", e),
+            TypedStatement::AssertNonZero(ref e) => write!(f, "AssertNonZero({:?})", e),
+            TypedStatement::Comment(ref text) => write!(f, "Comment({:?})", text),
         }
     }
 }
@@ -431,6 +479,19 @@ impl<'ast, T: Field> fmt::Display for TypedStatement<'ast, T> {
                 }
                 write!(f, " = {}", rhs)
             }
+            TypedStatement::CondAssign(ref var, ref condition, ref consequence, ref alternative) => {
+                write!(
+                    f,
+                    "{} = if {} then {} else {} fi",
+                    var, condition, consequence, alternative
+                )
+            }
+            TypedStatement::CheckedSub(ref var, ref left, ref right) => {
+                write!(f, "{} = checked {} - {}", var, left, right)
+            }
+            TypedStatement::AssertBool(ref e) => write!(f, "assert_bool({})", e),
+            TypedStatement::AssertNonZero(ref e) => write!(f, "assert_non_zero({})", e),
+            TypedStatement::Comment(ref text) => write!(f, "# {}", text),
         }
     }
 }
@@ -627,6 +688,40 @@ pub enum FieldElementExpression<'ast, T: Field> {
         Box<FieldElementExpression<'ast, T>>,
         Box<FieldElementExpression<'ast, T>>,
     ),
+    BitAnd(
+        Box<FieldElementExpression<'ast, T>>,
+        Box<FieldElementExpression<'ast, T>>,
+    ),
+    BitOr(
+        Box<FieldElementExpression<'ast, T>>,
+        Box<FieldElementExpression<'ast, T>>,
+    ),
+    BitXor(
+        Box<FieldElementExpression<'ast, T>>,
+        Box<FieldElementExpression<'ast, T>>,
+    ),
+    Shl(Box<FieldElementExpression<'ast, T>>, usize),
+    Shr(Box<FieldElementExpression<'ast, T>>, usize),
+    Abs(Box<FieldElementExpression<'ast, T>>),
+    /// The canonical representative of a value on its lowest bits
+    ReduceBits(Box<FieldElementExpression<'ast, T>>, usize),
+    /// The number of set bits of a value on the given number of bits
+    PopCount(Box<FieldElementExpression<'ast, T>>, usize),
+    Sum(Vec<FieldElementExpression<'ast, T>>),
+    Product(Vec<FieldElementExpression<'ast, T>>),
+    Min(
+        Box<FieldElementExpression<'ast, T>>,
+        Box<FieldElementExpression<'ast, T>>,
+    ),
+    Max(
+        Box<FieldElementExpression<'ast, T>>,
+        Box<FieldElementExpression<'ast, T>>,
+    ),
+    Switch(
+        Box<FieldElementExpression<'ast, T>>,
+        Vec<(T, FieldElementExpression<'ast, T>)>,
+        Box<FieldElementExpression<'ast, T>>,
+    ),
     IfElse(
         Box<BooleanExpression<'ast, T>>,
         Box<FieldElementExpression<'ast, T>>,
@@ -638,6 +733,103 @@ pub enum FieldElementExpression<'ast, T: Field> {
         Box<ArrayExpression<'ast, T>>,
         Box<FieldElementExpression<'ast, T>>,
     ),
+    DynSelect(
+        Identifier<'ast>,
+        Box<FieldElementExpression<'ast, T>>,
+        usize,
+    ),
+}
+
+impl<'ast, T: Field> FieldElementExpression<'ast, T> {
+    /// Rewrites a switch as a chain of `IfElse` expressions, one for each case, ending with the default
+    pub fn switch_to_if_else(
+        selector: FieldElementExpression<'ast, T>,
+        cases: Vec<(T, FieldElementExpression<'ast, T>)>,
+        default: FieldElementExpression<'ast, T>,
+    ) -> FieldElementExpression<'ast, T> {
+        cases
+            .into_iter()
+            .rev()
+            .fold(default, |rest, (value, result)| {
+                FieldElementExpression::IfElse(
+                    box BooleanExpression::FieldEq(
+                        box selector.clone(),
+                        box FieldElementExpression::Number(value),
+                    ),
+                    box result,
+                    box rest,
+                )
+            })
+    }
+
+    /// Rewrites the minimum of `a` and `b` as `if a < b then a else b fi`
+    ///
+    /// # Remarks
+    /// * The operands appear twice, so non-linear operands are flattened twice.
+    pub fn min_to_if_else(
+        a: FieldElementExpression<'ast, T>,
+        b: FieldElementExpression<'ast, T>,
+    ) -> FieldElementExpression<'ast, T> {
+        FieldElementExpression::IfElse(
+            box BooleanExpression::Lt(box a.clone(), box b.clone()),
+            box a,
+            box b,
+        )
+    }
+
+    /// Rewrites the maximum of `a` and `b` as `if a < b then b else a fi`
+    ///
+    /// # Remarks
+    /// * The operands appear twice, so non-linear operands are flattened twice.
+    pub fn max_to_if_else(
+        a: FieldElementExpression<'ast, T>,
+        b: FieldElementExpression<'ast, T>,
+    ) -> FieldElementExpression<'ast, T> {
+        FieldElementExpression::IfElse(
+            box BooleanExpression::Lt(box a.clone(), box b.clone()),
+            box b,
+            box a,
+        )
+    }
+}
+
+/// A comparison between two field elements, used in chained comparisons
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
+pub enum CompareOp {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl CompareOp {
+    /// Returns the comparison of `lhs` and `rhs` with this operator
+    pub fn compare<'ast, T: Field>(
+        self,
+        lhs: FieldElementExpression<'ast, T>,
+        rhs: FieldElementExpression<'ast, T>,
+    ) -> BooleanExpression<'ast, T> {
+        match self {
+            CompareOp::Lt => BooleanExpression::Lt(box lhs, box rhs),
+            CompareOp::Le => BooleanExpression::Le(box lhs, box rhs),
+            CompareOp::Eq => BooleanExpression::FieldEq(box lhs, box rhs),
+            CompareOp::Ge => BooleanExpression::Ge(box lhs, box rhs),
+            CompareOp::Gt => BooleanExpression::Gt(box lhs, box rhs),
+        }
+    }
+}
+
+impl fmt::Display for CompareOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompareOp::Lt => write!(f, "<"),
+            CompareOp::Le => write!(f, "<="),
+            CompareOp::Eq => write!(f, "=="),
+            CompareOp::Ge => write!(f, ">="),
+            CompareOp::Gt => write!(f, ">"),
+        }
+    }
 }
 
 /// An expression of type `bool`
@@ -653,6 +845,18 @@ pub enum BooleanExpression<'ast, T: Field> {
         Box<FieldElementExpression<'ast, T>>,
         Box<FieldElementExpression<'ast, T>>,
     ),
+    /// `lhs < rhs` for operands of at most the given number of bits
+    LtWidth(
+        Box<FieldElementExpression<'ast, T>>,
+        Box<FieldElementExpression<'ast, T>>,
+        usize,
+    ),
+    /// `lhs <= rhs` for operands of at most the given number of bits
+    LeWidth(
+        Box<FieldElementExpression<'ast, T>>,
+        Box<FieldElementExpression<'ast, T>>,
+        usize,
+    ),
     FieldEq(
         Box<FieldElementExpression<'ast, T>>,
         Box<FieldElementExpression<'ast, T>>,
@@ -678,6 +882,11 @@ pub enum BooleanExpression<'ast, T: Field> {
         Box<BooleanExpression<'ast, T>>,
     ),
     Not(Box<BooleanExpression<'ast, T>>),
+    IsZero(Box<FieldElementExpression<'ast, T>>),
+    Chain(
+        Box<FieldElementExpression<'ast, T>>,
+        Vec<(CompareOp, FieldElementExpression<'ast, T>)>,
+    ),
     IfElse(
         Box<BooleanExpression<'ast, T>>,
         Box<BooleanExpression<'ast, T>>,
@@ -691,6 +900,32 @@ pub enum BooleanExpression<'ast, T: Field> {
     ),
 }
 
+impl<'ast, T: Field> BooleanExpression<'ast, T> {
+    /// Rewrites the chained comparison `first op_0 e_0 op_1 e_1 ...` as the conjunction of the comparisons of
+    /// adjacent operands, `first op_0 e_0 && e_0 op_1 e_1 && ...`
+    ///
+    /// # Remarks
+    /// * Inner operands appear in two comparisons, so non-linear inner operands are flattened twice.
+    /// * An empty chain is `true`.
+    pub fn chain_to_and(
+        first: FieldElementExpression<'ast, T>,
+        rest: Vec<(CompareOp, FieldElementExpression<'ast, T>)>,
+    ) -> BooleanExpression<'ast, T> {
+        let mut lhs = first;
+        let mut comparisons = vec![];
+        for (op, rhs) in rest {
+            comparisons.push(op.compare(lhs, rhs.clone()));
+            lhs = rhs;
+        }
+
+        let mut comparisons = comparisons.into_iter();
+        match comparisons.next() {
+            Some(c) => comparisons.fold(c, |acc, c| BooleanExpression::And(box acc, box c)),
+            None => BooleanExpression::Value(true),
+        }
+    }
+}
+
 /// An expression of type `array`
 /// # Remarks
 /// * Contrary to basic types which are represented as enums, we wrap an enum `ArrayExpressionInner` in a struct in order to keep track of the type (content and size)
@@ -851,6 +1086,45 @@ impl<'ast, T: Field> fmt::Display for FieldElementExpression<'ast, T> {
             FieldElementExpression::Mult(ref lhs, ref rhs) => write!(f, "({} * {})", lhs, rhs),
             FieldElementExpression::Div(ref lhs, ref rhs) => write!(f, "({} / {})", lhs, rhs),
             FieldElementExpression::Pow(ref lhs, ref rhs) => write!(f, "{}**{}", lhs, rhs),
+            FieldElementExpression::BitAnd(ref lhs, ref rhs) => write!(f, "({} & {})", lhs, rhs),
+            FieldElementExpression::BitOr(ref lhs, ref rhs) => write!(f, "({} | {})", lhs, rhs),
+            FieldElementExpression::BitXor(ref lhs, ref rhs) => write!(f, "({} ^ {})", lhs, rhs),
+            FieldElementExpression::Shl(ref e, ref by) => write!(f, "({} << {})", e, by),
+            FieldElementExpression::Shr(ref e, ref by) => write!(f, "({} >> {})", e, by),
+            FieldElementExpression::Abs(ref e) => write!(f, "abs({})", e),
+            FieldElementExpression::ReduceBits(ref e, ref bits) => {
+                write!(f, "reduce_bits({}, {})", e, bits)
+            }
+            FieldElementExpression::PopCount(ref e, ref bits) => {
+                write!(f, "pop_count({}, {})", e, bits)
+            }
+            FieldElementExpression::Sum(ref terms) => write!(
+                f,
+                "({})",
+                terms
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" + ")
+            ),
+            FieldElementExpression::Product(ref factors) => write!(
+                f,
+                "({})",
+                factors
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" * ")
+            ),
+            FieldElementExpression::Min(ref lhs, ref rhs) => write!(f, "min({}, {})", lhs, rhs),
+            FieldElementExpression::Max(ref lhs, ref rhs) => write!(f, "max({}, {})", lhs, rhs),
+            FieldElementExpression::Switch(ref selector, ref cases, ref default) => {
+                write!(f, "switch {} {{ ", selector)?;
+                for (value, result) in cases {
+                    write!(f, "{} => {}, ", value, result)?;
+                }
+                write!(f, "_ => {} }}", default)
+            }
             FieldElementExpression::IfElse(ref condition, ref consequent, ref alternative) => {
                 write!(
                     f,
@@ -870,6 +1144,9 @@ impl<'ast, T: Field> fmt::Display for FieldElementExpression<'ast, T> {
             }
             FieldElementExpression::Member(ref struc, ref id) => write!(f, "{}.{}", struc, id),
             FieldElementExpression::Select(ref id, ref index) => write!(f, "{}[{}]", id, index),
+            FieldElementExpression::DynSelect(ref id, ref index, _) => {
+                write!(f, "{}[{}]", id, index)
+            }
         }
     }
 }
@@ -880,6 +1157,12 @@ impl<'ast, T: Field> fmt::Display for BooleanExpression<'ast, T> {
             BooleanExpression::Identifier(ref var) => write!(f, "{}", var),
             BooleanExpression::Lt(ref lhs, ref rhs) => write!(f, "{} < {}", lhs, rhs),
             BooleanExpression::Le(ref lhs, ref rhs) => write!(f, "{} <= {}", lhs, rhs),
+            BooleanExpression::LtWidth(ref lhs, ref rhs, width) => {
+                write!(f, "{} <_{} {}", lhs, width, rhs)
+            }
+            BooleanExpression::LeWidth(ref lhs, ref rhs, width) => {
+                write!(f, "{} <=_{} {}", lhs, width, rhs)
+            }
             BooleanExpression::FieldEq(ref lhs, ref rhs) => write!(f, "{} == {}", lhs, rhs),
             BooleanExpression::BoolEq(ref lhs, ref rhs) => write!(f, "{} == {}", lhs, rhs),
             BooleanExpression::Ge(ref lhs, ref rhs) => write!(f, "{} >= {}", lhs, rhs),
@@ -887,6 +1170,14 @@ impl<'ast, T: Field> fmt::Display for BooleanExpression<'ast, T> {
             BooleanExpression::Or(ref lhs, ref rhs) => write!(f, "{} || {}", lhs, rhs),
             BooleanExpression::And(ref lhs, ref rhs) => write!(f, "{} && {}", lhs, rhs),
             BooleanExpression::Not(ref exp) => write!(f, "!{}", exp),
+            BooleanExpression::IsZero(ref exp) => write!(f, "{} == 0", exp),
+            BooleanExpression::Chain(ref first, ref rest) => {
+                write!(f, "{}", first)?;
+                for (op, e) in rest {
+                    write!(f, " {} {}", op, e)?;
+                }
+                Ok(())
+            }
             BooleanExpression::Value(b) => write!(f, "{}", b),
             BooleanExpression::FunctionCall(ref k, ref p) => {
                 write!(f, "{}(", k.id,)?;
@@ -961,6 +1252,31 @@ impl<'ast, T: Field> fmt::Debug for FieldElementExpression<'ast, T> {
             }
             FieldElementExpression::Div(ref lhs, ref rhs) => write!(f, "Div({:?}, {:?})", lhs, rhs),
             FieldElementExpression::Pow(ref lhs, ref rhs) => write!(f, "Pow({:?}, {:?})", lhs, rhs),
+            FieldElementExpression::BitAnd(ref lhs, ref rhs) => {
+                write!(f, "BitAnd({:?}, {:?})", lhs, rhs)
+            }
+            FieldElementExpression::BitOr(ref lhs, ref rhs) => {
+                write!(f, "BitOr({:?}, {:?})", lhs, rhs)
+            }
+            FieldElementExpression::BitXor(ref lhs, ref rhs) => {
+                write!(f, "BitXor({:?}, {:?})", lhs, rhs)
+            }
+            FieldElementExpression::Shl(ref e, ref by) => write!(f, "Shl({:?}, {:?})", e, by),
+            FieldElementExpression::Shr(ref e, ref by) => write!(f, "Shr({:?}, {:?})", e, by),
+            FieldElementExpression::Abs(ref e) => write!(f, "Abs({:?})", e),
+            FieldElementExpression::ReduceBits(ref e, ref bits) => {
+                write!(f, "ReduceBits({:?}, {:?})", e, bits)
+            }
+            FieldElementExpression::PopCount(ref e, ref bits) => {
+                write!(f, "PopCount({:?}, {:?})", e, bits)
+            }
+            FieldElementExpression::Sum(ref terms) => write!(f, "Sum({:?})", terms),
+            FieldElementExpression::Product(ref factors) => write!(f, "Product({:?})", factors),
+            FieldElementExpression::Min(ref lhs, ref rhs) => write!(f, "Min({:?}, {:?})", lhs, rhs),
+            FieldElementExpression::Max(ref lhs, ref rhs) => write!(f, "Max({:?}, {:?})", lhs, rhs),
+            FieldElementExpression::Switch(ref selector, ref cases, ref default) => {
+                write!(f, "Switch({:?}, {:?}, {:?})", selector, cases, default)
+            }
             FieldElementExpression::IfElse(ref condition, ref consequent, ref alternative) => {
                 write!(
                     f,
@@ -979,6 +1295,9 @@ impl<'ast, T: Field> fmt::Debug for FieldElementExpression<'ast, T> {
             FieldElementExpression::Select(ref id, ref index) => {
                 write!(f, "Select({:?}, {:?})", id, index)
             }
+            FieldElementExpression::DynSelect(ref id, ref index, ref size) => {
+                write!(f, "DynSelect({:?}, {:?}, {})", id, index, size)
+            }
         }
     }
 }
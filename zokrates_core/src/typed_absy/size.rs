@@ -0,0 +1,159 @@
+//! Module containing size metrics of typed expressions
+//!
+//! The number of nodes and the depth of an expression give a cheap estimate of its cost, for passes deciding
+//! whether to inline or hoist it.
+
+use crate::typed_absy::folder::*;
+use crate::typed_absy::*;
+use std::cmp;
+use zokrates_field::field::Field;
+
+/// Counts the nodes of expressions and the depth of the deepest one
+#[derive(Default)]
+struct Size {
+    nodes: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Size {
+    fn of<'ast, T: Field>(e: TypedExpression<'ast, T>) -> Self {
+        let mut size = Size::default();
+        size.fold_expression(e);
+        size
+    }
+
+    fn enter(&mut self) {
+        self.nodes += 1;
+        self.depth += 1;
+        self.max_depth = cmp::max(self.max_depth, self.depth);
+    }
+
+    fn exit(&mut self) {
+        self.depth -= 1;
+    }
+}
+
+impl<'ast, T: Field> Folder<'ast, T> for Size {
+    fn fold_field_expression(
+        &mut self,
+        e: FieldElementExpression<'ast, T>,
+    ) -> FieldElementExpression<'ast, T> {
+        self.enter();
+        let e = fold_field_expression(self, e);
+        self.exit();
+        e
+    }
+
+    fn fold_boolean_expression(
+        &mut self,
+        e: BooleanExpression<'ast, T>,
+    ) -> BooleanExpression<'ast, T> {
+        self.enter();
+        let e = fold_boolean_expression(self, e);
+        self.exit();
+        e
+    }
+
+    fn fold_array_expression(&mut self, e: ArrayExpression<'ast, T>) -> ArrayExpression<'ast, T> {
+        self.enter();
+        let e = fold_array_expression(self, e);
+        self.exit();
+        e
+    }
+
+    fn fold_struct_expression(
+        &mut self,
+        e: StructExpression<'ast, T>,
+    ) -> StructExpression<'ast, T> {
+        self.enter();
+        let e = fold_struct_expression(self, e);
+        self.exit();
+        e
+    }
+}
+
+impl<'ast, T: Field> TypedExpression<'ast, T> {
+    /// Returns the number of nodes of this expression, including the conditions of `if` expressions
+    pub fn node_count(&self) -> usize {
+        Size::of(self.clone()).nodes
+    }
+
+    /// Returns the number of nodes on the longest path from this expression to one of its leaves
+    pub fn depth(&self) -> usize {
+        Size::of(self.clone()).max_depth
+    }
+}
+
+impl<'ast, T: Field> FieldElementExpression<'ast, T> {
+    /// Returns the number of nodes of this expression, including the conditions of `if` expressions
+    pub fn node_count(&self) -> usize {
+        TypedExpression::from(self.clone()).node_count()
+    }
+
+    /// Returns the number of nodes on the longest path from this expression to one of its leaves
+    pub fn depth(&self) -> usize {
+        TypedExpression::from(self.clone()).depth()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zokrates_field::field::FieldPrime;
+
+    fn a() -> FieldElementExpression<'static, FieldPrime> {
+        FieldElementExpression::Identifier("a".into())
+    }
+
+    fn n(x: u32) -> FieldElementExpression<'static, FieldPrime> {
+        FieldElementExpression::Number(FieldPrime::from(x))
+    }
+
+    #[test]
+    fn leaves() {
+        assert_eq!(a().node_count(), 1);
+        assert_eq!(a().depth(), 1);
+        assert_eq!(n(42).node_count(), 1);
+    }
+
+    #[test]
+    fn binary() {
+        // (a + 1) * a
+        let e = FieldElementExpression::Mult(
+            box FieldElementExpression::Add(box a(), box n(1)),
+            box a(),
+        );
+        assert_eq!(e.node_count(), 5);
+        assert_eq!(e.depth(), 3);
+    }
+
+    #[test]
+    fn if_else() {
+        // if a < 2 then a else 0 fi
+        let e = FieldElementExpression::IfElse(
+            box BooleanExpression::Lt(box a(), box n(2)),
+            box a(),
+            box n(0),
+        );
+        assert_eq!(e.node_count(), 6);
+        assert_eq!(e.depth(), 3);
+
+        let e: TypedExpression<_> = e.into();
+        assert_eq!(e.node_count(), 6);
+    }
+
+    #[test]
+    fn array() {
+        // [a, a * a][1]
+        let array = ArrayExpressionInner::Value(vec![
+            a().into(),
+            FieldElementExpression::Mult(box a(), box a()).into(),
+        ])
+        .annotate(Type::FieldElement, 2);
+        let e = FieldElementExpression::Select(box array, box n(1));
+
+        assert_eq!(e.node_count(), 7);
+        assert_eq!(e.depth(), 4);
+    }
+}
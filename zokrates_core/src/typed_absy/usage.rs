@@ -0,0 +1,106 @@
+//! Module containing the detection of unused arguments
+//!
+//! An argument of `main` which is never read still takes an input slot, which usually indicates a mistake in
+//! the program.
+
+use crate::typed_absy::folder::*;
+use crate::typed_absy::*;
+use std::collections::HashSet;
+use zokrates_field::field::Field;
+
+/// Collects the identifiers read by expressions
+#[derive(Default)]
+struct Reads<'ast> {
+    identifiers: HashSet<Identifier<'ast>>,
+}
+
+impl<'ast, T: Field> Folder<'ast, T> for Reads<'ast> {
+    // parameters, declared and assigned variables are not read
+    fn fold_variable(&mut self, v: Variable<'ast>) -> Variable<'ast> {
+        v
+    }
+
+    fn fold_name(&mut self, n: Identifier<'ast>) -> Identifier<'ast> {
+        self.identifiers.insert(n.clone());
+        n
+    }
+}
+
+/// Returns the names of the arguments of the main function of `prog` which are never read, in order
+///
+/// # Remarks
+/// * An argument read anywhere counts as used, including in the condition of an `if` expression.
+pub fn unused_arguments<'ast, T: Field>(prog: &TypedProgram<'ast, T>) -> Vec<String> {
+    let main = prog.modules[&prog.main]
+        .functions
+        .iter()
+        .find(|(id, _)| id.id == "main")
+        .unwrap()
+        .1;
+    let main = match main {
+        TypedFunctionSymbol::Here(main) => main,
+        _ => unreachable!(),
+    };
+
+    let mut reads = Reads::default();
+    reads.fold_function(main.clone());
+
+    main.arguments
+        .iter()
+        .filter(|p| !reads.identifiers.contains(&p.id.id))
+        .map(|p| p.id.id.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use typed_absy::types::FunctionKey;
+    use zokrates_field::field::FieldPrime;
+
+    #[test]
+    fn unused_argument() {
+        // def main(field a, field b, bool c) -> (field):
+        //     return if c then a else 0 fi
+        let main = TypedFunction {
+            arguments: vec![
+                Parameter::private(Variable::field_element("a".into())),
+                Parameter {
+                    id: Variable::field_element("b".into()),
+                    private: false,
+                },
+                Parameter::private(Variable::boolean("c".into())),
+            ],
+            statements: vec![TypedStatement::Return(vec![
+                FieldElementExpression::IfElse(
+                    box BooleanExpression::Identifier("c".into()),
+                    box FieldElementExpression::Identifier("a".into()),
+                    box FieldElementExpression::Number(FieldPrime::from(0)),
+                )
+                .into(),
+            ])],
+            signature: Signature::new()
+                .inputs(vec![Type::FieldElement, Type::FieldElement, Type::Boolean])
+                .outputs(vec![Type::FieldElement]),
+        };
+
+        let prog = TypedProgram {
+            modules: vec![(
+                "main".into(),
+                TypedModule {
+                    functions: vec![(
+                        FunctionKey::with_id("main"),
+                        TypedFunctionSymbol::Here(main),
+                    )]
+                    .into_iter()
+                    .collect(),
+                },
+            )]
+            .into_iter()
+            .collect(),
+            main: "main".into(),
+        };
+
+        assert_eq!(unused_arguments(&prog), vec![String::from("b")]);
+    }
+}
@@ -31,6 +31,11 @@ pub trait Pow<RHS> {
     fn pow(self, _: RHS) -> Self::Output;
 }
 
+/// A prime field `Z/pZ`
+///
+/// # Remarks
+/// * Values are always kept reduced, in `[0, p)`. Arithmetic operations must return reduced values even
+/// when intermediate results do not fit in `[0, p)`, for example when adding two values close to `p`.
 pub trait Field:
     From<i32>
     + From<u32>
@@ -63,7 +68,7 @@ pub trait Field:
         use bellman::pairing::ff::{PrimeField, PrimeFieldRepr};
         let mut res: Vec<u8> = vec![];
         e.into_repr().write_le(&mut res).unwrap();
-        Self::from_byte_vector(res)
+        Self::from_byte_vector(&res)
     }
 
     fn into_bellman(self) -> <Self::BellmanEngine as ScalarEngine>::Fr {
@@ -74,23 +79,114 @@ pub trait Field:
 
     /// Returns this `Field`'s contents as little-endian byte vector
     fn into_byte_vector(&self) -> Vec<u8>;
-    /// Returns an element of this `Field` from a little-endian byte vector
-    fn from_byte_vector(_: Vec<u8>) -> Self;
+    /// Returns this `Field`'s contents as little-endian byte vector of `get_required_bytes()` bytes
+    fn to_byte_vector(&self) -> Vec<u8> {
+        let mut res = self.into_byte_vector();
+        res.resize(Self::get_required_bytes(), 0);
+        res
+    }
+    /// Returns an element of this `Field` from a little-endian byte vector of at most `get_required_bytes()` bytes,
+    /// reduced modulo p
+    fn from_byte_vector(_: &[u8]) -> Self;
     /// Returns this `Field`'s contents as decimal string
     fn to_dec_string(&self) -> String;
     /// Returns the multiplicative inverse, i.e.: self * self.inverse_mul() = Self::one()
     fn inverse_mul(&self) -> Self;
+    /// Returns a square root of this element, or `None` if it is not a quadratic residue
+    fn sqrt(&self) -> Option<Self> {
+        if self.is_zero() {
+            return Some(Self::zero());
+        }
+
+        let exponent = |n: &BigUint| Self::from_byte_vector(&n.to_bytes_le());
+
+        let p_minus_one = Self::max_value().to_biguint();
+        let half = exponent(&(&p_minus_one >> 1));
+
+        // Euler's criterion: x^((p - 1) / 2) is 1 for residues and -1 otherwise
+        if self.clone().pow(&half) != Self::one() {
+            return None;
+        }
+
+        // Tonelli-Shanks, with p - 1 = q * 2^s and q odd
+        let mut q = p_minus_one;
+        let mut s = 0;
+        while q.is_even() {
+            q = q >> 1;
+            s += 1;
+        }
+
+        let mut z = Self::from(2);
+        while z.clone().pow(&half) != Self::max_value() {
+            z = z + Self::one();
+        }
+
+        let mut m = s;
+        let mut c = z.pow(exponent(&q));
+        let mut t = self.clone().pow(exponent(&q));
+        let mut r = self.clone().pow(exponent(&((&q + 1u32) >> 1)));
+
+        while t != Self::one() {
+            // the least i such that t^(2^i) == 1
+            let mut i = 0;
+            let mut t_squared = t.clone();
+            while t_squared != Self::one() {
+                t_squared = t_squared.clone() * &t_squared;
+                i += 1;
+            }
+
+            // b = c^(2^(m - i - 1))
+            let mut b = c.clone();
+            for _ in 0..m - i - 1 {
+                b = b.clone() * &b;
+            }
+
+            m = i;
+            c = b.clone() * &b;
+            t = t * &c;
+            r = r * &b;
+        }
+
+        Some(r)
+    }
     /// Returns the smallest value that can be represented by this field type.
     fn min_value() -> Self;
     /// Returns the largest value that can be represented by this field type.
     fn max_value() -> Self;
     /// Returns the number of required bits to represent this field type.
     fn get_required_bits() -> usize;
-    /// Tries to parse a string into this representation
+    /// Returns the number of required bytes to represent this field type.
+    fn get_required_bytes() -> usize {
+        (Self::get_required_bits() + 7) / 8
+    }
+    /// Tries to parse a string into this representation. Negative values are accepted and reduced
+    /// modulo p, so that `-v` parses to p - v, the inverse of `to_signed_string`
     fn try_from_dec_str<'a>(s: &'a str) -> Result<Self, ()>;
     /// Returns a decimal string representing the member of the equivalence class of this `Field` in Z/pZ
     /// which lies in [-(p-1)/2, (p-1)/2]
     fn to_compact_dec_string(&self) -> String;
+    /// Returns a decimal string representing this `Field` as a signed value: values up to (p-1)/2 are
+    /// rendered as positive, values above as negative, such as `-1` for p-1
+    fn to_signed_string(&self) -> String {
+        let value = self.to_biguint();
+        let max = Self::max_value().to_biguint();
+        if value <= &max / 2u32 {
+            value.to_str_radix(10)
+        } else {
+            format!("-{}", (max + 1u32 - value).to_str_radix(10))
+        }
+    }
+    /// Returns the element of this `Field` representing the signed integer `v`, such as p-1 for `-1`
+    fn from_i64(v: i64) -> Self {
+        let magnitude = v.wrapping_abs() as u64;
+        let high = Self::from((magnitude >> 32) as u32);
+        let low = Self::from(magnitude as u32);
+        let magnitude = high * Self::from(1u32 << 16) * Self::from(1u32 << 16) + low;
+        match v < 0 {
+            true => Self::zero() - magnitude,
+            false => magnitude,
+        }
+    }
     /// Returns this `Field`'s largest value as a big-endian bit vector
     fn max_value_bit_vector_be() -> Vec<bool> {
         fn bytes_to_bits(bytes: &[u8]) -> Vec<bool> {
@@ -110,6 +206,25 @@ pub trait Field:
     }
     /// Returns the value as a BigUint
     fn to_biguint(&self) -> BigUint;
+    /// Returns a uniformly random element of this `Field`
+    ///
+    /// # Remarks
+    /// * Values of `get_required_bits()` random bits are drawn until one is in the field, which takes less than two
+    /// draws on average.
+    #[cfg(feature = "rand")]
+    fn random<R: rand::Rng>(rng: &mut R) -> Self {
+        let bits = Self::get_required_bits();
+        let max = Self::max_value().to_biguint();
+        loop {
+            let mut bytes: Vec<u8> = (0..Self::get_required_bytes()).map(|_| rng.gen()).collect();
+            // clear the bits above `bits` in the most significant byte
+            let last = bytes.len() - 1;
+            bytes[last] &= 0xff >> (bytes.len() * 8 - bits);
+            if BigUint::from_bytes_le(&bytes) <= max {
+                return Self::from_byte_vector(&bytes);
+            }
+        }
+    }
 }
 
 #[derive(PartialEq, PartialOrd, Clone, Eq, Ord, Hash, Serialize, Deserialize)]
@@ -131,10 +246,16 @@ impl Field for FieldPrime {
         }
     }
 
-    fn from_byte_vector(bytes: Vec<u8>) -> Self {
-        let uval = BigUint::from_bytes_le(bytes.as_slice());
+    fn from_byte_vector(bytes: &[u8]) -> Self {
+        assert!(
+            bytes.len() <= Self::get_required_bytes(),
+            "Expected at most {} bytes, found {}",
+            Self::get_required_bytes(),
+            bytes.len()
+        );
+        let uval = BigUint::from_bytes_le(bytes);
         FieldPrime {
-            value: BigInt::from_biguint(Sign::Plus, uval),
+            value: BigInt::from_biguint(Sign::Plus, uval) % &*P,
         }
     }
 
@@ -252,7 +373,7 @@ impl Add<FieldPrime> for FieldPrime {
 
     fn add(self, other: FieldPrime) -> FieldPrime {
         FieldPrime {
-            value: (self.value + other.value) % &*P,
+            value: add_reduce(self.value, &other.value, &*P),
         }
     }
 }
@@ -262,7 +383,7 @@ impl<'a> Add<&'a FieldPrime> for FieldPrime {
 
     fn add(self, other: &FieldPrime) -> FieldPrime {
         FieldPrime {
-            value: (self.value + other.value.clone()) % &*P,
+            value: add_reduce(self.value, &other.value, &*P),
         }
     }
 }
@@ -325,15 +446,18 @@ impl<'a> Div<&'a FieldPrime> for FieldPrime {
     }
 }
 
+impl FieldPrime {
+    /// Raises `self` to the power `exp` by square-and-multiply, for exponents of any size such as `p - 2`
+    pub fn pow_bigint(&self, exp: &BigUint) -> FieldPrime {
+        pow_bigint(self, exp)
+    }
+}
+
 impl Pow<usize> for FieldPrime {
     type Output = FieldPrime;
 
     fn pow(self, exp: usize) -> FieldPrime {
-        let mut res = FieldPrime::from(1);
-        for _ in 0..exp {
-            res = res * &self;
-        }
-        res
+        self.pow_bigint(&BigUint::from(exp))
     }
 }
 
@@ -341,15 +465,7 @@ impl Pow<FieldPrime> for FieldPrime {
     type Output = FieldPrime;
 
     fn pow(self, exp: FieldPrime) -> FieldPrime {
-        let mut res = FieldPrime::one();
-        let mut current = FieldPrime::zero();
-        loop {
-            if current >= exp {
-                return res;
-            }
-            res = res * &self;
-            current = current + FieldPrime::one();
-        }
+        self.pow_bigint(&exp.to_biguint())
     }
 }
 
@@ -357,16 +473,47 @@ impl<'a> Pow<&'a FieldPrime> for FieldPrime {
     type Output = FieldPrime;
 
     fn pow(self, exp: &'a FieldPrime) -> FieldPrime {
-        let mut res = FieldPrime::one();
-        let mut current = FieldPrime::zero();
-        loop {
-            if &current >= exp {
-                return res;
+        self.pow_bigint(&exp.to_biguint())
+    }
+}
+
+/// Raises `base` to the power `exp` by square-and-multiply
+pub(crate) fn pow_bigint<F>(base: &F, exp: &BigUint) -> F
+where
+    F: One + Clone + for<'a> Mul<&'a F, Output = F>,
+{
+    let mut res = F::one();
+    // one squaring per bit of the exponent, most significant first
+    for byte in exp.to_bytes_be() {
+        for i in (0..8).rev() {
+            res = res.clone() * &res;
+            if byte & (1 << i) != 0 {
+                res = res * base;
             }
-            res = res * &self;
-            current = current + FieldPrime::one();
         }
     }
+    res
+}
+
+/// Adds two values of `Z/pZ` and reduces the result
+///
+/// # Arguments
+/// * `a` - First value, non-negative
+/// * `b` - Second value, non-negative
+/// * `p` - Modulus
+///
+/// # Remarks
+/// * The sum is computed on `BigInt`, so it cannot overflow even if it exceeds `p`. When both values are
+/// in `[0, p)`, the sum is below `2p` and a single subtraction reduces it.
+pub(crate) fn add_reduce(a: BigInt, b: &BigInt, p: &BigInt) -> BigInt {
+    let sum = a + b;
+    if &sum < p {
+        sum
+    } else if &sum < &(p + p) {
+        sum - p
+    } else {
+        sum % p
+    }
 }
 
 /// Calculates the gcd using an iterative implementation of the extended euclidian algorithm.
@@ -375,7 +522,7 @@ impl<'a> Pow<&'a FieldPrime> for FieldPrime {
 /// # Arguments
 /// * `a` - First number as `BigInt`
 /// * `b` - Second number as `BigInt`
-fn extended_euclid(a: &BigInt, b: &BigInt) -> (BigInt, BigInt, BigInt) {
+pub(crate) fn extended_euclid(a: &BigInt, b: &BigInt) -> (BigInt, BigInt, BigInt) {
     let (mut s, mut old_s) = (BigInt::zero(), BigInt::one());
     let (mut t, mut old_t) = (BigInt::one(), BigInt::zero());
     let (mut r, mut old_r) = (b.clone(), a.clone());
@@ -628,6 +775,45 @@ mod tests {
             );
         }
 
+        #[test]
+        fn pow_naive() {
+            let x = FieldPrime::from("-54");
+            let mut naive = FieldPrime::one();
+            for exp in 0..70 {
+                assert_eq!(x.clone().pow(exp), naive);
+                assert_eq!(x.clone().pow(FieldPrime::from(exp)), naive);
+                naive = naive * &x;
+            }
+        }
+
+        #[test]
+        fn pow_modulus() {
+            // Fermat's little theorem: x^(p - 1) == 1 and x^(p - 2) == 1 / x
+            let x = FieldPrime::from("54");
+            let p = FieldPrime::max_value().to_biguint() + 1u32;
+
+            assert_eq!(x.pow_bigint(&(&p - 1u32)), FieldPrime::one());
+            assert_eq!(x.pow_bigint(&(&p - 2u32)), x.inverse_mul());
+            assert_eq!(x.clone().pow(FieldPrime::max_value()), FieldPrime::one());
+        }
+
+        #[test]
+        fn sqrt_of_squares() {
+            for x in &["0", "1", "2", "3", "12345", "-54", "1234245612123456789"] {
+                let x = FieldPrime::from(*x);
+                let square = x.clone() * &x;
+                let root = square.sqrt().unwrap();
+                assert_eq!(root.clone() * &root, square);
+            }
+        }
+
+        #[test]
+        fn sqrt_of_non_residue() {
+            // 5 and 7 are not squares modulo p
+            assert_eq!(FieldPrime::from("5").sqrt(), None);
+            assert_eq!(FieldPrime::from("7").sqrt(), None);
+        }
+
         #[test]
         fn pow_negative() {
             assert_eq!(
@@ -662,7 +848,48 @@ mod tests {
         fn bytes_ser_deser() {
             let fp = FieldPrime::from("101");
             let bv = fp.into_byte_vector();
-            assert_eq!(fp, FieldPrime::from_byte_vector(bv));
+            assert_eq!(fp, FieldPrime::from_byte_vector(&bv));
+        }
+
+        #[test]
+        fn fixed_width_bytes_ser_deser() {
+            for fp in vec![
+                FieldPrime::from(0),
+                FieldPrime::from(1),
+                FieldPrime::max_value(),
+            ] {
+                let bv = fp.to_byte_vector();
+                assert_eq!(bv.len(), 32);
+                assert_eq!(fp, FieldPrime::from_byte_vector(&bv));
+            }
+        }
+
+        #[test]
+        fn fixed_width_bytes_little_endian() {
+            let mut expected = vec![0; 32];
+            expected[0] = 1;
+            expected[1] = 2;
+            assert_eq!(FieldPrime::from(513).to_byte_vector(), expected);
+        }
+
+        #[test]
+        fn unreduced_bytes() {
+            let p = FieldPrime::max_value().to_biguint() + 1u32;
+            assert_eq!(
+                FieldPrime::from_byte_vector(&p.to_bytes_le()),
+                FieldPrime::from(0)
+            );
+            let p_plus_one = p + 1u32;
+            assert_eq!(
+                FieldPrime::from_byte_vector(&p_plus_one.to_bytes_le()),
+                FieldPrime::from(1)
+            );
+        }
+
+        #[test]
+        #[should_panic]
+        fn overlong_bytes() {
+            FieldPrime::from_byte_vector(&vec![0; 33]);
         }
 
         #[test]
@@ -694,6 +921,73 @@ mod tests {
                 &p_minus_one_over_two_plus_one.to_compact_dec_string()
             );
         }
+
+        #[test]
+        fn negative_dec_str() {
+            assert_eq!(
+                FieldPrime::try_from_dec_str("-5").unwrap(),
+                FieldPrime::from(0) - FieldPrime::from(5)
+            );
+            assert_eq!(
+                FieldPrime::try_from_dec_str("-5")
+                    .unwrap()
+                    .to_signed_string(),
+                "-5"
+            );
+            assert_eq!(
+                FieldPrime::try_from_dec_str("-0").unwrap(),
+                FieldPrime::from(0)
+            );
+        }
+
+        #[test]
+        fn signed_representation() {
+            let minus_one = FieldPrime::from(0) - FieldPrime::from(1);
+            assert_eq!("-1", &minus_one.to_signed_string());
+            assert_eq!(
+                minus_one,
+                FieldPrime::try_from_dec_str(&minus_one.to_signed_string()).unwrap()
+            );
+            assert_eq!("0", &FieldPrime::from(0).to_signed_string());
+            assert_eq!("42", &FieldPrime::from(42).to_signed_string());
+            // the sign term of the `Lt` gadget
+            let sign = FieldPrime::from(0) - FieldPrime::from(2).pow(252);
+            assert_eq!(
+                "-7237005577332262213973186563042994240829374041602535252466099000494570602496",
+                &sign.to_signed_string()
+            );
+            // (p-1)/2 is the largest positive value
+            let p_minus_one_over_two = minus_one.clone() / FieldPrime::from(2);
+            assert_eq!(
+                "10944121435919637611123202872628637544274182200208017171849102093287904247808",
+                &p_minus_one_over_two.to_signed_string()
+            );
+            assert_eq!(
+                "-10944121435919637611123202872628637544274182200208017171849102093287904247808",
+                &(p_minus_one_over_two + FieldPrime::from(1)).to_signed_string()
+            );
+        }
+
+        #[test]
+        fn from_i64() {
+            assert_eq!(FieldPrime::from_i64(0), FieldPrime::from(0));
+            assert_eq!(FieldPrime::from_i64(42), FieldPrime::from(42));
+            assert_eq!("42", &FieldPrime::from_i64(42).to_signed_string());
+
+            let minus_five = FieldPrime::from_i64(-5);
+            assert_eq!(minus_five, FieldPrime::from(0) - FieldPrime::from(5));
+            assert_eq!("-5", &minus_five.to_signed_string());
+            assert_eq!("(-5)", &minus_five.to_compact_dec_string());
+
+            let max = FieldPrime::from_i64(i64::max_value());
+            assert_eq!("9223372036854775807", &max.to_dec_string());
+            assert_eq!("9223372036854775807", &max.to_signed_string());
+
+            // the magnitude of i64::MIN does not fit in an i64
+            let min = FieldPrime::from_i64(i64::min_value());
+            assert_eq!(min, FieldPrime::from(0) - FieldPrime::from(2).pow(63));
+            assert_eq!("-9223372036854775808", &min.to_signed_string());
+        }
     }
 
     #[test]
@@ -714,6 +1008,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn add_near_modulus() {
+        let p_minus_one = FieldPrime::max_value();
+        let p_minus_two = FieldPrime::max_value() - FieldPrime::from(1);
+
+        // (p - 1) + (p - 1) = p - 2
+        assert_eq!(p_minus_one.clone() + &p_minus_one, p_minus_two);
+        // (p - 1) + 1 = 0
+        assert_eq!(
+            p_minus_one.clone() + FieldPrime::from(1),
+            FieldPrime::from(0)
+        );
+        // (p - 2) + 1 = p - 1
+        assert_eq!(p_minus_two + FieldPrime::from(1), p_minus_one);
+
+        let p = &*P;
+        let a = p - BigInt::one();
+        let b = p - BigInt::from(3);
+        assert_eq!(add_reduce(a, &b, p), p - BigInt::from(4));
+    }
+
     #[test]
     fn test_extended_euclid() {
         assert_eq!(
@@ -830,4 +1145,20 @@ mod tests {
             assert_eq!(FieldPrime::from_bellman(a), cc);
         }
     }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn random() {
+        use rand::thread_rng;
+
+        let rng = &mut thread_rng();
+        let values: Vec<FieldPrime> = (0..100).map(|_| FieldPrime::random(rng)).collect();
+
+        assert!(values.iter().all(|v| *v <= FieldPrime::max_value()));
+        assert!(values.iter().any(|v| *v != values[0]));
+        // values are spread over the whole field, so some are above (p - 1) / 2
+        assert!(values
+            .iter()
+            .any(|v| v.to_biguint() > FieldPrime::max_value().to_biguint() / 2u32));
+    }
 }
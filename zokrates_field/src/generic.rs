@@ -0,0 +1,491 @@
+//! Module containing `FieldGeneric`, a prime field whose modulus is chosen at runtime
+//!
+//! The modulus is set per thread with `FieldGeneric::set_modulus` and applies to all values created
+//! afterwards, since the `Field` trait builds values without any context (`zero`, `From<i32>`, ...).
+//! It defaults to the modulus of `FieldPrime`. Each value remembers the modulus it was created with, and
+//! arithmetic panics when an operand was created with a modulus other than the current one.
+
+use crate::field::{add_reduce, extended_euclid, pow_bigint, Field, FieldPrime, Pow};
+use bellman::pairing::bn256::Bn256;
+use bellman::pairing::ff::ScalarEngine;
+use num_bigint::{BigInt, BigUint, Sign, ToBigInt};
+use num_integer::Integer;
+use num_traits::{One, Zero};
+use serde_derive::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::convert::From;
+use std::fmt;
+use std::fmt::{Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, Div, Mul, Sub};
+use std::sync::atomic::{self, AtomicUsize};
+
+/// The id of the next modulus set with `FieldGeneric::set_modulus`, 0 being the default modulus
+static NEXT_MODULUS_ID: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    static MODULUS: RefCell<BigInt> = RefCell::new(bn254_modulus());
+    static MODULUS_ID: Cell<usize> = Cell::new(0);
+}
+
+/// Returns the modulus of `FieldPrime`, the scalar field of BN254
+fn bn254_modulus() -> BigInt {
+    BigInt::from_biguint(Sign::Plus, FieldPrime::max_value().to_biguint()) + BigInt::one()
+}
+
+/// Returns the current modulus
+fn modulus() -> BigInt {
+    MODULUS.with(|m| m.borrow().clone())
+}
+
+/// Returns the id of the current modulus
+fn modulus_id() -> usize {
+    MODULUS_ID.with(|id| id.get())
+}
+
+/// Returns the representative of `x` in `[0, modulus)`
+fn reduce(x: BigInt) -> BigInt {
+    let p = modulus();
+    &x - x.div_floor(&p) * &p
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FieldGeneric {
+    value: BigInt,
+    // the id of the modulus `value` was reduced with. Deserialized values are assumed to use the current one.
+    #[serde(skip, default = "modulus_id")]
+    modulus: usize,
+}
+
+impl FieldGeneric {
+    /// Sets the modulus of the values created on this thread from now on
+    ///
+    /// # Remarks
+    /// * `modulus` is expected to be a prime, otherwise inversion fails for some values.
+    /// * Values created before this call must not be used anymore, and arithmetic on them panics.
+    pub fn set_modulus(modulus: BigInt) {
+        assert!(modulus > BigInt::one());
+        MODULUS.with(|m| *m.borrow_mut() = modulus);
+        MODULUS_ID.with(|id| id.set(NEXT_MODULUS_ID.fetch_add(1, atomic::Ordering::Relaxed)));
+    }
+
+    /// Returns the modulus of the values created on this thread
+    pub fn modulus() -> BigInt {
+        modulus()
+    }
+
+    fn new(value: BigInt) -> FieldGeneric {
+        FieldGeneric {
+            value,
+            modulus: modulus_id(),
+        }
+    }
+
+    /// Panics unless `self` was created with the current modulus
+    fn check_modulus(&self) {
+        assert_eq!(
+            self.modulus,
+            modulus_id(),
+            "Field element was created with a different modulus than the current one"
+        );
+    }
+
+    /// Panics unless `self` and `other` were both created with the current modulus
+    fn check_moduli(&self, other: &FieldGeneric) {
+        self.check_modulus();
+        other.check_modulus();
+    }
+}
+
+impl Field for FieldGeneric {
+    // the modulus is not known statically, so there is no matching engine. This field is meant
+    // for flattening and evaluation only, and converting to and from Bellman panics unless the
+    // current modulus is the one of Bn256.
+    type BellmanEngine = Bn256;
+
+    fn from_bellman(e: <Bn256 as ScalarEngine>::Fr) -> Self {
+        use bellman::pairing::ff::{PrimeField, PrimeFieldRepr};
+        assert_eq!(
+            modulus(),
+            bn254_modulus(),
+            "Modulus is not the one of Bn256"
+        );
+        let mut res: Vec<u8> = vec![];
+        e.into_repr().write_le(&mut res).unwrap();
+        Self::from_byte_vector(&res)
+    }
+
+    fn into_bellman(self) -> <Bn256 as ScalarEngine>::Fr {
+        use bellman::pairing::ff::PrimeField;
+        assert_eq!(
+            modulus(),
+            bn254_modulus(),
+            "Modulus is not the one of Bn256"
+        );
+        self.check_modulus();
+        let s = self.to_dec_string();
+        <Bn256 as ScalarEngine>::Fr::from_str(&s).unwrap()
+    }
+
+    fn to_biguint(&self) -> BigUint {
+        self.value.to_biguint().unwrap()
+    }
+
+    fn into_byte_vector(&self) -> Vec<u8> {
+        match self.value.to_biguint() {
+            Option::Some(val) => val.to_bytes_le(),
+            Option::None => panic!("Should never happen."),
+        }
+    }
+
+    fn from_byte_vector(bytes: &[u8]) -> Self {
+        assert!(
+            bytes.len() <= Self::get_required_bytes(),
+            "Expected at most {} bytes, found {}",
+            Self::get_required_bytes(),
+            bytes.len()
+        );
+        let uval = BigUint::from_bytes_le(bytes);
+        FieldGeneric::new(reduce(BigInt::from_biguint(Sign::Plus, uval)))
+    }
+
+    fn to_dec_string(&self) -> String {
+        self.value.to_str_radix(10)
+    }
+
+    fn inverse_mul(&self) -> FieldGeneric {
+        self.check_modulus();
+        let p = modulus();
+        let (b, s, _) = extended_euclid(&self.value, &p);
+        assert_eq!(b, BigInt::one());
+        FieldGeneric::new(reduce(s))
+    }
+    fn min_value() -> FieldGeneric {
+        FieldGeneric::new(ToBigInt::to_bigint(&0).unwrap())
+    }
+    fn max_value() -> FieldGeneric {
+        FieldGeneric::new(modulus() - ToBigInt::to_bigint(&1).unwrap())
+    }
+    fn get_required_bits() -> usize {
+        modulus().bits()
+    }
+    fn try_from_dec_str<'a>(s: &'a str) -> Result<Self, ()> {
+        let x = BigInt::parse_bytes(s.as_bytes(), 10).ok_or(())?;
+        Ok(FieldGeneric::new(reduce(x)))
+    }
+    fn to_compact_dec_string(&self) -> String {
+        // values up to (p-1)/2 included are represented as positive, values between (p+1)/2 and p-1 are represented as negative by subtracting p
+        if self.value <= FieldGeneric::max_value().value / 2 {
+            format!("{}", self.value.to_str_radix(10))
+        } else {
+            format!("({})", (&self.value - modulus()).to_str_radix(10))
+        }
+    }
+}
+
+// values are compared and hashed like in arithmetic, so values of different moduli are not silently unequal
+
+impl PartialEq for FieldGeneric {
+    fn eq(&self, other: &FieldGeneric) -> bool {
+        self.check_moduli(other);
+        self.value == other.value
+    }
+}
+
+impl Eq for FieldGeneric {}
+
+impl PartialOrd for FieldGeneric {
+    fn partial_cmp(&self, other: &FieldGeneric) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FieldGeneric {
+    fn cmp(&self, other: &FieldGeneric) -> Ordering {
+        self.check_moduli(other);
+        self.value.cmp(&other.value)
+    }
+}
+
+impl Hash for FieldGeneric {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.check_modulus();
+        self.value.hash(state);
+    }
+}
+
+impl Default for FieldGeneric {
+    fn default() -> Self {
+        FieldGeneric::new(BigInt::default())
+    }
+}
+
+impl Display for FieldGeneric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value.to_str_radix(10))
+    }
+}
+
+impl Debug for FieldGeneric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value.to_str_radix(10))
+    }
+}
+
+impl From<i32> for FieldGeneric {
+    fn from(num: i32) -> Self {
+        FieldGeneric::new(reduce(ToBigInt::to_bigint(&num).unwrap()))
+    }
+}
+
+impl From<u32> for FieldGeneric {
+    fn from(num: u32) -> Self {
+        FieldGeneric::new(reduce(ToBigInt::to_bigint(&num).unwrap()))
+    }
+}
+
+impl From<usize> for FieldGeneric {
+    fn from(num: usize) -> Self {
+        FieldGeneric::new(reduce(ToBigInt::to_bigint(&num).unwrap()))
+    }
+}
+
+impl Zero for FieldGeneric {
+    fn zero() -> FieldGeneric {
+        FieldGeneric::new(ToBigInt::to_bigint(&0).unwrap())
+    }
+    fn is_zero(&self) -> bool {
+        self.value == ToBigInt::to_bigint(&0).unwrap()
+    }
+}
+
+impl One for FieldGeneric {
+    fn one() -> FieldGeneric {
+        FieldGeneric::new(reduce(ToBigInt::to_bigint(&1).unwrap()))
+    }
+}
+
+impl Add<FieldGeneric> for FieldGeneric {
+    type Output = FieldGeneric;
+
+    fn add(self, other: FieldGeneric) -> FieldGeneric {
+        self.check_moduli(&other);
+        FieldGeneric::new(add_reduce(self.value, &other.value, &modulus()))
+    }
+}
+
+impl<'a> Add<&'a FieldGeneric> for FieldGeneric {
+    type Output = FieldGeneric;
+
+    fn add(self, other: &FieldGeneric) -> FieldGeneric {
+        self.check_moduli(other);
+        FieldGeneric::new(add_reduce(self.value, &other.value, &modulus()))
+    }
+}
+
+impl Sub<FieldGeneric> for FieldGeneric {
+    type Output = FieldGeneric;
+
+    fn sub(self, other: FieldGeneric) -> FieldGeneric {
+        self.check_moduli(&other);
+        FieldGeneric::new(reduce(self.value - other.value))
+    }
+}
+
+impl<'a> Sub<&'a FieldGeneric> for FieldGeneric {
+    type Output = FieldGeneric;
+
+    fn sub(self, other: &FieldGeneric) -> FieldGeneric {
+        self.check_moduli(other);
+        FieldGeneric::new(reduce(self.value - other.value.clone()))
+    }
+}
+
+impl Mul<FieldGeneric> for FieldGeneric {
+    type Output = FieldGeneric;
+
+    fn mul(self, other: FieldGeneric) -> FieldGeneric {
+        self.check_moduli(&other);
+        FieldGeneric::new(reduce(self.value * other.value))
+    }
+}
+
+impl<'a> Mul<&'a FieldGeneric> for FieldGeneric {
+    type Output = FieldGeneric;
+
+    fn mul(self, other: &FieldGeneric) -> FieldGeneric {
+        self.check_moduli(other);
+        FieldGeneric::new(reduce(self.value * other.value.clone()))
+    }
+}
+
+impl Div<FieldGeneric> for FieldGeneric {
+    type Output = FieldGeneric;
+
+    fn div(self, other: FieldGeneric) -> FieldGeneric {
+        self * other.inverse_mul()
+    }
+}
+
+impl<'a> Div<&'a FieldGeneric> for FieldGeneric {
+    type Output = FieldGeneric;
+
+    fn div(self, other: &FieldGeneric) -> FieldGeneric {
+        self / other.clone()
+    }
+}
+
+impl FieldGeneric {
+    /// Raises `self` to the power `exp` by square-and-multiply, for exponents of any size such as `p - 2`
+    pub fn pow_bigint(&self, exp: &BigUint) -> FieldGeneric {
+        pow_bigint(self, exp)
+    }
+}
+
+impl Pow<usize> for FieldGeneric {
+    type Output = FieldGeneric;
+
+    fn pow(self, exp: usize) -> FieldGeneric {
+        self.pow_bigint(&BigUint::from(exp))
+    }
+}
+
+impl Pow<FieldGeneric> for FieldGeneric {
+    type Output = FieldGeneric;
+
+    fn pow(self, exp: FieldGeneric) -> FieldGeneric {
+        self.pow_bigint(&exp.to_biguint())
+    }
+}
+
+impl<'a> Pow<&'a FieldGeneric> for FieldGeneric {
+    type Output = FieldGeneric;
+
+    fn pow(self, exp: &'a FieldGeneric) -> FieldGeneric {
+        self.pow_bigint(&exp.to_biguint())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mod_97() {
+        FieldGeneric::set_modulus(ToBigInt::to_bigint(&97).unwrap());
+    }
+
+    #[test]
+    fn addition() {
+        mod_97();
+        assert_eq!(
+            FieldGeneric::from(60) + FieldGeneric::from(50),
+            FieldGeneric::from(13)
+        );
+        assert_eq!(
+            FieldGeneric::from(3) - FieldGeneric::from(5),
+            FieldGeneric::from(95)
+        );
+        assert_eq!(FieldGeneric::from(-1), FieldGeneric::from(96));
+        assert_eq!(FieldGeneric::max_value(), FieldGeneric::from(96));
+        assert_eq!(FieldGeneric::from(97), FieldGeneric::zero());
+    }
+
+    #[test]
+    fn add_near_modulus() {
+        mod_97();
+        assert_eq!(
+            FieldGeneric::from(96) + FieldGeneric::from(96),
+            FieldGeneric::from(95)
+        );
+        assert_eq!(
+            FieldGeneric::from(96) + &FieldGeneric::from(1),
+            FieldGeneric::zero()
+        );
+    }
+
+    #[test]
+    fn multiplication() {
+        mod_97();
+        assert_eq!(
+            FieldGeneric::from(20) * FieldGeneric::from(10),
+            FieldGeneric::from(6)
+        );
+        assert_eq!(FieldGeneric::from(2).pow(7), FieldGeneric::from(31));
+        assert_eq!(FieldGeneric::get_required_bits(), 7);
+    }
+
+    #[test]
+    fn inversion() {
+        mod_97();
+        for i in 1..97 {
+            let x = FieldGeneric::from(i);
+            assert_eq!(x.clone() * x.inverse_mul(), FieldGeneric::one());
+        }
+        assert_eq!(
+            FieldGeneric::from(1) / FieldGeneric::from(2),
+            FieldGeneric::from(49)
+        );
+    }
+
+    #[test]
+    fn default_modulus() {
+        // the modulus is per thread, and tests run on their own thread
+        assert_eq!(
+            FieldGeneric::max_value().to_dec_string(),
+            FieldPrime::max_value().to_dec_string()
+        );
+    }
+
+    #[test]
+    fn pow_modulus() {
+        mod_97();
+        for i in 1..97 {
+            let x = FieldGeneric::from(i);
+            assert_eq!(x.clone().pow(96), FieldGeneric::one());
+            assert_eq!(x.clone().pow(FieldGeneric::from(95)), x.inverse_mul());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn stale_modulus() {
+        mod_97();
+        let x = FieldGeneric::from(3);
+        FieldGeneric::set_modulus(ToBigInt::to_bigint(&101).unwrap());
+        let _ = x + FieldGeneric::from(1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn stale_modulus_comparison() {
+        mod_97();
+        let x = FieldGeneric::from(3);
+        FieldGeneric::set_modulus(ToBigInt::to_bigint(&101).unwrap());
+        let _ = x == FieldGeneric::from(3);
+    }
+
+    #[test]
+    fn bellman_default_modulus() {
+        let x = FieldGeneric::from(42);
+        assert_eq!(FieldGeneric::from_bellman(x.clone().into_bellman()), x);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bellman_other_modulus() {
+        mod_97();
+        FieldGeneric::from(42).into_bellman();
+    }
+
+    #[test]
+    fn sqrt() {
+        mod_97();
+        for i in 0..97 {
+            let square = FieldGeneric::from(i) * FieldGeneric::from(i);
+            let root = square.sqrt().unwrap();
+            assert_eq!(root.clone() * &root, square);
+        }
+        assert_eq!(FieldGeneric::from(5).sqrt(), None);
+    }
+}
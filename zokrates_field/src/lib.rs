@@ -1,3 +1,4 @@
 extern crate bellman_ce as bellman;
 
 pub mod field;
+pub mod generic;